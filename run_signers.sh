@@ -27,11 +27,12 @@ validate_key_file() {
 start_signer() {
     local signer_id=$1
     local key_file="$KEYS_DIR/signer${signer_id}.store"
+    local secret_file="$KEYS_DIR/signer${signer_id}.secret"
     local log_file="$LOG_DIR/signer${signer_id}.log"
     local pid_file="$LOG_DIR/signer${signer_id}.pid"
 
-    # Validate key file
-    if ! validate_key_file "$key_file"; then
+    # Validate key and secret files
+    if ! validate_key_file "$key_file" || ! validate_key_file "$secret_file"; then
         return 1
     fi
 
@@ -51,6 +52,7 @@ start_signer() {
     # Start the signer with nohup
     RUST_LOG=info nohup cargo run --bin signer -- \
         --key-file "$key_file" \
+        --signer-secret-file "$secret_file" \
         >> "$log_file" 2>&1 &
 
     # Save PID
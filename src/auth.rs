@@ -1,20 +1,26 @@
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
 use rocket::{Request, State};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tracing::{debug, warn};
+use tracing::{debug, error, warn};
 
-use crate::config::Settings;
+use crate::config::{JwtKeyConfig, SecurityConfig, Settings};
 use crate::error::TssError;
+use crate::manager::policy::SigningPolicy;
+use crate::manager::service::ManagerService;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum Role {
-    Public, // For general API access
-    Signer, // For signer-specific endpoints
-    Admin,  // For administrative functions
+    Public,   // For general API access
+    Signer,   // For signer-specific endpoints
+    Admin,    // For administrative functions
+    Approver, // For approving signing requests
 }
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -22,6 +28,210 @@ pub struct Claims {
     pub exp: usize,  // Expiration time
     pub role: Role,  // User role
     pub iat: usize,  // Issued at
+    #[serde(default)]
+    pub jti: String, // Token ID, checked against the revocation list
+}
+
+/// Client of the API registered by an admin, the tokens issued to it carry its
+/// id as `sub`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiClient {
+    pub client_id: String,
+    pub name: String,
+    pub role: Role,
+    // Lifetime of the tokens issued to the client, in seconds
+    pub token_expiration: u64,
+    // Unix times in milliseconds
+    pub created_at: u64,
+    pub revoked_at: Option<u64>,
+    // `jti` of every token issued to the client
+    #[serde(default)]
+    pub tokens: Vec<String>,
+    #[serde(default)]
+    pub policy: SigningPolicy,
+}
+
+/// Signed API token with its id and expiration (unix seconds)
+pub struct IssuedToken {
+    pub token: String,
+    pub jti: String,
+    pub expires_at: usize,
+}
+
+/// Keys API tokens are signed and verified with. New tokens are signed with
+/// one key and verified with the key their `kid` names, so a retired key keeps
+/// verifying the tokens it signed until they expire. Tokens without a `kid`
+/// are HS256 tokens of `jwt_secret`, only accepted alongside `jwt_keys` when
+/// `jwt_accept_legacy_hs256` is set.
+pub struct JwtKeys {
+    header: Header,
+    signing_key: EncodingKey,
+    verifying_keys: HashMap<String, (Algorithm, DecodingKey)>,
+    secret: Option<DecodingKey>,
+}
+
+impl JwtKeys {
+    pub fn load(security: &SecurityConfig) -> Result<Self, TssError> {
+        let secret = (!security.jwt_secret.is_empty())
+            .then(|| DecodingKey::from_secret(security.jwt_secret.as_ref()));
+        if security.jwt_keys.is_empty() {
+            if secret.is_none() {
+                return Err(TssError::JWTError(
+                    "Either jwt_secret or jwt_keys must be configured".into(),
+                ));
+            }
+            return Ok(Self {
+                header: Header::default(),
+                signing_key: EncodingKey::from_secret(security.jwt_secret.as_ref()),
+                verifying_keys: HashMap::new(),
+                secret,
+            });
+        }
+
+        // Anyone holding the shared secret could otherwise bypass the asymmetric keys
+        let secret = secret.filter(|_| security.jwt_accept_legacy_hs256);
+        let mut verifying_keys = HashMap::new();
+        for key in &security.jwt_keys {
+            let pem = read_pem(&key.public_key)?;
+            let decoding_key = match key.algorithm {
+                Algorithm::ES256 => DecodingKey::from_ec_pem(&pem),
+                Algorithm::RS256 => DecodingKey::from_rsa_pem(&pem),
+                _ => return Err(unsupported_algorithm(key)),
+            }
+            .map_err(|e| TssError::JWTError(format!("Public key {}: {}", key.kid, e)))?;
+            verifying_keys.insert(key.kid.clone(), (key.algorithm, decoding_key));
+        }
+
+        let signing = match &security.jwt_signing_key {
+            Some(kid) => security.jwt_keys.iter().find(|key| &key.kid == kid),
+            None => security
+                .jwt_keys
+                .iter()
+                .find(|key| key.private_key.is_some()),
+        };
+        let (signing, private_key) = signing
+            .and_then(|key| Some((key, key.private_key.as_ref()?)))
+            .ok_or_else(|| TssError::JWTError("No JWT key to sign tokens with".into()))?;
+        let pem = read_pem(private_key)?;
+        let signing_key = match signing.algorithm {
+            Algorithm::ES256 => EncodingKey::from_ec_pem(&pem),
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(&pem),
+            _ => return Err(unsupported_algorithm(signing)),
+        }
+        .map_err(|e| TssError::JWTError(format!("Private key {}: {}", signing.kid, e)))?;
+
+        let mut header = Header::new(signing.algorithm);
+        header.kid = Some(signing.kid.clone());
+        Ok(Self {
+            header,
+            signing_key,
+            verifying_keys,
+            secret,
+        })
+    }
+
+    /// Signs the token `jti` for `subject`, valid for `expires_in` seconds
+    pub fn issue(
+        &self,
+        subject: &str,
+        role: Role,
+        expires_in: u64,
+        jti: &str,
+    ) -> Result<IssuedToken, TssError> {
+        if role == Role::Signer {
+            return Err(TssError::AuthError(
+                "Cannot create token for Signer role".into(),
+            ));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as usize;
+        let claims = Claims {
+            sub: subject.to_string(),
+            exp: now + expires_in as usize,
+            role,
+            iat: now,
+            jti: jti.to_string(),
+        };
+        let token = encode(&self.header, &claims, &self.signing_key)
+            .map_err(|e| TssError::JWTError(e.to_string()))?;
+        Ok(IssuedToken {
+            token,
+            jti: claims.jti,
+            expires_at: claims.exp,
+        })
+    }
+
+    /// Verifies the signature and expiration of a token, revocation is checked
+    /// by the caller
+    pub fn validate(&self, token: &str) -> Result<Claims, TssError> {
+        let header = decode_header(token).map_err(|e| TssError::JWTError(e.to_string()))?;
+        let (algorithm, key) = match &header.kid {
+            Some(kid) => self
+                .verifying_keys
+                .get(kid)
+                .map(|(algorithm, key)| (*algorithm, key))
+                .ok_or_else(|| TssError::AuthError(format!("Unknown key id {}", kid)))?,
+            None => self
+                .secret
+                .as_ref()
+                .map(|key| (Algorithm::HS256, key))
+                .ok_or_else(|| TssError::AuthError("Token without a key id".into()))?,
+        };
+
+        let claims = decode::<Claims>(token, key, &Validation::new(algorithm))
+            .map_err(|e| TssError::JWTError(e.to_string()))?
+            .claims;
+        if claims.jti.is_empty() {
+            return Err(TssError::AuthError("Token without an id".into()));
+        }
+        Ok(claims)
+    }
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>, TssError> {
+    std::fs::read(path).map_err(|e| TssError::JWTError(format!("{}: {}", path, e)))
+}
+
+fn unsupported_algorithm(key: &JwtKeyConfig) -> TssError {
+    TssError::JWTError(format!(
+        "Key {} uses {:?}, only ES256 and RS256 keys are supported",
+        key.kid, key.algorithm
+    ))
+}
+
+// Signers sign a token for every request, a short lifetime limits replays
+pub const SIGNER_TOKEN_TTL_SECONDS: usize = 60;
+
+/// Claims of a token a signer signs with its own secret. The party id is also
+/// the `kid` of the header, it tells the manager which secret verifies the token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignerClaims {
+    pub party_id: u16,
+    pub exp: usize,
+    pub iat: usize,
+}
+
+/// Credential a signer authenticates to the manager with
+#[derive(Clone)]
+pub struct SignerCredentials {
+    pub party_id: u16,
+    secret: String,
+}
+
+impl SignerCredentials {
+    pub fn new(party_id: u16, secret: &str) -> Self {
+        Self {
+            party_id,
+            secret: secret.to_string(),
+        }
+    }
+
+    pub fn token(&self) -> Result<String, TssError> {
+        create_signer_token(self.party_id, &self.secret)
+    }
 }
 
 #[derive(Debug)]
@@ -31,6 +241,7 @@ pub enum AuthError {
     Expired,
     WrongRole,
     IpNotAllowed,
+    Revoked,
 }
 
 pub struct AuthenticatedUser {
@@ -38,10 +249,11 @@ pub struct AuthenticatedUser {
     pub role: Role,
 }
 
-// New struct for IP-based authentication
-pub struct SignerAuth;
+/// Signer calling from an allowed IP with a token signed by its own credential
+pub struct SignerAuth {
+    pub party_id: u16,
+}
 
-// New struct for IP-based authentication
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for SignerAuth {
     type Error = AuthError;
@@ -54,18 +266,31 @@ impl<'r> FromRequest<'r> for SignerAuth {
 
         if let Some(client_ip) = request.client_ip() {
             debug!("Signer API request from IP: {}", client_ip);
-            if settings.is_ip_whitelisted(client_ip) {
-                Outcome::Success(SignerAuth)
-            } else {
+            if !settings.is_ip_whitelisted(client_ip) {
                 warn!(
                     "Unauthorized signer API access attempt from IP: {}",
                     client_ip
                 );
-                Outcome::Error((Status::Unauthorized, AuthError::IpNotAllowed))
+                return Outcome::Error((Status::Unauthorized, AuthError::IpNotAllowed));
             }
         } else {
             warn!("No client IP found in signer API request");
-            Outcome::Error((Status::Unauthorized, AuthError::IpNotAllowed))
+            return Outcome::Error((Status::Unauthorized, AuthError::IpNotAllowed));
+        }
+
+        let token = request
+            .headers()
+            .get_one("Authorization")
+            .map(|value| value.replace("Bearer ", ""));
+        match token {
+            Some(token) => match validate_signer_token(&token, settings) {
+                Ok(party_id) => Outcome::Success(SignerAuth { party_id }),
+                Err(e) => {
+                    warn!("Rejected signer token: {}", e);
+                    Outcome::Error((Status::Unauthorized, AuthError::Invalid))
+                }
+            },
+            None => Outcome::Error((Status::Unauthorized, AuthError::Missing)),
         }
     }
 }
@@ -75,10 +300,14 @@ impl<'r> FromRequest<'r> for AuthenticatedUser {
     type Error = AuthError;
 
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let settings = request
-            .guard::<&State<Arc<Settings>>>()
+        let keys = request
+            .guard::<&State<Arc<JwtKeys>>>()
             .await
-            .expect("Settings not found in request state");
+            .expect("JWT keys not found in request state");
+        let manager = request
+            .guard::<&State<Arc<ManagerService>>>()
+            .await
+            .expect("Manager service not found in request state");
 
         // Get and validate JWT token
         let token = request
@@ -87,8 +316,22 @@ impl<'r> FromRequest<'r> for AuthenticatedUser {
             .map(|value| value.replace("Bearer ", ""));
 
         match token {
-            Some(token) => match validate_token(&token, &settings.inner().security.jwt_secret) {
+            Some(token) => match keys.validate(&token) {
                 Ok(claims) => {
+                    match manager.is_token_revoked(&claims.jti).await {
+                        Ok(false) => {}
+                        Ok(true) => {
+                            return Outcome::Error((Status::Unauthorized, AuthError::Revoked))
+                        }
+                        Err(e) => {
+                            error!("Error checking token revocation: {:?}", e);
+                            return Outcome::Error((
+                                Status::InternalServerError,
+                                AuthError::Invalid,
+                            ));
+                        }
+                    }
+
                     // Verify role permissions for endpoint
                     if !has_permission_for_endpoint(
                         &claims.role,
@@ -113,11 +356,19 @@ fn has_permission_for_endpoint(role: &Role, path: &str) -> bool {
     match (role, path) {
         // Public endpoints
         (Role::Public, "/sign") => true,
+        (Role::Public, "/sign_psbt") => true,
+        (Role::Public, "/verify") => true,
         (Role::Public, path) if path.starts_with("/signing_result/") => true,
 
         // Signer endpoints
         (Role::Signer, _) => false,
 
+        // Approver endpoints
+        (Role::Approver, "/signing_requests") => true,
+        (Role::Approver, path) if path.starts_with("/signing_requests/") => {
+            path.ends_with("/approve") || path.ends_with("/reject")
+        }
+
         // Admin endpoints
         (Role::Admin, _) => true,
 
@@ -125,43 +376,61 @@ fn has_permission_for_endpoint(role: &Role, path: &str) -> bool {
     }
 }
 
-pub fn validate_token(token: &str, secret: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let validation = Validation::default();
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &validation,
-    )?;
-
-    Ok(token_data.claims)
+/// Signs a token with a new id for `user_id`, valid for `expires_in` seconds
+pub fn create_token(
+    keys: &JwtKeys,
+    user_id: &str,
+    role: Role,
+    expires_in: u64,
+) -> Result<String, TssError> {
+    let jti = uuid::Uuid::new_v4().to_string();
+    Ok(keys.issue(user_id, role, expires_in, &jti)?.token)
 }
 
-pub fn create_token(user_id: &str, role: Role, settings: &Settings) -> Result<String, TssError> {
-    match role {
-        Role::Signer => {
-            return Err(TssError::AuthError(
-                "Cannot create token for Signer role".into(),
-            ))
-        }
-        _ => (),
-    }
-
+pub fn create_signer_token(party_id: u16, secret: &str) -> Result<String, TssError> {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs() as usize;
 
-    let claims = Claims {
-        sub: user_id.to_string(),
-        exp: now + 3600, // Expires in 1 hour
-        role,
+    let header = Header {
+        kid: Some(party_id.to_string()),
+        ..Header::default()
+    };
+    let claims = SignerClaims {
+        party_id,
+        exp: now + SIGNER_TOKEN_TTL_SECONDS,
         iat: now,
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(settings.security.jwt_secret.as_ref()),
+    encode(&header, &claims, &EncodingKey::from_secret(secret.as_ref()))
+        .map_err(|e| TssError::JWTError(e.to_string()))
+}
+
+/// Verifies a signer token with the secret of the party named by its key id,
+/// returns the party id
+pub fn validate_signer_token(token: &str, settings: &Settings) -> Result<u16, TssError> {
+    let header = decode_header(token).map_err(|e| TssError::JWTError(e.to_string()))?;
+    let party_id: u16 = header
+        .kid
+        .as_deref()
+        .and_then(|kid| kid.parse().ok())
+        .ok_or_else(|| TssError::AuthError("Signer token without a party id".into()))?;
+    let secret = settings
+        .signer_secret(party_id)
+        .ok_or_else(|| TssError::AuthError(format!("No credential for party {}", party_id)))?;
+
+    let claims = decode::<SignerClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &Validation::default(),
     )
-    .map_err(|e| TssError::JWTError(e.to_string()))
+    .map_err(|e| TssError::JWTError(e.to_string()))?
+    .claims;
+    if claims.party_id != party_id {
+        return Err(TssError::AuthError(
+            "Signer token party id does not match its key id".into(),
+        ));
+    }
+    Ok(party_id)
 }
@@ -1,20 +1,40 @@
+use clap::Parser;
 use rocket::routes;
 use rocket::{figment::Figment, Config};
 use std::sync::Arc;
+use tss_network::auth::{JwtKeys, Role};
 use tss_network::config::Settings;
 use tss_network::manager::api::{
-    generate_keys, generate_test_token, get_key_gen_result, get_signing_result, sign,
+    approve_signing_request, create_api_client, generate_keys, generate_test_token,
+    get_key_gen_result, get_room, get_signing_result, issue_api_token, list_api_clients,
+    list_dead_letters, list_policy_denials, list_signing_requests, reject_signing_request,
+    replay_dead_letters, revoke_api_client, revoke_token, set_api_client_policy, sign, sign_psbt,
+    verify,
 };
-use tss_network::manager::handlers::{get, set, signup_keygen, signup_sign, update_signing_result};
-use tss_network::manager::service::ManagerService;
+use tss_network::manager::handlers::{
+    get, report_signing_refusal, set, signup_sign, update_signing_progress, update_signing_result,
+};
+use tss_network::manager::policy::SigningPolicy;
+use tss_network::manager::service::{ManagerService, SchemePublicKeys};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Registers an admin API client with this name, prints its token and exits
+    #[arg(long)]
+    create_admin_client: Option<String>,
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
+    let args = Args::parse();
     // Load configuration
     let settings = Settings::new().expect("Failed to load configuration");
+    let jwt_keys = Arc::new(JwtKeys::load(&settings.security)?);
+    let public_keys = Arc::new(SchemePublicKeys::load(&settings)?);
 
     // Initialize ManagerService
     let manager_service: Arc<ManagerService> = Arc::new(
@@ -23,10 +43,29 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             &settings.rabbitmq_uri,
             settings.threshold,
             settings.total_parties,
+            &settings.storage,
+            &settings.room_store,
+            &settings.queue,
         )
         .await?,
     );
 
+    // First admin credential of a deployment, later clients are managed through the API
+    if let Some(name) = args.create_admin_client {
+        let (client, issued) = manager_service
+            .create_api_client(
+                &name,
+                Role::Admin,
+                settings.security.jwt_expiration,
+                SigningPolicy::default(),
+                &jwt_keys,
+            )
+            .await?;
+        println!("client_id: {}", client.client_id);
+        println!("token: {}", issued.token);
+        return Ok(());
+    }
+
     let manager_service_for_rocket = manager_service.clone();
 
     let ip = settings
@@ -42,17 +81,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let rocket_future = rocket::custom(config)
         .manage(manager_service_for_rocket)
         .manage(Arc::new(settings))
+        .manage(jwt_keys)
+        .manage(public_keys)
         .mount(
             "/",
             routes![
                 sign,
+                sign_psbt,
+                verify,
                 signup_sign,
                 set,
                 get,
                 get_signing_result,
+                get_room,
+                list_signing_requests,
+                approve_signing_request,
+                reject_signing_request,
+                list_dead_letters,
+                replay_dead_letters,
+                create_api_client,
+                list_api_clients,
+                issue_api_token,
+                set_api_client_policy,
+                revoke_api_client,
+                revoke_token,
+                list_policy_denials,
                 update_signing_result,
+                update_signing_progress,
+                report_signing_refusal,
                 generate_keys,
-                signup_keygen,
                 get_key_gen_result,
                 generate_test_token
             ],
@@ -61,6 +118,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     tokio::select! {
         _ = rocket_future => println!("Rocket server shut down"),
+        result = manager_service.run() => {
+            if let Err(e) = result {
+                println!("Manager service stopped: {:?}", e);
+            }
+        }
     }
     Ok(())
 }
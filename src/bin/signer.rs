@@ -1,7 +1,11 @@
 use clap::Parser;
+use std::time::Duration;
 use std::{path::PathBuf, sync::Arc};
+use tss_network::common::MeshNode;
 use tss_network::config::Settings;
+use tss_network::queue;
 use tss_network::signer::service::SignerService;
+use tss_network::signer::SignerPolicy;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -9,6 +13,18 @@ struct Args {
     /// Path to the key file
     #[arg(short, long)]
     key_file: Option<PathBuf>,
+
+    /// Path to the FROST key share used for Schnorr signing
+    #[arg(long)]
+    frost_key_file: Option<PathBuf>,
+
+    /// Path to the FROST key share used for Ed25519 signing
+    #[arg(long)]
+    ed25519_key_file: Option<PathBuf>,
+
+    /// Path to a file holding the secret this signer authenticates to the manager with
+    #[arg(long)]
+    signer_secret_file: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -20,17 +36,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let settings = Settings::new().expect("Failed to load configuration");
 
     // Get and validate key file
+    #[allow(clippy::map_identity)]
     let key_file = get_key_file(&args, &settings).map_err(|e| e)?;
+    let frost_key_file = get_frost_key_file(&args.frost_key_file, &settings.frost_key_file)?;
+    let ed25519_key_file = get_frost_key_file(&args.ed25519_key_file, &settings.ed25519_key_file)?;
 
+    if (settings.queue.prefetch as usize) < settings.max_concurrent_sessions {
+        tracing::warn!(
+            "queue.prefetch {} limits the signer below max_concurrent_sessions {}",
+            settings.queue.prefetch,
+            settings.max_concurrent_sessions
+        );
+    }
+    let signer_secret = get_signer_secret(&args, &settings)?;
+    let signer_policy = SignerPolicy::from_config(&settings.signer_policy)?;
+    let queue = queue::connect(&settings.queue, &settings.rabbitmq_uri).await?;
+    let mesh = if settings.mesh.enabled {
+        Some(MeshNode::bind(&settings.mesh).await?)
+    } else {
+        None
+    };
     let signer_service: Arc<SignerService> = Arc::new(
         SignerService::new(
             &settings.manager_url,
             &settings.manager_port,
-            &settings.rabbitmq_uri,
+            queue,
             &key_file,
+            &signer_secret,
             &settings.threshold,
             &settings.total_parties,
             &settings.path,
+            frost_key_file.as_deref(),
+            ed25519_key_file.as_deref(),
+            settings.max_concurrent_sessions,
+            Duration::from_secs(settings.session_timeout_seconds),
+            mesh,
+            signer_policy,
         )
         .await?,
     );
@@ -73,3 +114,41 @@ fn get_key_file(args: &Args, settings: &Settings) -> Result<String, String> {
             .to_string(),
     )
 }
+
+fn get_frost_key_file(
+    cli_path: &Option<PathBuf>,
+    config_path: &Option<String>,
+) -> Result<Option<String>, String> {
+    let key_path = match (cli_path, config_path) {
+        (Some(key_path), _) => key_path.clone(),
+        (None, Some(key_file)) if !key_file.is_empty() => PathBuf::from(key_file),
+        // The signer does not serve this scheme without a key share
+        _ => return Ok(None),
+    };
+    if !key_path.exists() {
+        return Err(format!("FROST key file not found: {}", key_path.display()));
+    }
+    Ok(Some(
+        key_path
+            .to_str()
+            .ok_or("Invalid path encoding")?
+            .to_string(),
+    ))
+}
+
+fn get_signer_secret(args: &Args, settings: &Settings) -> Result<String, String> {
+    let secret = match &args.signer_secret_file {
+        Some(secret_path) => std::fs::read_to_string(secret_path)
+            .map_err(|e| format!("Signer secret file {}: {}", secret_path.display(), e))?
+            .trim()
+            .to_string(),
+        None => settings.security.signer_secret.clone(),
+    };
+    if secret.is_empty() {
+        return Err(
+            "No signer secret provided. Please specify either security.signer_secret in config file or via --signer-secret-file argument"
+                .to_string(),
+        );
+    }
+    Ok(secret)
+}
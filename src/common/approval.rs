@@ -0,0 +1,57 @@
+use std::str::FromStr;
+
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::ecdsa::Signature;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1};
+use sha2::{Digest, Sha256};
+
+use crate::common::{ApprovalSignature, SigningRequest};
+
+/// Digest approvers sign to approve `request`. For PSBT inputs it covers the
+/// transaction, so that one approval covers all its inputs, otherwise the key,
+/// the derivation path and the message.
+pub fn approval_digest(request: &SigningRequest) -> Result<[u8; 32], String> {
+    let statement = match &request.transaction {
+        Some(transaction) => {
+            let psbt =
+                Psbt::from_str(&transaction.psbt).map_err(|e| format!("invalid PSBT: {}", e))?;
+            format!(
+                "tss-network approval\ntransaction\n{}",
+                psbt.unsigned_tx.compute_txid()
+            )
+        }
+        // The derivation path of the signers when unset
+        None => format!(
+            "tss-network approval\nmessage\n{}\n{}\n{}",
+            request.scheme.key_id(),
+            request.derivation_path.as_deref().unwrap_or("default"),
+            hex::encode(&request.message)
+        ),
+    };
+    Ok(Sha256::digest(statement.as_bytes()).into())
+}
+
+/// Checks an approver signature over `digest` and returns the approver key
+pub fn verify_approval(
+    digest: &[u8; 32],
+    approval: &ApprovalSignature,
+) -> Result<PublicKey, String> {
+    let public_key = hex::decode(&approval.public_key)
+        .ok()
+        .and_then(|bytes| PublicKey::from_slice(&bytes).ok())
+        .ok_or_else(|| format!("invalid approver key {}", approval.public_key))?;
+    let mut signature = hex::decode(&approval.signature)
+        .ok()
+        .and_then(|bytes| Signature::from_der(&bytes).ok())
+        .ok_or_else(|| "invalid approval signature encoding".to_string())?;
+    signature.normalize_s();
+    Secp256k1::verification_only()
+        .verify_ecdsa(&Message::from_digest(*digest), &signature, &public_key)
+        .map_err(|_| {
+            format!(
+                "approval signature of {} does not verify",
+                approval.public_key
+            )
+        })?;
+    Ok(public_key)
+}
@@ -0,0 +1,523 @@
+use curv::arithmetic::{BitManipulation, Converter};
+use curv::elliptic::curves::{Curve, Ed25519, Point, Scalar, Secp256k1};
+use curv::BigInt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::common::signature::to_fixed_bytes;
+use crate::common::types::SignatureScheme;
+use crate::error::TssError;
+
+// FROST (two round threshold Schnorr), generic over the ciphersuite so the same
+// keygen and signing rounds produce BIP340 signatures on secp256k1 and RFC 8032
+// signatures on Ed25519. Participants are identified by their key share index,
+// which doubles as the x-coordinate of their share.
+
+/// Curve and hash choices of a FROST instantiation
+pub trait Ciphersuite {
+    type Curve: Curve;
+
+    /// Scheme recorded on signing requests and results using this ciphersuite
+    const SCHEME: SignatureScheme;
+    /// Prefix of the domain separation tags used for keygen and binding factors
+    const ID: &'static str;
+
+    /// Hashes `data` into a scalar, domain separated by `tag`
+    fn hash_to_scalar(tag: &str, data: &[&[u8]]) -> Scalar<Self::Curve>;
+
+    /// Signature challenge `c = H(R, Y, m)` as defined by the target scheme
+    fn challenge(
+        r: &Point<Self::Curve>,
+        public_key: &Point<Self::Curve>,
+        message: &[u8],
+    ) -> Scalar<Self::Curve>;
+
+    /// Whether the scheme can use `point` as is for a key or a nonce. BIP340
+    /// only admits points with an even y, the others are negated.
+    fn is_canonical(point: &Point<Self::Curve>) -> bool;
+
+    /// Wire encoding of keys and nonces in signatures
+    fn encode_point(point: &Point<Self::Curve>) -> Vec<u8>;
+
+    /// Wire encoding of the `s` half of a signature
+    fn encode_scalar(scalar: &Scalar<Self::Curve>) -> Vec<u8>;
+
+    /// Inverse of `encode_point`, `None` for bytes that encode no point
+    fn decode_point(bytes: &[u8]) -> Option<Point<Self::Curve>>;
+
+    /// Inverse of `encode_scalar`, `None` for values out of the scalar range
+    fn decode_scalar(bytes: &[u8]) -> Option<Scalar<Self::Curve>>;
+}
+
+/// BIP340 Schnorr signatures over secp256k1 with x-only keys
+#[derive(Clone, Debug)]
+pub struct Secp256k1Bip340;
+
+/// RFC 8032 Ed25519 signatures
+#[derive(Clone, Debug)]
+pub struct Ed25519Sha512;
+
+impl Ciphersuite for Secp256k1Bip340 {
+    type Curve = Secp256k1;
+
+    const SCHEME: SignatureScheme = SignatureScheme::Schnorr;
+    const ID: &'static str = "FROST/secp256k1";
+
+    fn hash_to_scalar(tag: &str, data: &[&[u8]]) -> Scalar<Secp256k1> {
+        let tag_hash = Sha256::digest(tag.as_bytes());
+        let mut hasher = Sha256::new();
+        hasher.update(tag_hash);
+        hasher.update(tag_hash);
+        for chunk in data {
+            hasher.update(chunk);
+        }
+        Scalar::from_bigint(&BigInt::from_bytes(&hasher.finalize()))
+    }
+
+    fn challenge(
+        r: &Point<Secp256k1>,
+        public_key: &Point<Secp256k1>,
+        message: &[u8],
+    ) -> Scalar<Secp256k1> {
+        Self::hash_to_scalar(
+            "BIP0340/challenge",
+            &[&x_only(r), &x_only(public_key), message],
+        )
+    }
+
+    fn is_canonical(point: &Point<Secp256k1>) -> bool {
+        has_even_y(point)
+    }
+
+    fn encode_point(point: &Point<Secp256k1>) -> Vec<u8> {
+        x_only(point).to_vec()
+    }
+
+    fn encode_scalar(scalar: &Scalar<Secp256k1>) -> Vec<u8> {
+        to_fixed_bytes(&scalar.to_bigint()).to_vec()
+    }
+
+    // x-only points are lifted to the point with an even y
+    fn decode_point(bytes: &[u8]) -> Option<Point<Secp256k1>> {
+        if bytes.len() != 32 {
+            return None;
+        }
+        Point::from_bytes(&[&[0x02], bytes].concat()).ok()
+    }
+
+    fn decode_scalar(bytes: &[u8]) -> Option<Scalar<Secp256k1>> {
+        scalar_from_be_bytes(bytes)
+    }
+}
+
+impl Ciphersuite for Ed25519Sha512 {
+    type Curve = Ed25519;
+
+    const SCHEME: SignatureScheme = SignatureScheme::Eddsa;
+    const ID: &'static str = "FROST/ed25519";
+
+    fn hash_to_scalar(tag: &str, data: &[&[u8]]) -> Scalar<Ed25519> {
+        let mut hasher = Sha512::new();
+        hasher.update(tag.as_bytes());
+        for chunk in data {
+            hasher.update(chunk);
+        }
+        scalar_from_le_bytes(&hasher.finalize())
+    }
+
+    fn challenge(
+        r: &Point<Ed25519>,
+        public_key: &Point<Ed25519>,
+        message: &[u8],
+    ) -> Scalar<Ed25519> {
+        let mut hasher = Sha512::new();
+        hasher.update(&*r.to_bytes(true));
+        hasher.update(&*public_key.to_bytes(true));
+        hasher.update(message);
+        scalar_from_le_bytes(&hasher.finalize())
+    }
+
+    fn is_canonical(_point: &Point<Ed25519>) -> bool {
+        true
+    }
+
+    fn encode_point(point: &Point<Ed25519>) -> Vec<u8> {
+        point.to_bytes(true).to_vec()
+    }
+
+    fn encode_scalar(scalar: &Scalar<Ed25519>) -> Vec<u8> {
+        let mut bytes = to_fixed_bytes(&scalar.to_bigint());
+        bytes.reverse();
+        bytes.to_vec()
+    }
+
+    fn decode_point(bytes: &[u8]) -> Option<Point<Ed25519>> {
+        if bytes.len() != 32 {
+            return None;
+        }
+        Point::from_bytes(bytes).ok()
+    }
+
+    fn decode_scalar(bytes: &[u8]) -> Option<Scalar<Ed25519>> {
+        let mut bytes = bytes.to_vec();
+        bytes.reverse();
+        scalar_from_be_bytes(&bytes)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct FrostKeyShare<E: Curve> {
+    pub party_id: u16,
+    pub threshold: u16,
+    pub parties: u16,
+    pub secret_share: Scalar<E>,
+    // Group key, normalized so the ciphersuite accepts it as is
+    pub public_key: Point<E>,
+    // `x_i * G` for every party, indexed by `party_id - 1`
+    pub verification_shares: Vec<Point<E>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct KeyGenCommitment<E: Curve> {
+    pub party_id: u16,
+    pub commitments: Vec<Point<E>>,
+    pub proof_r: Point<E>,
+    pub proof_mu: Scalar<E>,
+    // Ephemeral key used to encrypt the round 2 shares sent to this party
+    pub encryption_key: Point<E>,
+}
+
+pub struct KeyGenSecrets<E: Curve> {
+    coefficients: Vec<Scalar<E>>,
+    encryption_secret: Scalar<E>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct NonceCommitment<E: Curve> {
+    pub party_id: u16,
+    pub hiding: Point<E>,
+    pub binding: Point<E>,
+}
+
+pub struct SigningNonces<E: Curve> {
+    hiding: Scalar<E>,
+    binding: Scalar<E>,
+}
+
+/// Samples the secret polynomial and commits to it together with a proof of
+/// knowledge of the constant term.
+pub fn keygen_commit<C: Ciphersuite>(
+    party_id: u16,
+    threshold: u16,
+    context: &str,
+) -> (KeyGenSecrets<C::Curve>, KeyGenCommitment<C::Curve>) {
+    let coefficients: Vec<Scalar<C::Curve>> = (0..=threshold).map(|_| Scalar::random()).collect();
+    let commitments: Vec<Point<C::Curve>> = coefficients
+        .iter()
+        .map(|a| Point::generator() * a)
+        .collect();
+
+    let k = Scalar::random();
+    let proof_r = Point::generator() * &k;
+    let c = dkg_challenge::<C>(party_id, context, &commitments[0], &proof_r);
+    let proof_mu = k + c * &coefficients[0];
+
+    let encryption_secret = Scalar::random();
+    let encryption_key = Point::generator() * &encryption_secret;
+
+    (
+        KeyGenSecrets {
+            coefficients,
+            encryption_secret,
+        },
+        KeyGenCommitment {
+            party_id,
+            commitments,
+            proof_r,
+            proof_mu,
+            encryption_key,
+        },
+    )
+}
+
+pub fn verify_keygen_commitment<C: Ciphersuite>(
+    commitment: &KeyGenCommitment<C::Curve>,
+    threshold: u16,
+    context: &str,
+) -> bool {
+    if commitment.commitments.len() != usize::from(threshold) + 1 {
+        return false;
+    }
+    let c = dkg_challenge::<C>(
+        commitment.party_id,
+        context,
+        &commitment.commitments[0],
+        &commitment.proof_r,
+    );
+    Point::generator() * &commitment.proof_mu
+        == &commitment.proof_r + &commitment.commitments[0] * c
+}
+
+impl<E: Curve> KeyGenSecrets<E> {
+    /// Share of this party's polynomial for `receiver`
+    pub fn share_for(&self, receiver: u16) -> Scalar<E> {
+        let x = Scalar::from(receiver);
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(Scalar::zero(), |acc, a| acc * &x + a)
+    }
+
+    /// Symmetric key shared with `peer` for encrypting round 2 shares
+    pub fn encryption_key_with(&self, peer: &KeyGenCommitment<E>) -> Vec<u8> {
+        let shared = &peer.encryption_key * &self.encryption_secret;
+        Sha256::digest(&shared.to_bytes(true)).to_vec()
+    }
+}
+
+/// Checks a received share against the sender's polynomial commitments
+pub fn verify_keygen_share<E: Curve>(
+    share: &Scalar<E>,
+    receiver: u16,
+    commitments: &[Point<E>],
+) -> bool {
+    Point::generator() * share == evaluate_commitments(commitments, receiver)
+}
+
+/// Combines the shares received from every party (including our own) into the
+/// long lived key share. `commitments` holds every party's round 1 message.
+pub fn finalize_keygen<C: Ciphersuite>(
+    party_id: u16,
+    threshold: u16,
+    shares: &[Scalar<C::Curve>],
+    commitments: &[KeyGenCommitment<C::Curve>],
+) -> FrostKeyShare<C::Curve> {
+    let parties = commitments.len() as u16;
+    let mut secret_share: Scalar<C::Curve> = shares.iter().sum();
+    let mut public_key: Point<C::Curve> = commitments.iter().map(|c| &c.commitments[0]).sum();
+    let mut verification_shares: Vec<Point<C::Curve>> = (1..=parties)
+        .map(|j| {
+            commitments
+                .iter()
+                .map(|c| evaluate_commitments(&c.commitments, j))
+                .sum()
+        })
+        .collect();
+
+    // BIP340 keys are x-only, negate everything if the group key has an odd y
+    if !C::is_canonical(&public_key) {
+        secret_share = -secret_share;
+        public_key = -public_key;
+        verification_shares = verification_shares.into_iter().map(|y| -y).collect();
+    }
+
+    FrostKeyShare {
+        party_id,
+        threshold,
+        parties,
+        secret_share,
+        public_key,
+        verification_shares,
+    }
+}
+
+/// Round 1 of signing: fresh hiding and binding nonces
+pub fn commit_nonces<E: Curve>(party_id: u16) -> (SigningNonces<E>, NonceCommitment<E>) {
+    let nonces = SigningNonces {
+        hiding: Scalar::random(),
+        binding: Scalar::random(),
+    };
+    let commitment = NonceCommitment {
+        party_id,
+        hiding: Point::generator() * &nonces.hiding,
+        binding: Point::generator() * &nonces.binding,
+    };
+    (nonces, commitment)
+}
+
+/// Round 2 of signing: this party's signature share
+pub fn sign_share<C: Ciphersuite>(
+    key_share: &FrostKeyShare<C::Curve>,
+    nonces: &SigningNonces<C::Curve>,
+    message: &[u8],
+    commitments: &[NonceCommitment<C::Curve>],
+) -> Result<Scalar<C::Curve>, TssError> {
+    let (group_commitment, rho) = group_commitment::<C>(message, commitments);
+    let own_rho = &rho[commitment_index(commitments, key_share.party_id)?];
+    let mut nonce = &nonces.hiding + &nonces.binding * own_rho;
+    if !C::is_canonical(&group_commitment) {
+        nonce = -nonce;
+    }
+    let c = C::challenge(&group_commitment, &key_share.public_key, message);
+    let lambda = lagrange_coefficient(key_share.party_id, &signer_ids(commitments));
+    Ok(nonce + c * lambda * &key_share.secret_share)
+}
+
+/// Verifies the signature share of `party_id` before aggregating it. Party ids
+/// come from peers, ids outside the key or the signing set are an error.
+pub fn verify_signature_share<C: Ciphersuite>(
+    key_share: &FrostKeyShare<C::Curve>,
+    party_id: u16,
+    share: &Scalar<C::Curve>,
+    message: &[u8],
+    commitments: &[NonceCommitment<C::Curve>],
+) -> Result<bool, TssError> {
+    let verification_share = verification_share(key_share, party_id)?;
+    let (group_commitment, rho) = group_commitment::<C>(message, commitments);
+    let index = commitment_index(commitments, party_id)?;
+    let commitment = &commitments[index];
+    let mut nonce_commitment = &commitment.hiding + &commitment.binding * &rho[index];
+    if !C::is_canonical(&group_commitment) {
+        nonce_commitment = -nonce_commitment;
+    }
+    let c = C::challenge(&group_commitment, &key_share.public_key, message);
+    let lambda = lagrange_coefficient(party_id, &signer_ids(commitments));
+    Ok(Point::generator() * share == nonce_commitment + verification_share * (c * lambda))
+}
+
+/// Sums the signature shares into the signature `(R, s)`, with `R` in canonical form
+pub fn aggregate<C: Ciphersuite>(
+    message: &[u8],
+    commitments: &[NonceCommitment<C::Curve>],
+    shares: &[Scalar<C::Curve>],
+) -> (Point<C::Curve>, Scalar<C::Curve>) {
+    let (group_commitment, _) = group_commitment::<C>(message, commitments);
+    let r = if C::is_canonical(&group_commitment) {
+        group_commitment
+    } else {
+        -group_commitment
+    };
+    (r, shares.iter().sum())
+}
+
+/// Standard single key verification of the aggregated signature
+pub fn verify<C: Ciphersuite>(
+    public_key: &Point<C::Curve>,
+    message: &[u8],
+    r: &Point<C::Curve>,
+    s: &Scalar<C::Curve>,
+) -> bool {
+    C::is_canonical(public_key)
+        && C::is_canonical(r)
+        && Point::generator() * s == r + public_key * C::challenge(r, public_key, message)
+}
+
+pub fn has_even_y(point: &Point<Secp256k1>) -> bool {
+    point.y_coord().is_some_and(|y| !y.test_bit(0))
+}
+
+pub fn x_only(point: &Point<Secp256k1>) -> [u8; 32] {
+    to_fixed_bytes(&point.x_coord().unwrap())
+}
+
+fn dkg_challenge<C: Ciphersuite>(
+    party_id: u16,
+    context: &str,
+    constant: &Point<C::Curve>,
+    proof_r: &Point<C::Curve>,
+) -> Scalar<C::Curve> {
+    C::hash_to_scalar(
+        &format!("{}/dkg", C::ID),
+        &[
+            &party_id.to_be_bytes(),
+            context.as_bytes(),
+            &constant.to_bytes(true),
+            &proof_r.to_bytes(true),
+        ],
+    )
+}
+
+/// Group commitment `R = sum(D_i + rho_i * E_i)` and the binding factors `rho_i`
+fn group_commitment<C: Ciphersuite>(
+    message: &[u8],
+    commitments: &[NonceCommitment<C::Curve>],
+) -> (Point<C::Curve>, Vec<Scalar<C::Curve>>) {
+    let tag = format!("{}/rho", C::ID);
+    let encoded: Vec<u8> = commitments
+        .iter()
+        .flat_map(|c| {
+            [
+                c.party_id.to_be_bytes().to_vec(),
+                c.hiding.to_bytes(true).to_vec(),
+                c.binding.to_bytes(true).to_vec(),
+            ]
+            .concat()
+        })
+        .collect();
+    let rho: Vec<Scalar<C::Curve>> = commitments
+        .iter()
+        .map(|c| C::hash_to_scalar(&tag, &[&c.party_id.to_be_bytes(), message, &encoded]))
+        .collect();
+    let r = commitments
+        .iter()
+        .zip(rho.iter())
+        .map(|(c, rho)| &c.hiding + &c.binding * rho)
+        .sum();
+    (r, rho)
+}
+
+fn lagrange_coefficient<E: Curve>(party_id: u16, signers: &[u16]) -> Scalar<E> {
+    let x_i = Scalar::<E>::from(party_id);
+    signers
+        .iter()
+        .filter(|j| **j != party_id)
+        .fold(Scalar::from(1u16), |acc, j| {
+            let x_j = Scalar::<E>::from(*j);
+            acc * &x_j * (x_j.clone() - &x_i).invert().unwrap()
+        })
+}
+
+fn evaluate_commitments<E: Curve>(commitments: &[Point<E>], x: u16) -> Point<E> {
+    let x = Scalar::<E>::from(x);
+    commitments
+        .iter()
+        .rev()
+        .fold(Point::zero(), |acc, c| acc * &x + c)
+}
+
+fn signer_ids<E: Curve>(commitments: &[NonceCommitment<E>]) -> Vec<u16> {
+    commitments.iter().map(|c| c.party_id).collect()
+}
+
+fn commitment_index<E: Curve>(
+    commitments: &[NonceCommitment<E>],
+    party_id: u16,
+) -> Result<usize, TssError> {
+    commitments
+        .iter()
+        .position(|c| c.party_id == party_id)
+        .ok_or(TssError::InvalidPartyId(party_id))
+}
+
+// Verification share of `party_id`, parties of the key are numbered `1..=parties`
+fn verification_share<E: Curve>(
+    key_share: &FrostKeyShare<E>,
+    party_id: u16,
+) -> Result<&Point<E>, TssError> {
+    if !(1..=key_share.parties).contains(&party_id) {
+        return Err(TssError::InvalidPartyId(party_id));
+    }
+    key_share
+        .verification_shares
+        .get(usize::from(party_id) - 1)
+        .ok_or(TssError::InvalidPartyId(party_id))
+}
+
+// Ed25519 hashes are read as little endian integers, curv expects big endian
+fn scalar_from_le_bytes(bytes: &[u8]) -> Scalar<Ed25519> {
+    let mut bytes = bytes.to_vec();
+    bytes.reverse();
+    Scalar::from_bigint(&BigInt::from_bytes(&bytes))
+}
+
+// 32 byte big-endian scalar, without reducing values past the group order
+fn scalar_from_be_bytes<E: Curve>(bytes: &[u8]) -> Option<Scalar<E>> {
+    let value = BigInt::from_bytes(bytes);
+    if bytes.len() != 32 || &value >= Scalar::<E>::group_order() {
+        return None;
+    }
+    Some(Scalar::from_bigint(&value))
+}
@@ -2,7 +2,7 @@ extern crate curv;
 
 use curv::arithmetic::traits::Converter;
 
-use crate::signer::secp256k1def::{FE, GE};
+use crate::common::secp256k1def::{FE, GE};
 use curv::{
     arithmetic::{BasicOps, One},
     BigInt,
@@ -11,6 +11,42 @@ use hmac::{Hmac, Mac, NewMac};
 use sha2::Sha512;
 use zeroize::Zeroize;
 
+/// Whether `path` is a derivation path, indices separated by `/`
+pub fn is_valid_path(path: &str) -> bool {
+    path.split('/')
+        .all(|s| BigInt::from_str_radix(s.trim(), 10).is_ok())
+}
+
+/// Whether the derivation path `path` matches `pattern`, a trailing `/*`
+/// matching every path below
+pub fn path_matches(pattern: &str, path: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(parent) => {
+            let parent = components(parent);
+            let path = components(path);
+            path.len() > parent.len() && path.starts_with(&parent)
+        }
+        None => components(pattern) == components(path),
+    }
+}
+
+fn components(path: &str) -> Vec<&str> {
+    if path.trim().is_empty() {
+        return Vec::new();
+    }
+    path.split('/').map(str::trim).collect()
+}
+
+/// Derives the child of `public_key` at `path`, returns the tweak and the child key
+pub fn call_hd_key(path: &str, public_key: GE) -> (FE, GE) {
+    let path_vector: Vec<BigInt> = path
+        .split('/')
+        .map(|s| BigInt::from_str_radix(s.trim(), 10).unwrap())
+        .collect();
+    let (public_key_child, f_l_new) = get_hd_key(&public_key, path_vector.clone());
+    (f_l_new, public_key_child.clone())
+}
+
 pub fn get_hd_key(y_sum: &GE, path_vector: Vec<BigInt>) -> (GE, FE) {
     // generate a random but shared chain code, this will do
     let chain_code = GE::generator().as_point();
@@ -0,0 +1,450 @@
+use crate::common::{Backoff, Transport};
+use crate::config::{MeshConfig, MeshPeer};
+use crate::error::TssError;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Mutex as AsyncMutex, Notify};
+use tokio::task::JoinHandle;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+use tokio_rustls::rustls::{
+    Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName,
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tracing::{info, warn};
+
+// Messages of a session nobody reads are dropped after this long
+const SESSION_TTL: Duration = Duration::from_secs(600);
+// Peers may still be starting, dialing is retried with backoff
+const CONNECT_RETRIES: u32 = 6;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(2);
+// Largest accepted frame, GG18 round messages stay well below
+const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+struct Frame {
+    session_id: String,
+    round: String,
+    p2p: bool,
+    data: String,
+}
+
+// Sender party id, whether the message is P2P, round
+type MessageKey = (u16, bool, String);
+
+struct SessionMessages {
+    started: Instant,
+    messages: HashMap<MessageKey, String>,
+}
+
+#[derive(Default)]
+struct Mailbox {
+    sessions: Mutex<HashMap<String, SessionMessages>>,
+    received: Notify,
+}
+
+impl Mailbox {
+    fn post(&self, from: u16, frame: Frame) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| session.started.elapsed() < SESSION_TTL);
+        sessions
+            .entry(frame.session_id)
+            .or_insert_with(|| SessionMessages {
+                started: Instant::now(),
+                messages: HashMap::new(),
+            })
+            .messages
+            .insert((from, frame.p2p, frame.round), frame.data);
+        drop(sessions);
+        self.received.notify_waiters();
+    }
+
+    // Waits until every key is received, returns the messages in the order of `keys`
+    async fn wait_for(&self, session_id: &str, keys: Vec<MessageKey>) -> Vec<String> {
+        loop {
+            // Registered before reading, a message received meanwhile still wakes us
+            let received = self.received.notified();
+            {
+                let sessions = self.sessions.lock().unwrap();
+                if let Some(session) = sessions.get(session_id) {
+                    let found: Option<Vec<String>> = keys
+                        .iter()
+                        .map(|key| session.messages.get(key).cloned())
+                        .collect();
+                    if let Some(found) = found {
+                        return found;
+                    }
+                }
+            }
+            received.await;
+        }
+    }
+
+    fn close(&self, session_id: &str) {
+        self.sessions.lock().unwrap().remove(session_id);
+    }
+}
+
+/// Endpoint of a signer in the mesh. Accepts mutually authenticated TLS
+/// connections from the peers of the roster and dials them to send round
+/// messages, one connection per direction and peer.
+pub struct MeshNode {
+    peers: Arc<HashMap<u16, MeshPeer>>,
+    connector: TlsConnector,
+    // Outgoing connections, dialed on first use
+    connections: HashMap<u16, AsyncMutex<Option<TlsStream<TcpStream>>>>,
+    mailbox: Arc<Mailbox>,
+    listener: JoinHandle<()>,
+}
+
+impl MeshNode {
+    /// Listens on `config.listen_addr` for the peers of `config.peers`
+    pub async fn bind(config: &MeshConfig) -> Result<Arc<Self>> {
+        let mut roots = RootCertStore::empty();
+        for ca_cert in load_certs(&config.ca_cert)? {
+            roots
+                .add(&ca_cert)
+                .map_err(|e| anyhow!("Invalid mesh CA certificate: {}", e))?;
+        }
+        let certs = load_certs(&config.cert)?;
+        let key = load_key(&config.key)?;
+
+        let server_config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots.clone()).boxed())
+            .with_single_cert(certs.clone(), key.clone())?;
+        let client_config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_client_auth_cert(certs, key)?;
+
+        let peers: Arc<HashMap<u16, MeshPeer>> = Arc::new(
+            config
+                .peers
+                .iter()
+                .map(|peer| (peer.party_id, peer.clone()))
+                .collect(),
+        );
+        let connections = peers
+            .keys()
+            .map(|party_id| (*party_id, AsyncMutex::new(None)))
+            .collect();
+
+        let listener = TcpListener::bind(&config.listen_addr).await?;
+        let local_addr = listener.local_addr()?;
+        let mailbox = Arc::new(Mailbox::default());
+        let listener = tokio::spawn(accept(
+            listener,
+            TlsAcceptor::from(Arc::new(server_config)),
+            peers.clone(),
+            mailbox.clone(),
+        ));
+        info!("Mesh listening on {}", local_addr);
+
+        Ok(Arc::new(Self {
+            peers,
+            connector: TlsConnector::from(Arc::new(client_config)),
+            connections,
+            mailbox,
+            listener,
+        }))
+    }
+
+    async fn send(&self, party_id: u16, frame: &Frame) -> Result<()> {
+        let connection = self
+            .connections
+            .get(&party_id)
+            .ok_or_else(|| anyhow!("Party {} is not in the mesh roster", party_id))?;
+        let payload = serde_json::to_vec(frame)?;
+        let mut connection = connection.lock().await;
+
+        // The peer may have restarted since the last message, redial once
+        let mut last_error = None;
+        for _ in 0..2 {
+            if connection.is_none() {
+                *connection = Some(self.connect(party_id).await?);
+            }
+            match write_frame(connection.as_mut().unwrap(), &payload).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    warn!("Mesh connection to party {} failed: {:?}", party_id, e);
+                    *connection = None;
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(anyhow!(
+            "Failed to send {} message to party {}: {:?}",
+            frame.round,
+            party_id,
+            last_error
+        ))
+    }
+
+    async fn connect(&self, party_id: u16) -> Result<TlsStream<TcpStream>> {
+        let peer = &self.peers[&party_id];
+        let server_name = ServerName::try_from(peer.server_name.as_str())
+            .map_err(|_| anyhow!("Invalid server name {}", peer.server_name))?;
+        let mut backoff = Backoff::new(CONNECT_RETRY_DELAY, MAX_CONNECT_RETRY_DELAY);
+        let mut attempts = 0;
+        loop {
+            let result = match TcpStream::connect(&peer.addr).await {
+                Ok(stream) => self.connector.connect(server_name.clone(), stream).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) if attempts < CONNECT_RETRIES => {
+                    attempts += 1;
+                    warn!("Dialing party {} at {} failed: {}", party_id, peer.addr, e);
+                    backoff.wait().await;
+                }
+                Err(e) => {
+                    return Err(anyhow!(
+                        "Could not connect to party {} at {}: {}",
+                        party_id,
+                        peer.addr,
+                        e
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for MeshNode {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+async fn accept(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    peers: Arc<HashMap<u16, MeshPeer>>,
+    mailbox: Arc<Mailbox>,
+) {
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("Error accepting mesh connection: {:?}", e);
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let peers = peers.clone();
+        let mailbox = mailbox.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(acceptor, stream, &peers, &mailbox).await {
+                warn!("Mesh connection from {} closed: {:?}", remote, e);
+            }
+        });
+    }
+}
+
+// Reads the frames of one peer, its party id is taken from its certificate
+async fn serve(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    peers: &HashMap<u16, MeshPeer>,
+    mailbox: &Mailbox,
+) -> Result<()> {
+    let mut stream = acceptor.accept(stream).await?;
+    let party_id = {
+        let (_, connection) = stream.get_ref();
+        let cert = connection
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .ok_or_else(|| anyhow!("Peer sent no certificate"))?;
+        identify(peers, cert)?
+    };
+
+    loop {
+        let len = match stream.read_u32().await {
+            Ok(len) => len as usize,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if len > MAX_FRAME_LEN {
+            return Err(TssError::MessageTooLarge.into());
+        }
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload).await?;
+        let frame: Frame = serde_json::from_slice(&payload)?;
+        mailbox.post(party_id, frame);
+    }
+}
+
+// The CA signs every signer, so the certificate name tells peers apart
+fn identify(peers: &HashMap<u16, MeshPeer>, cert: &Certificate) -> Result<u16> {
+    let cert = webpki::EndEntityCert::try_from(cert.0.as_slice())
+        .map_err(|e| anyhow!("Invalid peer certificate: {:?}", e))?;
+    peers
+        .values()
+        .find(|peer| {
+            webpki::SubjectNameRef::try_from_ascii_str(&peer.server_name)
+                .is_ok_and(|name| cert.verify_is_valid_for_subject_name(name).is_ok())
+        })
+        .map(|peer| peer.party_id)
+        .ok_or_else(|| anyhow!("Peer certificate matches no party of the mesh roster"))
+}
+
+async fn write_frame(stream: &mut TlsStream<TcpStream>, payload: &[u8]) -> io::Result<()> {
+    stream.write_u32(payload.len() as u32).await?;
+    stream.write_all(payload).await?;
+    stream.flush().await
+}
+
+fn load_certs(path: &str) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open certificate {}", path))?,
+    );
+    let certs: Vec<Certificate> = rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certs.is_empty() {
+        return Err(anyhow!("No certificate found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("Failed to open private key {}", path))?,
+    );
+    for item in rustls_pemfile::read_all(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key)
+            | rustls_pemfile::Item::RSAKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    Err(anyhow!("No private key found in {}", path))
+}
+
+/// Signing session over the mesh. Parties are addressed by their order in the
+/// room, `members` maps each order to the key share party id of the signer.
+pub struct MeshTransport {
+    node: Arc<MeshNode>,
+    party_num: u16,
+    members: Vec<u16>,
+    session_id: String,
+}
+
+impl MeshTransport {
+    pub fn new(
+        node: Arc<MeshNode>,
+        party_num: u16,
+        members: Vec<u16>,
+        session_id: &str,
+    ) -> Result<Self> {
+        if party_num == 0 || usize::from(party_num) > members.len() {
+            return Err(anyhow!(
+                "Party {} is not among the {} members of the session",
+                party_num,
+                members.len()
+            ));
+        }
+        if let Some(party_id) = members.iter().find(|id| !node.peers.contains_key(id)) {
+            return Err(anyhow!("Party {} is not in the mesh roster", party_id));
+        }
+        Ok(Self {
+            node,
+            party_num,
+            members,
+            session_id: session_id.to_string(),
+        })
+    }
+
+    fn party_id(&self, party_num: u16) -> Result<u16> {
+        usize::from(party_num)
+            .checked_sub(1)
+            .and_then(|index| self.members.get(index).copied())
+            .ok_or_else(|| anyhow!("No party {} in the session", party_num))
+    }
+
+    // Party ids of the other members, by party order
+    fn others(&self) -> impl Iterator<Item = u16> + '_ {
+        self.members
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| index + 1 != usize::from(self.party_num))
+            .map(|(_, party_id)| *party_id)
+    }
+
+    fn frame(&self, round: &str, p2p: bool, data: String) -> Frame {
+        Frame {
+            session_id: self.session_id.clone(),
+            round: round.to_string(),
+            p2p,
+            data,
+        }
+    }
+
+    fn keys(&self, round: &str, p2p: bool) -> Vec<MessageKey> {
+        self.others()
+            .map(|party_id| (party_id, p2p, round.to_string()))
+            .collect()
+    }
+}
+
+impl Drop for MeshTransport {
+    fn drop(&mut self) {
+        self.node.mailbox.close(&self.session_id);
+    }
+}
+
+#[async_trait]
+impl Transport for MeshTransport {
+    fn party_num(&self) -> u16 {
+        self.party_num
+    }
+
+    fn parties(&self) -> u16 {
+        self.members.len() as u16
+    }
+
+    fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    async fn broadcast(&self, round: &str, data: String) -> Result<()> {
+        let frame = self.frame(round, false, data);
+        try_join_all(
+            self.others()
+                .map(|party_id| self.node.send(party_id, &frame)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn send_p2p(&self, to: u16, round: &str, data: String) -> Result<()> {
+        let party_id = self.party_id(to)?;
+        self.node
+            .send(party_id, &self.frame(round, true, data))
+            .await
+    }
+
+    async fn receive_broadcasts(&self, round: &str) -> Result<Vec<String>> {
+        let keys = self.keys(round, false);
+        Ok(self.node.mailbox.wait_for(&self.session_id, keys).await)
+    }
+
+    async fn receive_p2p(&self, round: &str) -> Result<Vec<String>> {
+        let keys = self.keys(round, true);
+        Ok(self.node.mailbox.wait_for(&self.session_id, keys).await)
+    }
+}
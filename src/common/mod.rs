@@ -1,8 +1,17 @@
+pub mod approval;
+pub mod frost;
+pub mod hd_keys;
+pub mod mesh;
+pub mod scripts;
 pub mod secp256k1def;
+pub mod signature;
 pub mod signing_room;
+pub mod transport;
 pub mod types;
 pub mod utils;
 
+pub use mesh::{MeshNode, MeshTransport};
 pub use signing_room::*;
+pub use transport::{HttpTransport, InMemoryTransport, Transport};
 pub use types::*;
 pub use utils::*;
@@ -0,0 +1,10 @@
+use bitcoin::{PublicKey, ScriptBuf};
+
+/// Single key scripts locked to `public_key`
+pub fn owned_scripts(public_key: &PublicKey) -> Vec<ScriptBuf> {
+    let mut scripts = vec![ScriptBuf::new_p2pkh(&public_key.pubkey_hash())];
+    if let Ok(wpubkey_hash) = public_key.wpubkey_hash() {
+        scripts.push(ScriptBuf::new_p2wpkh(&wpubkey_hash));
+    }
+    scripts
+}
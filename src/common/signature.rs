@@ -0,0 +1,262 @@
+use curv::arithmetic::{BasicOps, Converter, Modulo, Zero};
+use curv::elliptic::curves::{Point, Scalar};
+use curv::BigInt;
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2018::party_i::{verify, SignatureRecid};
+
+use crate::common::frost::{self, Ciphersuite, Ed25519Sha512, Secp256k1Bip340};
+use crate::common::secp256k1def::{FE, GE};
+use crate::common::types::{SignatureData, SignatureScheme};
+use crate::error::TssError;
+
+pub const SIGNATURE_READY: &str = "signature_ready";
+
+impl SignatureData {
+    /// Builds the signature result handed back to clients. `s` is normalized to
+    /// the lower half of the curve order (flipping the recovery id accordingly)
+    /// and all encodings are derived from the normalized value.
+    pub fn new(r: &FE, s: &FE, recid: u8, public_key: &GE, message: &[u8]) -> Self {
+        let (s, recid) = normalize_s(s, recid);
+        let r_bytes = to_fixed_bytes(&r.to_bigint());
+        let s_bytes = to_fixed_bytes(&s.to_bigint());
+
+        let compact = [r_bytes.as_slice(), s_bytes.as_slice()].concat();
+        let mut recoverable = compact.clone();
+        recoverable.push(recid);
+
+        SignatureData {
+            r: hex::encode(r_bytes),
+            s: hex::encode(s_bytes),
+            status: SIGNATURE_READY.to_string(),
+            recid: i32::from(recid),
+            x: public_key.x_coord().unwrap().to_hex(),
+            y: public_key.y_coord().unwrap().to_hex(),
+            msg_int: message.to_vec(),
+            der: hex::encode(der_encode(&r_bytes, &s_bytes)),
+            compact: hex::encode(compact),
+            recoverable: hex::encode(recoverable),
+            public_key: hex::encode(&*public_key.to_bytes(true)),
+            scheme: SignatureScheme::Ecdsa,
+        }
+    }
+
+    /// Builds the result of a FROST signing session. `compact` holds the 64 byte
+    /// signature in the ciphersuite's wire format (BIP340 or RFC 8032) and
+    /// `public_key` the group key as the scheme encodes it.
+    pub fn new_frost<C: Ciphersuite>(
+        r: &Point<C::Curve>,
+        s: &Scalar<C::Curve>,
+        public_key: &Point<C::Curve>,
+        message: &[u8],
+    ) -> Self {
+        let r_bytes = C::encode_point(r);
+        let s_bytes = C::encode_scalar(s);
+
+        SignatureData {
+            r: hex::encode(&r_bytes),
+            s: hex::encode(&s_bytes),
+            status: SIGNATURE_READY.to_string(),
+            recid: 0,
+            x: public_key.x_coord().unwrap().to_hex(),
+            y: public_key.y_coord().unwrap().to_hex(),
+            msg_int: message.to_vec(),
+            der: String::new(),
+            compact: hex::encode([r_bytes, s_bytes].concat()),
+            recoverable: String::new(),
+            public_key: hex::encode(C::encode_point(public_key)),
+            scheme: C::SCHEME,
+        }
+    }
+}
+
+/// Checks that `signature` is a `scheme` signature of `message` under the hex
+/// encoded `public_key` (as in `SignatureData::public_key`), and that every
+/// encoding it carries is the one the signers derive from it
+pub fn verify_signature_data(
+    signature: &SignatureData,
+    scheme: SignatureScheme,
+    message: &[u8],
+    public_key: &str,
+) -> Result<(), TssError> {
+    let invalid = |reason: &str| TssError::InvalidSignature(reason.to_string());
+    let compact = hex::decode(&signature.compact)
+        .ok()
+        .filter(|compact| compact.len() == 64)
+        .ok_or_else(|| invalid("malformed compact encoding"))?;
+    let public_key = hex::decode(public_key).map_err(|_| invalid("malformed public key"))?;
+    let expected = match scheme {
+        SignatureScheme::Ecdsa => {
+            let (r, s, _) = decode_signature(&compact)?;
+            let public_key =
+                GE::from_bytes(&public_key).map_err(|_| invalid("malformed public key"))?;
+            let message_bn = message_to_bigint(message);
+            if !verify_signature(&r, &s, &public_key, &message_bn) {
+                return Err(invalid("verification failed"));
+            }
+            let recid = u8::try_from(signature.recid)
+                .ok()
+                .filter(|recid| {
+                    recover_public_key(&r, &s, *recid, &message_bn).as_ref() == Some(&public_key)
+                })
+                .ok_or_else(|| invalid("wrong recovery id"))?;
+            SignatureData::new(&r, &s, recid, &public_key, message)
+        }
+        SignatureScheme::Schnorr => {
+            frost_signature_data::<Secp256k1Bip340>(&compact, &public_key, message)?
+        }
+        SignatureScheme::Eddsa => {
+            frost_signature_data::<Ed25519Sha512>(&compact, &public_key, message)?
+        }
+    };
+    if *signature != expected {
+        return Err(invalid("encodings do not match the signature"));
+    }
+    Ok(())
+}
+
+fn frost_signature_data<C: Ciphersuite>(
+    compact: &[u8],
+    public_key: &[u8],
+    message: &[u8],
+) -> Result<SignatureData, TssError> {
+    let invalid = |reason: &str| TssError::InvalidSignature(reason.to_string());
+    let public_key = C::decode_point(public_key).ok_or_else(|| invalid("malformed public key"))?;
+    let r = C::decode_point(&compact[..32]).ok_or_else(|| invalid("malformed nonce"))?;
+    let s = C::decode_scalar(&compact[32..]).ok_or_else(|| invalid("malformed scalar"))?;
+    if !frost::verify::<C>(&public_key, message, &r, &s) {
+        return Err(invalid("verification failed"));
+    }
+    Ok(SignatureData::new_frost::<C>(&r, &s, &public_key, message))
+}
+
+/// Returns the low-s form of `s` together with the matching recovery id
+pub fn normalize_s(s: &FE, recid: u8) -> (FE, u8) {
+    let order = FE::group_order();
+    let s_bn = s.to_bigint();
+    let s_neg = order - &s_bn;
+    if s_bn > s_neg {
+        (FE::from_bigint(&s_neg), recid ^ 1)
+    } else {
+        (s.clone(), recid)
+    }
+}
+
+/// Big-endian encoding left padded to 32 bytes
+pub fn to_fixed_bytes(value: &BigInt) -> [u8; 32] {
+    let bytes = BigInt::to_bytes(value);
+    let mut out = [0u8; 32];
+    out[32 - bytes.len()..].copy_from_slice(&bytes);
+    out
+}
+
+/// ASN.1 DER `SEQUENCE { r INTEGER, s INTEGER }`
+pub fn der_encode(r: &[u8; 32], s: &[u8; 32]) -> Vec<u8> {
+    let r = der_integer(r);
+    let s = der_integer(s);
+    let mut out = vec![0x30, (r.len() + s.len()) as u8];
+    out.extend(r);
+    out.extend(s);
+    out
+}
+
+fn der_integer(bytes: &[u8]) -> Vec<u8> {
+    let mut value: Vec<u8> = bytes.iter().skip_while(|b| **b == 0).cloned().collect();
+    // Keep the integer positive and non-empty
+    if value.first().is_none_or(|b| b & 0x80 != 0) {
+        value.insert(0, 0);
+    }
+    let mut out = vec![0x02, value.len() as u8];
+    out.extend(value);
+    out
+}
+
+/// Integer the signers sign for `message`. Messages are expected to be hashed
+/// already, longer inputs are reduced modulo 2^256.
+pub fn message_to_bigint(message: &[u8]) -> BigInt {
+    BigInt::from_bytes(message).modulus(&BigInt::from(2).pow(256))
+}
+
+/// Decodes a DER, 64 byte compact or 65 byte recoverable signature into
+/// `(r, s, recid)`, the recovery id is only known for the recoverable form.
+pub fn decode_signature(bytes: &[u8]) -> Result<(FE, FE, Option<u8>), TssError> {
+    // DER signatures with short r and s are as long as the compact forms, the
+    // header tells them apart
+    if bytes.len() >= 2 && bytes[0] == 0x30 && usize::from(bytes[1]) == bytes.len() - 2 {
+        match der_decode(bytes) {
+            Ok((r, s)) => return Ok((r, s, None)),
+            Err(e) if !matches!(bytes.len(), 64 | 65) => return Err(e),
+            // A compact signature whose r starts like a DER header
+            Err(_) => {}
+        }
+    }
+    let (r, s, recid) = match bytes.len() {
+        64 => (&bytes[..32], &bytes[32..], None),
+        65 if bytes[64] < 4 => (&bytes[..32], &bytes[32..64], Some(bytes[64])),
+        _ => {
+            return Err(TssError::SigningError(
+                "Unsupported signature encoding".to_string(),
+            ))
+        }
+    };
+    Ok((to_scalar(r)?, to_scalar(s)?, recid))
+}
+
+/// Verifies the signature with the same check the signers run on their output
+pub fn verify_signature(r: &FE, s: &FE, public_key: &GE, message_bn: &BigInt) -> bool {
+    let signature = SignatureRecid {
+        r: r.clone(),
+        s: s.clone(),
+        recid: 0,
+    };
+    verify(&signature, public_key, message_bn).is_ok()
+}
+
+/// Recovers the public key from a signature, `Q = r^-1 (sR - eG)`
+pub fn recover_public_key(r: &FE, s: &FE, recid: u8, message_bn: &BigInt) -> Option<GE> {
+    let r_inv = r.invert()?;
+    let mut encoded = vec![0x02 | (recid & 1)];
+    encoded.extend_from_slice(&to_fixed_bytes(&r.to_bigint()));
+    let big_r = GE::from_bytes(&encoded).ok()?;
+    let e = FE::from_bigint(message_bn);
+    let public_key = (big_r * s - GE::generator() * e) * r_inv;
+    if public_key.is_zero() {
+        None
+    } else {
+        Some(public_key)
+    }
+}
+
+fn to_scalar(bytes: &[u8]) -> Result<FE, TssError> {
+    let value = BigInt::from_bytes(bytes);
+    if value.is_zero() || &value >= FE::group_order() {
+        return Err(TssError::SigningError(
+            "Signature component out of range".to_string(),
+        ));
+    }
+    Ok(FE::from_bigint(&value))
+}
+
+fn der_decode(bytes: &[u8]) -> Result<(FE, FE), TssError> {
+    let invalid = || TssError::SigningError("Invalid DER signature".to_string());
+    if bytes.len() < 8 || bytes[0] != 0x30 || usize::from(bytes[1]) != bytes.len() - 2 {
+        return Err(invalid());
+    }
+    let mut rest = &bytes[2..];
+    let mut values = Vec::with_capacity(2);
+    for _ in 0..2 {
+        if rest.len() < 2 || rest[0] != 0x02 {
+            return Err(invalid());
+        }
+        let len = usize::from(rest[1]);
+        if len == 0 || len > 33 || rest.len() < 2 + len {
+            return Err(invalid());
+        }
+        values.push(to_scalar(&rest[2..2 + len])?);
+        rest = &rest[2 + len..];
+    }
+    if !rest.is_empty() {
+        return Err(invalid());
+    }
+    let s = values.pop().unwrap();
+    let r = values.pop().unwrap();
+    Ok((r, s))
+}
@@ -1,20 +1,87 @@
 use crate::common::types::*;
+use crate::error::TssError;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::env;
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 pub const SIGNUP_TIMEOUT_ENV: &str = "TSS_MANAGER_SIGNUP_TIMEOUT";
 pub const SIGNUP_TIMEOUT_DEFAULT: &str = "2";
 
+// Room store key of a signing room is this prefix followed by the room id
+pub const SIGNING_ROOM_PREFIX: &str = "signup-sign-";
+
+// Room store key mapping a room uuid back to its room id, the round messages
+// of a room are keyed by its uuid only
+pub const ROOM_UUID_INDEX_PREFIX: &str = "signing-room-";
+
+/// Lifecycle of a signing room. Parties join during `Signup`, the room is
+/// `Locked` once enough parties are active and moves through the protocol
+/// rounds until a signature is reported or the room is abandoned.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomState {
+    Signup,
+    Locked,
+    InRound(u16),
+    Completed,
+    Aborted,
+}
+
+impl RoomState {
+    pub fn can_transition_to(self, next: RoomState) -> bool {
+        match (self, next) {
+            (RoomState::Signup, RoomState::Locked) => true,
+            (RoomState::Locked, RoomState::InRound(_)) => true,
+            (RoomState::InRound(current), RoomState::InRound(round)) => round > current,
+            (RoomState::Locked | RoomState::InRound(_), RoomState::Completed) => true,
+            (RoomState::Signup | RoomState::Locked | RoomState::InRound(_), RoomState::Aborted) => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_closed(self) -> bool {
+        matches!(self, RoomState::Completed | RoomState::Aborted)
+    }
+
+    // State name without the round number, used as a metrics label
+    pub fn name(self) -> &'static str {
+        match self {
+            RoomState::Signup => "signup",
+            RoomState::Locked => "locked",
+            RoomState::InRound(_) => "in_round",
+            RoomState::Completed => "completed",
+            RoomState::Aborted => "aborted",
+        }
+    }
+}
+
+impl fmt::Display for RoomState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoomState::InRound(round) => write!(f, "in_round_{}", round),
+            state => f.write_str(state.name()),
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct SigningRoom {
     pub room_id: String,
     pub room_uuid: String,
     pub room_size: u16,
     pub member_info: HashMap<u16, SigningPartyInfo>,
-    pub last_stage: String,
+    pub state: RoomState,
+    // Party orders that posted a message, by round name
+    #[serde(default)]
+    pub rounds: BTreeMap<String, BTreeSet<u16>>,
+    // Unix time at which the room was completed or aborted
+    #[serde(default)]
+    pub closed_at: Option<u64>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -30,8 +97,10 @@ impl SigningRoom {
             room_size: size,
             member_info: Default::default(),
             room_id,
-            last_stage: "signup".to_string(),
+            state: RoomState::Signup,
             room_uuid: Uuid::new_v4().to_string(),
+            rounds: BTreeMap::new(),
+            closed_at: None,
         }
     }
 
@@ -41,6 +110,7 @@ impl SigningRoom {
             room_uuid: "".to_string(),
             party_uuid: Uuid::new_v4().to_string(),
             total_joined: 0,
+            members: Vec::new(),
         }
     }
 
@@ -48,6 +118,7 @@ impl SigningRoom {
         self.member_info.len() >= usize::from(self.room_size)
     }
 
+    #[allow(clippy::from_str_radix_10)]
     fn is_timeout(party: &SigningPartyInfo) -> bool {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -128,7 +199,7 @@ impl SigningRoom {
     fn close_signup_window(&mut self) {
         let active_members = self.active_members();
         if self.is_full() && active_members.len() >= usize::from(self.room_size) {
-            self.last_stage = "terminated".to_string();
+            self.state = RoomState::Locked;
             let mut new_order = 1;
             for (key, value) in self.member_info.iter_mut() {
                 if active_members.contains_key(key) {
@@ -139,6 +210,58 @@ impl SigningRoom {
         }
     }
 
+    pub fn is_closed(&self) -> bool {
+        self.state.is_closed()
+    }
+
+    /// Moves the room to `next`, closing it when the room is completed or aborted
+    /// so its messages become eligible for garbage collection
+    pub fn transition(&mut self, next: RoomState) -> Result<(), TssError> {
+        if !self.state.can_transition_to(next) {
+            return Err(TssError::InvalidRoomTransition(self.state, next));
+        }
+        self.state = next;
+        if next.is_closed() {
+            self.closed_at = Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Records that the party with `party_order` posted its `round` message and
+    /// advances the room to that round. Returns false if nothing changed.
+    pub fn record_round(&mut self, round: &str, party_order: u16) -> bool {
+        if self.is_closed()
+            || !self
+                .rounds
+                .entry(round.to_string())
+                .or_default()
+                .insert(party_order)
+        {
+            return false;
+        }
+        if let Some(number) = round.strip_prefix("round").and_then(|n| n.parse().ok()) {
+            let next = RoomState::InRound(number);
+            if self.state.can_transition_to(next) {
+                self.state = next;
+            }
+        }
+        true
+    }
+
+    /// Rounds every active party has posted
+    pub fn completed_rounds(&self) -> Vec<String> {
+        self.rounds
+            .iter()
+            .filter(|(_, parties)| parties.len() >= usize::from(self.room_size))
+            .map(|(round, _)| round.clone())
+            .collect()
+    }
+
     pub fn has_member(&self, party_number: u16, party_uuid: String) -> bool {
         self.member_info.contains_key(&party_number)
             && self.member_info.get(&party_number).unwrap().party_id == party_uuid
@@ -152,18 +275,47 @@ impl SigningRoom {
             .collect()
     }
 
+    /// Key share party ids of the members, ordered by party order
+    pub fn members(&self) -> Vec<u16> {
+        let mut members: Vec<(u16, u16)> = self
+            .member_info
+            .iter()
+            .map(|(party_number, info)| (info.party_order, *party_number))
+            .collect();
+        members.sort_unstable();
+        members
+            .into_iter()
+            .map(|(_, party_number)| party_number)
+            .collect()
+    }
+
     pub fn get_signup_info(&self, party_number: u16) -> SigningPartySignup {
         let member_info = self.member_info.get(&party_number).unwrap();
-        let room_uuid = if self.last_stage == "signup" {
-            "".to_string()
+        let (room_uuid, members) = if self.state == RoomState::Signup {
+            ("".to_string(), Vec::new())
         } else {
-            self.room_uuid.clone()
+            (self.room_uuid.clone(), self.members())
         };
         SigningPartySignup {
             party_order: member_info.party_order,
             party_uuid: member_info.party_id.clone(),
             room_uuid,
             total_joined: u16::try_from(self.active_members().len()).unwrap(),
+            members,
         }
     }
 }
+
+/// Splits a round message key, `{party}-{round}-{uuid}` for broadcasts or
+/// `{from}-{to}-{round}-{uuid}` for P2P messages, into sender, round and uuid
+pub fn parse_round_key(key: &str) -> Option<(u16, &str, &str)> {
+    let uuid_start = key.len().checked_sub(36)?;
+    let (head, uuid) = (key.get(..uuid_start)?, &key[uuid_start..]);
+    Uuid::parse_str(uuid).ok()?;
+    let parts: Vec<&str> = head.strip_suffix('-')?.split('-').collect();
+    let (sender, round) = match parts.as_slice() {
+        [sender, round] | [sender, _, round] => (sender.parse().ok()?, *round),
+        _ => return None,
+    };
+    Some((sender, round, uuid))
+}
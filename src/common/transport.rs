@@ -0,0 +1,230 @@
+use crate::common::{broadcast, poll_for_broadcasts, poll_for_p2p, sendp2p, ManagerClient};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// Message exchange between the parties of one protocol session. Parties are
+/// numbered from 1, received messages are ordered by sender and skip this party.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Number of this party in the session
+    fn party_num(&self) -> u16;
+
+    /// Parties taking part in the session
+    fn parties(&self) -> u16;
+
+    /// Unique id of the session, protocols bind their proofs to it
+    fn session_id(&self) -> &str;
+
+    async fn broadcast(&self, round: &str, data: String) -> Result<()>;
+
+    async fn send_p2p(&self, to: u16, round: &str, data: String) -> Result<()>;
+
+    /// Waits for the message of `round` broadcast by every other party
+    async fn receive_broadcasts(&self, round: &str) -> Result<Vec<String>>;
+
+    /// Waits for the message of `round` sent to this party by every other party
+    async fn receive_p2p(&self, round: &str) -> Result<Vec<String>>;
+}
+
+/// Relays messages through the key-value store of the manager
+pub struct HttpTransport {
+    addr: String,
+    client: ManagerClient,
+    party_num: u16,
+    parties: u16,
+    session_id: String,
+    poll_delay: Duration,
+    // Receiving fails once it passes
+    deadline: Instant,
+}
+
+impl HttpTransport {
+    pub fn new(
+        addr: &str,
+        client: ManagerClient,
+        party_num: u16,
+        parties: u16,
+        session_id: &str,
+        poll_delay: Duration,
+        deadline: Instant,
+    ) -> Self {
+        Self {
+            addr: addr.to_string(),
+            client,
+            party_num,
+            parties,
+            session_id: session_id.to_string(),
+            poll_delay,
+            deadline,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    fn party_num(&self) -> u16 {
+        self.party_num
+    }
+
+    fn parties(&self) -> u16 {
+        self.parties
+    }
+
+    fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    async fn broadcast(&self, round: &str, data: String) -> Result<()> {
+        broadcast(
+            &self.addr,
+            &self.client,
+            self.party_num,
+            round,
+            data,
+            self.session_id.clone(),
+        )
+        .await
+    }
+
+    async fn send_p2p(&self, to: u16, round: &str, data: String) -> Result<()> {
+        sendp2p(
+            &self.addr,
+            &self.client,
+            self.party_num,
+            to,
+            round,
+            data,
+            self.session_id.clone(),
+        )
+        .await
+        .with_context(|| format!("Failed to send {} message to party {}", round, to))
+    }
+
+    async fn receive_broadcasts(&self, round: &str) -> Result<Vec<String>> {
+        poll_for_broadcasts(
+            &self.addr,
+            &self.client,
+            self.party_num,
+            self.parties,
+            self.poll_delay,
+            round,
+            self.session_id.clone(),
+            self.deadline,
+        )
+        .await
+    }
+
+    async fn receive_p2p(&self, round: &str) -> Result<Vec<String>> {
+        poll_for_p2p(
+            &self.addr,
+            &self.client,
+            self.party_num,
+            self.parties,
+            self.poll_delay,
+            round,
+            self.session_id.clone(),
+            self.deadline,
+        )
+        .await
+    }
+}
+
+#[derive(Default)]
+struct InMemoryRelay {
+    messages: Mutex<HashMap<String, String>>,
+    posted: Notify,
+}
+
+/// Parties of a session exchanging messages in memory, for tests and for
+/// running every party in one process
+pub struct InMemoryTransport {
+    relay: Arc<InMemoryRelay>,
+    party_num: u16,
+    parties: u16,
+    session_id: String,
+}
+
+impl InMemoryTransport {
+    /// Transports of all the parties of a new session
+    pub fn session(parties: u16) -> Vec<Self> {
+        let relay = Arc::new(InMemoryRelay::default());
+        let session_id = uuid::Uuid::new_v4().to_string();
+        (1..=parties)
+            .map(|party_num| Self {
+                relay: relay.clone(),
+                party_num,
+                parties,
+                session_id: session_id.clone(),
+            })
+            .collect()
+    }
+
+    fn post(&self, key: String, data: String) {
+        self.relay.messages.lock().unwrap().insert(key, data);
+        self.relay.posted.notify_waiters();
+    }
+
+    // Waits until every key is posted, returns the messages in the order of `keys`
+    async fn wait_for(&self, keys: Vec<String>) -> Vec<String> {
+        loop {
+            // Registered before reading, a message posted meanwhile still wakes us
+            let posted = self.relay.posted.notified();
+            {
+                let messages = self.relay.messages.lock().unwrap();
+                let found: Option<Vec<String>> =
+                    keys.iter().map(|key| messages.get(key).cloned()).collect();
+                if let Some(found) = found {
+                    return found;
+                }
+            }
+            posted.await;
+        }
+    }
+
+    fn others(&self) -> impl Iterator<Item = u16> + '_ {
+        (1..=self.parties).filter(move |i| *i != self.party_num)
+    }
+}
+
+#[async_trait]
+impl Transport for InMemoryTransport {
+    fn party_num(&self) -> u16 {
+        self.party_num
+    }
+
+    fn parties(&self) -> u16 {
+        self.parties
+    }
+
+    fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    async fn broadcast(&self, round: &str, data: String) -> Result<()> {
+        self.post(format!("{}-{}", self.party_num, round), data);
+        Ok(())
+    }
+
+    async fn send_p2p(&self, to: u16, round: &str, data: String) -> Result<()> {
+        self.post(format!("{}-{}-{}", self.party_num, to, round), data);
+        Ok(())
+    }
+
+    async fn receive_broadcasts(&self, round: &str) -> Result<Vec<String>> {
+        let keys = self.others().map(|i| format!("{}-{}", i, round)).collect();
+        Ok(self.wait_for(keys).await)
+    }
+
+    async fn receive_p2p(&self, round: &str) -> Result<Vec<String>> {
+        let keys = self
+            .others()
+            .map(|i| format!("{}-{}-{}", i, self.party_num, round))
+            .collect();
+        Ok(self.wait_for(keys).await)
+    }
+}
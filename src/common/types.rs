@@ -12,40 +12,269 @@ pub struct MessageToSignStored {
     pub message: Vec<u8>,
     pub status: MessageStatus,
     pub signature: Option<SignatureData>,
+    #[serde(default)]
+    pub scheme: SignatureScheme,
+    // Network key the message is signed with
+    #[serde(default)]
+    pub key_id: String,
+    #[serde(default)]
+    pub derivation_path: Option<String>,
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub transaction: Option<TransactionInput>,
+    // Unix times in milliseconds
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    #[serde(default)]
+    pub completed_at: Option<u64>,
+    // Room of the latest signing attempt and the signers that joined it
+    #[serde(default)]
+    pub room_uuid: Option<String>,
+    #[serde(default)]
+    pub participants: Vec<u16>,
+    // Signers that reported the stored signature
+    #[serde(default)]
+    pub reported_by: Vec<u16>,
+    // Number of signing rooms the request was attempted in
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub status_history: Vec<StatusChange>,
+    // Set on requests that needed approval before signing
+    #[serde(default)]
+    pub approval: Option<Approval>,
+    // Signers that refused the request under their local policy
+    #[serde(default)]
+    pub refusals: Vec<SigningFailure>,
+}
+
+// Approvals a request waits for before it is published to the signers
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Approval {
+    pub required: u32,
+    // What is being signed, for the approvers
+    pub summary: String,
+    // Hex encoded digest approvers sign for signers to verify their approval
+    #[serde(default)]
+    pub digest: String,
+    #[serde(default)]
+    pub decisions: Vec<ApprovalDecision>,
+    // Requests released along with this one, the other inputs of a PSBT
+    #[serde(default)]
+    pub batch: Vec<SigningRequest>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ApprovalDecision {
+    // API client of the approver
+    pub approver: String,
+    pub approved: bool,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub signature: Option<ApprovalSignature>,
+    // Unix time in milliseconds
+    pub at: u64,
+}
+
+// Signature of an approver over the approval digest of a request
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ApprovalSignature {
+    // Hex encoded SEC1 secp256k1 key of the approver
+    pub public_key: String,
+    // Hex encoded DER ECDSA signature
+    pub signature: String,
+}
+
+// Transaction whose input a request signs the sighash of, lets signers check
+// what they sign
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct TransactionInput {
+    // Base64 encoded PSBT
+    pub psbt: String,
+    pub input: usize,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: MessageStatus,
+    // Unix time in milliseconds
+    pub at: u64,
+    // Signer that reported the change, none for changes made by the manager
+    pub party_id: Option<u16>,
+    // Why the manager made the change, set on rejections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum SignatureScheme {
+    // GG18 threshold ECDSA
+    #[default]
+    Ecdsa,
+    // FROST threshold Schnorr, BIP340 x-only keys
+    Schnorr,
+    // FROST threshold Ed25519
+    Eddsa,
+}
+
+impl SignatureScheme {
+    pub fn curve(&self) -> CurveKind {
+        match self {
+            SignatureScheme::Ecdsa | SignatureScheme::Schnorr => CurveKind::Secp256k1,
+            SignatureScheme::Eddsa => CurveKind::Ed25519,
+        }
+    }
+
+    // The network holds one key per scheme
+    pub fn key_id(&self) -> &'static str {
+        match self {
+            SignatureScheme::Ecdsa => "ecdsa",
+            SignatureScheme::Schnorr => "schnorr",
+            SignatureScheme::Eddsa => "eddsa",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum CurveKind {
+    #[default]
+    Secp256k1,
+    Ed25519,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum MessageStatus {
+    // Held back until enough approvers approve it
+    AwaitingApproval,
     Pending,
     InProgress,
     Completed,
+    Rejected,
 }
 
 impl From<MessageStatus> for Bson {
     fn from(status: MessageStatus) -> Self {
         match status {
+            MessageStatus::AwaitingApproval => Bson::String("AwaitingApproval".to_string()),
             MessageStatus::Pending => Bson::String("Pending".to_string()),
             MessageStatus::InProgress => Bson::String("InProgress".to_string()),
             MessageStatus::Completed => Bson::String("Completed".to_string()),
+            MessageStatus::Rejected => Bson::String("Rejected".to_string()),
         }
     }
 }
 
+impl FromStr for MessageStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AwaitingApproval" => Ok(MessageStatus::AwaitingApproval),
+            "Pending" => Ok(MessageStatus::Pending),
+            "InProgress" => Ok(MessageStatus::InProgress),
+            "Completed" => Ok(MessageStatus::Completed),
+            "Rejected" => Ok(MessageStatus::Rejected),
+            _ => Err(format!("Invalid MessageStatus: {}", s)),
+        }
+    }
+}
+
+// Criteria of the signing request listing, all optional
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct SigningRequestFilter {
+    pub status: Option<MessageStatus>,
+    // Bounds on `created_at`, unix milliseconds, `until` is exclusive
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub key_id: Option<String>,
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct SignatureData {
-    r: String,
-    s: String,
-    status: String,
-    recid: i32,
-    x: String,
-    y: String,
-    msg_int: Vec<u8>,
+    pub r: String,
+    pub s: String,
+    pub status: String,
+    pub recid: i32,
+    pub x: String,
+    pub y: String,
+    pub msg_int: Vec<u8>,
+    // Encodings of the low-s normalized signature, all hex
+    #[serde(default)]
+    pub der: String,
+    #[serde(default)]
+    pub compact: String,
+    #[serde(default)]
+    pub recoverable: String,
+    // SEC1 compressed public key the signature verifies against, x-only for Schnorr
+    #[serde(default)]
+    pub public_key: String,
+    #[serde(default)]
+    pub scheme: SignatureScheme,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct SignerResult {
     pub request_id: String,
     pub signature: SignatureData,
+    // Signer reporting the signature
+    #[serde(default)]
+    pub party_id: u16,
+}
+
+// Sent by a signer once its signing room is locked
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SigningProgress {
+    pub request_id: String,
+    pub room_uuid: String,
+    pub party_id: u16,
+}
+
+// Sent by a signer that refused a request under its local policy
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SigningRefusal {
+    pub request_id: String,
+    pub party_id: u16,
+    pub reason: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SigningFailure {
+    pub party_id: u16,
+    pub error: String,
+    // Unix time in milliseconds
+    pub failed_at: u64,
+}
+
+// Published by a signer that gave up on a request
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub request: SigningRequest,
+    pub failure: SigningFailure,
+}
+
+// A request an API client's signing policy refused
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PolicyDenial {
+    pub client_id: String,
+    pub endpoint: String,
+    pub reason: String,
+    // Unix time in milliseconds
+    pub denied_at: u64,
+}
+
+// A dead-lettered request kept by the manager until it is replayed, with the
+// failures reported by every signer
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct DeadLetterStored {
+    pub request_id: String,
+    pub request: SigningRequest,
+    pub failures: Vec<SigningFailure>,
+    pub first_failed_at: u64,
+    pub last_failed_at: u64,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -83,6 +312,8 @@ pub struct Params {
 pub struct KeyGenParams {
     pub parties: u16,
     pub threshold: u16,
+    #[serde(default)]
+    pub scheme: SignatureScheme,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -91,6 +322,9 @@ pub struct KeysToStore {
     pub status: MessageStatus,
     pub key_gen_params: KeyGenParams,
     pub keys: Option<Vec<String>>,
+    // Curve the generated key shares belong to
+    #[serde(default)]
+    pub curve: CurveKind,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -113,6 +347,9 @@ pub struct SigningPartySignup {
     pub party_uuid: String,
     pub room_uuid: String,
     pub total_joined: u16,
+    // Key share party ids by party order, set with the room uuid once the room is locked
+    #[serde(default)]
+    pub members: Vec<u16>,
 }
 
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
@@ -120,10 +357,26 @@ pub struct ManagerError {
     pub error: String,
 }
 
+// Error of `/get` for a key not posted yet, the only one a poll retries
+pub const KEY_NOT_FOUND: &str = "Key not found";
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct SigningRequest {
     pub id: String,
     pub message: Vec<u8>,
+    #[serde(default)]
+    pub scheme: SignatureScheme,
+    // Child key ECDSA requests are signed with, the signers' configured path if unset
+    #[serde(default)]
+    pub derivation_path: Option<String>,
+    // API client that submitted the request
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub transaction: Option<TransactionInput>,
+    // Approver signatures, set once the request was approved
+    #[serde(default)]
+    pub approvals: Vec<ApprovalSignature>,
     // pub threshold: usize,
     // pub total_parties: usize,
 }
@@ -1,3 +1,4 @@
+use crate::auth::SignerCredentials;
 use crate::{common::types::*, manager::constants::NONCE_SIZE};
 use aes_gcm::{
     aead::{Aead, NewAead, Payload},
@@ -6,10 +7,12 @@ use aes_gcm::{
 use anyhow::{anyhow, Result};
 use rand::{rngs::OsRng, RngCore};
 use reqwest::Client;
-use serde_json::Value;
 use sha2::{Digest, Sha256};
-use std::{thread, time};
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, warn};
 
+#[allow(clippy::needless_borrow)]
 pub fn aes_encrypt(key: &[u8], plaintext: &[u8]) -> AEAD {
     let mut key_sized = [0u8; 32];
     key_sized[(32 - key.len())..].copy_from_slice(key);
@@ -55,125 +58,209 @@ pub fn aes_decrypt(key: &[u8], aead_pack: AEAD) -> Vec<u8> {
     out.unwrap_or_default()
 }
 
-pub async fn postb<T>(addr: &str, client: &Client, path: &str, body: T) -> Option<String>
+// Polls back off up to this multiple of their initial delay
+const MAX_BACKOFF_FACTOR: u32 = 8;
+
+/// Exponentially growing wait between attempts, without blocking the runtime
+pub struct Backoff {
+    initial: Duration,
+    current: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            current: initial,
+            max,
+        }
+    }
+
+    pub async fn wait(&mut self) {
+        tokio::time::sleep(self.current).await;
+        self.current = (self.current * 2).min(self.max);
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.initial;
+    }
+}
+
+/// HTTP client of the manager endpoints, a signer signs every request with
+/// its credential
+#[derive(Clone, Default)]
+pub struct ManagerClient {
+    http: Client,
+    credentials: Option<SignerCredentials>,
+}
+
+impl ManagerClient {
+    pub fn new(credentials: Option<SignerCredentials>) -> Self {
+        Self {
+            http: Client::new(),
+            credentials,
+        }
+    }
+
+    fn post(&self, endpoint: &str) -> reqwest::RequestBuilder {
+        let request = self.http.post(endpoint);
+        let Some(credentials) = &self.credentials else {
+            return request;
+        };
+        match credentials.token() {
+            Ok(token) => request.bearer_auth(token),
+            Err(e) => {
+                warn!("Failed to sign request to the manager: {}", e);
+                request
+            }
+        }
+    }
+}
+
+pub async fn postb<T>(addr: &str, client: &ManagerClient, path: &str, body: T) -> Option<String>
 where
     T: serde::ser::Serialize,
 {
     let retries = 3;
-    let retry_delay = time::Duration::from_millis(250);
+    let retry_delay = Duration::from_millis(250);
+    let mut backoff = Backoff::new(retry_delay, retry_delay * MAX_BACKOFF_FACTOR);
     let endpoint = format!("{}/{}", addr, path);
     for _ in 0..retries {
-        match client.post(&endpoint).json(&body).send().await {
-            Ok(response) => return Some(response.text().await.unwrap()),
-            Err(_) => thread::sleep(retry_delay),
+        let response = match client.post(&endpoint).json(&body).send().await {
+            Ok(response) => response,
+            Err(_) => {
+                backoff.wait().await;
+                continue;
+            }
+        };
+        match response.text().await {
+            Ok(body) => return Some(body),
+            Err(_) => backoff.wait().await,
         }
     }
     None
 }
 
+// Posts `body` to `path` of the manager and parses its JSON answer
+async fn post_manager<T, R>(addr: &str, client: &ManagerClient, path: &str, body: T) -> Result<R>
+where
+    T: serde::ser::Serialize,
+    R: serde::de::DeserializeOwned,
+{
+    let res_body = postb(addr, client, path, body)
+        .await
+        .ok_or_else(|| anyhow!("Manager at {} is unreachable", addr))?;
+    debug!("Response to /{}: {}", path, res_body);
+    serde_json::from_str(&res_body)
+        .map_err(|e| anyhow!("Unexpected response to /{}: {}: {}", path, e, res_body))
+}
+
+async fn set_entry(addr: &str, client: &ManagerClient, entry: Entry) -> Result<()> {
+    let answer: Result<(), ManagerError> = post_manager(addr, client, "set", entry).await?;
+    answer.map_err(|e| anyhow!("Manager refused message: {}", e.error))
+}
+
+// Waits for the entries at `keys` in order, failing on any error but a key not
+// posted yet, and once `deadline` passes
+async fn poll_for_entries(
+    addr: &str,
+    client: &ManagerClient,
+    keys: Vec<String>,
+    delay: Duration,
+    deadline: Instant,
+) -> Result<Vec<String>> {
+    let poll = async {
+        let mut backoff = Backoff::new(delay, delay * MAX_BACKOFF_FACTOR);
+        let mut values = Vec::new();
+        for key in keys {
+            let index = Index { key };
+            backoff.reset();
+            loop {
+                backoff.wait().await;
+                let answer: Result<Entry, ManagerError> =
+                    post_manager(addr, client, "get", index.clone()).await?;
+                match answer {
+                    Ok(entry) => {
+                        values.push(entry.value);
+                        break;
+                    }
+                    Err(e) if e.error == KEY_NOT_FOUND => continue,
+                    Err(e) => return Err(anyhow!("Failed to read {}: {}", index.key, e.error)),
+                }
+            }
+        }
+        Ok(values)
+    };
+    tokio::time::timeout_at(deadline, poll)
+        .await
+        .map_err(|_| anyhow!("Timed out waiting for messages"))?
+}
+
 pub async fn broadcast(
     addr: &str,
-    client: &Client,
+    client: &ManagerClient,
     party_num: u16,
     round: &str,
     data: String,
     sender_uuid: String,
-) -> anyhow::Result<()> {
+) -> Result<()> {
     let key = format!("{}-{}-{}", party_num, round, sender_uuid);
-    let entry = Entry {
-        key: key.clone(),
-        value: data,
-    };
-
-    let res_body = postb(addr, client, "set", entry).await.unwrap();
-    let parsed: Value = serde_json::from_str(&res_body)
-        .map_err(|err| anyhow!("Failed to parse JSON response: {}", err))?;
-    match parsed {
-        Value::Object(map) if map.contains_key("Ok") => Ok(()),
-        _ => Err(anyhow!("Unexpected response structure: {}", res_body)),
-    }
+    set_entry(addr, client, Entry { key, value: data }).await
 }
 
 pub async fn sendp2p(
     addr: &str,
-    client: &Client,
+    client: &ManagerClient,
     party_from: u16,
     party_to: u16,
     round: &str,
     data: String,
     sender_uuid: String,
-) -> Result<(), ()> {
+) -> Result<()> {
     let key = format!("{}-{}-{}-{}", party_from, party_to, round, sender_uuid);
-    let entry = Entry {
-        key: key.clone(),
-        value: data,
-    };
-
-    let res_body = postb(addr, client, "set", entry).await.unwrap();
-    println!("res_body: {}", res_body);
-    serde_json::from_str(&res_body).unwrap()
+    set_entry(addr, client, Entry { key, value: data }).await
 }
 
+/// Waits for the message of `round` broadcast by every other party. Polls back
+/// off while a party is late and gives up at `deadline`, dropping the future
+/// cancels the wait.
+#[allow(clippy::too_many_arguments)]
 pub async fn poll_for_broadcasts(
     addr: &str,
-    client: &Client,
+    client: &ManagerClient,
     party_num: u16,
     n: u16,
-    delay: std::time::Duration,
+    delay: Duration,
     round: &str,
     sender_uuid: String,
-) -> Vec<String> {
-    let mut ans_vec = Vec::new();
-    for i in 1..=n {
-        if i != party_num {
-            let key = format!("{}-{}-{}", i, round, sender_uuid);
-            let index = Index { key };
-            loop {
-                thread::sleep(delay);
-                let res_body = postb(addr, client, "get", index.clone()).await.unwrap();
-                let answer: Result<Entry, ManagerError> = serde_json::from_str(&res_body).unwrap();
-                match answer {
-                    Ok(entry) => {
-                        ans_vec.push(entry.value);
-                        break;
-                    }
-                    Err(_) => continue,
-                }
-            }
-        }
-    }
-    ans_vec
+    deadline: Instant,
+) -> Result<Vec<String>> {
+    let keys = (1..=n)
+        .filter(|i| *i != party_num)
+        .map(|i| format!("{}-{}-{}", i, round, sender_uuid))
+        .collect();
+    poll_for_entries(addr, client, keys, delay, deadline).await
 }
 
+/// Waits for the message of `round` sent to `party_num` by every other party,
+/// with the same backoff, deadline and cancellation as `poll_for_broadcasts`
+#[allow(clippy::too_many_arguments)]
 pub async fn poll_for_p2p(
     addr: &str,
-    client: &Client,
+    client: &ManagerClient,
     party_num: u16,
     n: u16,
-    delay: std::time::Duration,
+    delay: Duration,
     round: &str,
     sender_uuid: String,
-) -> Vec<String> {
-    let mut ans_vec = Vec::new();
-    for i in 1..=n {
-        if i != party_num {
-            let key = format!("{}-{}-{}-{}", i, party_num, round, sender_uuid);
-            let index = Index { key };
-            loop {
-                thread::sleep(delay);
-                let res_body = postb(addr, client, "get", index.clone()).await.unwrap();
-                let answer: Result<Entry, ManagerError> = serde_json::from_str(&res_body).unwrap();
-                match answer {
-                    Ok(entry) => {
-                        ans_vec.push(entry.value);
-                        break;
-                    }
-                    Err(_) => continue,
-                }
-            }
-        }
-    }
-    ans_vec
+    deadline: Instant,
+) -> Result<Vec<String>> {
+    let keys = (1..=n)
+        .filter(|i| *i != party_num)
+        .map(|i| format!("{}-{}-{}-{}", i, party_num, round, sender_uuid))
+        .collect();
+    poll_for_entries(addr, client, keys, delay, deadline).await
 }
 
 pub fn sha256_digest(input: &[u8]) -> String {
@@ -181,3 +268,8 @@ pub fn sha256_digest(input: &[u8]) -> String {
     hasher.update(input);
     hex::encode(hasher.finalize())
 }
+
+// Each request signs in its own room, so requests for the same message never share rounds
+pub fn signing_room_id(scheme: SignatureScheme, request_id: &str) -> String {
+    format!("{}-{}", scheme.key_id(), request_id)
+}
@@ -1,17 +1,247 @@
 use std::net::IpAddr;
 
 use config::{Config, ConfigError, Environment, File};
+use jsonwebtoken::Algorithm;
 use serde::Deserialize;
 
+use crate::common::secp256k1def::GE;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SignerCredential {
+    pub party_id: u16,
+    // Secret the signer signs its requests to the manager with
+    pub secret: String,
+}
+
+// Asymmetric key API tokens are signed or verified with, PEM files
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtKeyConfig {
+    // Key id, set as the `kid` of the tokens the key signs
+    pub kid: String,
+    // ES256 or RS256
+    pub algorithm: Algorithm,
+    pub public_key: String,
+    // Keys without a private key only verify the tokens they signed before
+    #[serde(default)]
+    pub private_key: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SecurityConfig {
+    // HS256 secret, signs the API tokens when no `jwt_keys` are configured
+    #[serde(default)]
     pub jwt_secret: String,
+    // Default lifetime of API tokens, in seconds
     pub jwt_expiration: u64,
+    #[serde(default)]
+    pub jwt_keys: Vec<JwtKeyConfig>,
+    // Kid of the key new tokens are signed with, the first key with a private key by default
+    #[serde(default)]
+    pub jwt_signing_key: Option<String>,
+    // Keeps accepting HS256 tokens of `jwt_secret`, which carry no kid, once
+    // `jwt_keys` are configured, while clients move to tokens of the keys
+    #[serde(default)]
+    pub jwt_accept_legacy_hs256: bool,
     pub allowed_signer_ips: Vec<String>,
+    // Manager side, the credential of every signer allowed on the signer endpoints
+    #[serde(default)]
+    pub signers: Vec<SignerCredential>,
+    // Signer side, the secret of this signer's own credential
+    #[serde(default)]
+    pub signer_secret: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueBackend {
+    // Broker at `rabbitmq_uri`
+    #[default]
+    Rabbitmq,
+    Nats,
+    // Channel shared by the manager and signers running in the same process
+    InProcess,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct QueueConfig {
+    #[serde(default)]
+    pub backend: QueueBackend,
+    #[serde(default = "default_nats_url")]
+    pub nats_url: String,
+    // Requests a RabbitMQ consumer holds unacknowledged at once
+    #[serde(default = "default_prefetch")]
+    pub prefetch: u16,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            backend: QueueBackend::default(),
+            nats_url: default_nats_url(),
+            prefetch: default_prefetch(),
+        }
+    }
+}
+
+fn default_nats_url() -> String {
+    "nats://127.0.0.1:4222".to_string()
+}
+
+fn default_prefetch() -> u16 {
+    4
+}
+
+fn default_max_concurrent_sessions() -> usize {
+    4
+}
+
+fn default_session_timeout_seconds() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MeshPeer {
+    // Key share party id of the signer
+    pub party_id: u16,
+    // `host:port` the signer listens on
+    pub addr: String,
+    // DNS name in the certificate of the signer
+    pub server_name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MeshConfig {
+    // Signers exchange round messages directly instead of through the manager
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_mesh_listen_addr")]
+    pub listen_addr: String,
+    // PEM files, the CA signs the certificates of every signer
+    #[serde(default)]
+    pub ca_cert: String,
+    #[serde(default)]
+    pub cert: String,
+    #[serde(default)]
+    pub key: String,
+    // Every signer of the network, this signer included
+    #[serde(default)]
+    pub peers: Vec<MeshPeer>,
+}
+
+fn default_mesh_listen_addr() -> String {
+    "0.0.0.0:7100".to_string()
+}
+
+// Checks a signer runs on every request before joining its signing room, so
+// that it does not depend on the manager for what it signs. Empty lists and
+// no required approvals leave that part unrestricted.
+#[derive(Debug, Default, Deserialize)]
+pub struct SignerPolicyConfig {
+    // "message" for plain messages, sighash types such as "SIGHASH_ALL" for
+    // transaction inputs
+    #[serde(default)]
+    pub allowed_hash_modes: Vec<String>,
+    // Paths of the ECDSA child keys the signer signs with, a trailing `/*`
+    // allows every path below
+    #[serde(default)]
+    pub allowed_derivation_paths: Vec<String>,
+    // Addresses transactions may pay to, change to the signing key is always
+    // allowed. Plain messages are refused when set.
+    #[serde(default)]
+    pub allowed_destinations: Vec<String>,
+    // Hex encoded SEC1 secp256k1 keys of the approvers
+    #[serde(default)]
+    pub approver_keys: Vec<String>,
+    // Signatures from distinct approver keys a request needs
+    #[serde(default)]
+    pub approvals_required: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    #[default]
+    Mongodb,
+    // Embedded database in `path`, a single manager instance only
+    Sled,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StorageConfig {
+    #[serde(default)]
+    pub backend: StorageBackend,
+    #[serde(default = "default_storage_path")]
+    pub path: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            backend: StorageBackend::default(),
+            path: default_storage_path(),
+        }
+    }
+}
+
+fn default_storage_path() -> String {
+    "data/tss_network".to_string()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoomStoreBackend {
+    // Process local, a single manager instance only
+    #[default]
+    Memory,
+    // Shared between manager replicas through the `signing_rooms` collection
+    Mongodb,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoomStoreConfig {
+    #[serde(default)]
+    pub backend: RoomStoreBackend,
+    // Idle time after which a room and its round messages are dropped
+    #[serde(default = "default_room_ttl_seconds")]
+    pub ttl_seconds: u64,
+    // How often the sweeper garbage collects closed and expired rooms
+    #[serde(default = "default_sweep_interval_seconds")]
+    pub sweep_interval_seconds: u64,
+    // Delay before a finished room is reaped, lets slower signers read the last round
+    #[serde(default = "default_finished_grace_seconds")]
+    pub finished_grace_seconds: u64,
+}
+
+impl Default for RoomStoreConfig {
+    fn default() -> Self {
+        Self {
+            backend: RoomStoreBackend::default(),
+            ttl_seconds: default_room_ttl_seconds(),
+            sweep_interval_seconds: default_sweep_interval_seconds(),
+            finished_grace_seconds: default_finished_grace_seconds(),
+        }
+    }
+}
+
+fn default_room_ttl_seconds() -> u64 {
+    3600
+}
+
+fn default_sweep_interval_seconds() -> u64 {
+    60
+}
+
+fn default_finished_grace_seconds() -> u64 {
+    30
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Settings {
+    // Not needed when both the storage and the room store are embedded
+    #[serde(default)]
     pub mongodb_uri: String,
+    // Not needed with the NATS or in-process queue
+    #[serde(default)]
     pub rabbitmq_uri: String,
     pub manager_url: String,
     pub manager_port: u16,
@@ -20,8 +250,37 @@ pub struct Settings {
     pub total_parties: u16,
     pub path: String,
     pub signer_key_file: String,
+    // Signing sessions a signer runs at once, keep `queue.prefetch` at least as high
+    #[serde(default = "default_max_concurrent_sessions")]
+    pub max_concurrent_sessions: usize,
+    // A signing session still running after this long is cancelled and counts as failed
+    #[serde(default = "default_session_timeout_seconds")]
+    pub session_timeout_seconds: u64,
+    // Optional FROST key share, required to serve Schnorr signing requests
+    pub frost_key_file: Option<String>,
+    // Optional FROST Ed25519 key share, required to serve EdDSA signing requests
+    pub ed25519_key_file: Option<String>,
+    // Hex encoded SEC1 public key of the network, used to match PSBT inputs and
+    // to verify the ECDSA signatures signers report
+    pub public_key: Option<String>,
+    // Hex encoded FROST group keys, used to verify the Schnorr and EdDSA
+    // signatures signers report
+    #[serde(default)]
+    pub schnorr_public_key: Option<String>,
+    #[serde(default)]
+    pub eddsa_public_key: Option<String>,
     // New secuirty configuration section
     pub security: SecurityConfig,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    #[serde(default)]
+    pub mesh: MeshConfig,
+    #[serde(default)]
+    pub signer_policy: SignerPolicyConfig,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub room_store: RoomStoreConfig,
 }
 
 impl Settings {
@@ -39,6 +298,25 @@ impl Settings {
         builder.build()?.try_deserialize()
     }
 
+    // Parses the configured network public key
+    pub fn network_public_key(&self) -> Result<GE, ConfigError> {
+        let encoded = self
+            .public_key
+            .as_ref()
+            .ok_or_else(|| ConfigError::NotFound("public_key".to_string()))?;
+        let bytes = hex::decode(encoded).map_err(|e| ConfigError::Message(e.to_string()))?;
+        GE::from_bytes(&bytes).map_err(|e| ConfigError::Message(e.to_string()))
+    }
+
+    // Secret of the signer with `party_id`, if it has a credential
+    pub fn signer_secret(&self, party_id: u16) -> Option<&str> {
+        self.security
+            .signers
+            .iter()
+            .find(|signer| signer.party_id == party_id)
+            .map(|signer| signer.secret.as_str())
+    }
+
     // Helper method to validate IP against whitelist
     pub fn is_ip_whitelisted(&self, ip: IpAddr) -> bool {
         self.security
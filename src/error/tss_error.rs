@@ -1,3 +1,4 @@
+use crate::common::RoomState;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -17,6 +18,9 @@ pub enum TssError {
     #[error("Signing error: {0}")]
     SigningError(String),
 
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
     #[error("Configuration error: {0}")]
     ConfigError(#[from] config::ConfigError),
 
@@ -55,6 +59,30 @@ pub enum TssError {
 
     #[error("Not found error: {0}")]
     NotFoundError(String),
+
+    #[error("Denied by signing policy: {0}")]
+    PolicyDenied(String),
+
+    #[error("Approval error: {0}")]
+    ApprovalError(String),
+
+    #[error("PSBT error: {0}")]
+    PsbtError(String),
+
+    #[error("Database schema version {0} is newer than the supported version {1}")]
+    UnsupportedSchemaVersion(u32, u32),
+
+    #[error("Duplicate request: {0}")]
+    DuplicateRequest(String),
+
+    #[error("Invalid cursor: {0}")]
+    InvalidCursor(String),
+
+    #[error("Invalid room transition from {0} to {1}")]
+    InvalidRoomTransition(RoomState, RoomState),
+
+    #[error("Signing room {0} kept changing, update abandoned")]
+    RoomContention(String),
 }
 
 impl From<lapin::Error> for TssError {
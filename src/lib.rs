@@ -4,6 +4,6 @@ pub mod queue;
 pub mod signer;
 pub mod storage;
 
-mod auth;
+pub mod auth;
 pub mod config;
 pub mod error;
@@ -1,23 +1,40 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
 
-use crate::auth::{AuthenticatedUser, Role};
-use crate::common::types::SigningRequest;
-use crate::common::{KeyGenParams, KeyGenRequest, KeysToStore, MessageToSignStored};
+use crate::auth::{create_token, ApiClient, AuthenticatedUser, IssuedToken, JwtKeys, Role};
+use crate::common::hd_keys::{call_hd_key, is_valid_path};
+use crate::common::signature::{
+    decode_signature, message_to_bigint, recover_public_key, verify_signature,
+};
+use crate::common::types::{SignatureScheme, SigningRequest};
+use crate::common::{
+    Approval, ApprovalSignature, DeadLetterStored, KeyGenParams, KeyGenRequest, KeysToStore,
+    MessageStatus, MessageToSignStored, PolicyDenial, RoomState, SigningRequestFilter, SigningRoom,
+};
+use crate::config::Settings;
 use crate::error::TssError;
+use crate::manager::policy::SigningPolicy;
+use crate::manager::psbt::{self, PsbtSigning};
 use crate::manager::service::ManagerService;
-use crate::{auth::create_token, config::Settings};
 use anyhow::Context;
 use rocket::http::Status;
-use rocket::response::status::Created;
+use rocket::response::status::{Accepted, Created, Custom};
 use rocket::serde::json::Json;
-use rocket::{get, post, State};
+use rocket::{get, post, put, Responder, State};
 use serde::{Deserialize, Serialize};
 
-use super::constants::MAX_MESSAGE_SIZE;
+use super::constants::{
+    DEFAULT_LIST_LIMIT, MAX_LIST_LIMIT, MAX_MESSAGE_SIZE, MAX_TOKEN_EXPIRATION,
+};
 
 #[derive(Deserialize)]
 pub struct SigningRequestDTO {
     pub message: String,
+    // Key the message is signed with, ECDSA unless specified
+    #[serde(default)]
+    pub scheme: SignatureScheme,
+    // ECDSA only, the configured `path` unless specified
+    pub derivation_path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -28,9 +45,13 @@ pub struct SigningResponseDTO {
 
 #[derive(Deserialize)]
 pub struct KeyGenRequestDTO {
-    pub manager_url: String,
+    // Still accepted from older clients, key generation runs in the manager process
+    #[serde(default)]
+    pub manager_url: Option<String>,
     pub threshold: u16,
     pub total_parties: u16,
+    #[serde(default)]
+    pub scheme: SignatureScheme,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -39,6 +60,107 @@ pub struct KeyGenResponseDTO {
     pub keys: Vec<String>,
 }
 
+#[derive(Deserialize)]
+pub struct PsbtSigningRequestDTO {
+    pub psbt: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PsbtSigningResponseDTO {
+    pub psbt: String,
+    pub signed_inputs: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct ApprovalDecisionDTO {
+    #[serde(default)]
+    pub comment: Option<String>,
+    // Approver signature over the approval digest, for signers that require one
+    #[serde(default)]
+    pub signature: Option<ApprovalSignature>,
+}
+
+#[derive(Deserialize)]
+pub struct VerifyRequestDTO {
+    pub message: Option<String>,
+    // Hex encoded digest, alternative to `message`
+    pub digest: Option<String>,
+    // Hex encoded DER, compact or recoverable signature
+    pub signature: String,
+    pub derivation_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VerifyResponseDTO {
+    pub valid: bool,
+    pub public_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SigningRequestListDTO {
+    pub requests: Vec<MessageToSignStored>,
+    // Pass as `cursor` to fetch the next page, absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplayRequestDTO {
+    pub request_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplayResponseDTO {
+    // Requests published again, the others were not dead-lettered or are already signed
+    pub replayed: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RoomMemberDTO {
+    pub party_number: u16,
+    pub party_order: u16,
+    pub last_ping: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RoomStatusDTO {
+    pub room_id: String,
+    pub room_uuid: String,
+    pub state: RoomState,
+    pub room_size: u16,
+    pub members: Vec<RoomMemberDTO>,
+    // Party orders that posted each round
+    pub rounds: BTreeMap<String, BTreeSet<u16>>,
+    pub completed_rounds: Vec<String>,
+    pub closed_at: Option<u64>,
+}
+
+impl From<SigningRoom> for RoomStatusDTO {
+    fn from(room: SigningRoom) -> Self {
+        let completed_rounds = room.completed_rounds();
+        let mut members: Vec<RoomMemberDTO> = room
+            .member_info
+            .iter()
+            .map(|(party_number, info)| RoomMemberDTO {
+                party_number: *party_number,
+                party_order: info.party_order,
+                last_ping: info.last_ping,
+            })
+            .collect();
+        members.sort_by_key(|member| member.party_order);
+
+        Self {
+            room_id: room.room_id,
+            room_uuid: room.room_uuid,
+            state: room.state,
+            room_size: room.room_size,
+            members,
+            rounds: room.rounds,
+            completed_rounds,
+            closed_at: room.closed_at,
+        }
+    }
+}
+
 #[derive(Serialize)]
 pub struct TokenResponse {
     token: String,
@@ -46,41 +168,259 @@ pub struct TokenResponse {
     token_type: String,
 }
 
+#[derive(Deserialize)]
+pub struct ApiClientRequestDTO {
+    pub name: String,
+    pub role: Role,
+    // Lifetime of the client's tokens in seconds, `jwt_expiration` by default
+    pub token_expiration: Option<u64>,
+    #[serde(default)]
+    pub policy: SigningPolicy,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiTokenDTO {
+    pub client_id: String,
+    pub jti: String,
+    pub token: String,
+    // Unix time in seconds
+    pub expires_at: usize,
+    pub token_type: String,
+}
+
+impl ApiTokenDTO {
+    fn new(client_id: &str, issued: IssuedToken) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            jti: issued.jti,
+            token: issued.token,
+            expires_at: issued.expires_at,
+            token_type: "Bearer".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiClientCreatedDTO {
+    pub client: ApiClient,
+    pub token: ApiTokenDTO,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PolicyDeniedDTO {
+    pub error: String,
+    pub reason: String,
+}
+
+/// Error of the signing endpoints, denials by the client's signing policy
+/// carry their reason
+#[derive(Responder)]
+pub enum SigningApiError {
+    Denied(Custom<Json<PolicyDeniedDTO>>),
+    Status(Status),
+}
+
+/// A signed PSBT, or the request it waits on for approval
+#[derive(Responder)]
+pub enum PsbtSigningResult {
+    Signed(Json<PsbtSigningResponseDTO>),
+    AwaitingApproval(Accepted<Json<SigningResponseDTO>>),
+}
+
+impl From<Status> for SigningApiError {
+    fn from(status: Status) -> Self {
+        SigningApiError::Status(status)
+    }
+}
+
+impl SigningApiError {
+    fn denied(reason: &str) -> Self {
+        SigningApiError::Denied(Custom(
+            Status::Forbidden,
+            Json(PolicyDeniedDTO {
+                error: "policy_denied".to_string(),
+                reason: reason.to_string(),
+            }),
+        ))
+    }
+}
+
 #[post("/sign", format = "json", data = "<request>")]
 pub async fn sign(
     auth: AuthenticatedUser,
     manager: &State<Arc<ManagerService>>,
+    settings: &State<Arc<Settings>>,
     request: Json<SigningRequestDTO>,
-) -> Result<Created<Json<SigningResponseDTO>>, Status> {
+) -> Result<Created<Json<SigningResponseDTO>>, SigningApiError> {
     // Verify that we have a public role
     if auth.role != Role::Public {
-        return Err(Status::Forbidden);
+        return Err(Status::Forbidden.into());
     }
 
     // validate messgage size
     if request.message.len() > MAX_MESSAGE_SIZE {
-        return Err(Status::PayloadTooLarge);
+        return Err(Status::PayloadTooLarge.into());
     }
 
+    // FROST keys are not derived
+    if let Some(path) = &request.derivation_path {
+        if request.scheme != SignatureScheme::Ecdsa || !(path.is_empty() || is_valid_path(path)) {
+            return Err(Status::BadRequest.into());
+        }
+    }
+    let path = request.derivation_path.as_deref().unwrap_or(&settings.path);
+    let policy = match manager
+        .enforce_policy(&auth.user_id, "/sign", 1, |policy| {
+            policy.check_message(request.scheme, path)
+        })
+        .await
+    {
+        Ok(policy) => policy,
+        Err(e) => match e.downcast_ref::<TssError>() {
+            Some(TssError::PolicyDenied(reason)) => return Err(SigningApiError::denied(reason)),
+            _ => return Err(Status::InternalServerError.into()),
+        },
+    };
+
     let message: Vec<u8> = request.message.as_bytes().to_vec();
     let signing_request = SigningRequest {
         id: uuid::Uuid::new_v4().to_string(),
         message,
+        scheme: request.scheme,
+        derivation_path: request.derivation_path.clone(),
+        client_id: Some(auth.user_id.clone()),
+        transaction: None,
+        approvals: Vec::new(),
     };
 
-    match manager
-        .process_signing_request(signing_request.clone())
-        .await
-    {
+    // Held back until enough approvers approve it
+    let required = policy.approvals_for_message();
+    let (result, status) = if required > 0 {
+        let approval = Approval {
+            required,
+            summary: format!(
+                "Message of {} bytes signed with the {} key at path {:?} for client {}",
+                signing_request.message.len(),
+                request.scheme.key_id(),
+                path,
+                auth.user_id
+            ),
+            digest: String::new(),
+            decisions: Vec::new(),
+            batch: Vec::new(),
+        };
+        (
+            manager
+                .request_approval(signing_request.clone(), approval)
+                .await,
+            "AwaitingApproval",
+        )
+    } else {
+        (
+            manager
+                .process_signing_request(signing_request.clone())
+                .await,
+            "Pending",
+        )
+    };
+
+    match result {
         Ok(_) => {
             let response = SigningResponseDTO {
                 request_id: signing_request.id,
-                status: "Pending".to_string(),
+                status: status.to_string(),
             };
             Ok(Created::new("/").body(Json(response)))
         }
-        Err(_) => Err(Status::InternalServerError),
+        Err(_) => Err(Status::InternalServerError.into()),
+    }
+}
+
+#[post("/sign_psbt", format = "json", data = "<request>")]
+pub async fn sign_psbt(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    settings: &State<Arc<Settings>>,
+    request: Json<PsbtSigningRequestDTO>,
+) -> Result<PsbtSigningResult, SigningApiError> {
+    // Verify that we have a public role
+    if auth.role != Role::Public {
+        return Err(Status::Forbidden.into());
+    }
+
+    if request.psbt.len() > MAX_MESSAGE_SIZE {
+        return Err(Status::PayloadTooLarge.into());
+    }
+
+    match psbt::sign_psbt(manager, settings, &auth.user_id, &request.psbt).await {
+        Ok(PsbtSigning::Signed {
+            psbt,
+            signed_inputs,
+        }) => Ok(PsbtSigningResult::Signed(Json(PsbtSigningResponseDTO {
+            psbt,
+            signed_inputs,
+        }))),
+        Ok(PsbtSigning::AwaitingApproval { request_id }) => Ok(
+            PsbtSigningResult::AwaitingApproval(Accepted(Json(SigningResponseDTO {
+                request_id,
+                status: "AwaitingApproval".to_string(),
+            }))),
+        ),
+        Err(e) => match e.downcast_ref::<TssError>() {
+            Some(TssError::PolicyDenied(reason)) => Err(SigningApiError::denied(reason)),
+            Some(TssError::PsbtError(_)) => Err(Status::BadRequest.into()),
+            Some(TssError::TimeoutError) => Err(Status::GatewayTimeout.into()),
+            _ => Err(Status::InternalServerError.into()),
+        },
+    }
+}
+
+#[post("/verify", format = "json", data = "<request>")]
+pub async fn verify(
+    auth: AuthenticatedUser,
+    settings: &State<Arc<Settings>>,
+    request: Json<VerifyRequestDTO>,
+) -> Result<Json<VerifyResponseDTO>, Status> {
+    // Verify that we have a public role
+    if auth.role != Role::Public {
+        return Err(Status::Forbidden);
+    }
+
+    let message = match (&request.message, &request.digest) {
+        (Some(message), None) => message.as_bytes().to_vec(),
+        (None, Some(digest)) => hex::decode(digest).map_err(|_| Status::BadRequest)?,
+        _ => return Err(Status::BadRequest),
+    };
+    let signature = hex::decode(&request.signature).map_err(|_| Status::BadRequest)?;
+    let (r, s, recid) = decode_signature(&signature).map_err(|_| Status::BadRequest)?;
+
+    // Without an explicit path check against the key the signers use by default
+    let path = request.derivation_path.as_deref().unwrap_or(&settings.path);
+    let mut expected_key = settings
+        .network_public_key()
+        .map_err(|_| Status::InternalServerError)?;
+    if !path.is_empty() {
+        if !is_valid_path(path) {
+            return Err(Status::BadRequest);
+        }
+        (_, expected_key) = call_hd_key(path, expected_key);
     }
+
+    let message_bn = message_to_bigint(&message);
+    let valid = verify_signature(&r, &s, &expected_key, &message_bn);
+
+    let recovered: Vec<_> = recid
+        .map_or(vec![0, 1], |recid| vec![recid])
+        .into_iter()
+        .filter_map(|recid| recover_public_key(&r, &s, recid, &message_bn))
+        .collect();
+    let public_key = recovered
+        .iter()
+        .find(|key| **key == expected_key)
+        .or(recovered.first())
+        .map(|key| hex::encode(&*key.to_bytes(true)));
+
+    Ok(Json(VerifyResponseDTO { valid, public_key }))
 }
 
 #[get("/signing_result/<request_id>")]
@@ -100,6 +440,323 @@ pub async fn get_signing_result(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+#[get("/signing_requests?<status>&<since>&<until>&<key_id>&<limit>&<cursor>")]
+pub async fn list_signing_requests(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    status: Option<String>,
+    since: Option<u64>,
+    until: Option<u64>,
+    key_id: Option<String>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+) -> Result<Json<SigningRequestListDTO>, Status> {
+    // Approvers find the requests awaiting their decision here
+    if auth.role != Role::Admin && auth.role != Role::Approver {
+        return Err(Status::Forbidden);
+    }
+
+    let status = status
+        .map(|status| status.parse::<MessageStatus>())
+        .transpose()
+        .map_err(|_| Status::BadRequest)?;
+    let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+        return Err(Status::BadRequest);
+    }
+    let filter = SigningRequestFilter {
+        status,
+        since,
+        until,
+        key_id,
+    };
+
+    match manager
+        .list_signing_requests(&filter, limit, cursor.as_deref())
+        .await
+    {
+        Ok((requests, next_cursor)) => Ok(Json(SigningRequestListDTO {
+            requests,
+            next_cursor,
+        })),
+        Err(e) if matches!(e.downcast_ref(), Some(TssError::InvalidCursor(_))) => {
+            Err(Status::BadRequest)
+        }
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/rooms/<room_id>")]
+pub async fn get_room(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    room_id: String,
+) -> Result<Json<RoomStatusDTO>, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    match manager.get_signing_room(&room_id).await {
+        Ok(Some(room)) => Ok(Json(room.into())),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/dead_letters?<limit>")]
+pub async fn list_dead_letters(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    limit: Option<i64>,
+) -> Result<Json<Vec<DeadLetterStored>>, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+        return Err(Status::BadRequest);
+    }
+
+    match manager.list_dead_letters(limit).await {
+        Ok(dead_letters) => Ok(Json(dead_letters)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[post("/dead_letters/replay", format = "json", data = "<request>")]
+pub async fn replay_dead_letters(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    request: Json<ReplayRequestDTO>,
+) -> Result<Json<ReplayResponseDTO>, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    match manager.replay_dead_letters(&request.request_ids).await {
+        Ok(replayed) => Ok(Json(ReplayResponseDTO { replayed })),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[post("/api_clients", format = "json", data = "<request>")]
+pub async fn create_api_client(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    keys: &State<Arc<JwtKeys>>,
+    settings: &State<Arc<Settings>>,
+    request: Json<ApiClientRequestDTO>,
+) -> Result<Created<Json<ApiClientCreatedDTO>>, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    let token_expiration = request
+        .token_expiration
+        .unwrap_or(settings.security.jwt_expiration);
+    if request.role == Role::Signer
+        || request.name.is_empty()
+        || !(1..=MAX_TOKEN_EXPIRATION).contains(&token_expiration)
+        || request.policy.validate().is_err()
+    {
+        return Err(Status::BadRequest);
+    }
+
+    match manager
+        .create_api_client(
+            &request.name,
+            request.role,
+            token_expiration,
+            request.policy.clone(),
+            keys,
+        )
+        .await
+    {
+        Ok((client, issued)) => {
+            let token = ApiTokenDTO::new(&client.client_id, issued);
+            let location = format!("/api_clients/{}", client.client_id);
+            Ok(Created::new(location).body(Json(ApiClientCreatedDTO { client, token })))
+        }
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/api_clients")]
+pub async fn list_api_clients(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+) -> Result<Json<Vec<ApiClient>>, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    match manager.list_api_clients().await {
+        Ok(clients) => Ok(Json(clients)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+/// Issues another token to a client, to rotate its credentials
+#[post("/api_clients/<client_id>/tokens")]
+pub async fn issue_api_token(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    keys: &State<Arc<JwtKeys>>,
+    client_id: String,
+) -> Result<Json<ApiTokenDTO>, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    match manager.issue_api_token(&client_id, keys).await {
+        Ok(Some(issued)) => Ok(Json(ApiTokenDTO::new(&client_id, issued))),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[put("/api_clients/<client_id>/policy", format = "json", data = "<policy>")]
+pub async fn set_api_client_policy(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    client_id: String,
+    policy: Json<SigningPolicy>,
+) -> Result<Json<ApiClient>, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    if policy.validate().is_err() {
+        return Err(Status::BadRequest);
+    }
+
+    match manager.set_api_client_policy(&client_id, &policy).await {
+        Ok(Some(client)) => Ok(Json(client)),
+        Ok(None) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[get("/policy_denials?<limit>")]
+pub async fn list_policy_denials(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    limit: Option<i64>,
+) -> Result<Json<Vec<PolicyDenial>>, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+        return Err(Status::BadRequest);
+    }
+
+    match manager.list_policy_denials(limit).await {
+        Ok(denials) => Ok(Json(denials)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[post(
+    "/signing_requests/<request_id>/approve",
+    format = "json",
+    data = "<decision>"
+)]
+pub async fn approve_signing_request(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    request_id: String,
+    decision: Json<ApprovalDecisionDTO>,
+) -> Result<Json<MessageToSignStored>, Status> {
+    decide_approval(&auth, manager, &request_id, true, decision.into_inner()).await
+}
+
+#[post(
+    "/signing_requests/<request_id>/reject",
+    format = "json",
+    data = "<decision>"
+)]
+pub async fn reject_signing_request(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    request_id: String,
+    decision: Json<ApprovalDecisionDTO>,
+) -> Result<Json<MessageToSignStored>, Status> {
+    // Signatures only vouch for approvals
+    let decision = ApprovalDecisionDTO {
+        signature: None,
+        ..decision.into_inner()
+    };
+    decide_approval(&auth, manager, &request_id, false, decision).await
+}
+
+async fn decide_approval(
+    auth: &AuthenticatedUser,
+    manager: &ManagerService,
+    request_id: &str,
+    approved: bool,
+    decision: ApprovalDecisionDTO,
+) -> Result<Json<MessageToSignStored>, Status> {
+    if auth.role != Role::Approver {
+        return Err(Status::Forbidden);
+    }
+
+    match manager
+        .decide_approval(
+            request_id,
+            &auth.user_id,
+            approved,
+            decision.comment,
+            decision.signature,
+        )
+        .await
+    {
+        Ok(request) => Ok(Json(request)),
+        Err(e) => match e.downcast_ref::<TssError>() {
+            Some(TssError::InvalidSignature(_)) => Err(Status::BadRequest),
+            Some(TssError::NotFoundError(_)) => Err(Status::NotFound),
+            Some(TssError::ApprovalError(_)) => Err(Status::Conflict),
+            _ => Err(Status::InternalServerError),
+        },
+    }
+}
+
+#[post("/api_clients/<client_id>/revoke")]
+pub async fn revoke_api_client(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    client_id: String,
+) -> Result<Status, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    match manager.revoke_api_client(&client_id).await {
+        Ok(true) => Ok(Status::NoContent),
+        Ok(false) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[post("/tokens/<jti>/revoke")]
+pub async fn revoke_token(
+    auth: AuthenticatedUser,
+    manager: &State<Arc<ManagerService>>,
+    jti: String,
+) -> Result<Status, Status> {
+    if auth.role != Role::Admin {
+        return Err(Status::Forbidden);
+    }
+
+    match manager.revoke_token(&jti).await {
+        Ok(()) => Ok(Status::NoContent),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
 // For testing and development purposes
 // Only compile these endpoints in debug/development mode
 #[cfg(debug_assertions)]
@@ -107,15 +764,17 @@ pub async fn get_signing_result(
 pub async fn generate_test_token(
     role: String,
     settings: &rocket::State<Arc<Settings>>,
+    keys: &State<Arc<JwtKeys>>,
 ) -> Result<Json<TokenResponse>, rocket::http::Status> {
     let role = match role.to_lowercase().as_str() {
         "public" => Role::Public,
         "signer" => Role::Signer,
         "admin" => Role::Admin,
+        "approver" => Role::Approver,
         _ => return Err(rocket::http::Status::BadRequest),
     };
 
-    match create_token("test-user", role, settings) {
+    match create_token(keys, "test-user", role, settings.security.jwt_expiration) {
         Ok(token) => Ok(Json(TokenResponse {
             token,
             expires_in: settings.security.jwt_expiration,
@@ -132,7 +791,6 @@ pub async fn generate_keys(
 ) -> Result<Created<Json<KeyGenResponseDTO>>, Status> {
     let threshold = request.threshold;
     let total_parties = request.total_parties;
-    let manger_addr = request.manager_url.clone();
 
     if threshold > total_parties {
         return Err(Status::BadRequest);
@@ -143,11 +801,12 @@ pub async fn generate_keys(
         keygen_params: KeyGenParams {
             parties: total_parties,
             threshold,
+            scheme: request.scheme,
         },
     };
 
     let result = manager
-        .process_keygen_request(keygen_request.clone(), &manger_addr)
+        .process_keygen_request(keygen_request.clone())
         .await
         .context("Failed to process key generation request")
         .map_err(|_| Status::InternalServerError)?;
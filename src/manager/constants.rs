@@ -1,2 +1,12 @@
 pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024; // 1MB
 pub const NONCE_SIZE: usize = 12;
+pub const SIGNING_RESULT_POLL_INTERVAL_MS: u64 = 500;
+pub const DEFAULT_LIST_LIMIT: i64 = 50;
+pub const MAX_LIST_LIMIT: i64 = 500;
+pub const DEAD_LETTER_RETRY_INTERVAL_MS: u64 = 1000;
+pub const MAX_TOKEN_EXPIRATION: u64 = 90 * 24 * 3600; // 90 days
+pub const RATE_LIMIT_WINDOW_MS: u64 = 60 * 1000;
+pub const DAILY_QUOTA_WINDOW_MS: u64 = 24 * 3600 * 1000;
+pub const ROOM_UPDATE_RETRY_DELAY_MS: u64 = 5;
+pub const MAX_ROOM_UPDATE_RETRY_DELAY_MS: u64 = 200;
+pub const MAX_ROOM_UPDATE_ATTEMPTS: u32 = 20;
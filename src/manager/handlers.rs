@@ -1,164 +1,199 @@
 use crate::auth::SignerAuth;
 use crate::common::{
-    Entry, Index, KeyGenParams, ManagerError, PartySignup, PartySignupRequestBody, SignerResult,
-    SigningPartySignup, SigningRoom,
+    Entry, Index, ManagerError, PartySignupRequestBody, RoomState, SignerResult,
+    SigningPartySignup, SigningProgress, SigningRefusal, SigningRoom, KEY_NOT_FOUND,
+    ROOM_UUID_INDEX_PREFIX, SIGNING_ROOM_PREFIX,
 };
 use crate::error::TssError;
+use crate::manager::constants::MAX_ROOM_UPDATE_ATTEMPTS;
+use crate::manager::service::{room_update_backoff, SchemePublicKeys};
 use crate::manager::ManagerService;
+use crate::storage::room_store::Swap;
 use rocket::http::Status;
 use rocket::post;
 use rocket::response::Responder;
 use rocket::serde::json::Json;
 use rocket::{Request, State};
 use std::sync::Arc;
+use tracing::error;
 
 #[post("/get", format = "json", data = "<request>")]
 pub async fn get(
-    _auth: SignerAuth,
+    auth: SignerAuth,
     manager: &State<Arc<ManagerService>>,
     request: Json<Index>,
 ) -> Json<Result<Entry, ManagerError>> {
     let index: Index = request.into_inner();
-    let signing_rooms = manager.signing_rooms.read().await;
-    match signing_rooms.get(&index.key) {
-        Some(value) => {
+    if let Err(e) = manager
+        .authorize_round_key(&index.key, auth.party_id, false)
+        .await
+    {
+        return Json(Err(unauthorized(e)));
+    }
+    match manager.room_store.get(&index.key).await {
+        Ok(Some(value)) => {
             let entry = Entry {
                 key: index.key.clone(),
-                value: value.clone().to_string(),
+                value,
             };
             Json(Ok(entry))
         }
-        None => Json(Err(ManagerError {
-            error: "Key not found".to_string(),
+        Ok(None) => Json(Err(ManagerError {
+            error: KEY_NOT_FOUND.to_string(),
         })),
+        Err(e) => Json(Err(room_store_error(e))),
     }
 }
 
 #[post("/set", format = "json", data = "<request>")]
 pub async fn set(
-    _auth: SignerAuth,
+    auth: SignerAuth,
     manager: &State<Arc<ManagerService>>,
     request: Json<Entry>,
 ) -> Json<Result<(), ManagerError>> {
     let entry: Entry = request.into_inner();
-    let mut signing_rooms = manager.signing_rooms.write().await;
-    signing_rooms.insert(entry.key.clone(), entry.value.clone());
-    Json(Ok(()))
-}
-
-#[post("/signupkeygen", format = "json", data = "<request>")]
-pub async fn signup_keygen(
-    _auth: SignerAuth,
-    manager: &State<Arc<ManagerService>>,
-    request: Json<KeyGenParams>,
-) -> Json<Result<PartySignup, ManagerError>> {
-    let parties = request.parties;
-    let key = "signup-keygen".to_string();
-    let mut hm = manager.signing_rooms.write().await;
-
-    let client_signup = match hm.get(&key) {
-        Some(o) => serde_json::from_str(o).unwrap(),
-        None => PartySignup {
-            number: 0,
-            uuid: uuid::Uuid::new_v4().to_string(),
-        },
+    let room = match manager
+        .authorize_round_key(&entry.key, auth.party_id, true)
+        .await
+    {
+        Ok(room) => room,
+        Err(e) => return Json(Err(unauthorized(e))),
     };
-
-    let party_signup = {
-        if client_signup.number < parties {
-            PartySignup {
-                number: client_signup.number + 1,
-                uuid: client_signup.uuid,
-            }
-        } else {
-            PartySignup {
-                number: 1,
-                uuid: uuid::Uuid::new_v4().to_string(),
-            }
-        }
-    };
-
-    hm.insert(key, serde_json::to_string(&party_signup).unwrap());
-    Json(Ok(party_signup))
+    if let Err(e) = manager.room_store.set(&entry.key, &entry.value).await {
+        return Json(Err(room_store_error(e)));
+    }
+    if let Err(e) = manager.record_round_message(&entry.key, room).await {
+        error!("Error recording round message {}: {:?}", entry.key, e);
+    }
+    Json(Ok(()))
 }
 
 #[post("/signupsign", format = "json", data = "<request>")]
 pub async fn signup_sign(
-    _auth: SignerAuth,
+    auth: SignerAuth,
     manager: &State<Arc<ManagerService>>,
     request: Json<PartySignupRequestBody>,
 ) -> Json<Result<SigningPartySignup, ManagerError>> {
     let req = request.into_inner();
+    if req.party_number != auth.party_id {
+        return Json(Err(party_mismatch(req.party_number, auth.party_id)));
+    }
     let threshold = req.threshold;
     let room_id = req.room_id.clone();
     let party_uuid = req.party_uuid.clone();
     let new_signup_request = party_uuid.is_empty();
     let party_number = req.party_number;
-    let mut key = "signup-sign-".to_owned();
+    let mut key = SIGNING_ROOM_PREFIX.to_owned();
     key.push_str(&room_id);
 
-    let mut signing_rooms = manager.signing_rooms.write().await;
-
-    let mut signing_room = match signing_rooms.get(&key) {
-        Some(room) => serde_json::from_str(room).unwrap(),
-        None => SigningRoom::new(room_id.clone(), threshold + 1),
+    // Other replicas may update the room concurrently, retry until our write lands
+    let mut stored = match manager.room_store.get(&key).await {
+        Ok(stored) => stored,
+        Err(e) => return Json(Err(room_store_error(e))),
     };
+    let mut backoff = room_update_backoff();
+    for _ in 0..MAX_ROOM_UPDATE_ATTEMPTS {
+        let mut signing_room = match &stored {
+            Some(room) => serde_json::from_str(room).unwrap(),
+            None => SigningRoom::new(room_id.clone(), threshold + 1),
+        };
+
+        let mut abandoned_room = None;
+        if signing_room.state != RoomState::Signup {
+            if signing_room.has_member(party_number, party_uuid.clone()) {
+                return Json(Ok(signing_room.get_signup_info(party_number)));
+            }
 
-    if signing_room.last_stage != "signup" {
-        if signing_room.has_member(party_number, party_uuid.clone()) {
-            return Json(Ok(signing_room.get_signup_info(party_number)));
+            if signing_room.is_closed() {
+                // The message is signed again, messages of the closed room expire with the TTL
+                signing_room = SigningRoom::new(room_id.clone(), threshold + 1);
+            } else if signing_room.are_all_members_inactive() {
+                if signing_room.transition(RoomState::Aborted).is_ok() {
+                    abandoned_room = Some(signing_room);
+                }
+                signing_room = SigningRoom::new(room_id.clone(), threshold + 1);
+            } else {
+                return Json(Err(ManagerError {
+                    error: "Room signup phase is terminated".to_string(),
+                }));
+            }
         }
 
-        if signing_room.are_all_members_inactive() {
-            signing_room = SigningRoom::new(room_id, threshold + 1);
-        } else {
+        if signing_room.is_full() && signing_room.are_all_members_active() && new_signup_request {
             return Json(Err(ManagerError {
-                error: "Room signup phase is terminated".to_string(),
+                error: "Room is full, all members active".to_string(),
             }));
         }
-    }
 
-    if signing_room.is_full() && signing_room.are_all_members_active() && new_signup_request {
-        return Json(Err(ManagerError {
-            error: "Room is full, all members active".to_string(),
-        }));
-    }
+        let party_signup = if !new_signup_request {
+            if !signing_room.has_member(party_number, party_uuid.clone()) {
+                return Json(Err(ManagerError {
+                    error: "No party found with the given uuid, probably replaced due to timeout"
+                        .to_string(),
+                }));
+            }
+            signing_room.update_ping(party_number)
+        } else if signing_room.member_info.contains_key(&party_number) {
+            if signing_room.is_member_active(party_number) {
+                return Json(Err(ManagerError {
+                    error: "Received a re-signup request for an active party. Request ignored"
+                        .to_string(),
+                }));
+            }
+            println!(
+                "Received a re-signup request for a timed-out party {:?}, thus UUID is renewed",
+                party_number
+            );
+            signing_room.replace_party(party_number)
+        } else {
+            signing_room.add_party(party_number)
+        };
 
-    let party_signup = if !new_signup_request {
-        if !signing_room.has_member(party_number, party_uuid.clone()) {
-            return Json(Err(ManagerError {
-                error: "No party found with the given uuid, probably replaced due to timeout"
-                    .to_string(),
-            }));
-        }
-        signing_room.update_ping(party_number)
-    } else if signing_room.member_info.contains_key(&party_number) {
-        if signing_room.is_member_active(party_number) {
-            return Json(Err(ManagerError {
-                error: "Received a re-signup request for an active party. Request ignored"
-                    .to_string(),
-            }));
+        // Index the room by uuid before any party learns it, so the rounds it
+        // posts can be attributed to the room
+        if signing_room.state == RoomState::Locked {
+            let index = format!("{}{}", ROOM_UUID_INDEX_PREFIX, signing_room.room_uuid);
+            if let Err(e) = manager.room_store.set(&index, &room_id).await {
+                return Json(Err(room_store_error(e)));
+            }
         }
-        println!(
-            "Received a re-signup request for a timed-out party {:?}, thus UUID is renewed",
-            party_number
-        );
-        signing_room.replace_party(party_number)
-    } else {
-        signing_room.add_party(party_number)
-    };
 
-    signing_rooms.insert(key.clone(), serde_json::to_string(&signing_room).unwrap());
-    Json(Ok(party_signup))
+        let updated = serde_json::to_string(&signing_room).unwrap();
+        match manager
+            .room_store
+            .compare_and_swap(&key, stored.as_deref(), &updated)
+            .await
+        {
+            Ok(Swap::Written) => {
+                if let Some(room) = abandoned_room {
+                    if let Err(e) = manager.reap_room_messages(&room).await {
+                        error!("Error reaping aborted room {}: {:?}", room.room_id, e);
+                    }
+                }
+                return Json(Ok(party_signup));
+            }
+            Ok(Swap::Conflict(found)) => stored = found,
+            Err(e) => return Json(Err(room_store_error(e))),
+        }
+        backoff.wait().await;
+    }
+    Json(Err(room_store_error(TssError::RoomContention(key).into())))
 }
 
 #[post("/update_signing_result", format = "json", data = "<result>")]
 pub async fn update_signing_result(
-    _auth: SignerAuth,
+    auth: SignerAuth,
     manager: &State<Arc<ManagerService>>,
+    public_keys: &State<Arc<SchemePublicKeys>>,
     result: Json<SignerResult>,
 ) -> Json<Result<(), ManagerError>> {
-    match manager.update_signing_result(result.into_inner()).await {
+    if result.party_id != auth.party_id {
+        return Json(Err(party_mismatch(result.party_id, auth.party_id)));
+    }
+    match manager
+        .update_signing_result(result.into_inner(), public_keys)
+        .await
+    {
         Ok(_) => {}
         Err(e) => {
             return Json(Err(ManagerError {
@@ -169,6 +204,61 @@ pub async fn update_signing_result(
     Json(Ok(()))
 }
 
+#[post("/update_signing_progress", format = "json", data = "<progress>")]
+pub async fn update_signing_progress(
+    auth: SignerAuth,
+    manager: &State<Arc<ManagerService>>,
+    progress: Json<SigningProgress>,
+) -> Json<Result<(), ManagerError>> {
+    if progress.party_id != auth.party_id {
+        return Json(Err(party_mismatch(progress.party_id, auth.party_id)));
+    }
+    match manager.update_signing_progress(progress.into_inner()).await {
+        Ok(_) => Json(Ok(())),
+        Err(e) => Json(Err(ManagerError {
+            error: e.to_string(),
+        })),
+    }
+}
+
+#[post("/report_signing_refusal", format = "json", data = "<refusal>")]
+pub async fn report_signing_refusal(
+    auth: SignerAuth,
+    manager: &State<Arc<ManagerService>>,
+    refusal: Json<SigningRefusal>,
+) -> Json<Result<(), ManagerError>> {
+    if refusal.party_id != auth.party_id {
+        return Json(Err(party_mismatch(refusal.party_id, auth.party_id)));
+    }
+    match manager.record_signing_refusal(refusal.into_inner()).await {
+        Ok(_) => Json(Ok(())),
+        Err(e) => Json(Err(ManagerError {
+            error: e.to_string(),
+        })),
+    }
+}
+
+fn unauthorized(e: anyhow::Error) -> ManagerError {
+    ManagerError {
+        error: format!("Unauthorized: {}", e),
+    }
+}
+
+fn party_mismatch(party_id: u16, authenticated: u16) -> ManagerError {
+    ManagerError {
+        error: format!(
+            "Unauthorized: party {} does not match the credential of party {}",
+            party_id, authenticated
+        ),
+    }
+}
+
+fn room_store_error(e: anyhow::Error) -> ManagerError {
+    ManagerError {
+        error: format!("Room store error: {}", e),
+    }
+}
+
 impl<'r> Responder<'r, 'static> for TssError {
     fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
         Err(Status::InternalServerError)
@@ -1,11 +1,9 @@
-use std::time;
-
 use crate::common::{
+    frost::{self, Ciphersuite},
     secp256k1def::{FE, GE},
-    KeyGenParams, KeyGenRequest,
 };
-use anyhow::Result;
-use curv::elliptic::curves::Secp256k1;
+use anyhow::{anyhow, Result};
+use curv::elliptic::curves::{Scalar, Secp256k1};
 use curv::{
     arithmetic::traits::Converter,
     cryptographic_primitives::{
@@ -17,61 +15,30 @@ use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2018::party_i::{
     KeyGenBroadcastMessage1, KeyGenDecommitMessage1, Keys, Parameters,
 };
 use paillier::EncryptionKey;
-use reqwest::Client;
 use sha2::Sha256;
 
-use crate::common::{
-    aes_decrypt, aes_encrypt, broadcast, poll_for_broadcasts, poll_for_p2p, postb, sendp2p,
-    PartySignup, AEAD,
-};
+use crate::common::{aes_decrypt, aes_encrypt, Transport, AEAD};
 
+/// GG18 key generation among the parties of `transport`, returns the key share
+/// of this party serialized as the signer key file
 #[allow(non_snake_case)]
-pub async fn run_keygen(addr: &String, keygen_request: &KeyGenRequest) -> Result<String> {
-    let params = keygen_request.keygen_params.clone();
-    let THRESHOLD: u16 = params.threshold;
-    let PARTIES: u16 = params.parties;
-    let client = Client::new();
-
-    // delay:
-    let delay = time::Duration::from_millis(25);
+pub async fn keygen<T: Transport + ?Sized>(transport: &T, threshold: u16) -> Result<String> {
+    let THRESHOLD: u16 = threshold;
+    let PARTIES: u16 = transport.parties();
+    let party_num_int = transport.party_num();
     let params = Parameters {
         threshold: THRESHOLD,
         share_count: PARTIES,
     };
 
-    //signup:
-    let tn_params = KeyGenParams {
-        threshold: THRESHOLD,
-        parties: PARTIES,
-    };
-    let (party_num_int, uuid) = match keygen_signup(&addr, &client, tn_params).await.unwrap() {
-        PartySignup { number, uuid } => (number, uuid),
-    };
-
     let party_keys = Keys::create(party_num_int);
     let (bc_i, decom_i) = party_keys.phase1_broadcast_phase3_proof_of_correct_key();
 
     // send commitment to ephemeral public keys, get round 1 commitments of other parties
-    assert!(broadcast(
-        &addr,
-        &client,
-        party_num_int,
-        "round1",
-        serde_json::to_string(&bc_i).unwrap(),
-        uuid.clone(),
-    )
-    .await
-    .is_ok());
-    let round1_ans_vec = poll_for_broadcasts(
-        &addr,
-        &client,
-        party_num_int,
-        PARTIES,
-        delay,
-        "round1",
-        uuid.clone(),
-    )
-    .await;
+    transport
+        .broadcast("round1", serde_json::to_string(&bc_i).unwrap())
+        .await?;
+    let round1_ans_vec = transport.receive_broadcasts("round1").await?;
 
     let mut bc1_vec = round1_ans_vec
         .iter()
@@ -81,26 +48,10 @@ pub async fn run_keygen(addr: &String, keygen_request: &KeyGenRequest) -> Result
     bc1_vec.insert(party_num_int as usize - 1, bc_i);
 
     // send ephemeral public keys and check commitments correctness
-    assert!(broadcast(
-        &addr,
-        &client,
-        party_num_int,
-        "round2",
-        serde_json::to_string(&decom_i).unwrap(),
-        uuid.clone(),
-    )
-    .await
-    .is_ok());
-    let round2_ans_vec = poll_for_broadcasts(
-        &addr,
-        &client,
-        party_num_int,
-        PARTIES,
-        delay,
-        "round2",
-        uuid.clone(),
-    )
-    .await;
+    transport
+        .broadcast("round2", serde_json::to_string(&decom_i).unwrap())
+        .await?;
+    let round2_ans_vec = transport.receive_broadcasts("round2").await?;
 
     let mut j = 0;
     let mut point_vec: Vec<GE> = Vec::new();
@@ -115,7 +66,7 @@ pub async fn run_keygen(addr: &String, keygen_request: &KeyGenRequest) -> Result
             point_vec.push(decom_j.y_i.clone());
             decom_vec.push(decom_j.clone());
             enc_keys.push((decom_j.y_i * &party_keys.u_i).x_coord().unwrap());
-            j = j + 1;
+            j += 1;
         }
     }
 
@@ -137,31 +88,14 @@ pub async fn run_keygen(addr: &String, keygen_request: &KeyGenRequest) -> Result
             let key_i = BigInt::to_bytes(&enc_keys[j]);
             let plaintext = BigInt::to_bytes(&secret_shares[k].to_bigint());
             let aead_pack_i = aes_encrypt(&key_i, &plaintext);
-            assert!(sendp2p(
-                &addr,
-                &client,
-                party_num_int,
-                i,
-                "round3",
-                serde_json::to_string(&aead_pack_i).unwrap(),
-                uuid.clone(),
-            )
-            .await
-            .is_ok());
+            transport
+                .send_p2p(i, "round3", serde_json::to_string(&aead_pack_i).unwrap())
+                .await?;
             j += 1;
         }
     }
 
-    let round3_ans_vec = poll_for_p2p(
-        &addr,
-        &client,
-        party_num_int,
-        PARTIES,
-        delay,
-        "round3",
-        uuid.clone(),
-    )
-    .await;
+    let round3_ans_vec = transport.receive_p2p("round3").await?;
 
     let mut j = 0;
     let mut party_shares: Vec<FE> = Vec::new();
@@ -181,26 +115,10 @@ pub async fn run_keygen(addr: &String, keygen_request: &KeyGenRequest) -> Result
     }
 
     // round 4: send vss commitments
-    assert!(broadcast(
-        &addr,
-        &client,
-        party_num_int,
-        "round4",
-        serde_json::to_string(&vss_scheme).unwrap(),
-        uuid.clone(),
-    )
-    .await
-    .is_ok());
-    let round4_ans_vec = poll_for_broadcasts(
-        &addr,
-        &client,
-        party_num_int,
-        PARTIES,
-        delay,
-        "round4",
-        uuid.clone(),
-    )
-    .await;
+    transport
+        .broadcast("round4", serde_json::to_string(&vss_scheme).unwrap())
+        .await?;
+    let round4_ans_vec = transport.receive_broadcasts("round4").await?;
 
     let mut j = 0;
     let mut vss_scheme_vec: Vec<VerifiableSS<Secp256k1>> = Vec::new();
@@ -226,26 +144,10 @@ pub async fn run_keygen(addr: &String, keygen_request: &KeyGenRequest) -> Result
         .expect("invalid vss");
 
     // round 5: send dlog proof
-    assert!(broadcast(
-        &addr,
-        &client,
-        party_num_int,
-        "round5",
-        serde_json::to_string(&dlog_proof).unwrap(),
-        uuid.clone(),
-    )
-    .await
-    .is_ok());
-    let round5_ans_vec = poll_for_broadcasts(
-        &addr,
-        &client,
-        party_num_int,
-        PARTIES,
-        delay,
-        "round5",
-        uuid.clone(),
-    )
-    .await;
+    transport
+        .broadcast("round5", serde_json::to_string(&dlog_proof).unwrap())
+        .await?;
+    let round5_ans_vec = transport.receive_broadcasts("round5").await?;
 
     let mut j = 0;
     let mut dlog_proof_vec: Vec<DLogProof<Secp256k1, Sha256>> = Vec::new();
@@ -278,13 +180,79 @@ pub async fn run_keygen(addr: &String, keygen_request: &KeyGenRequest) -> Result
     Ok(keygen_json)
 }
 
-pub async fn keygen_signup(
-    addr: &String,
-    client: &Client,
-    params: KeyGenParams,
-) -> Result<PartySignup, ()> {
-    let res_body = postb::<KeyGenParams>(&addr, &client, "signupkeygen", params)
-        .await
-        .unwrap();
-    serde_json::from_str(&res_body).unwrap()
+/// FROST key generation among the parties of `transport`, `C` picks the curve.
+/// Every party shares a random polynomial (Pedersen DKG), commitments carry a
+/// proof of knowledge of the constant term bound to the session id, shares are
+/// sent encrypted under an ephemeral Diffie-Hellman key. Returns the key share
+/// of this party serialized as the signer FROST key file.
+#[allow(non_snake_case)]
+pub async fn frost_keygen<C: Ciphersuite, T: Transport + ?Sized>(
+    transport: &T,
+    threshold: u16,
+) -> Result<String> {
+    let THRESHOLD: u16 = threshold;
+    let PARTIES: u16 = transport.parties();
+    let party_num_int = transport.party_num();
+    let uuid = transport.session_id();
+
+    // round 1: broadcast polynomial commitments and proof of knowledge
+    let (secrets, commitment_i) = frost::keygen_commit::<C>(party_num_int, THRESHOLD, uuid);
+    transport
+        .broadcast("round1", serde_json::to_string(&commitment_i).unwrap())
+        .await?;
+    let round1_ans_vec = transport.receive_broadcasts("round1").await?;
+
+    let mut j = 0;
+    let mut commitments: Vec<frost::KeyGenCommitment<C::Curve>> = Vec::new();
+    for i in 1..=PARTIES {
+        if i == party_num_int {
+            commitments.push(commitment_i.clone());
+        } else {
+            let commitment_j: frost::KeyGenCommitment<C::Curve> =
+                serde_json::from_str(&round1_ans_vec[j]).unwrap();
+            if commitment_j.party_id != i
+                || !frost::verify_keygen_commitment::<C>(&commitment_j, THRESHOLD, uuid)
+            {
+                return Err(anyhow!("Invalid keygen commitment from party {}", i));
+            }
+            commitments.push(commitment_j);
+            j += 1;
+        }
+    }
+
+    // round 2: send each party its encrypted share
+    for i in 1..=PARTIES {
+        if i != party_num_int {
+            let key_i = secrets.encryption_key_with(&commitments[(i - 1) as usize]);
+            let plaintext = BigInt::to_bytes(&secrets.share_for(i).to_bigint());
+            let aead_pack_i = aes_encrypt(&key_i, &plaintext);
+            transport
+                .send_p2p(i, "round2", serde_json::to_string(&aead_pack_i).unwrap())
+                .await?;
+        }
+    }
+
+    let round2_ans_vec = transport.receive_p2p("round2").await?;
+
+    let mut j = 0;
+    let mut party_shares: Vec<Scalar<C::Curve>> = Vec::new();
+    for i in 1..=PARTIES {
+        if i == party_num_int {
+            party_shares.push(secrets.share_for(party_num_int));
+        } else {
+            let sender = &commitments[(i - 1) as usize];
+            let aead_pack: AEAD = serde_json::from_str(&round2_ans_vec[j]).unwrap();
+            let out = aes_decrypt(&secrets.encryption_key_with(sender), aead_pack);
+            let share = Scalar::from_bigint(&BigInt::from_bytes(&out));
+            if !frost::verify_keygen_share(&share, party_num_int, &sender.commitments) {
+                return Err(anyhow!("Invalid keygen share from party {}", i));
+            }
+            party_shares.push(share);
+            j += 1;
+        }
+    }
+
+    let key_share =
+        frost::finalize_keygen::<C>(party_num_int, THRESHOLD, &party_shares, &commitments);
+    Ok(serde_json::to_string(&key_share)?)
 }
@@ -2,6 +2,8 @@ pub mod api;
 pub mod constants;
 pub mod handlers;
 pub mod keygen;
+pub mod policy;
+pub mod psbt;
 pub mod service;
 
 pub use service::ManagerService;
@@ -0,0 +1,160 @@
+use std::str::FromStr;
+
+use bitcoin::address::{Address, NetworkUnchecked};
+use bitcoin::psbt::Psbt;
+use bitcoin::ScriptBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::common::hd_keys::{is_valid_path, path_matches};
+use crate::common::SignatureScheme;
+
+const SCHEMES: [SignatureScheme; 3] = [
+    SignatureScheme::Ecdsa,
+    SignatureScheme::Schnorr,
+    SignatureScheme::Eddsa,
+];
+
+/// What an API client may get signed. Empty lists and unset limits leave that
+/// part unrestricted, so the default policy allows everything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SigningPolicy {
+    // Keys the client may sign with, by `key_id`
+    #[serde(default)]
+    pub allowed_key_ids: Vec<String>,
+    // Paths of the child keys the client may sign with, a trailing `/*` allows
+    // every path below
+    #[serde(default)]
+    pub allowed_derivation_paths: Vec<String>,
+    #[serde(default)]
+    pub max_requests_per_minute: Option<u32>,
+    // Signing requests in any 24 hours
+    #[serde(default)]
+    pub daily_quota: Option<u32>,
+    // Addresses signed transactions may pay to, change to the network key is always allowed
+    #[serde(default)]
+    pub allowed_destinations: Vec<String>,
+    // Satoshis a signed transaction may send to other keys than the network key
+    #[serde(default)]
+    pub max_transaction_value: Option<u64>,
+    // Approvals from distinct approvers a request needs before it is signed
+    #[serde(default)]
+    pub approvals_required: u32,
+    // Transactions sending at most this many satoshis are signed without approval
+    #[serde(default)]
+    pub approval_threshold: Option<u64>,
+}
+
+impl SigningPolicy {
+    /// Rejects unknown key ids, malformed paths and addresses
+    pub fn validate(&self) -> Result<(), String> {
+        for key_id in &self.allowed_key_ids {
+            if !SCHEMES.iter().any(|scheme| scheme.key_id() == key_id) {
+                return Err(format!("unknown key id {}", key_id));
+            }
+        }
+        for pattern in &self.allowed_derivation_paths {
+            let path = pattern.strip_suffix("/*").unwrap_or(pattern);
+            if !path.is_empty() && !is_valid_path(path) {
+                return Err(format!("invalid derivation path {}", pattern));
+            }
+        }
+        if self.approval_threshold.is_some() && self.approvals_required == 0 {
+            return Err("approval threshold set without approvals required".to_string());
+        }
+        self.destination_scripts().map(|_| ())
+    }
+
+    /// Approvals a message signing request needs. Messages carry no value, so
+    /// they all need approval once approvals are required, otherwise the sighash
+    /// of a transaction could be signed as a message without approval.
+    pub fn approvals_for_message(&self) -> u32 {
+        self.approvals_required
+    }
+
+    /// Approvals a transaction sending `value` satoshis to other keys needs
+    pub fn approvals_for_transaction(&self, value: u64) -> u32 {
+        match self.approval_threshold {
+            Some(threshold) if value <= threshold => 0,
+            _ => self.approvals_required,
+        }
+    }
+
+    /// Checks a request for a signature of `scheme` with the child key at `path`,
+    /// an empty path being the network key itself. Only ECDSA keys are derived,
+    /// the path is ignored for the other schemes.
+    pub fn check_request(&self, scheme: SignatureScheme, path: &str) -> Result<(), String> {
+        let key_id = scheme.key_id();
+        if !self.allowed_key_ids.is_empty() && !self.allowed_key_ids.iter().any(|k| k == key_id) {
+            return Err(format!("key {} is not allowed", key_id));
+        }
+        if scheme == SignatureScheme::Ecdsa
+            && !self.allowed_derivation_paths.is_empty()
+            && !self
+                .allowed_derivation_paths
+                .iter()
+                .any(|pattern| path_matches(pattern, path))
+        {
+            return Err(format!("derivation path {:?} is not allowed", path));
+        }
+        Ok(())
+    }
+
+    /// Checks a plain message signing request. A message can be the sighash of
+    /// any transaction, so clients whose transactions are constrained only
+    /// sign them through `/sign_psbt`.
+    pub fn check_message(&self, scheme: SignatureScheme, path: &str) -> Result<(), String> {
+        self.check_request(scheme, path)?;
+        if !self.allowed_destinations.is_empty() || self.max_transaction_value.is_some() {
+            return Err("plain messages are not signed under transaction constraints".to_string());
+        }
+        Ok(())
+    }
+
+    /// Checks the outputs of a transaction. Outputs locked to one of
+    /// `own_scripts` are change and exempt from both the destination list and
+    /// the value cap.
+    pub fn check_transaction(&self, psbt: &Psbt, own_scripts: &[ScriptBuf]) -> Result<(), String> {
+        let destinations = self.destination_scripts()?;
+        for (index, output) in psbt.unsigned_tx.output.iter().enumerate() {
+            if own_scripts.contains(&output.script_pubkey) {
+                continue;
+            }
+            if !destinations.is_empty() && !destinations.contains(&output.script_pubkey) {
+                return Err(format!(
+                    "output {} pays to a destination not allowed",
+                    index
+                ));
+            }
+        }
+        let value = transaction_value(psbt, own_scripts);
+        match self.max_transaction_value {
+            Some(max) if value > max => Err(format!(
+                "transaction sends {} sat, more than the {} sat allowed",
+                value, max
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn destination_scripts(&self) -> Result<Vec<ScriptBuf>, String> {
+        self.allowed_destinations
+            .iter()
+            .map(|address| {
+                Address::<NetworkUnchecked>::from_str(address)
+                    .map(|address| address.assume_checked().script_pubkey())
+                    .map_err(|_| format!("invalid destination address {}", address))
+            })
+            .collect()
+    }
+}
+
+/// Satoshis a transaction sends to other scripts than `own_scripts`
+pub fn transaction_value(psbt: &Psbt, own_scripts: &[ScriptBuf]) -> u64 {
+    psbt.unsigned_tx
+        .output
+        .iter()
+        .filter(|output| !own_scripts.contains(&output.script_pubkey))
+        .fold(0, |value, output| {
+            value.saturating_add(output.value.to_sat())
+        })
+}
@@ -0,0 +1,271 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use bitcoin::ecdsa::Signature;
+use bitcoin::psbt::Psbt;
+use bitcoin::script::Instruction;
+use bitcoin::secp256k1::ecdsa::Signature as EcdsaSignature;
+use bitcoin::secp256k1::Message;
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{PublicKey, Script, ScriptBuf};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::common::hd_keys::call_hd_key;
+use crate::common::scripts::owned_scripts;
+use crate::common::{
+    Approval, MessageStatus, SignatureData, SignatureScheme, SigningRequest, TransactionInput,
+};
+use crate::config::Settings;
+use crate::error::TssError;
+use crate::manager::policy::transaction_value;
+use crate::manager::ManagerService;
+
+/// Outcome of a PSBT signing request
+pub enum PsbtSigning {
+    /// The updated PSBT and the indices of the inputs that were signed
+    Signed {
+        psbt: String,
+        signed_inputs: Vec<usize>,
+    },
+    /// The transaction waits for approval under the request `request_id`. The
+    /// inputs are signed once it is approved, submitting the same PSBT again
+    /// then returns it signed.
+    AwaitingApproval { request_id: String },
+}
+
+/// Signs every input of a base64 encoded PSBT that is locked to the network key
+/// (or the child key at the configured derivation path). The transaction is
+/// checked against the signing policy of `client_id` first, and held back if
+/// the policy requires approval for it.
+pub async fn sign_psbt(
+    manager: &ManagerService,
+    settings: &Settings,
+    client_id: &str,
+    psbt_base64: &str,
+) -> Result<PsbtSigning> {
+    let mut psbt = parse_psbt(psbt_base64)?;
+    let public_key = tss_public_key(settings)?;
+    let timeout = Duration::from_secs(settings.signing_timeout);
+    let sighashes = input_sighashes(&psbt, &public_key)?;
+
+    // Inputs of a transaction held for approval are signed under ids derived
+    // from the transaction, so that submitting it again finds them. The
+    // transaction goes along for the signers to check what they sign.
+    let txid = psbt.unsigned_tx.compute_txid();
+    let requests: Vec<SigningRequest> = sighashes
+        .iter()
+        .map(|(index, message, _)| SigningRequest {
+            id: approval_request_id(client_id, &txid.to_string(), *index),
+            message: message.as_ref().to_vec(),
+            scheme: SignatureScheme::Ecdsa,
+            derivation_path: None,
+            client_id: Some(client_id.to_string()),
+            transaction: Some(TransactionInput {
+                psbt: psbt_base64.to_string(),
+                input: *index,
+            }),
+            approvals: Vec::new(),
+        })
+        .collect();
+    let held = match requests.first() {
+        Some(first) => manager.get_signing_result(&first.id).await?,
+        None => None,
+    };
+
+    // Every input signed counts as a signing request, once
+    let own_scripts = owned_scripts(&public_key);
+    let signatures = if held.is_some() { 0 } else { sighashes.len() };
+    let policy = manager
+        .enforce_policy(client_id, "/sign_psbt", signatures as u64, |policy| {
+            policy.check_request(SignatureScheme::Ecdsa, &settings.path)?;
+            policy.check_transaction(&psbt, &own_scripts)
+        })
+        .await?;
+
+    let value = transaction_value(&psbt, &own_scripts);
+    let required = policy.approvals_for_transaction(value);
+    let approved = match (held, requests.split_first()) {
+        (Some(held), _) => match held.status {
+            MessageStatus::AwaitingApproval => {
+                return Ok(PsbtSigning::AwaitingApproval {
+                    request_id: held.request_id,
+                })
+            }
+            MessageStatus::Rejected => {
+                return Err(TssError::PolicyDenied(format!(
+                    "transaction {} was rejected by the approvers",
+                    txid
+                ))
+                .into())
+            }
+            _ => true,
+        },
+        (None, Some((first, rest))) if required > 0 => {
+            let inputs: Vec<usize> = sighashes.iter().map(|(index, ..)| *index).collect();
+            let approval = Approval {
+                required,
+                summary: approval_summary(&psbt, &own_scripts, &inputs, value),
+                digest: String::new(),
+                decisions: Vec::new(),
+                batch: rest.to_vec(),
+            };
+            manager.request_approval(first.clone(), approval).await?;
+            return Ok(PsbtSigning::AwaitingApproval {
+                request_id: first.id.clone(),
+            });
+        }
+        _ => false,
+    };
+
+    // Signers pick requests from a fanout exchange one at a time, so the inputs
+    // are signed sequentially rather than published all at once
+    let mut signed_inputs = Vec::new();
+    for ((index, _, sighash_type), request) in sighashes.into_iter().zip(requests) {
+        info!("Signing PSBT input {}", index);
+        let signature_data = if approved {
+            manager.wait_for_signature(&request.id, timeout).await?
+        } else {
+            let request = SigningRequest {
+                id: uuid::Uuid::new_v4().to_string(),
+                ..request
+            };
+            manager.sign_and_wait(request, timeout).await?
+        };
+        let signature = Signature {
+            signature: to_low_s_signature(&signature_data)?,
+            sighash_type,
+        };
+        psbt.inputs[index]
+            .partial_sigs
+            .insert(public_key, signature);
+        signed_inputs.push(index);
+    }
+
+    Ok(PsbtSigning::Signed {
+        psbt: psbt.to_string(),
+        signed_inputs,
+    })
+}
+
+/// Decodes a base64 encoded PSBT
+pub fn parse_psbt(psbt_base64: &str) -> Result<Psbt> {
+    Ok(Psbt::from_str(psbt_base64).map_err(|e| TssError::PsbtError(e.to_string()))?)
+}
+
+/// Sighashes of the inputs of `psbt` locked to `public_key`, with the index of
+/// the input and the sighash type to sign it with. Inputs locked to other keys
+/// are skipped, an input that cannot be hashed fails the whole PSBT.
+pub fn input_sighashes(
+    psbt: &Psbt,
+    public_key: &PublicKey,
+) -> Result<Vec<(usize, Message, EcdsaSighashType)>> {
+    // The cache holds its own copy of the transaction
+    let mut cache = SighashCache::new(psbt.unsigned_tx.clone());
+    let mut sighashes = Vec::new();
+    for index in 0..psbt.inputs.len() {
+        if !input_uses_key(psbt, index, public_key) {
+            continue;
+        }
+        let (message, sighash_type) = psbt
+            .sighash_ecdsa(index, &mut cache)
+            .map_err(|e| TssError::PsbtError(format!("input {}: {}", index, e)))?;
+        sighashes.push((index, message, sighash_type));
+    }
+    Ok(sighashes)
+}
+
+/// Request id of an input of a transaction held for approval, a version 8 UUID
+/// holding the start of a SHA-256 digest
+pub fn approval_request_id(client_id: &str, txid: &str, index: usize) -> String {
+    let digest = Sha256::digest(format!("{}:{}:{}", client_id, txid, index).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    uuid::Builder::from_custom_bytes(bytes)
+        .into_uuid()
+        .to_string()
+}
+
+// What the approvers are asked to approve
+fn approval_summary(
+    psbt: &Psbt,
+    own_scripts: &[ScriptBuf],
+    inputs: &[usize],
+    value: u64,
+) -> String {
+    let outputs: Vec<String> = psbt
+        .unsigned_tx
+        .output
+        .iter()
+        .filter(|output| !own_scripts.contains(&output.script_pubkey))
+        .map(|output| format!("{} sat to {}", output.value.to_sat(), output.script_pubkey))
+        .collect();
+    let inputs: Vec<String> = inputs.iter().map(usize::to_string).collect();
+    format!(
+        "Transaction {} sending {} sat ({}), signing inputs {}",
+        psbt.unsigned_tx.compute_txid(),
+        value,
+        outputs.join(", "),
+        inputs.join(", ")
+    )
+}
+
+fn tss_public_key(settings: &Settings) -> Result<PublicKey> {
+    let mut y_sum = settings
+        .network_public_key()
+        .map_err(TssError::ConfigError)?;
+    if !settings.path.is_empty() {
+        (_, y_sum) = call_hd_key(&settings.path, y_sum);
+    }
+    Ok(PublicKey::from_slice(&y_sum.to_bytes(true))
+        .map_err(|e| TssError::PsbtError(e.to_string()))?)
+}
+
+fn input_uses_key(psbt: &Psbt, index: usize, public_key: &PublicKey) -> bool {
+    let input = &psbt.inputs[index];
+    if input.bip32_derivation.contains_key(&public_key.inner) {
+        return true;
+    }
+
+    let script_pubkey = match (&input.witness_utxo, &input.non_witness_utxo) {
+        (Some(utxo), _) => utxo.script_pubkey.clone(),
+        (None, Some(tx)) => {
+            let vout = psbt.unsigned_tx.input[index].previous_output.vout as usize;
+            match tx.output.get(vout) {
+                Some(output) => output.script_pubkey.clone(),
+                None => return false,
+            }
+        }
+        (None, None) => return false,
+    };
+
+    let redeem_script = input.redeem_script.as_ref();
+    if owned_scripts(public_key)
+        .iter()
+        .any(|s| *s == script_pubkey || Some(s) == redeem_script)
+    {
+        return true;
+    }
+
+    [redeem_script, input.witness_script.as_ref()]
+        .into_iter()
+        .flatten()
+        .any(|script| script_pushes_key(script, public_key))
+}
+
+fn script_pushes_key(script: &Script, public_key: &PublicKey) -> bool {
+    let key_bytes = public_key.to_bytes();
+    script.instructions().any(|instruction| {
+        matches!(instruction, Ok(Instruction::PushBytes(bytes)) if bytes.as_bytes() == key_bytes.as_slice())
+    })
+}
+
+fn to_low_s_signature(signature: &SignatureData) -> Result<EcdsaSignature> {
+    let der = hex::decode(&signature.der).map_err(|e| TssError::SigningError(e.to_string()))?;
+    let mut signature =
+        EcdsaSignature::from_der(&der).map_err(|e| TssError::SigningError(e.to_string()))?;
+    // Already low-s when produced by the signers, normalizing keeps the PSBT standard regardless
+    signature.normalize_s();
+    Ok(signature)
+}
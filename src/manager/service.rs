@@ -1,22 +1,65 @@
+use crate::auth::{ApiClient, IssuedToken, JwtKeys, Role};
+use crate::common::approval::{approval_digest, verify_approval};
+use crate::common::frost::{Ciphersuite, Ed25519Sha512, Secp256k1Bip340};
+use crate::common::hd_keys::{call_hd_key, is_valid_path};
+use crate::common::secp256k1def::GE;
+use crate::common::signature::verify_signature_data;
 use crate::common::{
-    Key, KeyGenRequest, KeysToStore, MessageToSignStored, SignerResult, SigningRequest,
+    parse_round_key, signing_room_id, Approval, ApprovalDecision, ApprovalSignature, Backoff,
+    DeadLetter, DeadLetterStored, InMemoryTransport, KeyGenRequest, KeysToStore, MessageStatus,
+    MessageToSignStored, PolicyDenial, RoomState, SignatureData, SignatureScheme, SignerResult,
+    SigningFailure, SigningProgress, SigningRefusal, SigningRequest, SigningRequestFilter,
+    SigningRoom, ROOM_UUID_INDEX_PREFIX, SIGNING_ROOM_PREFIX,
 };
-use crate::queue::rabbitmq::RabbitMQService;
-use crate::storage::mongodb::MongoDBStorage;
+use crate::config::{
+    QueueConfig, RoomStoreBackend, RoomStoreConfig, Settings, StorageBackend, StorageConfig,
+};
+use crate::error::TssError;
+use crate::queue::{self, RequestQueue};
+use crate::storage::mongodb::{MongoDBStorage, MongoRoomStore};
+use crate::storage::now_millis;
+use crate::storage::room_store::{InMemoryRoomStore, RoomStore, Swap};
+use crate::storage::sled::SledStorage;
+use crate::storage::Storage;
 use anyhow::Result;
 use futures::future::join_all;
-use std::collections::HashMap;
+use mongodb::Client;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::task;
-use tracing::info;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+use super::constants::{
+    DAILY_QUOTA_WINDOW_MS, DEAD_LETTER_RETRY_INTERVAL_MS, MAX_ROOM_UPDATE_ATTEMPTS,
+    MAX_ROOM_UPDATE_RETRY_DELAY_MS, RATE_LIMIT_WINDOW_MS, ROOM_UPDATE_RETRY_DELAY_MS,
+    SIGNING_RESULT_POLL_INTERVAL_MS,
+};
+use super::keygen::{frost_keygen, keygen};
+use super::policy::SigningPolicy;
 
-use super::keygen::run_keygen;
+/// A signing room as read from the room store, updated with a compare and swap
+/// against the value it was read from
+pub struct StoredRoom {
+    key: String,
+    stored: String,
+    room: SigningRoom,
+}
+
+// Waits between compare and swaps of a room other replicas keep writing
+pub(crate) fn room_update_backoff() -> Backoff {
+    Backoff::new(
+        Duration::from_millis(ROOM_UPDATE_RETRY_DELAY_MS),
+        Duration::from_millis(MAX_ROOM_UPDATE_RETRY_DELAY_MS),
+    )
+}
 
 pub struct ManagerService {
-    pub storage: MongoDBStorage,
-    pub queue: RabbitMQService,
-    pub(crate) signing_rooms: Arc<RwLock<HashMap<Key, String>>>,
+    pub storage: Arc<dyn Storage>,
+    pub queue: Arc<dyn RequestQueue>,
+    pub(crate) room_store: Arc<dyn RoomStore>,
+    sweep_interval: Duration,
+    finished_grace: Duration,
     pub threshold: u16,
     pub total_parties: u16,
 }
@@ -27,14 +70,43 @@ impl ManagerService {
         rabbitmq_uri: &str,
         threshold: u16,
         total_parties: u16,
+        storage_config: &StorageConfig,
+        room_store_config: &RoomStoreConfig,
+        queue_config: &QueueConfig,
     ) -> Result<Self> {
-        let storage = MongoDBStorage::new(mongodb_uri, "tss_network").await?;
-        let queue = RabbitMQService::new(rabbitmq_uri).await?;
+        // One client, and its connection pool, for every MongoDB backend
+        let uses_mongodb = storage_config.backend == StorageBackend::Mongodb
+            || room_store_config.backend == RoomStoreBackend::Mongodb;
+        let mongodb = if uses_mongodb {
+            Some(
+                Client::with_uri_str(mongodb_uri)
+                    .await?
+                    .database("tss_network"),
+            )
+        } else {
+            None
+        };
+        let storage: Arc<dyn Storage> = match (&storage_config.backend, &mongodb) {
+            (StorageBackend::Mongodb, Some(db)) => {
+                Arc::new(MongoDBStorage::with_database(db).await?)
+            }
+            _ => Arc::new(SledStorage::new(&storage_config.path)?),
+        };
+        let queue = queue::connect(queue_config, rabbitmq_uri).await?;
+        let room_ttl = Duration::from_secs(room_store_config.ttl_seconds);
+        let room_store: Arc<dyn RoomStore> = match (&room_store_config.backend, &mongodb) {
+            (RoomStoreBackend::Mongodb, Some(db)) => {
+                Arc::new(MongoRoomStore::new(db, room_ttl).await?)
+            }
+            _ => Arc::new(InMemoryRoomStore::new(room_ttl)),
+        };
 
         Ok(Self {
             storage,
             queue,
-            signing_rooms: Arc::new(RwLock::new(HashMap::new())),
+            room_store,
+            sweep_interval: Duration::from_secs(room_store_config.sweep_interval_seconds),
+            finished_grace: Duration::from_secs(room_store_config.finished_grace_seconds),
             threshold,
             total_parties,
         })
@@ -42,20 +114,96 @@ impl ManagerService {
 
     pub async fn run(&self) -> Result<()> {
         info!("Starting ManagerService");
+        // Signing requests are driven by the HTTP API, only background work runs here
+        tokio::join!(self.run_sweeper(), self.run_dead_letter_consumer());
+        Ok(())
+    }
+
+    async fn run_sweeper(&self) {
+        let mut interval = tokio::time::interval(self.sweep_interval);
         loop {
-            // match self.queue.receive_signing_request().await {
-            //     Ok(request) => {
-            //         if let Err(e) = self.handle_signing_request(request).await {
-            //             error!("Failed to handle signing request: {:?}", e);
-            //         }
-            //     }
-            //     Err(e) => {
-            //         error!("Failed to receive signing request: {:?}", e);
-            //     }
-            // }
+            interval.tick().await;
+            if let Err(e) = self.sweep_rooms().await {
+                error!("Error sweeping signing rooms: {:?}", e);
+            }
+        }
+    }
+
+    // Stores the requests signers gave up on until an operator replays them
+    async fn run_dead_letter_consumer(&self) {
+        loop {
+            let received = match self.queue.receive_dead_letter().await {
+                Ok(received) => received,
+                Err(e) => {
+                    error!("Error receiving dead letter: {:?}", e);
+                    tokio::time::sleep(Duration::from_millis(DEAD_LETTER_RETRY_INTERVAL_MS)).await;
+                    continue;
+                }
+            };
+            let letter = &received.letter;
+            info!(
+                "Signing request {} dead-lettered by party {}: {}",
+                letter.request.id, letter.failure.party_id, letter.failure.error
+            );
+            let settled = match self.storage.insert_dead_letter(letter).await {
+                Ok(()) => {
+                    metrics::counter!("tss_dead_letters_total").increment(1);
+                    self.queue.ack(received.delivery_tag).await
+                }
+                Err(e) => {
+                    error!("Error storing dead letter: {:?}", e);
+                    self.queue.nack(received.delivery_tag, true).await
+                }
+            };
+            if let Err(e) = settled {
+                error!("Error acknowledging dead letter: {:?}", e);
+            }
         }
     }
 
+    /// Garbage collects the rooms that finished more than the grace period ago
+    /// along with their round messages, then every entry idle past the store TTL
+    pub async fn sweep_rooms(&self) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        for (key, value) in self.room_store.scan_prefix(SIGNING_ROOM_PREFIX).await? {
+            let room: SigningRoom = match serde_json::from_str(&value) {
+                Ok(room) => room,
+                Err(_) => continue,
+            };
+            let reapable = match room.closed_at {
+                Some(closed_at) => closed_at + self.finished_grace.as_secs() <= now,
+                None => false,
+            };
+            // Skip the room if a signup reopened it since the scan
+            if reapable && self.room_store.remove_if(&key, &value).await? {
+                self.reap_room_messages(&room).await?;
+            }
+        }
+
+        let expired = self.room_store.purge_expired().await?;
+        if expired > 0 {
+            info!("Reaped {} expired room entries", expired);
+        }
+        metrics::counter!("tss_room_keys_reaped_total", "reason" => "expired").increment(expired);
+        Ok(())
+    }
+
+    /// Removes the round messages relayed for `room`, counted under its last stage
+    pub(crate) async fn reap_room_messages(&self, room: &SigningRoom) -> Result<()> {
+        let keys = self
+            .room_store
+            .remove_suffix(&format!("-{}", room.room_uuid))
+            .await?;
+        info!(
+            "Reaped {} room {} with {} round messages",
+            room.state, room.room_id, keys
+        );
+        metrics::counter!("tss_rooms_reaped_total", "state" => room.state.name()).increment(1);
+        metrics::counter!("tss_room_keys_reaped_total", "reason" => room.state.name())
+            .increment(keys);
+        Ok(())
+    }
+
     pub async fn get_signing_result(
         &self,
         request_id: &str,
@@ -63,8 +211,239 @@ impl ManagerService {
         self.storage.get_signing_result(request_id).await
     }
 
-    pub async fn update_signing_result(&self, result: SignerResult) -> Result<()> {
-        self.storage.update_signing_result(&result).await
+    /// Stores the signature a signer reports once it verifies against the
+    /// request's message and the network key of its scheme
+    pub async fn update_signing_result(
+        &self,
+        result: SignerResult,
+        public_keys: &SchemePublicKeys,
+    ) -> Result<()> {
+        let request = self
+            .storage
+            .get_signing_result(&result.request_id)
+            .await?
+            .ok_or_else(|| TssError::NotFoundError(result.request_id.clone()))?;
+        let public_key = public_keys.for_request(&request)?;
+        if let Err(e) = verify_signature_data(
+            &result.signature,
+            request.scheme,
+            &request.message,
+            &public_key,
+        ) {
+            warn!(
+                "Party {} reported an invalid signature for request {}: {}",
+                result.party_id, result.request_id, e
+            );
+            metrics::counter!("tss_invalid_signatures_total").increment(1);
+            return Err(e.into());
+        }
+        if let Some(signature) = &request.signature {
+            if signature.r != result.signature.r || signature.s != result.signature.s {
+                warn!(
+                    "Party {} reported another signature for completed request {}",
+                    result.party_id, result.request_id
+                );
+            }
+        }
+
+        self.storage.update_signing_result(&result).await?;
+        self.finish_signing_room(&signing_room_id(request.scheme, &request.request_id))
+            .await
+    }
+
+    pub async fn get_signing_room(&self, room_id: &str) -> Result<Option<SigningRoom>> {
+        let key = format!("{}{}", SIGNING_ROOM_PREFIX, room_id);
+        match self.room_store.get(&key).await? {
+            Some(stored) => Ok(Some(serde_json::from_str(&stored)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn signing_room_by_uuid(&self, room_uuid: &str) -> Result<Option<StoredRoom>> {
+        let index = format!("{}{}", ROOM_UUID_INDEX_PREFIX, room_uuid);
+        let Some(room_id) = self.room_store.get(&index).await? else {
+            return Ok(None);
+        };
+        let key = format!("{}{}", SIGNING_ROOM_PREFIX, room_id);
+        let Some(stored) = self.room_store.get(&key).await? else {
+            return Ok(None);
+        };
+        let room: SigningRoom = serde_json::from_str(&stored)?;
+        if room.room_uuid != room_uuid {
+            return Ok(None);
+        }
+        Ok(Some(StoredRoom { key, stored, room }))
+    }
+
+    /// Checks that the signer `party_id` may access the round message `key`:
+    /// it is a member of the room of the key and, for a write, the sender.
+    /// Returns the room read for the check.
+    pub async fn authorize_round_key(
+        &self,
+        key: &str,
+        party_id: u16,
+        write: bool,
+    ) -> Result<StoredRoom> {
+        let (sender, _, room_uuid) = parse_round_key(key).ok_or_else(|| {
+            TssError::AuthorizationError(format!("{} is not a round message key", key))
+        })?;
+        let stored_room = self
+            .signing_room_by_uuid(room_uuid)
+            .await?
+            .ok_or_else(|| TssError::AuthorizationError(format!("Unknown room {}", room_uuid)))?;
+        let member = stored_room.room.member_info.get(&party_id).ok_or_else(|| {
+            TssError::AuthorizationError(format!(
+                "Party {} is not a member of room {}",
+                party_id, room_uuid
+            ))
+        })?;
+        if write && member.party_order != sender {
+            return Err(TssError::AuthorizationError(format!(
+                "Party {} cannot post messages of party order {}",
+                party_id, sender
+            ))
+            .into());
+        }
+        Ok(stored_room)
+    }
+
+    /// Tracks which party posted which round from the key of a relayed message,
+    /// starting from `room` as read when the message was authorized
+    pub(crate) async fn record_round_message(&self, key: &str, room: StoredRoom) -> Result<()> {
+        let Some((party_order, round, room_uuid)) = parse_round_key(key) else {
+            return Ok(());
+        };
+        let StoredRoom {
+            key,
+            mut stored,
+            mut room,
+        } = room;
+        let mut backoff = room_update_backoff();
+        for _ in 0..MAX_ROOM_UPDATE_ATTEMPTS {
+            if room.room_uuid != room_uuid || !room.record_round(round, party_order) {
+                return Ok(());
+            }
+            let updated = serde_json::to_string(&room)?;
+            match self
+                .room_store
+                .compare_and_swap(&key, Some(&stored), &updated)
+                .await?
+            {
+                Swap::Written | Swap::Conflict(None) => return Ok(()),
+                Swap::Conflict(Some(current)) => stored = current,
+            }
+            room = serde_json::from_str(&stored)?;
+            backoff.wait().await;
+        }
+        Err(TssError::RoomContention(key).into())
+    }
+
+    // Every signer reports the signature, the first report closes the room.
+    // A room still in signup cannot have produced this signature.
+    async fn finish_signing_room(&self, room_id: &str) -> Result<()> {
+        self.close_signing_room(room_id, RoomState::Completed)
+            .await?;
+        Ok(())
+    }
+
+    // Moves the room to the closed state `state`, returns the room if this call closed it
+    async fn close_signing_room(
+        &self,
+        room_id: &str,
+        state: RoomState,
+    ) -> Result<Option<SigningRoom>> {
+        let key = format!("{}{}", SIGNING_ROOM_PREFIX, room_id);
+        let mut stored = self.room_store.get(&key).await?;
+        let mut backoff = room_update_backoff();
+        for _ in 0..MAX_ROOM_UPDATE_ATTEMPTS {
+            let Some(current) = stored else {
+                return Ok(None);
+            };
+            let mut room: SigningRoom = serde_json::from_str(&current)?;
+            if room.transition(state).is_err() {
+                return Ok(None);
+            }
+            let updated = serde_json::to_string(&room)?;
+            match self
+                .room_store
+                .compare_and_swap(&key, Some(&current), &updated)
+                .await?
+            {
+                Swap::Written => return Ok(Some(room)),
+                Swap::Conflict(found) => stored = found,
+            }
+            backoff.wait().await;
+        }
+        Err(TssError::RoomContention(key).into())
+    }
+
+    pub async fn list_dead_letters(&self, limit: i64) -> Result<Vec<DeadLetterStored>> {
+        self.storage.list_dead_letters(limit).await
+    }
+
+    /// Publishes dead-lettered requests again, each in a fresh signing room.
+    /// Returns the ids replayed, requests signed in the meantime are dropped.
+    pub async fn replay_dead_letters(&self, request_ids: &[String]) -> Result<Vec<String>> {
+        let mut replayed = Vec::new();
+        for request_id in request_ids {
+            // Taken once published, a replay failing before that keeps it
+            let Some(dead_letter) = self.storage.get_dead_letter(request_id).await? else {
+                continue;
+            };
+            let request = dead_letter.request;
+            if let Some(stored) = self.storage.get_signing_result(&request.id).await? {
+                if stored.status == MessageStatus::Completed {
+                    self.storage.take_dead_letter(&request.id).await?;
+                    continue;
+                }
+            }
+
+            // Signers of the failed attempt may still hold the room open
+            let room_id = signing_room_id(request.scheme, &request.id);
+            if let Some(room) = self
+                .close_signing_room(&room_id, RoomState::Aborted)
+                .await?
+            {
+                self.reap_room_messages(&room).await?;
+            }
+            self.queue.publish_signing_request(&request).await?;
+            self.storage.take_dead_letter(&request.id).await?;
+            info!("Replayed dead-lettered signing request {}", request.id);
+            metrics::counter!("tss_dead_letters_replayed_total").increment(1);
+            replayed.push(request.id);
+        }
+        Ok(replayed)
+    }
+
+    pub async fn list_signing_requests(
+        &self,
+        filter: &SigningRequestFilter,
+        limit: i64,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<MessageToSignStored>, Option<String>)> {
+        self.storage
+            .list_signing_requests(filter, limit, cursor)
+            .await
+    }
+
+    pub async fn update_signing_progress(&self, progress: SigningProgress) -> Result<()> {
+        self.storage.update_signing_progress(&progress).await
+    }
+
+    /// Records a signer refusing a request under its local policy. The request
+    /// is rejected once too few signers are left to reach the threshold.
+    pub async fn record_signing_refusal(&self, refusal: SigningRefusal) -> Result<()> {
+        warn!(
+            "Signer {} refused signing request {}: {}",
+            refusal.party_id, refusal.request_id, refusal.reason
+        );
+        metrics::counter!("tss_signing_refusals_total").increment(1);
+        // Signing takes threshold + 1 parties, the others may refuse
+        let tolerated =
+            usize::from(self.total_parties).saturating_sub(usize::from(self.threshold) + 1);
+        self.storage
+            .record_signing_refusal(&refusal, tolerated)
+            .await
     }
 
     pub async fn process_signing_request(&self, request: SigningRequest) -> Result<()> {
@@ -73,18 +452,284 @@ impl ManagerService {
         Ok(())
     }
 
-    pub async fn process_keygen_request(
+    /// Stores a request that is published to the signers once `approval` is met
+    pub async fn request_approval(
+        &self,
+        request: SigningRequest,
+        mut approval: Approval,
+    ) -> Result<()> {
+        let digest = approval_digest(&request).map_err(TssError::SigningError)?;
+        approval.digest = hex::encode(digest);
+        self.storage
+            .insert_request_for_approval(&request, &approval)
+            .await?;
+        info!(
+            "Signing request {} awaits {} approvals: {}",
+            request.id, approval.required, approval.summary
+        );
+        metrics::counter!("tss_approvals_requested_total").increment(1);
+        Ok(())
+    }
+
+    /// Records the decision of `approver` on a request awaiting approval and
+    /// publishes the request, with its batch, once the last approval needed is in.
+    /// A `signature` over the approval digest is handed to the signers along.
+    pub async fn decide_approval(
         &self,
-        request: KeyGenRequest,
-        manager_addr: &String,
-    ) -> Result<Vec<String>> {
+        request_id: &str,
+        approver: &str,
+        approved: bool,
+        comment: Option<String>,
+        signature: Option<ApprovalSignature>,
+    ) -> Result<MessageToSignStored> {
+        if let Some(signature) = &signature {
+            let digest = self
+                .storage
+                .get_signing_result(request_id)
+                .await?
+                .and_then(|request| request.approval)
+                .and_then(|approval| <[u8; 32]>::try_from(hex::decode(approval.digest).ok()?).ok())
+                .ok_or_else(|| TssError::NotFoundError(request_id.to_string()))?;
+            verify_approval(&digest, signature).map_err(TssError::InvalidSignature)?;
+        }
+        let decision = ApprovalDecision {
+            approver: approver.to_string(),
+            approved,
+            comment,
+            signature,
+            at: now_millis(),
+        };
+        let Some(request) = self
+            .storage
+            .record_approval_decision(request_id, &decision)
+            .await?
+        else {
+            return match self.storage.get_signing_result(request_id).await? {
+                Some(_) => Err(TssError::ApprovalError(format!(
+                    "Request {} is not awaiting approval from {}",
+                    request_id, approver
+                ))
+                .into()),
+                None => Err(TssError::NotFoundError(request_id.to_string()).into()),
+            };
+        };
+        info!(
+            "Approver {} {} signing request {}",
+            approver,
+            if approved { "approved" } else { "rejected" },
+            request_id
+        );
+        match request.status {
+            MessageStatus::Pending => {
+                metrics::counter!("tss_approvals_granted_total").increment(1);
+                self.release_approved(&request).await?;
+            }
+            MessageStatus::Rejected => {
+                metrics::counter!("tss_approvals_rejected_total").increment(1);
+            }
+            _ => {}
+        }
+        Ok(request)
+    }
+
+    // Publishes an approved request and its batch. A request that cannot be
+    // published is dead-lettered, so that an operator can replay it.
+    async fn release_approved(&self, stored: &MessageToSignStored) -> Result<()> {
+        let Some(approval) = &stored.approval else {
+            return Ok(());
+        };
+        // Signers with a local policy verify the approvals themselves
+        let approvals: Vec<ApprovalSignature> = approval
+            .decisions
+            .iter()
+            .filter(|decision| decision.approved)
+            .filter_map(|decision| decision.signature.clone())
+            .collect();
+        let request = SigningRequest {
+            id: stored.request_id.clone(),
+            message: stored.message.clone(),
+            scheme: stored.scheme,
+            derivation_path: stored.derivation_path.clone(),
+            client_id: stored.client_id.clone(),
+            transaction: stored.transaction.clone(),
+            approvals: approvals.clone(),
+        };
+        let mut requests = vec![request];
+        for member in &approval.batch {
+            match self.storage.insert_request(member).await {
+                Ok(()) => {}
+                Err(e) if matches!(e.downcast_ref(), Some(TssError::DuplicateRequest(_))) => {}
+                Err(e) => return Err(e),
+            }
+            requests.push(SigningRequest {
+                approvals: approvals.clone(),
+                ..member.clone()
+            });
+        }
+        for request in requests {
+            if let Err(e) = self.queue.publish_signing_request(&request).await {
+                error!("Error publishing approved request {}: {:?}", request.id, e);
+                let letter = DeadLetter {
+                    failure: SigningFailure {
+                        party_id: 0,
+                        error: format!("Publishing the approved request failed: {}", e),
+                        failed_at: now_millis(),
+                    },
+                    request,
+                };
+                self.storage.insert_dead_letter(&letter).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Submits `request` to the signers and waits until the signature is stored
+    pub async fn sign_and_wait(
+        &self,
+        request: SigningRequest,
+        timeout: Duration,
+    ) -> Result<SignatureData> {
+        self.process_signing_request(request.clone()).await?;
+        self.wait_for_signature(&request.id, timeout).await
+    }
+
+    /// Waits until the signature of a submitted request is stored
+    pub async fn wait_for_signature(
+        &self,
+        request_id: &str,
+        timeout: Duration,
+    ) -> Result<SignatureData> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(stored) = self.storage.get_signing_result(request_id).await? {
+                if stored.status == MessageStatus::Rejected {
+                    let reason = stored
+                        .status_history
+                        .last()
+                        .and_then(|change| change.reason.clone())
+                        .unwrap_or_else(|| "no reason given".to_string());
+                    return Err(TssError::SigningError(format!(
+                        "Signing request {} was rejected: {}",
+                        request_id, reason
+                    ))
+                    .into());
+                }
+                if let (MessageStatus::Completed, Some(signature)) =
+                    (stored.status, stored.signature)
+                {
+                    return Ok(signature);
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(TssError::TimeoutError.into());
+            }
+            tokio::time::sleep(Duration::from_millis(SIGNING_RESULT_POLL_INTERVAL_MS)).await;
+        }
+    }
+
+    /// Checks a submission of `client_id` to `endpoint` against the client's
+    /// signing policy, `check` covering what is signed and `signatures` being the
+    /// number of signing requests it results in. Returns the policy, tokens not
+    /// issued to an API client are not restricted. Denials are recorded and
+    /// returned as `TssError::PolicyDenied`.
+    pub async fn enforce_policy<F>(
+        &self,
+        client_id: &str,
+        endpoint: &str,
+        signatures: u64,
+        check: F,
+    ) -> Result<SigningPolicy>
+    where
+        F: FnOnce(&SigningPolicy) -> Result<(), String>,
+    {
+        let Some(client) = self.storage.get_api_client(client_id).await? else {
+            return Ok(SigningPolicy::default());
+        };
+        let reason = match check(&client.policy) {
+            Ok(()) => match self.check_rate_limits(&client, signatures).await? {
+                Some(reason) => reason,
+                None => return Ok(client.policy),
+            },
+            Err(reason) => reason,
+        };
+
+        warn!(
+            "Denied {} request of API client {}: {}",
+            endpoint, client_id, reason
+        );
+        metrics::counter!("tss_policy_denials_total").increment(1);
+        let denial = PolicyDenial {
+            client_id: client_id.to_string(),
+            endpoint: endpoint.to_string(),
+            reason: reason.clone(),
+            denied_at: now_millis(),
+        };
+        self.storage.insert_policy_denial(&denial).await?;
+        Err(TssError::PolicyDenied(reason).into())
+    }
+
+    // Limits are counted over the stored requests of the client, submissions
+    // racing each other may both pass
+    async fn check_rate_limits(
+        &self,
+        client: &ApiClient,
+        signatures: u64,
+    ) -> Result<Option<String>> {
+        let now = now_millis();
+        let limits = [
+            (
+                client.policy.max_requests_per_minute,
+                RATE_LIMIT_WINDOW_MS,
+                "rate limit",
+            ),
+            (
+                client.policy.daily_quota,
+                DAILY_QUOTA_WINDOW_MS,
+                "daily quota",
+            ),
+        ];
+        for (limit, window, name) in limits {
+            let Some(limit) = limit else {
+                continue;
+            };
+            let submitted = self
+                .storage
+                .count_client_requests(&client.client_id, now.saturating_sub(window))
+                .await?;
+            if submitted + signatures > u64::from(limit) {
+                return Ok(Some(format!(
+                    "{} of {} requests exceeded, {} submitted",
+                    name, limit, submitted
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    pub async fn list_policy_denials(&self, limit: i64) -> Result<Vec<PolicyDenial>> {
+        self.storage.list_policy_denials(limit).await
+    }
+
+    /// Runs every party of the key generation in this process, returns the key
+    /// share of each party
+    pub async fn process_keygen_request(&self, request: KeyGenRequest) -> Result<Vec<String>> {
         self.storage.insert_key_gen_request(&request).await?;
-        let total_parties = request.keygen_params.parties;
-        let tasks: Vec<_> = (0..total_parties)
-            .map(|_| {
-                let manager_addr = manager_addr.clone();
-                let request = request.clone();
-                task::spawn(async move { run_keygen(&manager_addr, &request).await })
+        let params = request.keygen_params.clone();
+        let tasks: Vec<_> = InMemoryTransport::session(params.parties)
+            .into_iter()
+            .map(|transport| {
+                let params = params.clone();
+                task::spawn(async move {
+                    match params.scheme {
+                        SignatureScheme::Ecdsa => keygen(&transport, params.threshold).await,
+                        SignatureScheme::Schnorr => {
+                            frost_keygen::<Secp256k1Bip340, _>(&transport, params.threshold).await
+                        }
+                        SignatureScheme::Eddsa => {
+                            frost_keygen::<Ed25519Sha512, _>(&transport, params.threshold).await
+                        }
+                    }
+                })
             })
             .collect();
 
@@ -115,4 +760,200 @@ impl ManagerService {
     pub async fn get_key_gen_result(&self, request_id: &str) -> Result<Option<KeysToStore>> {
         self.storage.get_key_gen_result(request_id).await
     }
+
+    /// Registers an API client and issues its first token
+    pub async fn create_api_client(
+        &self,
+        name: &str,
+        role: Role,
+        token_expiration: u64,
+        policy: SigningPolicy,
+        keys: &JwtKeys,
+    ) -> Result<(ApiClient, IssuedToken)> {
+        if role == Role::Signer {
+            return Err(TssError::AuthError("Signers do not use API tokens".into()).into());
+        }
+        let client = ApiClient {
+            client_id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            role,
+            token_expiration,
+            created_at: now_millis(),
+            revoked_at: None,
+            tokens: Vec::new(),
+            policy,
+        };
+        self.storage.insert_api_client(&client).await?;
+        let token = self
+            .issue_api_token(&client.client_id, keys)
+            .await?
+            .ok_or_else(|| TssError::NotFoundError(client.client_id.clone()))?;
+        Ok((client, token))
+    }
+
+    /// Issues a new token to an active client, `None` if it is unknown or revoked
+    pub async fn issue_api_token(
+        &self,
+        client_id: &str,
+        keys: &JwtKeys,
+    ) -> Result<Option<IssuedToken>> {
+        let jti = uuid::Uuid::new_v4().to_string();
+        // Recorded first, so that revoking the client also revokes this token
+        let Some(client) = self.storage.add_api_client_token(client_id, &jti).await? else {
+            return Ok(None);
+        };
+        let token = keys.issue(
+            &client.client_id,
+            client.role,
+            client.token_expiration,
+            &jti,
+        )?;
+        info!("Issued token {} to API client {}", jti, client_id);
+        Ok(Some(token))
+    }
+
+    pub async fn list_api_clients(&self) -> Result<Vec<ApiClient>> {
+        self.storage.list_api_clients().await
+    }
+
+    /// Replaces the signing policy of a client, `None` if it is unknown
+    pub async fn set_api_client_policy(
+        &self,
+        client_id: &str,
+        policy: &SigningPolicy,
+    ) -> Result<Option<ApiClient>> {
+        let client = self
+            .storage
+            .set_api_client_policy(client_id, policy)
+            .await?;
+        if client.is_some() {
+            info!("Updated the signing policy of API client {}", client_id);
+        }
+        Ok(client)
+    }
+
+    pub async fn revoke_api_client(&self, client_id: &str) -> Result<bool> {
+        let revoked = self.storage.revoke_api_client(client_id).await?;
+        if revoked {
+            info!("Revoked API client {}", client_id);
+        }
+        Ok(revoked)
+    }
+
+    pub async fn revoke_token(&self, jti: &str) -> Result<()> {
+        self.storage.revoke_token(jti).await?;
+        info!("Revoked token {}", jti);
+        Ok(())
+    }
+
+    pub async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        self.storage.is_token_revoked(jti).await
+    }
+}
+
+/// Network keys the signatures of each scheme verify against, resolved from
+/// the settings once at startup so that a malformed key stops the manager
+/// rather than failing every request of its scheme
+#[derive(Debug, Clone)]
+pub struct SchemePublicKeys {
+    ecdsa: Option<GE>,
+    path: String,
+    schnorr: Option<String>,
+    eddsa: Option<String>,
+}
+
+impl SchemePublicKeys {
+    /// Parses the configured keys and the default derivation path. An empty
+    /// key leaves its scheme without one, its results are then rejected.
+    pub fn load(settings: &Settings) -> Result<Self, TssError> {
+        let ecdsa = match configured(&settings.public_key) {
+            Some(_) => Some(
+                settings
+                    .network_public_key()
+                    .map_err(TssError::ConfigError)?,
+            ),
+            None => None,
+        };
+        if !settings.path.is_empty() && !is_valid_path(&settings.path) {
+            return Err(config_error(format!(
+                "invalid derivation path {}",
+                settings.path
+            )));
+        }
+        let keys = Self {
+            ecdsa,
+            path: settings.path.clone(),
+            schnorr: frost_public_key::<Secp256k1Bip340>(&settings.schnorr_public_key)?,
+            eddsa: frost_public_key::<Ed25519Sha512>(&settings.eddsa_public_key)?,
+        };
+        for scheme in [
+            SignatureScheme::Ecdsa,
+            SignatureScheme::Schnorr,
+            SignatureScheme::Eddsa,
+        ] {
+            if !keys.is_configured(scheme) {
+                warn!(
+                    "No {}_public_key configured, {:?} signatures cannot be verified",
+                    scheme.key_id(),
+                    scheme
+                );
+            }
+        }
+        Ok(keys)
+    }
+
+    fn is_configured(&self, scheme: SignatureScheme) -> bool {
+        match scheme {
+            SignatureScheme::Ecdsa => self.ecdsa.is_some(),
+            SignatureScheme::Schnorr => self.schnorr.is_some(),
+            SignatureScheme::Eddsa => self.eddsa.is_some(),
+        }
+    }
+
+    /// Hex encoded key the signatures of `request` verify against, as the
+    /// signers encode it in `SignatureData::public_key`. ECDSA signs with the
+    /// child key at the request's derivation path, the configured `path` by
+    /// default.
+    pub fn for_request(&self, request: &MessageToSignStored) -> Result<String> {
+        let scheme = request.scheme;
+        let not_found = || {
+            TssError::ConfigError(config::ConfigError::NotFound(format!(
+                "{}_public_key",
+                scheme.key_id()
+            )))
+        };
+        match scheme {
+            SignatureScheme::Ecdsa => {
+                let mut public_key = self.ecdsa.clone().ok_or_else(not_found)?;
+                let path = request.derivation_path.as_ref().unwrap_or(&self.path);
+                if !path.is_empty() {
+                    (_, public_key) = call_hd_key(path, public_key);
+                }
+                Ok(hex::encode(&*public_key.to_bytes(true)))
+            }
+            SignatureScheme::Schnorr => Ok(self.schnorr.clone().ok_or_else(not_found)?),
+            SignatureScheme::Eddsa => Ok(self.eddsa.clone().ok_or_else(not_found)?),
+        }
+    }
+}
+
+fn configured(key: &Option<String>) -> Option<&str> {
+    key.as_deref().filter(|key| !key.is_empty())
+}
+
+fn config_error(message: String) -> TssError {
+    TssError::ConfigError(config::ConfigError::Message(message))
+}
+
+// Lower case hex of a configured FROST group key, checked to encode a point of `C`
+fn frost_public_key<C: Ciphersuite>(key: &Option<String>) -> Result<Option<String>, TssError> {
+    let Some(key) = configured(key) else {
+        return Ok(None);
+    };
+    let name = format!("{}_public_key", C::SCHEME.key_id());
+    let bytes = hex::decode(key).map_err(|e| config_error(format!("{}: {}", name, e)))?;
+    if C::decode_point(&bytes).is_none() {
+        return Err(config_error(format!("{} is not a valid group key", name)));
+    }
+    Ok(Some(key.to_lowercase()))
 }
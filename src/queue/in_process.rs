@@ -0,0 +1,120 @@
+use crate::common::types::{DeadLetter, SigningRequest};
+use crate::error::TssError;
+use crate::queue::{ReceivedDeadLetter, ReceivedRequest, RequestQueue};
+use anyhow::Result;
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex};
+use tracing::warn;
+
+const CHANNEL_CAPACITY: usize = 1024;
+
+lazy_static! {
+    // Joined by every component of the process configured with the in-process backend
+    static ref SHARED: InProcessQueue = InProcessQueue::new();
+}
+
+/// Broadcast channel between a manager and signers running in the same process,
+/// for single binary deployments and tests. Every handle receives each request
+/// published after it was created.
+pub struct InProcessQueue {
+    sender: broadcast::Sender<SigningRequest>,
+    receiver: Mutex<broadcast::Receiver<SigningRequest>>,
+    dead_letter_sender: broadcast::Sender<DeadLetter>,
+    dead_letter_receiver: Mutex<broadcast::Receiver<DeadLetter>>,
+}
+
+impl InProcessQueue {
+    pub fn new() -> Self {
+        let (sender, receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        let (dead_letter_sender, dead_letter_receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+            dead_letter_sender,
+            dead_letter_receiver: Mutex::new(dead_letter_receiver),
+        }
+    }
+
+    /// Handle on the queue shared by the whole process
+    pub fn shared() -> Self {
+        SHARED.subscribe()
+    }
+
+    /// New handle on the same channel, with its own position in it
+    pub fn subscribe(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            receiver: Mutex::new(self.sender.subscribe()),
+            dead_letter_sender: self.dead_letter_sender.clone(),
+            dead_letter_receiver: Mutex::new(self.dead_letter_sender.subscribe()),
+        }
+    }
+}
+
+impl Default for InProcessQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl RequestQueue for InProcessQueue {
+    async fn publish_signing_request(&self, request: &SigningRequest) -> Result<()> {
+        // Sending only fails without any handle left to receive, like an unbound exchange
+        let _ = self.sender.send(request.clone());
+        Ok(())
+    }
+
+    async fn subscribe(&self, _consumer: &str) -> Result<()> {
+        // Every handle is subscribed from its creation
+        Ok(())
+    }
+
+    async fn receive_signing_request(&self) -> Result<ReceivedRequest> {
+        let mut receiver = self.receiver.lock().await;
+        loop {
+            match receiver.recv().await {
+                Ok(request) => {
+                    return Ok(ReceivedRequest {
+                        request,
+                        delivery_tag: 0,
+                        redelivered: false,
+                    })
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("In-process queue dropped {} signing requests", skipped);
+                }
+                Err(RecvError::Closed) => {
+                    return Err(TssError::QueueError("In-process queue closed".into()).into())
+                }
+            }
+        }
+    }
+
+    async fn publish_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        let _ = self.dead_letter_sender.send(letter.clone());
+        Ok(())
+    }
+
+    async fn receive_dead_letter(&self) -> Result<ReceivedDeadLetter> {
+        let mut receiver = self.dead_letter_receiver.lock().await;
+        loop {
+            match receiver.recv().await {
+                Ok(letter) => {
+                    return Ok(ReceivedDeadLetter {
+                        letter,
+                        delivery_tag: 0,
+                    })
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("In-process queue dropped {} dead letters", skipped);
+                }
+                Err(RecvError::Closed) => {
+                    return Err(TssError::QueueError("In-process queue closed".into()).into())
+                }
+            }
+        }
+    }
+}
@@ -1,3 +1,76 @@
+pub mod in_process;
+pub mod nats;
 pub mod rabbitmq;
 
+pub use in_process::InProcessQueue;
+pub use nats::NatsQueue;
 pub use rabbitmq::RabbitMQService;
+
+use crate::common::types::{DeadLetter, SigningRequest};
+use crate::config::{QueueBackend, QueueConfig};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// A signing request taken from the queue, settled with `ack` or `nack` once handled
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    pub request: SigningRequest,
+    pub delivery_tag: u64,
+    // Set when an earlier delivery of this request was not acknowledged
+    pub redelivered: bool,
+}
+
+/// A dead letter taken from the queue, acknowledged once stored
+#[derive(Debug, Clone)]
+pub struct ReceivedDeadLetter {
+    pub letter: DeadLetter,
+    pub delivery_tag: u64,
+}
+
+/// Delivers signing requests from the manager to every signer
+#[async_trait]
+pub trait RequestQueue: Send + Sync {
+    async fn publish_signing_request(&self, request: &SigningRequest) -> Result<()>;
+
+    /// Starts consuming the requests addressed to `consumer`, called once per signer
+    /// before `receive_signing_request`
+    async fn subscribe(&self, consumer: &str) -> Result<()>;
+
+    /// Waits for the next signing request
+    async fn receive_signing_request(&self) -> Result<ReceivedRequest>;
+
+    /// Sends a request a signer gave up on to the dead-letter queue
+    async fn publish_dead_letter(&self, letter: &DeadLetter) -> Result<()>;
+
+    /// Waits for the next dead letter, the first call subscribes to the
+    /// dead-letter queue. Settled with `ack` and `nack` like requests.
+    async fn receive_dead_letter(&self) -> Result<ReceivedDeadLetter>;
+
+    /// Removes a handled message from the queue, a no-op for backends without acknowledgements
+    async fn ack(&self, _delivery_tag: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns a message to the queue, or drops it without `requeue`
+    async fn nack(&self, _delivery_tag: u64, _requeue: bool) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether a message returned with `nack` is delivered again, marked as
+    /// redelivered. Failures on backends without redelivery are not retried.
+    fn supports_redelivery(&self) -> bool {
+        false
+    }
+}
+
+/// Connects to the queue backend selected in `config`
+pub async fn connect(config: &QueueConfig, rabbitmq_uri: &str) -> Result<Arc<dyn RequestQueue>> {
+    Ok(match config.backend {
+        QueueBackend::Rabbitmq => {
+            Arc::new(RabbitMQService::new(rabbitmq_uri, config.prefetch).await?)
+        }
+        QueueBackend::Nats => Arc::new(NatsQueue::new(&config.nats_url).await?),
+        QueueBackend::InProcess => Arc::new(InProcessQueue::shared()),
+    })
+}
@@ -0,0 +1,106 @@
+use crate::common::types::{DeadLetter, SigningRequest};
+use crate::error::TssError;
+use crate::queue::{ReceivedDeadLetter, ReceivedRequest, RequestQueue};
+use anyhow::Result;
+use async_nats::{Client, Subscriber};
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::sync::Mutex;
+
+const REQUEST_SUBJECT: &str = "tss.signing_requests";
+const DEAD_LETTER_SUBJECT: &str = "tss.signing_requests.dead_letter";
+// Managers share dead letters instead of each receiving a copy
+const DEAD_LETTER_GROUP: &str = "managers";
+
+/// NATS core publish/subscribe, every connected signer receives each request.
+/// Delivery is at most once, requests published while a signer is offline are lost.
+pub struct NatsQueue {
+    client: Client,
+    // Only set on signers, publishers never subscribe
+    subscriber: Mutex<Option<Subscriber>>,
+    dead_letter_subscriber: Mutex<Option<Subscriber>>,
+}
+
+impl NatsQueue {
+    pub async fn new(url: &str) -> Result<Self> {
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|e| TssError::QueueError(e.to_string()))?;
+        Ok(Self {
+            client,
+            subscriber: Mutex::new(None),
+            dead_letter_subscriber: Mutex::new(None),
+        })
+    }
+
+    async fn publish(&self, subject: &'static str, payload: Vec<u8>) -> Result<()> {
+        self.client
+            .publish(subject, payload.into())
+            .await
+            .map_err(|e| TssError::QueueError(e.to_string()))?;
+        self.client
+            .flush()
+            .await
+            .map_err(|e| TssError::QueueError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RequestQueue for NatsQueue {
+    async fn publish_signing_request(&self, request: &SigningRequest) -> Result<()> {
+        self.publish(REQUEST_SUBJECT, serde_json::to_vec(request)?)
+            .await
+    }
+
+    async fn subscribe(&self, _consumer: &str) -> Result<()> {
+        let subscription = self
+            .client
+            .subscribe(REQUEST_SUBJECT)
+            .await
+            .map_err(|e| TssError::QueueError(e.to_string()))?;
+        *self.subscriber.lock().await = Some(subscription);
+        Ok(())
+    }
+
+    async fn receive_signing_request(&self) -> Result<ReceivedRequest> {
+        let mut subscriber = self.subscriber.lock().await;
+        let Some(subscriber) = subscriber.as_mut() else {
+            return Err(TssError::QueueError("Not subscribed to signing requests".into()).into());
+        };
+
+        match subscriber.next().await {
+            Some(message) => Ok(ReceivedRequest {
+                request: serde_json::from_slice(&message.payload)?,
+                delivery_tag: 0,
+                redelivered: false,
+            }),
+            None => Err(TssError::QueueError("NATS subscription closed".into()).into()),
+        }
+    }
+
+    async fn publish_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        self.publish(DEAD_LETTER_SUBJECT, serde_json::to_vec(letter)?)
+            .await
+    }
+
+    async fn receive_dead_letter(&self) -> Result<ReceivedDeadLetter> {
+        let mut subscriber = self.dead_letter_subscriber.lock().await;
+        if subscriber.is_none() {
+            let subscription = self
+                .client
+                .queue_subscribe(DEAD_LETTER_SUBJECT, DEAD_LETTER_GROUP.to_string())
+                .await
+                .map_err(|e| TssError::QueueError(e.to_string()))?;
+            *subscriber = Some(subscription);
+        }
+
+        match subscriber.as_mut().unwrap().next().await {
+            Some(message) => Ok(ReceivedDeadLetter {
+                letter: serde_json::from_slice(&message.payload)?,
+                delivery_tag: 0,
+            }),
+            None => Err(TssError::QueueError("NATS subscription closed".into()).into()),
+        }
+    }
+}
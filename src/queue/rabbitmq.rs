@@ -1,19 +1,40 @@
-use crate::common::types::SigningRequest;
+use crate::common::types::{DeadLetter, SigningRequest};
 use crate::error::TssError;
+use crate::queue::{ReceivedDeadLetter, ReceivedRequest, RequestQueue};
 use anyhow::Result;
+use async_trait::async_trait;
 use futures_lite::stream::StreamExt;
 use lapin::{
-    options::*, types::FieldTable, BasicProperties, Channel, Connection, ConnectionProperties,
-    ExchangeKind,
+    options::*,
+    types::{AMQPValue, FieldTable},
+    BasicProperties, Channel, Connection, ConnectionProperties, Consumer, ExchangeKind,
 };
+use tokio::sync::Mutex;
 
+const DEAD_LETTER_EXCHANGE: &str = "signing_requests_dead_letter";
+// Shared by all managers, each dead letter is stored once
+const DEAD_LETTER_QUEUE: &str = "signing_requests.dead_letter";
+// Deliveries rejected without requeue, kept as published for inspection
+const REJECTED_EXCHANGE: &str = "signing_requests_rejected";
+const REJECTED_QUEUE: &str = "signing_requests.rejected";
+
+// Messages marked persistent survive a broker restart in durable queues
+const PERSISTENT_DELIVERY_MODE: u8 = 2;
+
+/// Fanout of signing requests to one durable queue per signer, so requests
+/// published while a signer is busy or offline wait for it
 pub struct RabbitMQService {
     request_channel: Channel,
     request_exchange: String,
+    prefetch: u16,
+    // Only set on signers, publishers never subscribe
+    consumer: Mutex<Option<Consumer>>,
+    // Only set on managers
+    dead_letter_consumer: Mutex<Option<Consumer>>,
 }
 
 impl RabbitMQService {
-    pub async fn new(uri: &str) -> Result<Self> {
+    pub async fn new(uri: &str, prefetch: u16) -> Result<Self> {
         let conn = Connection::connect(uri, ConnectionProperties::default()).await?;
         let request_channel = conn.create_channel().await?;
         let result_channel = conn.create_channel().await?;
@@ -29,6 +50,72 @@ impl RabbitMQService {
             )
             .await?;
 
+        request_channel
+            .exchange_declare(
+                DEAD_LETTER_EXCHANGE,
+                ExchangeKind::Fanout,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..ExchangeDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+
+        request_channel
+            .exchange_declare(
+                REJECTED_EXCHANGE,
+                ExchangeKind::Fanout,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..ExchangeDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+
+        request_channel
+            .queue_declare(
+                REJECTED_QUEUE,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..QueueDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+
+        request_channel
+            .queue_bind(
+                REJECTED_QUEUE,
+                REJECTED_EXCHANGE,
+                "",
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+
+        request_channel
+            .queue_declare(
+                DEAD_LETTER_QUEUE,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..QueueDeclareOptions::default()
+                },
+                rejected_arguments(),
+            )
+            .await?;
+
+        request_channel
+            .queue_bind(
+                DEAD_LETTER_QUEUE,
+                DEAD_LETTER_EXCHANGE,
+                "",
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+
         result_channel
             .queue_declare(
                 &result_queue,
@@ -40,10 +127,26 @@ impl RabbitMQService {
         Ok(Self {
             request_channel,
             request_exchange,
+            prefetch,
+            consumer: Mutex::new(None),
+            dead_letter_consumer: Mutex::new(None),
         })
     }
+}
 
-    pub async fn publish_signing_request(&self, request: &SigningRequest) -> Result<()> {
+// Queue arguments sending messages nacked without requeue to the rejected queue
+fn rejected_arguments() -> FieldTable {
+    let mut arguments = FieldTable::default();
+    arguments.insert(
+        "x-dead-letter-exchange".into(),
+        AMQPValue::LongString(REJECTED_EXCHANGE.into()),
+    );
+    arguments
+}
+
+#[async_trait]
+impl RequestQueue for RabbitMQService {
+    async fn publish_signing_request(&self, request: &SigningRequest) -> Result<()> {
         let payload = serde_json::to_vec(request)?;
         self.request_channel
             .basic_publish(
@@ -51,27 +154,25 @@ impl RabbitMQService {
                 "",
                 BasicPublishOptions::default(),
                 &payload,
-                BasicProperties::default(),
+                BasicProperties::default().with_delivery_mode(PERSISTENT_DELIVERY_MODE),
             )
             .await?;
 
         Ok(())
     }
 
-    pub async fn receive_signing_request(&self) -> Result<SigningRequest> {
-        let queue_name = self
-            .request_channel
+    async fn subscribe(&self, consumer: &str) -> Result<()> {
+        let queue_name = format!("signing_requests.{}", consumer);
+        self.request_channel
             .queue_declare(
-                "",
+                &queue_name,
                 QueueDeclareOptions {
-                    exclusive: true,
+                    durable: true,
                     ..QueueDeclareOptions::default()
                 },
-                FieldTable::default(),
+                rejected_arguments(),
             )
-            .await?
-            .name()
-            .to_string();
+            .await?;
 
         self.request_channel
             .queue_bind(
@@ -83,27 +184,114 @@ impl RabbitMQService {
             )
             .await?;
 
-        let mut consumer = self
+        self.request_channel
+            .basic_qos(self.prefetch, BasicQosOptions::default())
+            .await?;
+
+        let request_consumer = self
             .request_channel
             .basic_consume(
                 &queue_name,
-                "signing_consumer",
+                consumer,
                 BasicConsumeOptions::default(),
                 FieldTable::default(),
             )
             .await?;
+        *self.consumer.lock().await = Some(request_consumer);
+        Ok(())
+    }
+
+    async fn receive_signing_request(&self) -> Result<ReceivedRequest> {
+        let mut consumer = self.consumer.lock().await;
+        let Some(consumer) = consumer.as_mut() else {
+            return Err(TssError::QueueError("Not subscribed to signing requests".into()).into());
+        };
+
+        match consumer.next().await {
+            Some(Ok(delivery)) => match serde_json::from_slice(&delivery.data) {
+                Ok(request) => Ok(ReceivedRequest {
+                    request,
+                    delivery_tag: delivery.delivery_tag,
+                    redelivered: delivery.redelivered,
+                }),
+                Err(e) => {
+                    // Retrying cannot fix a malformed request, it goes to the rejected queue
+                    self.nack(delivery.delivery_tag, false).await?;
+                    Err(e.into())
+                }
+            },
+            Some(Err(err)) => Err(err.into()),
+            None => Err(TssError::QueueError("Consumer cancelled".into()).into()),
+        }
+    }
+
+    async fn publish_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        let payload = serde_json::to_vec(letter)?;
+        self.request_channel
+            .basic_publish(
+                DEAD_LETTER_EXCHANGE,
+                "",
+                BasicPublishOptions::default(),
+                &payload,
+                BasicProperties::default().with_delivery_mode(PERSISTENT_DELIVERY_MODE),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn receive_dead_letter(&self) -> Result<ReceivedDeadLetter> {
+        let mut consumer = self.dead_letter_consumer.lock().await;
+        if consumer.is_none() {
+            let dead_letter_consumer = self
+                .request_channel
+                .basic_consume(
+                    DEAD_LETTER_QUEUE,
+                    "dead_letter_consumer",
+                    BasicConsumeOptions::default(),
+                    FieldTable::default(),
+                )
+                .await?;
+            *consumer = Some(dead_letter_consumer);
+        }
 
-        if let Some(delivery) = consumer.next().await {
-            match delivery {
-                Ok(delivery) => {
-                    let request: SigningRequest = serde_json::from_slice(&delivery.data)?;
-                    delivery.ack(BasicAckOptions::default()).await?;
-                    Ok(request)
+        match consumer.as_mut().unwrap().next().await {
+            Some(Ok(delivery)) => match serde_json::from_slice(&delivery.data) {
+                Ok(letter) => Ok(ReceivedDeadLetter {
+                    letter,
+                    delivery_tag: delivery.delivery_tag,
+                }),
+                Err(e) => {
+                    self.nack(delivery.delivery_tag, false).await?;
+                    Err(e.into())
                 }
-                Err(err) => Err(err.into()),
-            }
-        } else {
-            Err(TssError::QueueError("No message received".into()).into())
+            },
+            Some(Err(err)) => Err(err.into()),
+            None => Err(TssError::QueueError("Consumer cancelled".into()).into()),
         }
     }
+
+    async fn ack(&self, delivery_tag: u64) -> Result<()> {
+        self.request_channel
+            .basic_ack(delivery_tag, BasicAckOptions::default())
+            .await?;
+        Ok(())
+    }
+
+    fn supports_redelivery(&self) -> bool {
+        true
+    }
+
+    async fn nack(&self, delivery_tag: u64, requeue: bool) -> Result<()> {
+        self.request_channel
+            .basic_nack(
+                delivery_tag,
+                BasicNackOptions {
+                    requeue,
+                    ..BasicNackOptions::default()
+                },
+            )
+            .await?;
+        Ok(())
+    }
 }
@@ -1,5 +1,6 @@
-mod hd_keys;
+pub mod policy;
 mod secp256k1def;
 pub mod service;
 
+pub use policy::SignerPolicy;
 pub use service::SignerService;
@@ -0,0 +1,209 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use bitcoin::address::{Address, NetworkUnchecked};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1;
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::{PublicKey, ScriptBuf};
+
+use crate::common::approval::{approval_digest, verify_approval};
+use crate::common::hd_keys::{call_hd_key, is_valid_path, path_matches};
+use crate::common::scripts::owned_scripts;
+use crate::common::secp256k1def::GE;
+use crate::common::{SignatureScheme, SigningRequest, TransactionInput};
+use crate::config::SignerPolicyConfig;
+
+// What a request signs, a plain message or the sighash of a transaction input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashMode {
+    Message,
+    Sighash(EcdsaSighashType),
+}
+
+impl FromStr for HashMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "message" => Ok(HashMode::Message),
+            _ => EcdsaSighashType::from_str(s)
+                .map(HashMode::Sighash)
+                .map_err(|_| format!("unknown hash mode {}", s)),
+        }
+    }
+}
+
+impl Display for HashMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HashMode::Message => write!(f, "message"),
+            HashMode::Sighash(sighash_type) => write!(f, "{}", sighash_type),
+        }
+    }
+}
+
+/// Local policy of a signer, checked on every request before the signer joins
+/// its signing room. The default policy allows everything.
+#[derive(Debug, Default)]
+pub struct SignerPolicy {
+    hash_modes: Vec<HashMode>,
+    derivation_paths: Vec<String>,
+    destinations: Vec<ScriptBuf>,
+    approver_keys: Vec<secp256k1::PublicKey>,
+    approvals_required: usize,
+}
+
+impl SignerPolicy {
+    /// Parses the configured policy, rejecting unknown hash modes and malformed
+    /// paths, addresses and keys
+    pub fn from_config(config: &SignerPolicyConfig) -> Result<Self> {
+        let hash_modes = config
+            .allowed_hash_modes
+            .iter()
+            .map(|mode| mode.parse::<HashMode>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!(e))?;
+        for pattern in &config.allowed_derivation_paths {
+            let path = pattern.strip_suffix("/*").unwrap_or(pattern);
+            if !path.is_empty() && !is_valid_path(path) {
+                return Err(anyhow!("invalid derivation path {}", pattern));
+            }
+        }
+        let destinations = config
+            .allowed_destinations
+            .iter()
+            .map(|address| {
+                Address::<NetworkUnchecked>::from_str(address)
+                    .map(|address| address.assume_checked().script_pubkey())
+                    .map_err(|_| anyhow!("invalid destination address {}", address))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let approver_keys = config
+            .approver_keys
+            .iter()
+            .map(|key| {
+                hex::decode(key)
+                    .ok()
+                    .and_then(|bytes| secp256k1::PublicKey::from_slice(&bytes).ok())
+                    .ok_or_else(|| anyhow!("invalid approver key {}", key))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if config.approvals_required > approver_keys.len() {
+            return Err(anyhow!(
+                "{} approvals required from {} approver keys",
+                config.approvals_required,
+                approver_keys.len()
+            ));
+        }
+        Ok(Self {
+            hash_modes,
+            derivation_paths: config.allowed_derivation_paths.clone(),
+            destinations,
+            approver_keys,
+            approvals_required: config.approvals_required,
+        })
+    }
+
+    /// Checks `request`, signed with the child key at `path` of the network key
+    /// `y_sum`. Returns the reason of a refusal.
+    pub fn check(&self, request: &SigningRequest, path: &str, y_sum: &GE) -> Result<(), String> {
+        if request.scheme == SignatureScheme::Ecdsa
+            && !self.derivation_paths.is_empty()
+            && !self
+                .derivation_paths
+                .iter()
+                .any(|pattern| path_matches(pattern, path))
+        {
+            return Err(format!("derivation path {:?} is not allowed", path));
+        }
+        let mode = match &request.transaction {
+            Some(transaction) => self.check_transaction(request, transaction, path, y_sum)?,
+            // A plain message can be the sighash of any transaction
+            None if !self.destinations.is_empty() => {
+                return Err("plain messages are not signed with a destination allow-list".into())
+            }
+            None => HashMode::Message,
+        };
+        if !self.hash_modes.is_empty() && !self.hash_modes.contains(&mode) {
+            return Err(format!("hash mode {} is not allowed", mode));
+        }
+        self.check_approvals(request)
+    }
+
+    // Checks that the message is the sighash of the input and that the
+    // transaction pays to allowed destinations only, returns its hash mode
+    fn check_transaction(
+        &self,
+        request: &SigningRequest,
+        transaction: &TransactionInput,
+        path: &str,
+        y_sum: &GE,
+    ) -> Result<HashMode, String> {
+        if request.scheme != SignatureScheme::Ecdsa {
+            return Err("transaction inputs are signed with ECDSA only".into());
+        }
+        let psbt = Psbt::from_str(&transaction.psbt).map_err(|e| format!("invalid PSBT: {}", e))?;
+        if transaction.input >= psbt.inputs.len() {
+            return Err(format!("PSBT has no input {}", transaction.input));
+        }
+        let mut cache = SighashCache::new(&psbt.unsigned_tx);
+        let (sighash, sighash_type) = psbt
+            .sighash_ecdsa(transaction.input, &mut cache)
+            .map_err(|e| format!("input {}: {}", transaction.input, e))?;
+        if sighash.as_ref() != request.message.as_slice() {
+            return Err(format!(
+                "message is not the sighash of input {}",
+                transaction.input
+            ));
+        }
+        if !self.destinations.is_empty() {
+            let own_scripts = owned_scripts(&signing_key(path, y_sum)?);
+            for (index, output) in psbt.unsigned_tx.output.iter().enumerate() {
+                if !own_scripts.contains(&output.script_pubkey)
+                    && !self.destinations.contains(&output.script_pubkey)
+                {
+                    return Err(format!(
+                        "output {} pays to a destination not allowed",
+                        index
+                    ));
+                }
+            }
+        }
+        Ok(HashMode::Sighash(sighash_type))
+    }
+
+    fn check_approvals(&self, request: &SigningRequest) -> Result<(), String> {
+        if self.approvals_required == 0 {
+            return Ok(());
+        }
+        let digest = approval_digest(request)?;
+        let mut approvers = Vec::new();
+        for approval in &request.approvals {
+            // Invalid signatures and unknown keys do not count
+            if let Ok(key) = verify_approval(&digest, approval) {
+                if self.approver_keys.contains(&key) && !approvers.contains(&key) {
+                    approvers.push(key);
+                }
+            }
+        }
+        if approvers.len() < self.approvals_required {
+            return Err(format!(
+                "approved by {} of the {} known approvers required",
+                approvers.len(),
+                self.approvals_required
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Key the signer signs with at `path`
+fn signing_key(path: &str, y_sum: &GE) -> Result<PublicKey, String> {
+    let mut public_key = y_sum.clone();
+    if !path.is_empty() {
+        (_, public_key) = call_hd_key(path, public_key);
+    }
+    PublicKey::from_slice(&public_key.to_bytes(true)).map_err(|e| e.to_string())
+}
@@ -1,31 +1,43 @@
-use crate::queue::rabbitmq::RabbitMQService;
+use crate::auth::SignerCredentials;
+use crate::queue::{ReceivedRequest, RequestQueue};
+use crate::storage::now_millis;
 use anyhow::{anyhow, Result};
-use curv::arithmetic::{BasicOps, Converter, Modulo};
 use curv::cryptographic_primitives::proofs::sigma_correct_homomorphic_elgamal_enc::HomoELGamalProof;
 use curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
 use curv::cryptographic_primitives::secret_sharing::feldman_vss::VerifiableSS;
-use curv::elliptic::curves::Secp256k1;
-use curv::BigInt;
+use curv::elliptic::curves::{Curve, Ed25519, Scalar, Secp256k1};
+use futures::FutureExt;
 use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2018::party_i::*;
 use multi_party_ecdsa::utilities::mta::{MessageA, MessageB};
 use paillier::EncryptionKey;
-use reqwest::Client;
 use serde_json::{json, Value};
 use sha2::Sha256;
+use std::any::Any;
 use std::fs::File;
 use std::io::Read;
-use std::{thread, time};
-use tracing::{error, info};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
 
+use crate::common::frost::{
+    self, Ciphersuite, Ed25519Sha512, FrostKeyShare, NonceCommitment, Secp256k1Bip340,
+};
+use crate::common::hd_keys::call_hd_key;
+use crate::common::signature::message_to_bigint;
 use crate::common::{
-    broadcast, poll_for_broadcasts, poll_for_p2p, postb, sendp2p, sha256_digest, ManagerError,
-    Params, PartySignup, PartySignupRequestBody, SignatureData, SignerResult, SigningPartySignup,
-    SigningRequest,
+    postb, signing_room_id, DeadLetter, HttpTransport, ManagerClient, ManagerError, MeshNode,
+    MeshTransport, Params, PartySignup, PartySignupRequestBody, SignatureData, SignatureScheme,
+    SignerResult, SigningFailure, SigningPartySignup, SigningProgress, SigningRefusal,
+    SigningRequest, Transport,
 };
-use crate::signer::hd_keys;
+use crate::signer::policy::SignerPolicy;
 use crate::signer::secp256k1def::{FE, GE};
 
-struct SignerData {
+/// ECDSA key share of a signer
+pub struct SignerData {
     party_keys: Keys,
     shared_keys: SharedKeys,
     party_id: u16,
@@ -34,70 +46,130 @@ struct SignerData {
     y_sum: GE,
 }
 
+impl SignerData {
+    /// Parses the key file written by the ECDSA key generation
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let (party_keys, shared_keys, party_id, vss_scheme_vec, paillier_key_vector, y_sum): (
+            Keys,
+            SharedKeys,
+            u16,
+            Vec<VerifiableSS<Secp256k1>>,
+            Vec<EncryptionKey>,
+            GE,
+        ) = serde_json::from_str(contents)?;
+        Ok(Self {
+            party_keys,
+            shared_keys,
+            party_id,
+            vss_scheme_vec,
+            paillier_key_vector,
+            y_sum,
+        })
+    }
+}
+
 pub struct SignerService {
-    queue: RabbitMQService,
+    queue: Arc<dyn RequestQueue>,
+    // Shared by all sessions, reuses connections to the manager
+    client: ManagerClient,
+    sessions: Arc<Semaphore>,
+    session_timeout: time::Duration,
+    // Round messages go directly to the other signers when set
+    mesh: Option<Arc<MeshNode>>,
     manager_url: String,
     manager_port: String,
     signer_data: SignerData,
     threshold: u16,
     total_parties: u16,
     path: String,
+    frost_key: Option<FrostKeyShare<Secp256k1>>,
+    ed25519_key: Option<FrostKeyShare<Ed25519>>,
+    // Checked before joining any signing room
+    policy: SignerPolicy,
 }
 
 #[allow(non_snake_case)]
 impl SignerService {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         manager_url: &str,
         manager_port: &u16,
-        rabbitmq_uri: &str,
+        queue: Arc<dyn RequestQueue>,
         key_file: &str,
+        signer_secret: &str,
         threshold: &u16,
         total_parties: &u16,
         path: &str,
+        frost_key_file: Option<&str>,
+        ed25519_key_file: Option<&str>,
+        max_concurrent_sessions: usize,
+        session_timeout: time::Duration,
+        mesh: Option<Arc<MeshNode>>,
+        policy: SignerPolicy,
     ) -> Result<Self> {
-        let queue = RabbitMQService::new(rabbitmq_uri).await?;
         let mut file = File::open(key_file)?;
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
 
-        let (party_keys, shared_keys, party_id, vss_scheme_vec, paillier_key_vector, y_sum): (
-            Keys,
-            SharedKeys,
-            u16,
-            Vec<VerifiableSS<Secp256k1>>,
-            Vec<EncryptionKey>,
-            GE,
-        ) = serde_json::from_str(&contents).unwrap();
+        let signer_data = SignerData::from_json(&contents)?;
+        let frost_key = load_frost_key(frost_key_file)?;
+        let ed25519_key = load_frost_key(ed25519_key_file)?;
+        // The credential of the signer is bound to one party id for every scheme
+        let party_ids = [
+            frost_key.as_ref().map(|key| key.party_id),
+            ed25519_key.as_ref().map(|key| key.party_id),
+        ];
+        if let Some(party_id) = party_ids
+            .into_iter()
+            .flatten()
+            .find(|party_id| *party_id != signer_data.party_id)
+        {
+            return Err(anyhow!(
+                "FROST key share of party {} does not belong to party {}",
+                party_id,
+                signer_data.party_id
+            ));
+        }
+        let credentials = SignerCredentials::new(signer_data.party_id, signer_secret);
 
         Ok(Self {
             queue,
+            client: ManagerClient::new(Some(credentials)),
+            sessions: Arc::new(Semaphore::new(max_concurrent_sessions)),
+            session_timeout,
+            mesh,
             manager_url: manager_url.to_string(),
             manager_port: manager_port.to_string(),
-            signer_data: SignerData {
-                party_keys,
-                shared_keys,
-                party_id,
-                vss_scheme_vec,
-                paillier_key_vector,
-                y_sum,
-            },
+            signer_data,
             threshold: *threshold,
             total_parties: *total_parties,
             path: path.to_string(),
+            frost_key,
+            ed25519_key,
+            policy,
         })
     }
 
-    pub async fn run(&self) -> Result<()> {
+    pub async fn run(self: Arc<Self>) -> Result<()> {
         info!(
             "Starting SignerService for party {}",
             self.signer_data.party_id
         );
+        self.queue
+            .subscribe(&format!("signer-{}", self.signer_data.party_id))
+            .await?;
         loop {
+            // Requests stay in the queue until a session is free
+            let permit = self.sessions.clone().acquire_owned().await?;
             match self.queue.receive_signing_request().await {
-                Ok(request) => {
-                    if let Err(e) = self.handle_signing_request(request).await {
-                        error!("Error handling signing request: {:?}", e);
-                    }
+                Ok(received) => {
+                    let signer = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = signer.process_received(received).await {
+                            error!("Error acknowledging signing request: {:?}", e);
+                        }
+                        drop(permit);
+                    });
                 }
                 Err(e) => {
                     error!("Error receiving signing request: {:?}", e);
@@ -106,617 +178,209 @@ impl SignerService {
         }
     }
 
+    // Acknowledges the request once the signature is submitted. A failed
+    // request is retried once where the queue redelivers, then sent to the
+    // dead-letter queue.
+    async fn process_received(&self, received: ReceivedRequest) -> Result<()> {
+        // The ECDSA protocol panics on unexpected messages from the manager
+        let session =
+            AssertUnwindSafe(self.handle_signing_request(received.request.clone())).catch_unwind();
+        // Dropping the session on timeout cancels its pending polls
+        let result = match tokio::time::timeout(self.session_timeout, session).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(panic)) => Err(anyhow!("Signing panicked: {}", panic_message(&panic))),
+            Err(_) => Err(anyhow!(
+                "Signing session timed out after {:?}",
+                self.session_timeout
+            )),
+        };
+        let Err(e) = result else {
+            return self.queue.ack(received.delivery_tag).await;
+        };
+
+        error!("Error handling signing request: {:?}", e);
+        if !received.redelivered && self.queue.supports_redelivery() {
+            return self.queue.nack(received.delivery_tag, true).await;
+        }
+        let letter = DeadLetter {
+            request: received.request,
+            failure: SigningFailure {
+                party_id: self.signer_data.party_id,
+                error: e.to_string(),
+                failed_at: now_millis(),
+            },
+        };
+        self.queue.publish_dead_letter(&letter).await?;
+        self.queue.ack(received.delivery_tag).await
+    }
+
     pub async fn handle_signing_request(&self, request: SigningRequest) -> Result<()> {
         // this can be dynamic as well
         let params = Params {
             threshold: self.threshold,
             parties: self.total_parties,
-            path: self.path.clone(),
+            path: request
+                .derivation_path
+                .clone()
+                .unwrap_or_else(|| self.path.clone()),
         };
-        self.sign(&request.message, &request.id, &params).await;
+        if let Err(reason) = self
+            .policy
+            .check(&request, &params.path, &self.signer_data.y_sum)
+        {
+            return self.refuse(&request, &reason).await;
+        }
+        match request.scheme {
+            SignatureScheme::Ecdsa => self.sign(&request.message, &request.id, &params).await?,
+            SignatureScheme::Schnorr => {
+                self.sign_frost::<Secp256k1Bip340>(
+                    self.frost_key.as_ref(),
+                    &request.message,
+                    &request.id,
+                )
+                .await?
+            }
+            SignatureScheme::Eddsa => {
+                self.sign_frost::<Ed25519Sha512>(
+                    self.ed25519_key.as_ref(),
+                    &request.message,
+                    &request.id,
+                )
+                .await?
+            }
+        }
         Ok(())
     }
 
-    pub async fn sign(&self, message: &[u8], request_id: &str, params: &Params) {
-        let client = Client::new();
-        let delay = time::Duration::from_millis(250);
-        let room_id = sha256_digest(message);
-        let path_is_empty = params.path.is_empty();
-        let (f_l_new, y_sum) = match path_is_empty {
-            true => (FE::zero(), self.signer_data.y_sum.clone()),
-            false => call_hd_key(&params.path, self.signer_data.y_sum.clone()),
+    // Refusals are final, the request is acknowledged rather than retried
+    async fn refuse(&self, request: &SigningRequest, reason: &str) -> Result<()> {
+        warn!("Refusing signing request {}: {}", request.id, reason);
+        let addr = format!("{}:{}", self.manager_url, self.manager_port);
+        let refusal = SigningRefusal {
+            request_id: request.id.clone(),
+            party_id: self.signer_data.party_id,
+            reason: reason.to_string(),
         };
+        if let Err(e) = Self::report_refusal(&addr, &self.client, refusal).await {
+            error!("Error reporting signing refusal to manager: {:?}", e);
+        }
+        Ok(())
+    }
 
+    #[allow(clippy::match_single_binding)]
+    pub async fn sign(&self, message: &[u8], request_id: &str, params: &Params) -> Result<()> {
+        let client = self.client.clone();
+        let delay = time::Duration::from_millis(250);
+        let room_id = signing_room_id(SignatureScheme::Ecdsa, request_id);
         let addr = format!("{}:{}", self.manager_url, self.manager_port);
-        let party_keys = self.signer_data.party_keys.clone();
-        let shared_keys = self.signer_data.shared_keys.clone();
         let party_id = self.signer_data.party_id;
-        let mut vss_scheme_vec = self.signer_data.vss_scheme_vec.clone();
-        let paillier_key_vector = self.signer_data.paillier_key_vector.clone();
-        let sign_at_path = !path_is_empty;
 
         // Signup
-        let (party_num_int, uuid, total_parties) =
+        let (party_num_int, uuid, total_parties, members) =
             match Self::signup(&addr, &client, self.threshold, room_id, party_id)
                 .await
                 .unwrap()
             {
-                (PartySignup { number, uuid }, total_parties) => (number, uuid, total_parties),
+                (PartySignup { number, uuid }, total_parties, members) => {
+                    (number, uuid, total_parties, members)
+                }
             };
+        if let Err(e) = Self::report_in_progress(&addr, &client, request_id, &uuid, party_id).await
+        {
+            error!("Error reporting signing progress to manager: {:?}", e);
+        }
 
         let debug = json!({"manager_addr": &addr, "party_num": party_num_int, "uuid": uuid});
         println!("{}", serde_json::to_string_pretty(&debug).unwrap());
 
-        // round 0: collect signers IDs
-        assert!(broadcast(
-            &addr,
-            &client,
-            party_num_int,
-            "round0",
-            serde_json::to_string(&party_id).unwrap(),
-            uuid.clone(),
-        )
-        .await
-        .is_ok());
-
-        let round0_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round0",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut j = 0;
-        let mut signers_vec: Vec<u16> = Vec::new();
-        for i in 1..=total_parties {
-            if i == party_num_int {
-                signers_vec.push(party_id - 1);
-            } else {
-                let signer_j: u16 = serde_json::from_str(&round0_ans_vec[j]).unwrap();
-                signers_vec.push(signer_j - 1);
-                j += 1;
-            }
-        }
-
-        if sign_at_path {
-            // optimize!
-            let g: GE = GE::generator().to_point();
-            // apply on first commitment for leader (leader is party with num=1)
-            let com_zero_new = &vss_scheme_vec[0].commitments[0] + g * &f_l_new;
-            // println!("old zero: {:?}, new zero: {:?}", vss_scheme_vec[0].commitments[0], com_zero_new);
-            // get iterator of all commitments and skip first zero commitment
-            let mut com_iter_unchanged = vss_scheme_vec[0].commitments.iter();
-            com_iter_unchanged.next().unwrap();
-            // iterate commitments and inject changed commitments in the beginning then aggregate into vector
-            let com_vec_new = (0..vss_scheme_vec[1].commitments.len())
-                .map(|i| {
-                    if i == 0 {
-                        com_zero_new.clone()
-                    } else {
-                        com_iter_unchanged.next().unwrap().clone()
-                    }
-                })
-                .collect::<Vec<GE>>();
-            let new_vss = VerifiableSS {
-                parameters: vss_scheme_vec[0].parameters.clone(),
-                commitments: com_vec_new,
-            };
-            // replace old vss_scheme for leader with new one at position 0
-            //    println!("comparing vectors: \n{:?} \nand \n{:?}", vss_scheme_vec[0], new_vss);
-
-            vss_scheme_vec.remove(0);
-            vss_scheme_vec.insert(0, new_vss);
-            //    println!("NEW VSS VECTOR: {:?}", vss_scheme_vec);
-        }
-
-        let mut private = PartyPrivate::set_private(party_keys.clone(), shared_keys);
-
-        if sign_at_path {
-            if party_num_int == 1 {
-                // update u_i and x_i for leader
-                private = private.update_private_key(&f_l_new, &f_l_new);
-            } else {
-                // only update x_i for non-leaders
-                private = private.update_private_key(&FE::zero(), &f_l_new);
-            }
-        }
-
-        let sign_keys = SignKeys::create(
-            &private,
-            &vss_scheme_vec[signers_vec[(party_num_int - 1) as usize] as usize],
-            signers_vec[(party_num_int - 1) as usize],
-            &signers_vec,
-        );
-
-        //////////////////////////////////////////////////////////////////////////////
-        let (com, decommit) = sign_keys.phase1_broadcast();
-        let (m_a_k, _) = MessageA::a(&sign_keys.k_i, &party_keys.ek, &[]);
-        assert!(broadcast(
-            &addr,
-            &client,
-            party_num_int,
-            "round1",
-            serde_json::to_string(&(com.clone(), m_a_k.clone())).unwrap(),
-            uuid.clone(),
-        )
-        .await
-        .is_ok());
-        let round1_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round1",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut j = 0;
-        let mut bc1_vec: Vec<SignBroadcastPhase1> = Vec::new();
-        let mut m_a_vec: Vec<MessageA> = Vec::new();
-
-        for i in 1..total_parties + 1 {
-            if i == party_num_int {
-                bc1_vec.push(com.clone());
-                //   m_a_vec.push(m_a_k.clone());
-            } else {
-                //     if signers_vec.contains(&(i as usize)) {
-                let (bc1_j, m_a_party_j): (SignBroadcastPhase1, MessageA) =
-                    serde_json::from_str(&round1_ans_vec[j]).unwrap();
-                bc1_vec.push(bc1_j);
-                m_a_vec.push(m_a_party_j);
-
-                j += 1;
-                //       }
-            }
-        }
-        assert_eq!(signers_vec.len(), bc1_vec.len());
-
-        //////////////////////////////////////////////////////////////////////////////
-        let mut m_b_gamma_send_vec: Vec<MessageB> = Vec::new();
-        let mut beta_vec: Vec<FE> = Vec::new();
-        let mut m_b_w_send_vec: Vec<MessageB> = Vec::new();
-        let mut ni_vec: Vec<FE> = Vec::new();
-        let mut j = 0;
-        for i in 1..(total_parties as usize) + 1 {
-            if i != party_num_int as usize {
-                let (m_b_gamma, beta_gamma, _, _) = MessageB::b(
-                    &sign_keys.gamma_i,
-                    &paillier_key_vector[signers_vec[i - 1] as usize],
-                    m_a_vec[j].clone(),
-                    &[],
-                )
-                .unwrap();
-                let (m_b_w, beta_wi, _, _) = MessageB::b(
-                    &sign_keys.w_i,
-                    &paillier_key_vector[signers_vec[i - 1] as usize],
-                    m_a_vec[j].clone(),
-                    &[],
-                )
-                .unwrap();
-                m_b_gamma_send_vec.push(m_b_gamma);
-                m_b_w_send_vec.push(m_b_w);
-                beta_vec.push(beta_gamma);
-                ni_vec.push(beta_wi);
-                j += 1;
-            }
-        }
-
-        let mut j = 0;
-        for i in 1..total_parties + 1 {
-            if i != party_num_int {
-                assert!(sendp2p(
-                    &addr,
-                    &client,
-                    party_num_int,
-                    i,
-                    "round2",
-                    serde_json::to_string(&(
-                        m_b_gamma_send_vec[j].clone(),
-                        m_b_w_send_vec[j].clone()
-                    ))
-                    .unwrap(),
-                    uuid.clone(),
-                )
-                .await
-                .is_ok());
-                j += 1;
-            }
-        }
-
-        let round2_ans_vec = poll_for_p2p(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round2",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut m_b_gamma_rec_vec: Vec<MessageB> = Vec::new();
-        let mut m_b_w_rec_vec: Vec<MessageB> = Vec::new();
-
-        for i in 0..total_parties - 1 {
-            //  if signers_vec.contains(&(i as usize)) {
-            let (m_b_gamma_i, m_b_w_i): (MessageB, MessageB) =
-                serde_json::from_str(&round2_ans_vec[i as usize]).unwrap();
-            m_b_gamma_rec_vec.push(m_b_gamma_i);
-            m_b_w_rec_vec.push(m_b_w_i);
-            //     }
-        }
-
-        let mut alpha_vec: Vec<FE> = Vec::new();
-        let mut miu_vec: Vec<FE> = Vec::new();
-
-        let xi_com_vec = Keys::get_commitments_to_xi(&vss_scheme_vec);
-        let mut j = 0;
-        for i in 1..(total_parties as usize) + 1 {
-            //        println!("mbproof p={}, i={}, j={}", party_num_int, i, j);
-            if i != party_num_int as usize {
-                //            println!("verifying: p={}, i={}, j={}", party_num_int, i, j);
-                let m_b = m_b_gamma_rec_vec[j].clone();
-
-                let alpha_ij_gamma = m_b
-                    .verify_proofs_get_alpha(&party_keys.dk, &sign_keys.k_i)
-                    .expect("wrong dlog or m_b");
-                let m_b = m_b_w_rec_vec[j].clone();
-                let alpha_ij_wi = m_b
-                    .verify_proofs_get_alpha(&party_keys.dk, &sign_keys.k_i)
-                    .expect("wrong dlog or m_b");
-                alpha_vec.push(alpha_ij_gamma.0);
-                miu_vec.push(alpha_ij_wi.0);
-                let g_w_i = Keys::update_commitments_to_xi(
-                    &xi_com_vec[signers_vec[i - 1] as usize],
-                    &vss_scheme_vec[signers_vec[i - 1] as usize],
-                    signers_vec[i - 1],
-                    &signers_vec,
-                );
-                //println!("Verifying client {}", party_num_int);
-                assert_eq!(m_b.b_proof.pk.clone(), g_w_i);
-                //println!("Verified client {}", party_num_int);
-                j += 1;
-            }
-        }
-        //////////////////////////////////////////////////////////////////////////////
-        let delta_i = sign_keys.phase2_delta_i(&alpha_vec, &beta_vec);
-        let sigma = sign_keys.phase2_sigma_i(&miu_vec, &ni_vec);
-
-        assert!(broadcast(
-            &addr,
-            &client,
-            party_num_int,
-            "round3",
-            serde_json::to_string(&delta_i).unwrap(),
-            uuid.clone(),
-        )
-        .await
-        .is_ok());
-        let round3_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round3",
-            uuid.clone(),
-        )
-        .await;
-        let mut delta_vec: Vec<FE> = Vec::new();
-        format_vec_from_reads(
-            &round3_ans_vec,
-            party_num_int as usize,
-            delta_i,
-            &mut delta_vec,
-        );
-        let delta_inv = SignKeys::phase3_reconstruct_delta(&delta_vec);
-
-        //////////////////////////////////////////////////////////////////////////////
-        // decommit to gamma_i
-        assert!(broadcast(
-            &addr,
-            &client,
-            party_num_int,
-            "round4",
-            serde_json::to_string(&decommit).unwrap(),
-            uuid.clone(),
-        )
-        .await
-        .is_ok());
-        let round4_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round4",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut decommit_vec: Vec<SignDecommitPhase1> = Vec::new();
-        format_vec_from_reads(
-            &round4_ans_vec,
-            party_num_int as usize,
-            decommit,
-            &mut decommit_vec,
-        );
-        let decomm_i = decommit_vec.remove((party_num_int - 1) as usize);
-        bc1_vec.remove((party_num_int - 1) as usize);
-        let b_proof_vec = (0..m_b_gamma_rec_vec.len())
-            .map(|i| &m_b_gamma_rec_vec[i].b_proof)
-            .collect::<Vec<&DLogProof<Secp256k1, Sha256>>>();
-
-        let R = SignKeys::phase4(&delta_inv, &b_proof_vec, decommit_vec, &bc1_vec)
-            .expect("bad gamma_i decommit");
-
-        // adding local g_gamma_i
-        let R = R + decomm_i.g_gamma_i * &delta_inv;
-
-        // we assume the message is already hashed (by the signer).
-        let message_bn = BigInt::from_bytes(message);
-        //    println!("message_bn INT: {}", message_bn);
-        let message_int = BigInt::from_bytes(message);
-        let two = BigInt::from(2);
-        let message_bn = message_bn.modulus(&two.pow(256));
-        let local_sig =
-            LocalSignature::phase5_local_sig(&sign_keys.k_i, &message_bn, &R, &sigma, &y_sum);
-
-        let (phase5_com, phase_5a_decom, helgamal_proof, dlog_proof_rho) =
-            local_sig.phase5a_broadcast_5b_zkproof();
-
-        //phase (5A)  broadcast commit
-        assert!(broadcast(
-            &addr,
-            &client,
-            party_num_int,
-            "round5",
-            serde_json::to_string(&phase5_com).unwrap(),
-            uuid.clone(),
-        )
-        .await
-        .is_ok());
-        let round5_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round5",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut commit5a_vec: Vec<Phase5Com1> = Vec::new();
-        format_vec_from_reads(
-            &round5_ans_vec,
-            party_num_int as usize,
-            phase5_com,
-            &mut commit5a_vec,
-        );
-
-        //phase (5B)  broadcast decommit and (5B) ZK proof
-        assert!(broadcast(
-            &addr,
-            &client,
-            party_num_int,
-            "round6",
-            serde_json::to_string(&(
-                phase_5a_decom.clone(),
-                helgamal_proof.clone(),
-                dlog_proof_rho.clone()
-            ))
-            .unwrap(),
-            uuid.clone(),
-        )
-        .await
-        .is_ok());
-        let round6_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round6",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut decommit5a_and_elgamal_and_dlog_vec: Vec<(
-            Phase5ADecom1,
-            HomoELGamalProof<Secp256k1, Sha256>,
-            DLogProof<Secp256k1, Sha256>,
-        )> = Vec::new();
-        format_vec_from_reads(
-            &round6_ans_vec,
-            party_num_int as usize,
-            (
-                phase_5a_decom.clone(),
-                helgamal_proof.clone(),
-                dlog_proof_rho.clone(),
-            ),
-            &mut decommit5a_and_elgamal_and_dlog_vec,
-        );
-        let decommit5a_and_elgamal_vec_includes_i = decommit5a_and_elgamal_and_dlog_vec.clone();
-        decommit5a_and_elgamal_and_dlog_vec.remove((party_num_int - 1) as usize);
-        commit5a_vec.remove((party_num_int - 1) as usize);
-        let phase_5a_decomm_vec = (0..total_parties - 1)
-            .map(|i| decommit5a_and_elgamal_and_dlog_vec[i as usize].0.clone())
-            .collect::<Vec<Phase5ADecom1>>();
-        let phase_5a_elgamal_vec = (0..total_parties - 1)
-            .map(|i| decommit5a_and_elgamal_and_dlog_vec[i as usize].1.clone())
-            .collect::<Vec<HomoELGamalProof<Secp256k1, Sha256>>>();
-        let phase_5a_dlog_vec = (0..total_parties - 1)
-            .map(|i| decommit5a_and_elgamal_and_dlog_vec[i as usize].2.clone())
-            .collect::<Vec<DLogProof<Secp256k1, Sha256>>>();
-        let (phase5_com2, phase_5d_decom2) = local_sig
-            .phase5c(
-                &phase_5a_decomm_vec,
-                &commit5a_vec,
-                &phase_5a_elgamal_vec,
-                &phase_5a_dlog_vec,
-                &phase_5a_decom.V_i,
-                &R.clone(),
-            )
-            .expect("error phase5");
+        let transport =
+            self.transport(&addr, party_num_int, total_parties, members, &uuid, delay)?;
+        let signature = ecdsa_sign(&*transport, &self.signer_data, &params.path, message).await?;
+        // Unsubmitted signatures fail the request, so that it is retried
+        self.send_signature_to_manager(&addr, &client, &signature, request_id)
+            .await
+    }
 
-        //////////////////////////////////////////////////////////////////////////////
-        assert!(broadcast(
-            &addr,
-            &client,
-            party_num_int,
-            "round7",
-            serde_json::to_string(&phase5_com2).unwrap(),
-            uuid.clone(),
-        )
-        .await
-        .is_ok());
-        let round7_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round7",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut commit5c_vec: Vec<Phase5Com2> = Vec::new();
-        format_vec_from_reads(
-            &round7_ans_vec,
-            party_num_int as usize,
-            phase5_com2,
-            &mut commit5c_vec,
-        );
+    /// Two round FROST signing with the key share of the ciphersuite `C`. Produces
+    /// a BIP340 or Ed25519 signature under the group key, derivation paths are not
+    /// applied.
+    pub async fn sign_frost<C: Ciphersuite>(
+        &self,
+        key_share: Option<&FrostKeyShare<C::Curve>>,
+        message: &[u8],
+        request_id: &str,
+    ) -> Result<()> {
+        let key_share = key_share
+            .ok_or_else(|| anyhow!("No key share loaded, cannot sign {:?} requests", C::SCHEME))?;
+        let client = self.client.clone();
+        let delay = time::Duration::from_millis(250);
+        let room_id = signing_room_id(C::SCHEME, request_id);
+        let addr = format!("{}:{}", self.manager_url, self.manager_port);
 
-        //phase (5B)  broadcast decommit and (5B) ZK proof
-        assert!(broadcast(
-            &addr,
-            &client,
-            party_num_int,
-            "round8",
-            serde_json::to_string(&phase_5d_decom2).unwrap(),
-            uuid.clone(),
-        )
-        .await
-        .is_ok());
-        let round8_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
+        // Signup
+        let (
+            PartySignup {
+                number: party_num_int,
+                uuid,
+            },
             total_parties,
-            delay,
-            "round8",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut decommit5d_vec: Vec<Phase5DDecom2> = Vec::new();
-        format_vec_from_reads(
-            &round8_ans_vec,
-            party_num_int as usize,
-            phase_5d_decom2.clone(),
-            &mut decommit5d_vec,
-        );
-
-        let phase_5a_decomm_vec_includes_i = (0..total_parties)
-            .map(|i| decommit5a_and_elgamal_vec_includes_i[i as usize].0.clone())
-            .collect::<Vec<Phase5ADecom1>>();
-        let s_i = local_sig
-            .phase5d(
-                &decommit5d_vec,
-                &commit5c_vec,
-                &phase_5a_decomm_vec_includes_i,
-            )
-            .expect("bad com 5d");
-
-        //////////////////////////////////////////////////////////////////////////////
-        assert!(broadcast(
+            members,
+        ) = Self::signup(
             &addr,
             &client,
-            party_num_int,
-            "round9",
-            serde_json::to_string(&s_i).unwrap(),
-            uuid.clone(),
+            key_share.threshold,
+            room_id,
+            key_share.party_id,
         )
         .await
-        .is_ok());
-        let round9_ans_vec = poll_for_broadcasts(
-            &addr,
-            &client,
-            party_num_int,
-            total_parties,
-            delay,
-            "round9",
-            uuid.clone(),
-        )
-        .await;
-
-        let mut s_i_vec: Vec<FE> = Vec::new();
-        format_vec_from_reads(&round9_ans_vec, party_num_int as usize, s_i, &mut s_i_vec);
-
-        s_i_vec.remove((party_num_int - 1) as usize);
-        let sig = local_sig
-            .output_signature(&s_i_vec)
-            .expect("verification failed");
-        //    println!(" \n");
-        //    println!("party {:?} Output Signature: \n", party_num_int);
-        //    println!("SIG msg: {:?}", sig.m);
-        //    println!("R: {:?}", sig.r);
-        //    println!("s: {:?} \n", sig.s);
-        //    println!("child pubkey: {:?} \n", y_sum);
-
-        //    println!("pubkey: {:?} \n", y_sum);
-        //    println!("verifying signature with public key");
-        verify(&sig, &y_sum, &message_bn).expect("false");
-        //    println!("verifying signature with child pub key");
-        //    verify(&sig, &new_key, &message_bn).expect("false");
-
-        //    println!("{:?}", sig.recid.clone());
-        //    print(sig.recid.clone()
-
-        let ret_dict = json!({
-            "r": (BigInt::from_bytes(sig.r.to_bytes().as_ref())).to_str_radix(16),
-            "s": (BigInt::from_bytes(sig.s.to_bytes().as_ref())).to_str_radix(16),
-            "status": "signature_ready",
-            "recid": sig.recid.clone(),
-            "x": &y_sum.x_coord().unwrap().to_hex(),
-            "y": &y_sum.y_coord().unwrap().to_hex(),
-            "msg_int": message_int,
-        });
-        let signature: SignatureData = serde_json::from_value(ret_dict).unwrap();
-        match self
-            .send_signature_to_manager(&addr, &client, &signature, request_id)
-            .await
+        .unwrap();
+        if let Err(e) =
+            Self::report_in_progress(&addr, &client, request_id, &uuid, key_share.party_id).await
         {
-            Ok(_) => info!("Signature sent to manager"),
-            Err(e) => error!("Error sending signature to manager: {:?}", e),
+            error!("Error reporting signing progress to manager: {:?}", e);
         }
-        //    fs::write("signature".to_string(), sign_json).expect("Unable to save !");
-
-        //    println!("Public key Y: {:?}", to_bitcoin_public_key(y_sum.get_element()).to_bytes());
-        //    println!("Public child key X: {:?}", &new_key.x_coord());
-        //    println!("Public child key Y: {:?}", &new_key.y_coord());
-        //    println!("Public key big int: {:?}", &y_sum.bytes_compressed_to_big_int());
-        //    println!("Public key ge: {:?}", &y_sum.get_element().serialize());
-        //    println!("Public key ge: {:?}", PK::serialize_uncompressed(&y_sum.get_element()));
-        //    println!("New public key: {:?}", &y_sum.x_coor);
+
+        let transport =
+            self.transport(&addr, party_num_int, total_parties, members, &uuid, delay)?;
+        let signature = frost_sign::<C, _>(&*transport, key_share, message).await?;
+        self.send_signature_to_manager(&addr, &client, &signature, request_id)
+            .await
+    }
+
+    // Round messages of the session in room `uuid`, through the manager unless
+    // the mesh is enabled
+    fn transport(
+        &self,
+        addr: &str,
+        party_num: u16,
+        parties: u16,
+        members: Vec<u16>,
+        uuid: &str,
+        poll_delay: time::Duration,
+    ) -> Result<Box<dyn Transport>> {
+        Ok(match &self.mesh {
+            Some(mesh) => Box::new(MeshTransport::new(mesh.clone(), party_num, members, uuid)?),
+            None => Box::new(HttpTransport::new(
+                addr,
+                self.client.clone(),
+                party_num,
+                parties,
+                uuid,
+                poll_delay,
+                // The session is cancelled by then anyway
+                Instant::now() + self.session_timeout,
+            )),
+        })
     }
 
+    #[allow(clippy::ptr_arg)]
     async fn signup(
         addr: &String,
-        client: &Client,
+        client: &ManagerClient,
         threshold: u16,
         room_id: String,
         party_id: u16,
-    ) -> Result<(PartySignup, u16), ()> {
+    ) -> Result<(PartySignup, u16, Vec<u16>), ()> {
         let mut request_body = PartySignupRequestBody {
             threshold,
             room_id: room_id.clone(),
@@ -735,12 +399,13 @@ impl SignerService {
 
         let answer: Result<SigningPartySignup, ManagerError> =
             serde_json::from_str(&res_body).unwrap();
-        let (output, total_parties) = match answer {
+        let (output, total_parties, members) = match answer {
             Ok(SigningPartySignup {
                 party_order,
                 party_uuid,
                 room_uuid,
                 total_joined,
+                members,
             }) => {
                 println!(
                     "Signed up, party order: {:?}, joined so far: {:?}, waiting for room uuid",
@@ -748,12 +413,14 @@ impl SignerService {
                 );
                 let mut now = time::SystemTime::now();
                 let mut last_total_joined = total_joined;
+                let mut room_members = members;
                 let mut party_signup = PartySignup {
                     number: party_order,
                     uuid: room_uuid,
                 };
                 while party_signup.uuid.is_empty() {
-                    thread::sleep(delay);
+                    // A fixed delay, the signup requests double as pings keeping the party in the room
+                    tokio::time::sleep(delay).await;
                     request_body.party_uuid = party_uuid.clone();
                     let res_body = postb(addr, client, path, request_body.clone())
                         .await
@@ -766,8 +433,10 @@ impl SignerService {
                             party_uuid,
                             room_uuid,
                             total_joined,
+                            members,
                         }) => {
                             request_body.party_uuid = party_uuid;
+                            room_members = members;
                             if party_signup.number != party_order {
                                 party_signup.number = party_order;
                             }
@@ -792,52 +461,584 @@ impl SignerService {
                         timeout
                     );
                 }
-                (party_signup, last_total_joined)
+                (party_signup, last_total_joined, room_members)
             }
             Err(ManagerError { error }) => {
                 panic!("{}", error);
             }
         };
 
-        Ok((output, total_parties))
+        Ok((output, total_parties, members))
+    }
+
+    // Tells the manager the request is being signed in the locked room `room_uuid`
+    async fn report_in_progress(
+        addr: &str,
+        client: &ManagerClient,
+        request_id: &str,
+        room_uuid: &str,
+        party_id: u16,
+    ) -> Result<()> {
+        let progress = SigningProgress {
+            request_id: request_id.to_string(),
+            room_uuid: room_uuid.to_string(),
+            party_id,
+        };
+        let res_body = postb::<SigningProgress>(addr, client, "update_signing_progress", progress)
+            .await
+            .ok_or_else(|| anyhow!("No response from manager"))?;
+        let parsed: Value = serde_json::from_str(&res_body)
+            .map_err(|err| anyhow!("Failed to parse response from manager: {:?}", err))?;
+
+        match parsed {
+            Value::Object(map) if map.contains_key("Ok") => Ok(()),
+            _ => Err(anyhow!("Failed to report signing progress: {:?}", parsed)),
+        }
+    }
+
+    async fn report_refusal(
+        addr: &str,
+        client: &ManagerClient,
+        refusal: SigningRefusal,
+    ) -> Result<()> {
+        let res_body = postb::<SigningRefusal>(addr, client, "report_signing_refusal", refusal)
+            .await
+            .ok_or_else(|| anyhow!("No response from manager"))?;
+        let parsed: Value = serde_json::from_str(&res_body)
+            .map_err(|err| anyhow!("Failed to parse response from manager: {:?}", err))?;
+
+        match parsed {
+            Value::Object(map) if map.contains_key("Ok") => Ok(()),
+            _ => Err(anyhow!("Failed to report signing refusal: {:?}", parsed)),
+        }
     }
 
     async fn send_signature_to_manager(
         &self,
         addr: &str,
-        client: &Client,
+        client: &ManagerClient,
         signature: &SignatureData,
         request_id: &str,
     ) -> Result<()> {
         let signer_result = SignerResult {
             request_id: request_id.to_string(),
             signature: signature.clone(),
+            party_id: self.signer_data.party_id,
         };
-        let res_body = postb::<SignerResult>(addr, client, "update_signing_result", signer_result)
-            .await
+        submit_signature(addr, client, signer_result).await
+    }
+}
+
+/// Reports a signature to the manager, an error unless the manager stored it
+pub async fn submit_signature(
+    addr: &str,
+    client: &ManagerClient,
+    signer_result: SignerResult,
+) -> Result<()> {
+    let res_body = postb::<SignerResult>(addr, client, "update_signing_result", signer_result)
+        .await
+        .ok_or_else(|| anyhow!("No response from manager"))?;
+    let parsed: Value = serde_json::from_str(&res_body)
+        .map_err(|err| anyhow!("Failed to parse response from manager: {:?}", err))?;
+
+    match parsed {
+        Value::Object(map) if map.contains_key("Ok") => {
+            info!("Signature sent to manager");
+            Ok(())
+        }
+        _ => Err(anyhow!("Failed to send signature to manager: {:?}", parsed)),
+    }
+}
+
+/// GG18 signing of `message` among the parties of `transport`, with the child key
+/// at `path` unless it is empty
+#[allow(non_snake_case)]
+pub async fn ecdsa_sign<T: Transport + ?Sized>(
+    transport: &T,
+    signer_data: &SignerData,
+    path: &str,
+    message: &[u8],
+) -> Result<SignatureData> {
+    let party_num_int = transport.party_num();
+    let total_parties = transport.parties();
+    let path_is_empty = path.is_empty();
+    let (f_l_new, y_sum) = match path_is_empty {
+        true => (FE::zero(), signer_data.y_sum.clone()),
+        false => call_hd_key(path, signer_data.y_sum.clone()),
+    };
+
+    let party_keys = signer_data.party_keys.clone();
+    let shared_keys = signer_data.shared_keys.clone();
+    let party_id = signer_data.party_id;
+    let mut vss_scheme_vec = signer_data.vss_scheme_vec.clone();
+    let paillier_key_vector = signer_data.paillier_key_vector.clone();
+    let sign_at_path = !path_is_empty;
+
+    // round 0: collect signers IDs
+    transport
+        .broadcast("round0", serde_json::to_string(&party_id).unwrap())
+        .await?;
+
+    let round0_ans_vec = transport.receive_broadcasts("round0").await?;
+
+    let mut j = 0;
+    let mut signers_vec: Vec<u16> = Vec::new();
+    for i in 1..=total_parties {
+        if i == party_num_int {
+            signers_vec.push(party_id - 1);
+        } else {
+            let signer_j: u16 = serde_json::from_str(&round0_ans_vec[j]).unwrap();
+            signers_vec.push(signer_j - 1);
+            j += 1;
+        }
+    }
+
+    if sign_at_path {
+        // optimize!
+        let g: GE = GE::generator().to_point();
+        // apply on first commitment for leader (leader is party with num=1)
+        let com_zero_new = &vss_scheme_vec[0].commitments[0] + g * &f_l_new;
+        // println!("old zero: {:?}, new zero: {:?}", vss_scheme_vec[0].commitments[0], com_zero_new);
+        // get iterator of all commitments and skip first zero commitment
+        let mut com_iter_unchanged = vss_scheme_vec[0].commitments.iter();
+        com_iter_unchanged.next().unwrap();
+        // iterate commitments and inject changed commitments in the beginning then aggregate into vector
+        let com_vec_new = (0..vss_scheme_vec[1].commitments.len())
+            .map(|i| {
+                if i == 0 {
+                    com_zero_new.clone()
+                } else {
+                    com_iter_unchanged.next().unwrap().clone()
+                }
+            })
+            .collect::<Vec<GE>>();
+        let new_vss = VerifiableSS {
+            parameters: vss_scheme_vec[0].parameters.clone(),
+            commitments: com_vec_new,
+        };
+        // replace old vss_scheme for leader with new one at position 0
+        //    println!("comparing vectors: \n{:?} \nand \n{:?}", vss_scheme_vec[0], new_vss);
+
+        vss_scheme_vec.remove(0);
+        vss_scheme_vec.insert(0, new_vss);
+        //    println!("NEW VSS VECTOR: {:?}", vss_scheme_vec);
+    }
+
+    let mut private = PartyPrivate::set_private(party_keys.clone(), shared_keys);
+
+    if sign_at_path {
+        if party_num_int == 1 {
+            // update u_i and x_i for leader
+            private = private.update_private_key(&f_l_new, &f_l_new);
+        } else {
+            // only update x_i for non-leaders
+            private = private.update_private_key(&FE::zero(), &f_l_new);
+        }
+    }
+
+    let sign_keys = SignKeys::create(
+        &private,
+        &vss_scheme_vec[signers_vec[(party_num_int - 1) as usize] as usize],
+        signers_vec[(party_num_int - 1) as usize],
+        &signers_vec,
+    );
+
+    //////////////////////////////////////////////////////////////////////////////
+    let (com, decommit) = sign_keys.phase1_broadcast();
+    let (m_a_k, _) = MessageA::a(&sign_keys.k_i, &party_keys.ek, &[]);
+    transport
+        .broadcast(
+            "round1",
+            serde_json::to_string(&(com.clone(), m_a_k.clone())).unwrap(),
+        )
+        .await?;
+    let round1_ans_vec = transport.receive_broadcasts("round1").await?;
+
+    let mut j = 0;
+    let mut bc1_vec: Vec<SignBroadcastPhase1> = Vec::new();
+    let mut m_a_vec: Vec<MessageA> = Vec::new();
+
+    for i in 1..total_parties + 1 {
+        if i == party_num_int {
+            bc1_vec.push(com.clone());
+            //   m_a_vec.push(m_a_k.clone());
+        } else {
+            //     if signers_vec.contains(&(i as usize)) {
+            let (bc1_j, m_a_party_j): (SignBroadcastPhase1, MessageA) =
+                serde_json::from_str(&round1_ans_vec[j]).unwrap();
+            bc1_vec.push(bc1_j);
+            m_a_vec.push(m_a_party_j);
+
+            j += 1;
+            //       }
+        }
+    }
+    assert_eq!(signers_vec.len(), bc1_vec.len());
+
+    //////////////////////////////////////////////////////////////////////////////
+    let mut m_b_gamma_send_vec: Vec<MessageB> = Vec::new();
+    let mut beta_vec: Vec<FE> = Vec::new();
+    let mut m_b_w_send_vec: Vec<MessageB> = Vec::new();
+    let mut ni_vec: Vec<FE> = Vec::new();
+    let mut j = 0;
+    for i in 1..(total_parties as usize) + 1 {
+        if i != party_num_int as usize {
+            let (m_b_gamma, beta_gamma, _, _) = MessageB::b(
+                &sign_keys.gamma_i,
+                &paillier_key_vector[signers_vec[i - 1] as usize],
+                m_a_vec[j].clone(),
+                &[],
+            )
             .unwrap();
-        let parsed: Value = serde_json::from_str(&res_body)
-            .map_err(|err| anyhow!("Failed to parse response from manager: {:?}", err))?;
+            let (m_b_w, beta_wi, _, _) = MessageB::b(
+                &sign_keys.w_i,
+                &paillier_key_vector[signers_vec[i - 1] as usize],
+                m_a_vec[j].clone(),
+                &[],
+            )
+            .unwrap();
+            m_b_gamma_send_vec.push(m_b_gamma);
+            m_b_w_send_vec.push(m_b_w);
+            beta_vec.push(beta_gamma);
+            ni_vec.push(beta_wi);
+            j += 1;
+        }
+    }
 
-        match parsed {
-            Value::Object(map) if map.contains_key("Ok") => {
-                info!("Signature sent to manager");
-                Ok(())
-            }
-            _ => Err(anyhow!("Failed to send signature to manager: {:?}", parsed)),
+    let mut j = 0;
+    for i in 1..total_parties + 1 {
+        if i != party_num_int {
+            transport
+                .send_p2p(
+                    i,
+                    "round2",
+                    serde_json::to_string(&(
+                        m_b_gamma_send_vec[j].clone(),
+                        m_b_w_send_vec[j].clone(),
+                    ))
+                    .unwrap(),
+                )
+                .await?;
+            j += 1;
+        }
+    }
+
+    let round2_ans_vec = transport.receive_p2p("round2").await?;
+
+    let mut m_b_gamma_rec_vec: Vec<MessageB> = Vec::new();
+    let mut m_b_w_rec_vec: Vec<MessageB> = Vec::new();
+
+    for i in 0..total_parties - 1 {
+        //  if signers_vec.contains(&(i as usize)) {
+        let (m_b_gamma_i, m_b_w_i): (MessageB, MessageB) =
+            serde_json::from_str(&round2_ans_vec[i as usize]).unwrap();
+        m_b_gamma_rec_vec.push(m_b_gamma_i);
+        m_b_w_rec_vec.push(m_b_w_i);
+        //     }
+    }
+
+    let mut alpha_vec: Vec<FE> = Vec::new();
+    let mut miu_vec: Vec<FE> = Vec::new();
+
+    let xi_com_vec = Keys::get_commitments_to_xi(&vss_scheme_vec);
+    let mut j = 0;
+    for i in 1..(total_parties as usize) + 1 {
+        //        println!("mbproof p={}, i={}, j={}", party_num_int, i, j);
+        if i != party_num_int as usize {
+            //            println!("verifying: p={}, i={}, j={}", party_num_int, i, j);
+            let m_b = m_b_gamma_rec_vec[j].clone();
+
+            let alpha_ij_gamma = m_b
+                .verify_proofs_get_alpha(&party_keys.dk, &sign_keys.k_i)
+                .expect("wrong dlog or m_b");
+            let m_b = m_b_w_rec_vec[j].clone();
+            let alpha_ij_wi = m_b
+                .verify_proofs_get_alpha(&party_keys.dk, &sign_keys.k_i)
+                .expect("wrong dlog or m_b");
+            alpha_vec.push(alpha_ij_gamma.0);
+            miu_vec.push(alpha_ij_wi.0);
+            let g_w_i = Keys::update_commitments_to_xi(
+                &xi_com_vec[signers_vec[i - 1] as usize],
+                &vss_scheme_vec[signers_vec[i - 1] as usize],
+                signers_vec[i - 1],
+                &signers_vec,
+            );
+            //println!("Verifying client {}", party_num_int);
+            assert_eq!(m_b.b_proof.pk.clone(), g_w_i);
+            //println!("Verified client {}", party_num_int);
+            j += 1;
         }
     }
+    //////////////////////////////////////////////////////////////////////////////
+    let delta_i = sign_keys.phase2_delta_i(&alpha_vec, &beta_vec);
+    let sigma = sign_keys.phase2_sigma_i(&miu_vec, &ni_vec);
+
+    transport
+        .broadcast("round3", serde_json::to_string(&delta_i).unwrap())
+        .await?;
+    let round3_ans_vec = transport.receive_broadcasts("round3").await?;
+    let mut delta_vec: Vec<FE> = Vec::new();
+    format_vec_from_reads(
+        &round3_ans_vec,
+        party_num_int as usize,
+        delta_i,
+        &mut delta_vec,
+    );
+    let delta_inv = SignKeys::phase3_reconstruct_delta(&delta_vec);
+
+    //////////////////////////////////////////////////////////////////////////////
+    // decommit to gamma_i
+    transport
+        .broadcast("round4", serde_json::to_string(&decommit).unwrap())
+        .await?;
+    let round4_ans_vec = transport.receive_broadcasts("round4").await?;
+
+    let mut decommit_vec: Vec<SignDecommitPhase1> = Vec::new();
+    format_vec_from_reads(
+        &round4_ans_vec,
+        party_num_int as usize,
+        decommit,
+        &mut decommit_vec,
+    );
+    let decomm_i = decommit_vec.remove((party_num_int - 1) as usize);
+    bc1_vec.remove((party_num_int - 1) as usize);
+    let b_proof_vec = (0..m_b_gamma_rec_vec.len())
+        .map(|i| &m_b_gamma_rec_vec[i].b_proof)
+        .collect::<Vec<&DLogProof<Secp256k1, Sha256>>>();
+
+    let R = SignKeys::phase4(&delta_inv, &b_proof_vec, decommit_vec, &bc1_vec)
+        .expect("bad gamma_i decommit");
+
+    // adding local g_gamma_i
+    let R = R + decomm_i.g_gamma_i * &delta_inv;
+
+    // we assume the message is already hashed (by the signer).
+    let message_bn = message_to_bigint(message);
+    let local_sig =
+        LocalSignature::phase5_local_sig(&sign_keys.k_i, &message_bn, &R, &sigma, &y_sum);
+
+    let (phase5_com, phase_5a_decom, helgamal_proof, dlog_proof_rho) =
+        local_sig.phase5a_broadcast_5b_zkproof();
+
+    //phase (5A)  broadcast commit
+    transport
+        .broadcast("round5", serde_json::to_string(&phase5_com).unwrap())
+        .await?;
+    let round5_ans_vec = transport.receive_broadcasts("round5").await?;
+
+    let mut commit5a_vec: Vec<Phase5Com1> = Vec::new();
+    format_vec_from_reads(
+        &round5_ans_vec,
+        party_num_int as usize,
+        phase5_com,
+        &mut commit5a_vec,
+    );
+
+    //phase (5B)  broadcast decommit and (5B) ZK proof
+    transport
+        .broadcast(
+            "round6",
+            serde_json::to_string(&(
+                phase_5a_decom.clone(),
+                helgamal_proof.clone(),
+                dlog_proof_rho.clone(),
+            ))
+            .unwrap(),
+        )
+        .await?;
+    let round6_ans_vec = transport.receive_broadcasts("round6").await?;
+
+    #[allow(clippy::type_complexity)]
+    let mut decommit5a_and_elgamal_and_dlog_vec: Vec<(
+        Phase5ADecom1,
+        HomoELGamalProof<Secp256k1, Sha256>,
+        DLogProof<Secp256k1, Sha256>,
+    )> = Vec::new();
+    format_vec_from_reads(
+        &round6_ans_vec,
+        party_num_int as usize,
+        (
+            phase_5a_decom.clone(),
+            helgamal_proof.clone(),
+            dlog_proof_rho.clone(),
+        ),
+        &mut decommit5a_and_elgamal_and_dlog_vec,
+    );
+    let decommit5a_and_elgamal_vec_includes_i = decommit5a_and_elgamal_and_dlog_vec.clone();
+    decommit5a_and_elgamal_and_dlog_vec.remove((party_num_int - 1) as usize);
+    commit5a_vec.remove((party_num_int - 1) as usize);
+    let phase_5a_decomm_vec = (0..total_parties - 1)
+        .map(|i| decommit5a_and_elgamal_and_dlog_vec[i as usize].0.clone())
+        .collect::<Vec<Phase5ADecom1>>();
+    let phase_5a_elgamal_vec = (0..total_parties - 1)
+        .map(|i| decommit5a_and_elgamal_and_dlog_vec[i as usize].1.clone())
+        .collect::<Vec<HomoELGamalProof<Secp256k1, Sha256>>>();
+    let phase_5a_dlog_vec = (0..total_parties - 1)
+        .map(|i| decommit5a_and_elgamal_and_dlog_vec[i as usize].2.clone())
+        .collect::<Vec<DLogProof<Secp256k1, Sha256>>>();
+    let (phase5_com2, phase_5d_decom2) = local_sig
+        .phase5c(
+            &phase_5a_decomm_vec,
+            &commit5a_vec,
+            &phase_5a_elgamal_vec,
+            &phase_5a_dlog_vec,
+            &phase_5a_decom.V_i,
+            &R.clone(),
+        )
+        .expect("error phase5");
+
+    //////////////////////////////////////////////////////////////////////////////
+    transport
+        .broadcast("round7", serde_json::to_string(&phase5_com2).unwrap())
+        .await?;
+    let round7_ans_vec = transport.receive_broadcasts("round7").await?;
+
+    let mut commit5c_vec: Vec<Phase5Com2> = Vec::new();
+    format_vec_from_reads(
+        &round7_ans_vec,
+        party_num_int as usize,
+        phase5_com2,
+        &mut commit5c_vec,
+    );
+
+    //phase (5B)  broadcast decommit and (5B) ZK proof
+    transport
+        .broadcast("round8", serde_json::to_string(&phase_5d_decom2).unwrap())
+        .await?;
+    let round8_ans_vec = transport.receive_broadcasts("round8").await?;
+
+    let mut decommit5d_vec: Vec<Phase5DDecom2> = Vec::new();
+    format_vec_from_reads(
+        &round8_ans_vec,
+        party_num_int as usize,
+        phase_5d_decom2.clone(),
+        &mut decommit5d_vec,
+    );
+
+    let phase_5a_decomm_vec_includes_i = (0..total_parties)
+        .map(|i| decommit5a_and_elgamal_vec_includes_i[i as usize].0.clone())
+        .collect::<Vec<Phase5ADecom1>>();
+    let s_i = local_sig
+        .phase5d(
+            &decommit5d_vec,
+            &commit5c_vec,
+            &phase_5a_decomm_vec_includes_i,
+        )
+        .expect("bad com 5d");
+
+    //////////////////////////////////////////////////////////////////////////////
+    transport
+        .broadcast("round9", serde_json::to_string(&s_i).unwrap())
+        .await?;
+    let round9_ans_vec = transport.receive_broadcasts("round9").await?;
+
+    let mut s_i_vec: Vec<FE> = Vec::new();
+    format_vec_from_reads(&round9_ans_vec, party_num_int as usize, s_i, &mut s_i_vec);
+
+    s_i_vec.remove((party_num_int - 1) as usize);
+    let sig = local_sig
+        .output_signature(&s_i_vec)
+        .expect("verification failed");
+    //    println!(" \n");
+    //    println!("party {:?} Output Signature: \n", party_num_int);
+    //    println!("SIG msg: {:?}", sig.m);
+    //    println!("R: {:?}", sig.r);
+    //    println!("s: {:?} \n", sig.s);
+    //    println!("child pubkey: {:?} \n", y_sum);
+
+    //    println!("pubkey: {:?} \n", y_sum);
+    //    println!("verifying signature with public key");
+    verify(&sig, &y_sum, &message_bn).expect("false");
+    //    println!("verifying signature with child pub key");
+    //    verify(&sig, &new_key, &message_bn).expect("false");
+
+    //    println!("{:?}", sig.recid.clone());
+    //    print(sig.recid.clone()
+
+    let signature = SignatureData::new(&sig.r, &sig.s, sig.recid, &y_sum, message);
+    Ok(signature)
 }
 
-fn call_hd_key(path: &str, public_key: GE) -> (FE, GE) {
-    let path_vector: Vec<BigInt> = path
-        .split('/')
-        .map(|s| BigInt::from_str_radix(s.trim(), 10).unwrap())
-        .collect();
-    let (public_key_child, f_l_new) = hd_keys::get_hd_key(&public_key, path_vector.clone());
-    (f_l_new, public_key_child.clone())
+/// Two round FROST signing of `message` among the parties of `transport`
+pub async fn frost_sign<C: Ciphersuite, T: Transport + ?Sized>(
+    transport: &T,
+    key_share: &FrostKeyShare<C::Curve>,
+    message: &[u8],
+) -> Result<SignatureData> {
+    let party_num_int = transport.party_num();
+
+    // round 1: nonce commitments, they also identify the signing set
+    let (nonces, commitment_i) = frost::commit_nonces(key_share.party_id);
+    transport
+        .broadcast("round1", serde_json::to_string(&commitment_i).unwrap())
+        .await?;
+    let round1_ans_vec = transport.receive_broadcasts("round1").await?;
+
+    let mut commitments: Vec<NonceCommitment<C::Curve>> = Vec::new();
+    format_vec_from_reads(
+        &round1_ans_vec,
+        party_num_int as usize,
+        commitment_i,
+        &mut commitments,
+    );
+
+    // round 2: signature shares
+    let z_i = frost::sign_share::<C>(key_share, &nonces, message, &commitments)?;
+    transport
+        .broadcast("round2", serde_json::to_string(&z_i).unwrap())
+        .await?;
+    let round2_ans_vec = transport.receive_broadcasts("round2").await?;
+
+    let mut z_vec: Vec<Scalar<C::Curve>> = Vec::new();
+    format_vec_from_reads(&round2_ans_vec, party_num_int as usize, z_i, &mut z_vec);
+
+    for (commitment, z_j) in commitments.iter().zip(z_vec.iter()) {
+        if !frost::verify_signature_share::<C>(
+            key_share,
+            commitment.party_id,
+            z_j,
+            message,
+            &commitments,
+        )? {
+            return Err(anyhow!(
+                "Invalid signature share from party {}",
+                commitment.party_id
+            ));
+        }
+    }
+
+    let (r, s) = frost::aggregate::<C>(message, &commitments, &z_vec);
+    if !frost::verify::<C>(&key_share.public_key, message, &r, &s) {
+        return Err(anyhow!(
+            "Aggregated {:?} signature does not verify",
+            C::SCHEME
+        ));
+    }
+
+    let signature = SignatureData::new_frost::<C>(&r, &s, &key_share.public_key, message);
+    Ok(signature)
+}
+fn panic_message(panic: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+fn load_frost_key<E: Curve>(key_file: Option<&str>) -> Result<Option<FrostKeyShare<E>>> {
+    match key_file {
+        Some(key_file) => {
+            let contents = std::fs::read_to_string(key_file)?;
+            Ok(Some(serde_json::from_str(&contents)?))
+        }
+        None => Ok(None),
+    }
 }
 
+#[allow(clippy::ptr_arg)]
 fn format_vec_from_reads<'a, T: serde::Deserialize<'a> + Clone>(
     ans_vec: &'a Vec<String>,
     party_num: usize,
@@ -0,0 +1,262 @@
+use crate::error::TssError;
+use crate::storage::mongodb::is_duplicate_key;
+use anyhow::Result;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::options::{
+    FindOneAndUpdateOptions, FindOptions, IndexOptions, ReplaceOptions, ReturnDocument,
+};
+use mongodb::{Collection, Database, IndexModel};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// Schema version this build expects, the number of migrations below
+pub const SCHEMA_VERSION: u32 = 5;
+
+const SCHEMA_COLLECTION: &str = "schema_version";
+const SCHEMA_DOC_ID: &str = "tss_network";
+// A replica that crashed mid-migration releases the lock after this long
+const MIGRATION_LOCK_TIMEOUT: Duration = Duration::from_secs(300);
+const MIGRATION_LOCK_RETRY: Duration = Duration::from_secs(1);
+
+/// Brings the database to `SCHEMA_VERSION`. Replicas starting together take turns
+/// through a lock on the schema version document, so each migration runs once.
+pub async fn migrate(db: &Database) -> Result<()> {
+    let schema = db.collection::<Document>(SCHEMA_COLLECTION);
+    let mut version = acquire_lock(&schema).await?;
+    if version > SCHEMA_VERSION {
+        release_lock(&schema).await?;
+        return Err(TssError::UnsupportedSchemaVersion(version, SCHEMA_VERSION).into());
+    }
+
+    while version < SCHEMA_VERSION {
+        let next = version + 1;
+        info!("Migrating database schema to version {}", next);
+        if let Err(e) = apply(db, next).await {
+            release_lock(&schema).await?;
+            return Err(e);
+        }
+        schema
+            .update_one(
+                doc! { "_id": SCHEMA_DOC_ID },
+                doc! { "$set": { "version": next as i32 } },
+                None,
+            )
+            .await?;
+        version = next;
+    }
+
+    release_lock(&schema).await
+}
+
+async fn apply(db: &Database, version: u32) -> Result<()> {
+    let requests = db.collection::<Document>("messages_to_sign");
+    match version {
+        // Fields added to stored signing requests, backfilled on existing documents
+        1 => {
+            let pipeline = vec![doc! {
+                "$set": {
+                    "scheme": { "$ifNull": ["$scheme", "Ecdsa"] },
+                    "key_id": { "$ifNull": ["$key_id", { "$toLower": { "$ifNull": ["$scheme", "Ecdsa"] } }] },
+                    // The insertion time is embedded in the ObjectId
+                    "created_at": { "$ifNull": ["$created_at", { "$toLong": { "$toDate": "$_id" } }] },
+                    "participants": { "$ifNull": ["$participants", []] },
+                    "attempts": { "$ifNull": ["$attempts", 0] },
+                    "status_history": { "$ifNull": ["$status_history", []] },
+                }
+            }];
+            requests
+                .update_many(doc! { "key_id": { "$exists": false } }, pipeline, None)
+                .await?;
+        }
+        // Unique request ids, and the listing sorted newest first per status or key.
+        // Inserts racing before the index may have left duplicates, set aside first.
+        2 => {
+            for collection in ["messages_to_sign", "keys_gen_requests"] {
+                archive_duplicates(db, collection).await?;
+            }
+            let indexes = vec![
+                IndexModel::builder()
+                    .keys(doc! { "request_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! { "status": 1, "created_at": -1, "request_id": -1 })
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! { "key_id": 1, "created_at": -1, "request_id": -1 })
+                    .build(),
+            ];
+            requests.create_indexes(indexes, None).await?;
+            db.collection::<Document>("keys_gen_requests")
+                .create_index(
+                    IndexModel::builder()
+                        .keys(doc! { "request_id": 1 })
+                        .options(IndexOptions::builder().unique(true).build())
+                        .build(),
+                    None,
+                )
+                .await?;
+        }
+        // Dead letters, one per request, listed by the latest failure
+        3 => {
+            let indexes = vec![
+                IndexModel::builder()
+                    .keys(doc! { "request_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! { "last_failed_at": -1 })
+                    .build(),
+            ];
+            db.collection::<Document>("dead_letters")
+                .create_indexes(indexes, None)
+                .await?;
+        }
+        // API clients, unique by id and listed oldest first
+        4 => {
+            let indexes = vec![
+                IndexModel::builder()
+                    .keys(doc! { "client_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder().keys(doc! { "created_at": 1 }).build(),
+            ];
+            db.collection::<Document>("api_clients")
+                .create_indexes(indexes, None)
+                .await?;
+        }
+        // Rate limits count the recent requests of a client, denials are listed newest first
+        5 => {
+            requests
+                .create_index(
+                    IndexModel::builder()
+                        .keys(doc! { "client_id": 1, "created_at": -1 })
+                        .build(),
+                    None,
+                )
+                .await?;
+            db.collection::<Document>("policy_denials")
+                .create_index(
+                    IndexModel::builder().keys(doc! { "denied_at": -1 }).build(),
+                    None,
+                )
+                .await?;
+        }
+        _ => return Err(TssError::UnsupportedSchemaVersion(version, SCHEMA_VERSION).into()),
+    }
+    Ok(())
+}
+
+// Keeps one record per request id in `collection` and moves the others to
+// `<collection>_duplicates`, returns the number moved
+async fn archive_duplicates(db: &Database, collection: &str) -> Result<u64> {
+    let records = db.collection::<Document>(collection);
+    let archive = db.collection::<Document>(&format!("{}_duplicates", collection));
+    let pipeline = vec![
+        doc! { "$group": { "_id": "$request_id", "count": { "$sum": 1 } } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+    ];
+    let groups: Vec<Document> = records
+        .aggregate(pipeline, None)
+        .await?
+        .try_collect()
+        .await?;
+
+    let mut archived = 0;
+    for group in groups {
+        let request_id = group.get("_id").cloned().unwrap_or(Bson::Null);
+        let options = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+        let duplicates: Vec<Document> = records
+            .find(doc! { "request_id": request_id.clone() }, options)
+            .await?
+            .try_collect()
+            .await?;
+        let Some(keep) = duplicate_to_keep(&duplicates) else {
+            continue;
+        };
+        for (index, duplicate) in duplicates.into_iter().enumerate() {
+            if index == keep {
+                continue;
+            }
+            let id = duplicate.get("_id").cloned().unwrap_or(Bson::Null);
+            // Upserted by id, a migration interrupted here is retried safely
+            archive
+                .replace_one(
+                    doc! { "_id": id.clone() },
+                    duplicate,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+            records.delete_one(doc! { "_id": id }, None).await?;
+            archived += 1;
+        }
+        warn!(
+            "Moved duplicates of request {} in {} to {}_duplicates",
+            request_id, collection, collection
+        );
+    }
+    Ok(archived)
+}
+
+/// Index of the record kept among the duplicates of one request id, given
+/// oldest first: the record furthest along, the newest of those on a tie
+pub fn duplicate_to_keep(duplicates: &[Document]) -> Option<usize> {
+    let progress = |record: &Document| match record.get_str("status") {
+        Ok("Completed") => 3,
+        Ok("Rejected") => 2,
+        Ok("InProgress") => 1,
+        _ => 0,
+    };
+    duplicates
+        .iter()
+        .enumerate()
+        .max_by_key(|(index, record)| (progress(record), *index))
+        .map(|(index, _)| index)
+}
+
+// Waits for the migration lock and returns the current schema version
+async fn acquire_lock(schema: &Collection<Document>) -> Result<u32> {
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    loop {
+        let now = DateTime::now();
+        let locked_until = DateTime::from_system_time(SystemTime::now() + MIGRATION_LOCK_TIMEOUT);
+        let filter = doc! {
+            "_id": SCHEMA_DOC_ID,
+            "$or": [
+                { "locked_until": { "$exists": false } },
+                { "locked_until": { "$lt": now } },
+            ],
+        };
+        let update = doc! {
+            "$set": { "locked_until": locked_until },
+            "$setOnInsert": { "version": 0_i32 },
+        };
+        match schema
+            .find_one_and_update(filter, update, options.clone())
+            .await
+        {
+            Ok(Some(schema_doc)) => return Ok(schema_doc.get_i32("version").unwrap_or(0) as u32),
+            Ok(None) => {}
+            // The document exists and another replica holds the lock
+            Err(e) if is_duplicate_key(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        info!("Waiting for another instance to finish the database migration");
+        tokio::time::sleep(MIGRATION_LOCK_RETRY).await;
+    }
+}
+
+async fn release_lock(schema: &Collection<Document>) -> Result<()> {
+    schema
+        .update_one(
+            doc! { "_id": SCHEMA_DOC_ID },
+            doc! { "$unset": { "locked_until": "" } },
+            None,
+        )
+        .await?;
+    Ok(())
+}
@@ -1 +1,264 @@
+pub mod migrations;
 pub mod mongodb;
+pub mod room_store;
+pub mod sled;
+
+use crate::auth::ApiClient;
+use crate::common::{
+    Approval, ApprovalDecision, DeadLetter, DeadLetterStored, KeyGenRequest, KeysToStore,
+    MessageStatus, MessageToSignStored, PolicyDenial, SignerResult, SigningProgress,
+    SigningRefusal, SigningRequest, SigningRequestFilter, StatusChange,
+};
+use crate::error::TssError;
+use crate::manager::constants::MAX_MESSAGE_SIZE;
+use crate::manager::policy::SigningPolicy;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Persistence of signing and key generation requests
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn insert_request(&self, request: &SigningRequest) -> Result<()>;
+
+    /// Stores a request held back until `approval` is met
+    async fn insert_request_for_approval(
+        &self,
+        request: &SigningRequest,
+        approval: &Approval,
+    ) -> Result<()>;
+
+    /// Adds the decision of an approver to a request awaiting approval and
+    /// returns the updated request, `None` if the request is not awaiting
+    /// approval or the approver decided already
+    async fn record_approval_decision(
+        &self,
+        request_id: &str,
+        decision: &ApprovalDecision,
+    ) -> Result<Option<MessageToSignStored>>;
+
+    async fn insert_key_gen_request(&self, request: &KeyGenRequest) -> Result<()>;
+
+    async fn update_key_gen_result(&self, request_id: &str, keys: Vec<String>) -> Result<()>;
+
+    async fn get_key_gen_result(&self, request_id: &str) -> Result<Option<KeysToStore>>;
+
+    async fn get_signing_result(&self, id: &str) -> Result<Option<MessageToSignStored>>;
+
+    /// Lists the requests matching `filter`, newest first. Returns at most `limit`
+    /// requests and the cursor of the next page if there is one.
+    async fn list_signing_requests(
+        &self,
+        filter: &SigningRequestFilter,
+        limit: i64,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<MessageToSignStored>, Option<String>)>;
+
+    /// Stores the signature of a pending or in progress request, reported by
+    /// `result.party_id`. Reports of the stored signature on a completed request
+    /// add their party to `reported_by`, other reports are ignored.
+    async fn update_signing_result(&self, result: &SignerResult) -> Result<()>;
+
+    /// Records that a signer joined the locked room `progress.room_uuid`. The
+    /// first report of a room starts a new attempt, later ones add participants.
+    async fn update_signing_progress(&self, progress: &SigningProgress) -> Result<()>;
+
+    /// Records that a signer refused the request under its local policy, once
+    /// per signer. A pending or in progress request refused by more than
+    /// `tolerated` signers is rejected, since no quorum can form without them.
+    async fn record_signing_refusal(
+        &self,
+        refusal: &SigningRefusal,
+        tolerated: usize,
+    ) -> Result<()>;
+
+    /// Records a signer failure, merged into the dead letter of the same request
+    async fn insert_dead_letter(&self, letter: &DeadLetter) -> Result<()>;
+
+    /// Lists dead letters, the most recently failed first
+    async fn list_dead_letters(&self, limit: i64) -> Result<Vec<DeadLetterStored>>;
+
+    /// Dead letter of a request, left in place
+    async fn get_dead_letter(&self, request_id: &str) -> Result<Option<DeadLetterStored>>;
+
+    /// Removes and returns the dead letter of a request, so that it is replayed once
+    async fn take_dead_letter(&self, request_id: &str) -> Result<Option<DeadLetterStored>>;
+
+    /// Counts the signing requests a client submitted at or after `since`
+    async fn count_client_requests(&self, client_id: &str, since: u64) -> Result<u64>;
+
+    async fn insert_api_client(&self, client: &ApiClient) -> Result<()>;
+
+    async fn get_api_client(&self, client_id: &str) -> Result<Option<ApiClient>>;
+
+    /// Replaces the signing policy of a client, returns the updated client or
+    /// `None` if it is unknown
+    async fn set_api_client_policy(
+        &self,
+        client_id: &str,
+        policy: &SigningPolicy,
+    ) -> Result<Option<ApiClient>>;
+
+    /// Lists the API clients, revoked ones included, oldest first
+    async fn list_api_clients(&self) -> Result<Vec<ApiClient>>;
+
+    /// Records the token `jti` issued to a client, returns the client or `None`
+    /// if it is unknown or revoked
+    async fn add_api_client_token(&self, client_id: &str, jti: &str) -> Result<Option<ApiClient>>;
+
+    /// Revokes a client along with every token issued to it, returns false for
+    /// unknown clients
+    async fn revoke_api_client(&self, client_id: &str) -> Result<bool>;
+
+    /// Adds the token `jti` to the revocation list
+    async fn revoke_token(&self, jti: &str) -> Result<()>;
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool>;
+
+    async fn insert_policy_denial(&self, denial: &PolicyDenial) -> Result<()>;
+
+    /// Lists policy denials, the most recent first
+    async fn list_policy_denials(&self, limit: i64) -> Result<Vec<PolicyDenial>>;
+}
+
+// Stored form of a newly submitted signing request
+pub(crate) fn new_signing_record(request: &SigningRequest) -> Result<MessageToSignStored> {
+    if request.message.len() > MAX_MESSAGE_SIZE {
+        return Err(TssError::MessageTooLarge.into());
+    }
+
+    let now = now_millis();
+    Ok(MessageToSignStored {
+        request_id: request.id.clone(),
+        message: request.message.clone(),
+        status: MessageStatus::Pending,
+        signature: None,
+        scheme: request.scheme,
+        key_id: request.scheme.key_id().to_string(),
+        derivation_path: request.derivation_path.clone(),
+        client_id: request.client_id.clone(),
+        transaction: request.transaction.clone(),
+        created_at: now,
+        started_at: None,
+        completed_at: None,
+        room_uuid: None,
+        participants: Vec::new(),
+        reported_by: Vec::new(),
+        attempts: 0,
+        status_history: vec![StatusChange {
+            status: MessageStatus::Pending,
+            at: now,
+            party_id: None,
+            reason: None,
+        }],
+        approval: None,
+        refusals: Vec::new(),
+    })
+}
+
+// Stored form of a request held back until it is approved
+pub(crate) fn new_approval_record(
+    request: &SigningRequest,
+    approval: &Approval,
+) -> Result<MessageToSignStored> {
+    let mut record = new_signing_record(request)?;
+    record.status = MessageStatus::AwaitingApproval;
+    record.status_history[0].status = MessageStatus::AwaitingApproval;
+    record.approval = Some(approval.clone());
+    Ok(record)
+}
+
+// Adds `decision` to a request awaiting approval, false if it cannot be added.
+// A rejection rejects the request, the last approval needed makes it pending.
+pub(crate) fn apply_approval_decision(
+    record: &mut MessageToSignStored,
+    decision: &ApprovalDecision,
+) -> bool {
+    let Some(approval) = record.approval.as_mut() else {
+        return false;
+    };
+    if record.status != MessageStatus::AwaitingApproval
+        || approval
+            .decisions
+            .iter()
+            .any(|previous| previous.approver == decision.approver)
+    {
+        return false;
+    }
+    approval.decisions.push(decision.clone());
+    let approvals = approval.decisions.iter().filter(|d| d.approved).count() as u32;
+    let status = if !decision.approved {
+        MessageStatus::Rejected
+    } else if approvals >= approval.required {
+        MessageStatus::Pending
+    } else {
+        return true;
+    };
+    record.status = status.clone();
+    record.status_history.push(StatusChange {
+        status,
+        at: decision.at,
+        party_id: None,
+        reason: None,
+    });
+    true
+}
+
+// Rejection of a pending or in progress request refused by more than
+// `tolerated` signers, the reason listing their refusals
+pub(crate) fn refusal_rejection(
+    record: &MessageToSignStored,
+    tolerated: usize,
+) -> Option<StatusChange> {
+    let open = matches!(
+        record.status,
+        MessageStatus::Pending | MessageStatus::InProgress
+    );
+    if !open || record.refusals.len() <= tolerated {
+        return None;
+    }
+    let reasons: Vec<String> = record
+        .refusals
+        .iter()
+        .map(|refusal| format!("party {}: {}", refusal.party_id, refusal.error))
+        .collect();
+    Some(StatusChange {
+        status: MessageStatus::Rejected,
+        at: now_millis(),
+        party_id: None,
+        reason: Some(format!("refused by signers, {}", reasons.join("; "))),
+    })
+}
+
+// Stored form of the first failure of a request
+pub(crate) fn new_dead_letter(letter: &DeadLetter) -> DeadLetterStored {
+    DeadLetterStored {
+        request_id: letter.request.id.clone(),
+        request: letter.request.clone(),
+        failures: vec![letter.failure.clone()],
+        first_failed_at: letter.failure.failed_at,
+        last_failed_at: letter.failure.failed_at,
+    }
+}
+
+// Listing position, the sort key of the last request returned
+pub(crate) fn encode_cursor(created_at: u64, request_id: &str) -> String {
+    format!("{}_{}", created_at, request_id)
+}
+
+pub(crate) fn decode_cursor(cursor: &str) -> Result<(u64, String)> {
+    cursor
+        .split_once('_')
+        .and_then(|(created_at, request_id)| {
+            Some((created_at.parse().ok()?, request_id.to_string()))
+        })
+        .ok_or_else(|| TssError::InvalidCursor(cursor.to_string()).into())
+}
+
+// Unix time in milliseconds, the resolution of the request timestamps
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
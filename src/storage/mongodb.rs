@@ -1,57 +1,137 @@
+use crate::auth::ApiClient;
 use crate::common::types::SigningRequest;
-use crate::common::{KeyGenRequest, KeysToStore, MessageStatus, MessageToSignStored, SignerResult};
+use crate::common::Key;
+use crate::common::{
+    Approval, ApprovalDecision, DeadLetter, DeadLetterStored, KeyGenRequest, KeysToStore,
+    MessageStatus, MessageToSignStored, PolicyDenial, SignerResult, SigningFailure,
+    SigningProgress, SigningRefusal, SigningRequestFilter, StatusChange,
+};
 use crate::error::TssError;
-use crate::manager::constants::MAX_MESSAGE_SIZE;
+use crate::manager::policy::SigningPolicy;
+use crate::storage::room_store::{RoomStore, Swap};
+use crate::storage::{
+    apply_approval_decision, decode_cursor, encode_cursor, migrations, new_approval_record,
+    new_signing_record, now_millis, refusal_rejection, Storage,
+};
 use anyhow::Result;
-use mongodb::bson::{doc, to_document, Bson};
-use mongodb::options::UpdateOptions;
-use mongodb::{Client, Collection};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, to_bson, to_document, Bson, DateTime, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{
+    FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument, UpdateOptions,
+};
+use mongodb::{Client, Collection, Database, IndexModel};
+use std::time::{Duration, SystemTime};
+
+const DUPLICATE_KEY_ERROR: i32 = 11000;
 
 pub struct MongoDBStorage {
     requests: Collection<MessageToSignStored>,
     keys_gen_requests: Collection<KeysToStore>,
+    dead_letters: Collection<DeadLetterStored>,
+    api_clients: Collection<ApiClient>,
+    revoked_tokens: Collection<Document>,
+    policy_denials: Collection<PolicyDenial>,
 }
 
 impl MongoDBStorage {
     pub async fn new(uri: &str, db_name: &str) -> Result<Self> {
         let client = Client::with_uri_str(uri).await?;
-        let db = client.database(db_name);
+        Self::with_database(&client.database(db_name)).await
+    }
+
+    /// Storage in `db`, sharing the connection pool of its client
+    pub async fn with_database(db: &Database) -> Result<Self> {
+        migrations::migrate(db).await?;
 
         Ok(Self {
             requests: db.collection::<MessageToSignStored>("messages_to_sign"),
             keys_gen_requests: db.collection::<KeysToStore>("keys_gen_requests"),
+            dead_letters: db.collection::<DeadLetterStored>("dead_letters"),
+            api_clients: db.collection::<ApiClient>("api_clients"),
+            revoked_tokens: db.collection::<Document>("revoked_tokens"),
+            policy_denials: db.collection::<PolicyDenial>("policy_denials"),
         })
     }
 
-    pub async fn insert_request(&self, request: &SigningRequest) -> Result<()> {
-        if request.message.len() > MAX_MESSAGE_SIZE {
-            return Err(TssError::MessageTooLarge.into());
+    async fn insert_record(&self, record: MessageToSignStored) -> Result<()> {
+        let request_id = record.request_id.clone();
+        match self.requests.insert_one(record, None).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => Err(TssError::DuplicateRequest(request_id).into()),
+            Err(e) => Err(e.into()),
         }
+    }
+}
 
-        let message_to_sign = MessageToSignStored {
-            request_id: request.id.clone(),
-            message: request.message.clone(),
-            status: MessageStatus::Pending,
-            signature: None,
-        };
-        self.requests.insert_one(message_to_sign, None).await?;
-        Ok(())
+#[async_trait]
+impl Storage for MongoDBStorage {
+    async fn insert_request(&self, request: &SigningRequest) -> Result<()> {
+        self.insert_record(new_signing_record(request)?).await
+    }
+
+    async fn insert_request_for_approval(
+        &self,
+        request: &SigningRequest,
+        approval: &Approval,
+    ) -> Result<()> {
+        self.insert_record(new_approval_record(request, approval)?)
+            .await
     }
 
-    pub async fn insert_key_gen_request(&self, request: &KeyGenRequest) -> Result<()> {
+    async fn record_approval_decision(
+        &self,
+        request_id: &str,
+        decision: &ApprovalDecision,
+    ) -> Result<Option<MessageToSignStored>> {
+        loop {
+            let Some(mut request) = self.get_signing_result(request_id).await? else {
+                return Ok(None);
+            };
+            let decided = request
+                .approval
+                .as_ref()
+                .map_or(0, |approval| approval.decisions.len());
+            if !apply_approval_decision(&mut request, decision) {
+                return Ok(None);
+            }
+            // Replaced only if no other decision was recorded in the meantime
+            let filter = doc! {
+                "request_id": request_id,
+                "status": Bson::from(MessageStatus::AwaitingApproval),
+                "approval.decisions": { "$size": decided as i64 },
+            };
+            if self
+                .requests
+                .replace_one(filter, &request, None)
+                .await?
+                .matched_count
+                == 1
+            {
+                return Ok(Some(request));
+            }
+        }
+    }
+
+    async fn insert_key_gen_request(&self, request: &KeyGenRequest) -> Result<()> {
         let keys_to_store = KeysToStore {
             request_id: request.id.clone(),
             status: MessageStatus::Pending,
             key_gen_params: request.keygen_params.clone(),
             keys: None,
+            curve: request.keygen_params.scheme.curve(),
         };
-        self.keys_gen_requests
-            .insert_one(keys_to_store, None)
-            .await?;
-        Ok(())
+        match self.keys_gen_requests.insert_one(keys_to_store, None).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => {
+                Err(TssError::DuplicateRequest(request.id.clone()).into())
+            }
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub async fn update_key_gen_result(&self, request_id: &str, keys: Vec<String>) -> Result<()> {
+    async fn update_key_gen_result(&self, request_id: &str, keys: Vec<String>) -> Result<()> {
         let filter = doc! { "request_id": request_id };
         if let Some(mut stored_keys) = self
             .keys_gen_requests
@@ -70,7 +150,8 @@ impl MongoDBStorage {
         Ok(())
     }
 
-    pub async fn get_key_gen_result(&self, request_id: &str) -> Result<Option<KeysToStore>> {
+    #[allow(clippy::needless_borrow)]
+    async fn get_key_gen_result(&self, request_id: &str) -> Result<Option<KeysToStore>> {
         // Validate UUID
         if uuid::Uuid::parse_str(&request_id).is_err() {
             return Err(TssError::InvalidUuid(request_id.to_string()).into());
@@ -83,7 +164,8 @@ impl MongoDBStorage {
         }
     }
 
-    pub async fn get_signing_result(&self, id: &str) -> Result<Option<MessageToSignStored>> {
+    #[allow(clippy::needless_borrow)]
+    async fn get_signing_result(&self, id: &str) -> Result<Option<MessageToSignStored>> {
         // Validate UUID
         if uuid::Uuid::parse_str(&id).is_err() {
             return Err(TssError::InvalidUuid(id.to_string()).into());
@@ -96,28 +178,510 @@ impl MongoDBStorage {
         }
     }
 
-    pub async fn update_signing_result(&self, result: &SignerResult) -> Result<()> {
+    async fn list_signing_requests(
+        &self,
+        filter: &SigningRequestFilter,
+        limit: i64,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<MessageToSignStored>, Option<String>)> {
+        let mut query = Document::new();
+        if let Some(status) = &filter.status {
+            query.insert("status", Bson::from(status.clone()));
+        }
+        if let Some(key_id) = &filter.key_id {
+            query.insert("key_id", key_id);
+        }
+        let mut created_at = Document::new();
+        if let Some(since) = filter.since {
+            created_at.insert("$gte", to_bson(&since)?);
+        }
+        if let Some(until) = filter.until {
+            created_at.insert("$lt", to_bson(&until)?);
+        }
+        if !created_at.is_empty() {
+            query.insert("created_at", created_at);
+        }
+        if let Some(cursor) = cursor {
+            // Continue strictly after the last request of the previous page
+            let (created_at, request_id) = decode_cursor(cursor)?;
+            query.insert(
+                "$or",
+                vec![
+                    doc! { "created_at": { "$lt": to_bson(&created_at)? } },
+                    doc! { "created_at": to_bson(&created_at)?, "request_id": { "$lt": request_id } },
+                ],
+            );
+        }
+
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1, "request_id": -1 })
+            .limit(limit + 1)
+            .build();
+        let mut requests: Vec<MessageToSignStored> = self
+            .requests
+            .find(query, options)
+            .await?
+            .try_collect()
+            .await?;
+
+        let next_cursor = if requests.len() as i64 > limit {
+            requests.truncate(limit as usize);
+            requests
+                .last()
+                .map(|last| encode_cursor(last.created_at, &last.request_id))
+        } else {
+            None
+        };
+        Ok((requests, next_cursor))
+    }
+
+    async fn update_signing_result(&self, result: &SignerResult) -> Result<()> {
         // Validate UUID
         if uuid::Uuid::parse_str(&result.request_id).is_err() {
             return Err(TssError::InvalidUuid(result.request_id.clone()).into());
         }
         let filter = doc! {
             "request_id": &result.request_id,
-            "status": Bson::from(MessageStatus::Pending),
+            "status": { "$in": [Bson::from(MessageStatus::Pending), Bson::from(MessageStatus::InProgress)] },
         };
 
+        let now = now_millis();
+        let change = StatusChange {
+            status: MessageStatus::Completed,
+            at: now,
+            party_id: Some(result.party_id),
+            reason: None,
+        };
         let update = doc! {
             "$set": {
                 "signature": to_document(&result.signature)?,
                 "status": Bson::from(MessageStatus::Completed),
-            }
+                "completed_at": to_bson(&now)?,
+                "reported_by": [to_bson(&result.party_id)?],
+            },
+            "$push": { "status_history": to_bson(&change)? },
         };
 
         let options = UpdateOptions::builder().upsert(false).build();
 
         //Perform atomic update
-        self.requests.update_one(filter, update, options).await?;
-        // If the status is not pending, simply return Ok()
+        if self
+            .requests
+            .update_one(filter, update, options)
+            .await?
+            .matched_count
+            == 1
+        {
+            return Ok(());
+        }
+
+        // Already completed, count the signer if it agrees on the signature
+        let filter = doc! {
+            "request_id": &result.request_id,
+            "status": Bson::from(MessageStatus::Completed),
+            "signature.r": &result.signature.r,
+            "signature.s": &result.signature.s,
+        };
+        let update = doc! { "$addToSet": { "reported_by": to_bson(&result.party_id)? } };
+        self.requests.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    async fn update_signing_progress(&self, progress: &SigningProgress) -> Result<()> {
+        if uuid::Uuid::parse_str(&progress.request_id).is_err() {
+            return Err(TssError::InvalidUuid(progress.request_id.clone()).into());
+        }
+        let now = now_millis();
+        let change = StatusChange {
+            status: MessageStatus::InProgress,
+            at: now,
+            party_id: Some(progress.party_id),
+            reason: None,
+        };
+        let filter = doc! {
+            "request_id": &progress.request_id,
+            "status": { "$in": [Bson::from(MessageStatus::Pending), Bson::from(MessageStatus::InProgress)] },
+            "room_uuid": { "$ne": &progress.room_uuid },
+        };
+        let update = doc! {
+            "$set": {
+                "status": Bson::from(MessageStatus::InProgress),
+                "started_at": to_bson(&now)?,
+                "room_uuid": &progress.room_uuid,
+                "participants": [to_bson(&progress.party_id)?],
+            },
+            "$inc": { "attempts": 1 },
+            "$push": { "status_history": to_bson(&change)? },
+        };
+        if self
+            .requests
+            .update_one(filter, update, None)
+            .await?
+            .matched_count
+            == 1
+        {
+            return Ok(());
+        }
+
+        // Another signer already started this attempt
+        let filter = doc! {
+            "request_id": &progress.request_id,
+            "status": Bson::from(MessageStatus::InProgress),
+            "room_uuid": &progress.room_uuid,
+        };
+        let update = doc! {
+            "$addToSet": { "participants": to_bson(&progress.party_id)? },
+            "$push": { "status_history": to_bson(&change)? },
+        };
+        self.requests.update_one(filter, update, None).await?;
         Ok(())
     }
+
+    async fn record_signing_refusal(
+        &self,
+        refusal: &SigningRefusal,
+        tolerated: usize,
+    ) -> Result<()> {
+        if uuid::Uuid::parse_str(&refusal.request_id).is_err() {
+            return Err(TssError::InvalidUuid(refusal.request_id.clone()).into());
+        }
+        let failure = SigningFailure {
+            party_id: refusal.party_id,
+            error: refusal.reason.clone(),
+            failed_at: now_millis(),
+        };
+        let filter = doc! {
+            "request_id": &refusal.request_id,
+            "refusals.party_id": { "$ne": to_bson(&refusal.party_id)? },
+        };
+        let update = doc! { "$push": { "refusals": to_bson(&failure)? } };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let Some(record) = self
+            .requests
+            .find_one_and_update(filter, update, options)
+            .await?
+        else {
+            return Ok(());
+        };
+        let Some(rejection) = refusal_rejection(&record, tolerated) else {
+            return Ok(());
+        };
+        // A signature stored meanwhile wins over the rejection
+        let filter = doc! {
+            "request_id": &refusal.request_id,
+            "status": Bson::from(record.status),
+        };
+        let update = doc! {
+            "$set": { "status": Bson::from(MessageStatus::Rejected) },
+            "$push": { "status_history": to_bson(&rejection)? },
+        };
+        self.requests.update_one(filter, update, None).await?;
+        Ok(())
+    }
+
+    async fn insert_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        let filter = doc! { "request_id": &letter.request.id };
+        let update = doc! {
+            "$setOnInsert": {
+                "request": to_bson(&letter.request)?,
+                "first_failed_at": to_bson(&letter.failure.failed_at)?,
+            },
+            "$max": { "last_failed_at": to_bson(&letter.failure.failed_at)? },
+            "$push": { "failures": to_bson(&letter.failure)? },
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        loop {
+            match self
+                .dead_letters
+                .update_one(filter.clone(), update.clone(), options.clone())
+                .await
+            {
+                Ok(_) => return Ok(()),
+                // Another failure of the request was inserted first, merge into it
+                Err(e) if is_duplicate_key(&e) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    async fn list_dead_letters(&self, limit: i64) -> Result<Vec<DeadLetterStored>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "last_failed_at": -1 })
+            .limit(limit)
+            .build();
+        let cursor = self.dead_letters.find(None, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn get_dead_letter(&self, request_id: &str) -> Result<Option<DeadLetterStored>> {
+        Ok(self
+            .dead_letters
+            .find_one(doc! { "request_id": request_id }, None)
+            .await?)
+    }
+
+    async fn take_dead_letter(&self, request_id: &str) -> Result<Option<DeadLetterStored>> {
+        Ok(self
+            .dead_letters
+            .find_one_and_delete(doc! { "request_id": request_id }, None)
+            .await?)
+    }
+
+    async fn count_client_requests(&self, client_id: &str, since: u64) -> Result<u64> {
+        let filter = doc! { "client_id": client_id, "created_at": { "$gte": to_bson(&since)? } };
+        Ok(self.requests.count_documents(filter, None).await?)
+    }
+
+    async fn insert_api_client(&self, client: &ApiClient) -> Result<()> {
+        match self.api_clients.insert_one(client, None).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => {
+                Err(TssError::DuplicateRequest(client.client_id.clone()).into())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn list_api_clients(&self) -> Result<Vec<ApiClient>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
+            .build();
+        let cursor = self.api_clients.find(None, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn get_api_client(&self, client_id: &str) -> Result<Option<ApiClient>> {
+        Ok(self
+            .api_clients
+            .find_one(doc! { "client_id": client_id }, None)
+            .await?)
+    }
+
+    async fn set_api_client_policy(
+        &self,
+        client_id: &str,
+        policy: &SigningPolicy,
+    ) -> Result<Option<ApiClient>> {
+        let update = doc! { "$set": { "policy": to_bson(policy)? } };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        Ok(self
+            .api_clients
+            .find_one_and_update(doc! { "client_id": client_id }, update, options)
+            .await?)
+    }
+
+    async fn add_api_client_token(&self, client_id: &str, jti: &str) -> Result<Option<ApiClient>> {
+        let filter = doc! { "client_id": client_id, "revoked_at": Bson::Null };
+        let update = doc! { "$push": { "tokens": jti } };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        Ok(self
+            .api_clients
+            .find_one_and_update(filter, update, options)
+            .await?)
+    }
+
+    async fn revoke_api_client(&self, client_id: &str) -> Result<bool> {
+        let filter = doc! { "client_id": client_id, "revoked_at": Bson::Null };
+        let update = doc! { "$set": { "revoked_at": to_bson(&now_millis())? } };
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let client = match self
+            .api_clients
+            .find_one_and_update(filter, update, options)
+            .await?
+        {
+            Some(client) => client,
+            // Revoked before, repeating this completes an interrupted revocation
+            None => match self
+                .api_clients
+                .find_one(doc! { "client_id": client_id }, None)
+                .await?
+            {
+                Some(client) => client,
+                None => return Ok(false),
+            },
+        };
+        for jti in &client.tokens {
+            self.revoke_token(jti).await?;
+        }
+        Ok(true)
+    }
+
+    async fn revoke_token(&self, jti: &str) -> Result<()> {
+        let update = doc! { "$setOnInsert": { "revoked_at": to_bson(&now_millis())? } };
+        let options = UpdateOptions::builder().upsert(true).build();
+        match self
+            .revoked_tokens
+            .update_one(doc! { "_id": jti }, update, options)
+            .await
+        {
+            Ok(_) => Ok(()),
+            // Revoked concurrently
+            Err(e) if is_duplicate_key(&e) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        Ok(self
+            .revoked_tokens
+            .find_one(doc! { "_id": jti }, None)
+            .await?
+            .is_some())
+    }
+
+    async fn insert_policy_denial(&self, denial: &PolicyDenial) -> Result<()> {
+        self.policy_denials.insert_one(denial, None).await?;
+        Ok(())
+    }
+
+    async fn list_policy_denials(&self, limit: i64) -> Result<Vec<PolicyDenial>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "denied_at": -1 })
+            .limit(limit)
+            .build();
+        let cursor = self.policy_denials.find(None, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+}
+
+pub(crate) fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    match *error.kind {
+        ErrorKind::Write(WriteFailure::WriteError(ref write_error)) => {
+            write_error.code == DUPLICATE_KEY_ERROR
+        }
+        ErrorKind::Command(ref command_error) => command_error.code == DUPLICATE_KEY_ERROR,
+        _ => false,
+    }
+}
+
+/// Room store shared by manager replicas. Entries expire `ttl` after their last
+/// write, both through a TTL index and by filtering on read since the TTL monitor
+/// only runs periodically.
+pub struct MongoRoomStore {
+    rooms: Collection<Document>,
+    ttl: Duration,
+}
+
+impl MongoRoomStore {
+    pub async fn new(db: &Database, ttl: Duration) -> Result<Self> {
+        let rooms = db.collection::<Document>("signing_rooms");
+
+        let index = IndexModel::builder()
+            .keys(doc! { "expires_at": 1 })
+            .options(
+                IndexOptions::builder()
+                    .expire_after(Duration::from_secs(0))
+                    .build(),
+            )
+            .build();
+        rooms.create_index(index, None).await?;
+
+        Ok(Self { rooms, ttl })
+    }
+
+    fn expires_at(&self) -> DateTime {
+        DateTime::from_system_time(SystemTime::now() + self.ttl)
+    }
+}
+
+#[async_trait]
+impl RoomStore for MongoRoomStore {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        let filter = doc! { "_id": key, "expires_at": { "$gt": DateTime::now() } };
+        Ok(self
+            .rooms
+            .find_one(filter, None)
+            .await?
+            .and_then(|entry| entry.get_str("value").ok().map(str::to_string)))
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        let update = doc! { "$set": { "value": value, "expires_at": self.expires_at() } };
+        let options = UpdateOptions::builder().upsert(true).build();
+        self.rooms
+            .update_one(doc! { "_id": key }, update, options)
+            .await?;
+        Ok(())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&str>,
+        value: &str,
+    ) -> Result<Swap> {
+        let update = doc! { "$set": { "value": value, "expires_at": self.expires_at() } };
+        let filter = match expected {
+            Some(expected) => {
+                doc! { "_id": key, "value": expected, "expires_at": { "$gt": DateTime::now() } }
+            }
+            // An expired entry the TTL monitor has not removed yet counts as absent
+            None => doc! { "_id": key, "expires_at": { "$lte": DateTime::now() } },
+        };
+        let result = self.rooms.update_one(filter, update, None).await?;
+        if result.matched_count == 1 {
+            return Ok(Swap::Written);
+        }
+        if expected.is_none() {
+            let entry = doc! { "_id": key, "value": value, "expires_at": self.expires_at() };
+            match self.rooms.insert_one(entry, None).await {
+                Ok(_) => return Ok(Swap::Written),
+                Err(e) if is_duplicate_key(&e) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // Only read on a conflict, the caller retries from the value found
+        Ok(Swap::Conflict(self.get(key).await?))
+    }
+
+    async fn scan_prefix(&self, prefix: &str) -> Result<Vec<(Key, String)>> {
+        let filter = doc! {
+            "_id": { "$regex": format!("^{}", escape_regex(prefix)) },
+            "expires_at": { "$gt": DateTime::now() },
+        };
+        let entries: Vec<Document> = self.rooms.find(filter, None).await?.try_collect().await?;
+        Ok(entries
+            .into_iter()
+            .filter_map(|entry| {
+                let key = entry.get_str("_id").ok()?.to_string();
+                let value = entry.get_str("value").ok()?.to_string();
+                Some((key, value))
+            })
+            .collect())
+    }
+
+    async fn remove_suffix(&self, suffix: &str) -> Result<u64> {
+        let filter = doc! { "_id": { "$regex": format!("{}$", escape_regex(suffix)) } };
+        Ok(self.rooms.delete_many(filter, None).await?.deleted_count)
+    }
+
+    async fn remove_if(&self, key: &str, expected: &str) -> Result<bool> {
+        let filter = doc! { "_id": key, "value": expected };
+        Ok(self.rooms.delete_one(filter, None).await?.deleted_count == 1)
+    }
+
+    async fn purge_expired(&self) -> Result<u64> {
+        // Normally done by the TTL monitor, purging here keeps the reaped count accurate
+        let filter = doc! { "expires_at": { "$lte": DateTime::now() } };
+        Ok(self.rooms.delete_many(filter, None).await?.deleted_count)
+    }
+}
+
+fn escape_regex(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        if !c.is_ascii_alphanumeric() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
 }
@@ -0,0 +1,156 @@
+use crate::common::Key;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// Key value store backing signing rooms and the round messages relayed through
+/// `/set` and `/get`. Shared by every manager replica when the backend is external.
+#[async_trait]
+pub trait RoomStore: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<String>>;
+
+    async fn set(&self, key: &str, value: &str) -> Result<()>;
+
+    /// Writes `value` only if the stored value still equals `expected` (`None`
+    /// meaning the key is absent). A conflict carries the value found, callers
+    /// retry from it without reading the key again.
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&str>,
+        value: &str,
+    ) -> Result<Swap>;
+
+    /// Returns every live entry whose key starts with `prefix`
+    async fn scan_prefix(&self, prefix: &str) -> Result<Vec<(Key, String)>>;
+
+    /// Removes the entries whose key ends with `suffix`, returns how many were removed
+    async fn remove_suffix(&self, suffix: &str) -> Result<u64>;
+
+    /// Removes `key` only if it still holds `expected`, returns whether it was removed
+    async fn remove_if(&self, key: &str, expected: &str) -> Result<bool>;
+
+    /// Drops the entries not written for longer than the store TTL, returns how
+    /// many were removed
+    async fn purge_expired(&self) -> Result<u64>;
+}
+
+/// Outcome of `RoomStore::compare_and_swap`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Swap {
+    Written,
+    /// The value stored instead of the expected one, `None` if the key is absent
+    Conflict(Option<String>),
+}
+
+impl Swap {
+    pub fn is_written(&self) -> bool {
+        *self == Swap::Written
+    }
+}
+
+struct StoredEntry {
+    value: String,
+    written_at: Instant,
+}
+
+/// Process local store, rooms are lost on restart and not shared between replicas
+pub struct InMemoryRoomStore {
+    entries: RwLock<HashMap<Key, StoredEntry>>,
+    ttl: Duration,
+}
+
+impl InMemoryRoomStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    fn is_live(&self, entry: &StoredEntry) -> bool {
+        entry.written_at.elapsed() < self.ttl
+    }
+
+    fn entry(value: &str) -> StoredEntry {
+        StoredEntry {
+            value: value.to_string(),
+            written_at: Instant::now(),
+        }
+    }
+}
+
+#[async_trait]
+impl RoomStore for InMemoryRoomStore {
+    async fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .entries
+            .read()
+            .await
+            .get(key)
+            .filter(|entry| self.is_live(entry))
+            .map(|entry| entry.value.clone()))
+    }
+
+    async fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.entries
+            .write()
+            .await
+            .insert(key.to_string(), Self::entry(value));
+        Ok(())
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<&str>,
+        value: &str,
+    ) -> Result<Swap> {
+        let mut entries = self.entries.write().await;
+        let current = entries
+            .get(key)
+            .filter(|entry| self.is_live(entry))
+            .map(|entry| entry.value.as_str());
+        if current != expected {
+            return Ok(Swap::Conflict(current.map(str::to_string)));
+        }
+        entries.insert(key.to_string(), Self::entry(value));
+        Ok(Swap::Written)
+    }
+
+    async fn scan_prefix(&self, prefix: &str) -> Result<Vec<(Key, String)>> {
+        Ok(self
+            .entries
+            .read()
+            .await
+            .iter()
+            .filter(|(key, entry)| key.starts_with(prefix) && self.is_live(entry))
+            .map(|(key, entry)| (key.clone(), entry.value.clone()))
+            .collect())
+    }
+
+    async fn remove_suffix(&self, suffix: &str) -> Result<u64> {
+        let mut entries = self.entries.write().await;
+        let before = entries.len();
+        entries.retain(|key, _| !key.ends_with(suffix));
+        Ok((before - entries.len()) as u64)
+    }
+
+    async fn remove_if(&self, key: &str, expected: &str) -> Result<bool> {
+        let mut entries = self.entries.write().await;
+        if entries.get(key).map(|entry| entry.value.as_str()) != Some(expected) {
+            return Ok(false);
+        }
+        entries.remove(key);
+        Ok(true)
+    }
+
+    async fn purge_expired(&self) -> Result<u64> {
+        let mut entries = self.entries.write().await;
+        let before = entries.len();
+        entries.retain(|_, entry| entry.written_at.elapsed() < self.ttl);
+        Ok((before - entries.len()) as u64)
+    }
+}
@@ -0,0 +1,597 @@
+use crate::auth::ApiClient;
+use crate::common::{
+    Approval, ApprovalDecision, DeadLetter, DeadLetterStored, KeyGenRequest, KeysToStore,
+    MessageStatus, MessageToSignStored, PolicyDenial, SignerResult, SigningFailure,
+    SigningProgress, SigningRefusal, SigningRequest, SigningRequestFilter, StatusChange,
+};
+use crate::error::TssError;
+use crate::manager::policy::SigningPolicy;
+use crate::storage::{
+    apply_approval_decision, decode_cursor, encode_cursor, new_approval_record, new_dead_letter,
+    new_signing_record, now_millis, refusal_rejection, Storage,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use sled::transaction::{abort, TransactionError};
+use sled::{Transactional, Tree};
+
+/// Embedded storage for single node deployments and tests, no database server
+/// needed. Requests are stored as JSON under their id, with secondary indexes
+/// ordered by creation time for the listing and by client for the rate limits.
+pub struct SledStorage {
+    requests: Tree,
+    // Keys are the big endian `created_at` followed by the request id
+    requests_by_created_at: Tree,
+    // Keys are the client id and `created_at` of the request followed by its id,
+    // for the rate limits
+    requests_by_client: Tree,
+    keys_gen_requests: Tree,
+    dead_letters: Tree,
+    api_clients: Tree,
+    // Ids of revoked tokens, to the revocation time
+    revoked_tokens: Tree,
+    // Keys are the big endian `denied_at` followed by a random id
+    policy_denials: Tree,
+}
+
+impl SledStorage {
+    pub fn new(path: &str) -> Result<Self> {
+        Self::from_db(sled::open(path)?)
+    }
+
+    /// Storage deleted when dropped, for tests
+    pub fn temporary() -> Result<Self> {
+        Self::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    fn from_db(db: sled::Db) -> Result<Self> {
+        let storage = Self {
+            requests: db.open_tree("messages_to_sign")?,
+            requests_by_created_at: db.open_tree("messages_to_sign_by_created_at")?,
+            requests_by_client: db.open_tree("messages_to_sign_by_client")?,
+            keys_gen_requests: db.open_tree("keys_gen_requests")?,
+            dead_letters: db.open_tree("dead_letters")?,
+            api_clients: db.open_tree("api_clients")?,
+            revoked_tokens: db.open_tree("revoked_tokens")?,
+            policy_denials: db.open_tree("policy_denials")?,
+        };
+        storage.index_requests_by_client()?;
+        Ok(storage)
+    }
+
+    // Indexes the requests stored before the client index existed
+    fn index_requests_by_client(&self) -> Result<()> {
+        if !self.requests_by_client.is_empty() {
+            return Ok(());
+        }
+        for entry in self.requests.iter() {
+            let (_, value) = entry?;
+            let request: MessageToSignStored = serde_json::from_slice(&value)?;
+            if let Some(client_id) = &request.client_id {
+                let key = client_key(client_id, request.created_at, &request.request_id);
+                self.requests_by_client.insert(key, &[])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_record(&self, record: &MessageToSignStored) -> Result<()> {
+        let value = serde_json::to_vec(record)?;
+        let index_key = created_at_key(record.created_at, &record.request_id);
+
+        let client_index_key = record
+            .client_id
+            .as_ref()
+            .map(|client_id| client_key(client_id, record.created_at, &record.request_id));
+
+        let result = (
+            &self.requests,
+            &self.requests_by_created_at,
+            &self.requests_by_client,
+        )
+            .transaction(|(requests, requests_by_created_at, requests_by_client)| {
+                if requests.get(record.request_id.as_bytes())?.is_some() {
+                    return abort(());
+                }
+                requests.insert(record.request_id.as_bytes(), value.as_slice())?;
+                requests_by_created_at.insert(index_key.as_slice(), &[])?;
+                if let Some(key) = &client_index_key {
+                    requests_by_client.insert(key.as_slice(), &[])?;
+                }
+                Ok(())
+            });
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(())) => {
+                Err(TssError::DuplicateRequest(record.request_id.clone()).into())
+            }
+            Err(TransactionError::Storage(e)) => Err(e.into()),
+        }
+    }
+
+    // Applies `update` atomically, which returns false to leave the request unchanged
+    fn update_request<F>(&self, request_id: &str, update: F) -> Result<()>
+    where
+        F: Fn(&mut MessageToSignStored) -> bool,
+    {
+        loop {
+            let Some(current) = self.requests.get(request_id)? else {
+                return Ok(());
+            };
+            let mut request: MessageToSignStored = serde_json::from_slice(&current)?;
+            if !update(&mut request) {
+                return Ok(());
+            }
+            let updated = serde_json::to_vec(&request)?;
+            if self
+                .requests
+                .compare_and_swap(request_id, Some(current), Some(updated))?
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn created_at_key(created_at: u64, request_id: &str) -> Vec<u8> {
+    let mut key = created_at.to_be_bytes().to_vec();
+    key.extend_from_slice(request_id.as_bytes());
+    key
+}
+
+// UTF-8 never contains 0xff, so it ends the client id unambiguously
+fn client_prefix(client_id: &str) -> Vec<u8> {
+    let mut prefix = client_id.as_bytes().to_vec();
+    prefix.push(0xff);
+    prefix
+}
+
+fn client_key(client_id: &str, created_at: u64, request_id: &str) -> Vec<u8> {
+    let mut key = client_prefix(client_id);
+    key.extend_from_slice(&created_at_key(created_at, request_id));
+    key
+}
+
+fn validate_uuid(id: &str) -> Result<()> {
+    if uuid::Uuid::parse_str(id).is_err() {
+        return Err(TssError::InvalidUuid(id.to_string()).into());
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Storage for SledStorage {
+    async fn insert_request(&self, request: &SigningRequest) -> Result<()> {
+        self.insert_record(&new_signing_record(request)?)
+    }
+
+    async fn insert_request_for_approval(
+        &self,
+        request: &SigningRequest,
+        approval: &Approval,
+    ) -> Result<()> {
+        self.insert_record(&new_approval_record(request, approval)?)
+    }
+
+    async fn record_approval_decision(
+        &self,
+        request_id: &str,
+        decision: &ApprovalDecision,
+    ) -> Result<Option<MessageToSignStored>> {
+        validate_uuid(request_id)?;
+        let recorded = std::cell::Cell::new(None);
+        self.update_request(request_id, |request| {
+            let applied = apply_approval_decision(request, decision);
+            recorded.set(applied.then(|| request.clone()));
+            applied
+        })?;
+        Ok(recorded.take())
+    }
+
+    async fn insert_key_gen_request(&self, request: &KeyGenRequest) -> Result<()> {
+        let keys_to_store = KeysToStore {
+            request_id: request.id.clone(),
+            status: MessageStatus::Pending,
+            key_gen_params: request.keygen_params.clone(),
+            keys: None,
+            curve: request.keygen_params.scheme.curve(),
+        };
+        let value = serde_json::to_vec(&keys_to_store)?;
+        if self
+            .keys_gen_requests
+            .compare_and_swap(request.id.as_bytes(), None as Option<&[u8]>, Some(value))?
+            .is_err()
+        {
+            return Err(TssError::DuplicateRequest(request.id.clone()).into());
+        }
+        Ok(())
+    }
+
+    async fn update_key_gen_result(&self, request_id: &str, keys: Vec<String>) -> Result<()> {
+        loop {
+            let Some(current) = self.keys_gen_requests.get(request_id)? else {
+                return Ok(());
+            };
+            let mut stored_keys: KeysToStore = serde_json::from_slice(&current)?;
+            if stored_keys.status != MessageStatus::Pending {
+                return Ok(());
+            }
+            stored_keys.keys = Some(keys.clone());
+            stored_keys.status = MessageStatus::Completed;
+            let updated = serde_json::to_vec(&stored_keys)?;
+            if self
+                .keys_gen_requests
+                .compare_and_swap(request_id, Some(current), Some(updated))?
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn get_key_gen_result(&self, request_id: &str) -> Result<Option<KeysToStore>> {
+        validate_uuid(request_id)?;
+        match self.keys_gen_requests.get(request_id)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_signing_result(&self, id: &str) -> Result<Option<MessageToSignStored>> {
+        validate_uuid(id)?;
+        match self.requests.get(id)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn list_signing_requests(
+        &self,
+        filter: &SigningRequestFilter,
+        limit: i64,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<MessageToSignStored>, Option<String>)> {
+        let lower = filter.since.unwrap_or(0).to_be_bytes().to_vec();
+        // `until` and the cursor are exclusive, the cursor key is the last request returned
+        let mut upper = filter.until.map(|until| until.to_be_bytes().to_vec());
+        if let Some(cursor) = cursor {
+            let (created_at, request_id) = decode_cursor(cursor)?;
+            let cursor_key = created_at_key(created_at, &request_id);
+            upper = Some(match upper {
+                Some(until) => until.min(cursor_key),
+                None => cursor_key,
+            });
+        }
+        let entries = match upper {
+            Some(upper) => self.requests_by_created_at.range(lower..upper),
+            None => self.requests_by_created_at.range(lower..),
+        };
+
+        let mut requests = Vec::new();
+        for entry in entries.rev() {
+            let (index_key, _) = entry?;
+            let request_id = std::str::from_utf8(&index_key[8..])?;
+            let Some(value) = self.requests.get(request_id)? else {
+                continue;
+            };
+            let request: MessageToSignStored = serde_json::from_slice(&value)?;
+            if filter.status.as_ref().is_some_and(|s| *s != request.status)
+                || filter.key_id.as_ref().is_some_and(|k| *k != request.key_id)
+            {
+                continue;
+            }
+            requests.push(request);
+            if requests.len() as i64 > limit {
+                break;
+            }
+        }
+
+        let next_cursor = if requests.len() as i64 > limit {
+            requests.truncate(limit as usize);
+            requests
+                .last()
+                .map(|last| encode_cursor(last.created_at, &last.request_id))
+        } else {
+            None
+        };
+        Ok((requests, next_cursor))
+    }
+
+    async fn update_signing_result(&self, result: &SignerResult) -> Result<()> {
+        validate_uuid(&result.request_id)?;
+        self.update_request(&result.request_id, |request| {
+            if matches!(
+                request.status,
+                MessageStatus::AwaitingApproval | MessageStatus::Rejected
+            ) {
+                return false;
+            }
+            if request.status == MessageStatus::Completed {
+                // Another signer of the same session agreeing on the signature
+                let agrees = request.signature.as_ref().is_some_and(|signature| {
+                    signature.r == result.signature.r && signature.s == result.signature.s
+                });
+                if !agrees || request.reported_by.contains(&result.party_id) {
+                    return false;
+                }
+                request.reported_by.push(result.party_id);
+                return true;
+            }
+            let now = now_millis();
+            request.signature = Some(result.signature.clone());
+            request.status = MessageStatus::Completed;
+            request.completed_at = Some(now);
+            request.reported_by = vec![result.party_id];
+            request.status_history.push(StatusChange {
+                status: MessageStatus::Completed,
+                at: now,
+                party_id: Some(result.party_id),
+                reason: None,
+            });
+            true
+        })
+    }
+
+    async fn update_signing_progress(&self, progress: &SigningProgress) -> Result<()> {
+        validate_uuid(&progress.request_id)?;
+        self.update_request(&progress.request_id, |request| {
+            let now = now_millis();
+            let same_room = request.room_uuid.as_deref() == Some(progress.room_uuid.as_str());
+            match request.status {
+                MessageStatus::AwaitingApproval
+                | MessageStatus::Completed
+                | MessageStatus::Rejected => return false,
+                // Another signer already started this attempt
+                MessageStatus::InProgress if same_room => {
+                    if !request.participants.contains(&progress.party_id) {
+                        request.participants.push(progress.party_id);
+                    }
+                }
+                _ if same_room => return false,
+                _ => {
+                    request.status = MessageStatus::InProgress;
+                    request.started_at = Some(now);
+                    request.room_uuid = Some(progress.room_uuid.clone());
+                    request.participants = vec![progress.party_id];
+                    request.attempts += 1;
+                }
+            }
+            request.status_history.push(StatusChange {
+                status: MessageStatus::InProgress,
+                at: now,
+                party_id: Some(progress.party_id),
+                reason: None,
+            });
+            true
+        })
+    }
+
+    async fn record_signing_refusal(
+        &self,
+        refusal: &SigningRefusal,
+        tolerated: usize,
+    ) -> Result<()> {
+        validate_uuid(&refusal.request_id)?;
+        self.update_request(&refusal.request_id, |request| {
+            if request
+                .refusals
+                .iter()
+                .any(|previous| previous.party_id == refusal.party_id)
+            {
+                return false;
+            }
+            request.refusals.push(SigningFailure {
+                party_id: refusal.party_id,
+                error: refusal.reason.clone(),
+                failed_at: now_millis(),
+            });
+            if let Some(rejection) = refusal_rejection(request, tolerated) {
+                request.status = MessageStatus::Rejected;
+                request.status_history.push(rejection);
+            }
+            true
+        })
+    }
+
+    async fn insert_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        let request_id = letter.request.id.as_bytes();
+        loop {
+            let current = self.dead_letters.get(request_id)?;
+            let dead_letter = match &current {
+                Some(current) => {
+                    let mut dead_letter: DeadLetterStored = serde_json::from_slice(current)?;
+                    dead_letter.failures.push(letter.failure.clone());
+                    dead_letter.last_failed_at =
+                        dead_letter.last_failed_at.max(letter.failure.failed_at);
+                    dead_letter
+                }
+                None => new_dead_letter(letter),
+            };
+            let updated = serde_json::to_vec(&dead_letter)?;
+            if self
+                .dead_letters
+                .compare_and_swap(request_id, current, Some(updated))?
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn list_dead_letters(&self, limit: i64) -> Result<Vec<DeadLetterStored>> {
+        let mut dead_letters = self
+            .dead_letters
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect::<Result<Vec<DeadLetterStored>>>()?;
+        dead_letters.sort_by_key(|dead_letter| std::cmp::Reverse(dead_letter.last_failed_at));
+        dead_letters.truncate(limit as usize);
+        Ok(dead_letters)
+    }
+
+    async fn get_dead_letter(&self, request_id: &str) -> Result<Option<DeadLetterStored>> {
+        match self.dead_letters.get(request_id)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn take_dead_letter(&self, request_id: &str) -> Result<Option<DeadLetterStored>> {
+        match self.dead_letters.remove(request_id)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn count_client_requests(&self, client_id: &str, since: u64) -> Result<u64> {
+        let prefix = client_prefix(client_id);
+        let mut lower = prefix.clone();
+        lower.extend_from_slice(&since.to_be_bytes());
+        let mut count = 0;
+        for entry in self.requests_by_client.range(lower..) {
+            let (index_key, _) = entry?;
+            if !index_key.starts_with(&prefix) {
+                break;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    async fn insert_api_client(&self, client: &ApiClient) -> Result<()> {
+        let value = serde_json::to_vec(client)?;
+        if self
+            .api_clients
+            .compare_and_swap(
+                client.client_id.as_bytes(),
+                None as Option<&[u8]>,
+                Some(value),
+            )?
+            .is_err()
+        {
+            return Err(TssError::DuplicateRequest(client.client_id.clone()).into());
+        }
+        Ok(())
+    }
+
+    async fn list_api_clients(&self) -> Result<Vec<ApiClient>> {
+        let mut clients = self
+            .api_clients
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect::<Result<Vec<ApiClient>>>()?;
+        clients.sort_by_key(|client| client.created_at);
+        Ok(clients)
+    }
+
+    async fn get_api_client(&self, client_id: &str) -> Result<Option<ApiClient>> {
+        match self.api_clients.get(client_id)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn set_api_client_policy(
+        &self,
+        client_id: &str,
+        policy: &SigningPolicy,
+    ) -> Result<Option<ApiClient>> {
+        loop {
+            let Some(current) = self.api_clients.get(client_id)? else {
+                return Ok(None);
+            };
+            let mut client: ApiClient = serde_json::from_slice(&current)?;
+            client.policy = policy.clone();
+            let updated = serde_json::to_vec(&client)?;
+            if self
+                .api_clients
+                .compare_and_swap(client_id, Some(current), Some(updated))?
+                .is_ok()
+            {
+                return Ok(Some(client));
+            }
+        }
+    }
+
+    async fn add_api_client_token(&self, client_id: &str, jti: &str) -> Result<Option<ApiClient>> {
+        loop {
+            let Some(current) = self.api_clients.get(client_id)? else {
+                return Ok(None);
+            };
+            let mut client: ApiClient = serde_json::from_slice(&current)?;
+            if client.revoked_at.is_some() {
+                return Ok(None);
+            }
+            client.tokens.push(jti.to_string());
+            let updated = serde_json::to_vec(&client)?;
+            if self
+                .api_clients
+                .compare_and_swap(client_id, Some(current), Some(updated))?
+                .is_ok()
+            {
+                return Ok(Some(client));
+            }
+        }
+    }
+
+    async fn revoke_api_client(&self, client_id: &str) -> Result<bool> {
+        let client = loop {
+            let Some(current) = self.api_clients.get(client_id)? else {
+                return Ok(false);
+            };
+            let mut client: ApiClient = serde_json::from_slice(&current)?;
+            if client.revoked_at.is_some() {
+                break client;
+            }
+            client.revoked_at = Some(now_millis());
+            let updated = serde_json::to_vec(&client)?;
+            if self
+                .api_clients
+                .compare_and_swap(client_id, Some(current), Some(updated))?
+                .is_ok()
+            {
+                break client;
+            }
+        };
+        // No token is added to a revoked client, repeating this completes an
+        // interrupted revocation
+        for jti in &client.tokens {
+            self.revoke_token(jti).await?;
+        }
+        Ok(true)
+    }
+
+    async fn revoke_token(&self, jti: &str) -> Result<()> {
+        // Keeps the time of the first revocation
+        let _ = self.revoked_tokens.compare_and_swap(
+            jti,
+            None as Option<&[u8]>,
+            Some(&now_millis().to_be_bytes()),
+        )?;
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, jti: &str) -> Result<bool> {
+        Ok(self.revoked_tokens.contains_key(jti)?)
+    }
+
+    async fn insert_policy_denial(&self, denial: &PolicyDenial) -> Result<()> {
+        let key = created_at_key(denial.denied_at, &uuid::Uuid::new_v4().to_string());
+        self.policy_denials
+            .insert(key, serde_json::to_vec(denial)?)?;
+        Ok(())
+    }
+
+    async fn list_policy_denials(&self, limit: i64) -> Result<Vec<PolicyDenial>> {
+        self.policy_denials
+            .iter()
+            .values()
+            .rev()
+            .take(limit as usize)
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect()
+    }
+}
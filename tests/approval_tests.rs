@@ -0,0 +1,294 @@
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use config::{Config, File, FileFormat};
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tss_network::auth::{JwtKeys, Role};
+use tss_network::common::secp256k1def::GE;
+use tss_network::common::{MessageStatus, SigningRequest};
+use tss_network::config::{
+    QueueBackend, QueueConfig, RoomStoreConfig, Settings, SignerPolicyConfig, StorageBackend,
+    StorageConfig,
+};
+use tss_network::manager::api::{
+    approve_signing_request, list_signing_requests, reject_signing_request, sign,
+};
+use tss_network::manager::policy::SigningPolicy;
+use tss_network::manager::service::ManagerService;
+use tss_network::queue::{InProcessQueue, RequestQueue};
+use tss_network::signer::SignerPolicy;
+
+const SETTINGS: &str = r#"
+manager_url = "http://127.0.0.1"
+manager_port = 8080
+signing_timeout = 30
+threshold = 1
+total_parties = 3
+path = "0/1"
+signer_key_file = ""
+
+[security]
+jwt_secret = "jwt-secret"
+jwt_expiration = 600
+allowed_signer_ips = ["127.0.0.1"]
+"#;
+
+fn settings() -> Settings {
+    Config::builder()
+        .add_source(File::from_str(SETTINGS, FileFormat::Toml))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap()
+}
+
+async fn manager() -> ManagerService {
+    let storage_path = std::env::temp_dir().join(format!("tss-approval-{}", uuid::Uuid::new_v4()));
+    let storage = StorageConfig {
+        backend: StorageBackend::Sled,
+        path: storage_path.to_str().unwrap().to_string(),
+    };
+    let queue = QueueConfig {
+        backend: QueueBackend::InProcess,
+        ..QueueConfig::default()
+    };
+    ManagerService::new("", "", 1, 3, &storage, &RoomStoreConfig::default(), &queue)
+        .await
+        .unwrap()
+}
+
+// Tokens of a client whose requests need two approvals, and of three approvers
+async fn client(settings: Settings) -> (Client, String, Vec<String>) {
+    let keys = JwtKeys::load(&settings.security).unwrap();
+    let manager = manager().await;
+    let policy = SigningPolicy {
+        approvals_required: 2,
+        ..SigningPolicy::default()
+    };
+    let (_, wallet) = manager
+        .create_api_client("wallet", Role::Public, 600, policy, &keys)
+        .await
+        .unwrap();
+    let mut approvers = Vec::new();
+    for name in ["alice", "bob", "carol"] {
+        let (_, token) = manager
+            .create_api_client(name, Role::Approver, 600, Default::default(), &keys)
+            .await
+            .unwrap();
+        approvers.push(token.token);
+    }
+    let rocket = rocket::build()
+        .manage(Arc::new(manager))
+        .manage(Arc::new(settings))
+        .manage(Arc::new(keys))
+        .mount(
+            "/",
+            routes![
+                sign,
+                list_signing_requests,
+                approve_signing_request,
+                reject_signing_request
+            ],
+        );
+    (
+        Client::untracked(rocket).await.unwrap(),
+        wallet.token,
+        approvers,
+    )
+}
+
+async fn post(client: &Client, path: &str, token: &str, body: Value) -> (Status, Value) {
+    let response = client
+        .post(path.to_string())
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .body(body.to_string())
+        .dispatch()
+        .await;
+    let status = response.status();
+    (status, response.into_json().await.unwrap_or(Value::Null))
+}
+
+async fn awaiting_approval(client: &Client, token: &str) -> Vec<String> {
+    let response = client
+        .get("/signing_requests?status=AwaitingApproval")
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body: Value = response.into_json().await.unwrap();
+    body["requests"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|request| request["request_id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+// Digest the approvers of `request_id` sign, as listed
+async fn approval_digest(client: &Client, token: &str, request_id: &str) -> [u8; 32] {
+    let response = client
+        .get("/signing_requests?status=AwaitingApproval")
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .dispatch()
+        .await;
+    let body: Value = response.into_json().await.unwrap();
+    let request = body["requests"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|request| request["request_id"] == request_id)
+        .unwrap();
+    hex::decode(request["approval"]["digest"].as_str().unwrap())
+        .unwrap()
+        .try_into()
+        .unwrap()
+}
+
+fn sign_approval(digest: &[u8; 32], secret: u8) -> Value {
+    let secp = Secp256k1::new();
+    let secret = SecretKey::from_slice(&[secret; 32]).unwrap();
+    let signature = secp.sign_ecdsa(&Message::from_digest(*digest), &secret);
+    json!({
+        "public_key": hex::encode(secret.public_key(&secp).serialize()),
+        "signature": hex::encode(signature.serialize_der()),
+    })
+}
+
+// Tests share the process wide queue, requests of other tests are skipped
+async fn published(queue: &InProcessQueue, request_id: &str) -> SigningRequest {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            let received = queue.receive_signing_request().await.unwrap();
+            if received.request.id == request_id {
+                return received.request;
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_approved_request_is_published() {
+    let queue = InProcessQueue::shared();
+    let (client, wallet, approvers) = client(settings()).await;
+
+    let (status, body) = post(&client, "/sign", &wallet, json!({ "message": "a" })).await;
+    assert_eq!(status, Status::Created);
+    assert_eq!(body["status"], "AwaitingApproval");
+    let request_id = body["request_id"].as_str().unwrap().to_string();
+    assert_eq!(
+        awaiting_approval(&client, &approvers[0]).await,
+        vec![request_id.clone()]
+    );
+
+    let approve = format!("/signing_requests/{}/approve", request_id);
+    // Only approvers decide
+    let (status, _) = post(&client, &approve, &wallet, json!({})).await;
+    assert_eq!(status, Status::Forbidden);
+
+    let (status, body) = post(
+        &client,
+        &approve,
+        &approvers[0],
+        json!({ "comment": "checked the destination" }),
+    )
+    .await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], json!(MessageStatus::AwaitingApproval));
+    assert_eq!(
+        body["approval"]["decisions"][0]["comment"],
+        "checked the destination"
+    );
+    // One vote per approver
+    let (status, _) = post(&client, &approve, &approvers[0], json!({})).await;
+    assert_eq!(status, Status::Conflict);
+
+    let (status, body) = post(&client, &approve, &approvers[1], json!({})).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], json!(MessageStatus::Pending));
+    assert_eq!(published(&queue, &request_id).await.message, b"a".to_vec());
+    assert!(awaiting_approval(&client, &approvers[0]).await.is_empty());
+
+    // Decided requests take no more decisions
+    let (status, _) = post(&client, &approve, &approvers[2], json!({})).await;
+    assert_eq!(status, Status::Conflict);
+    let missing = format!("/signing_requests/{}/approve", uuid::Uuid::new_v4());
+    let (status, _) = post(&client, &missing, &approvers[2], json!({})).await;
+    assert_eq!(status, Status::NotFound);
+}
+
+#[tokio::test]
+async fn test_rejected_request_is_never_published() {
+    let queue = InProcessQueue::shared();
+    let (client, wallet, approvers) = client(settings()).await;
+
+    let (_, body) = post(&client, "/sign", &wallet, json!({ "message": "b" })).await;
+    let request_id = body["request_id"].as_str().unwrap().to_string();
+
+    let approve = format!("/signing_requests/{}/approve", request_id);
+    let reject = format!("/signing_requests/{}/reject", request_id);
+    let (status, _) = post(&client, &approve, &approvers[0], json!({})).await;
+    assert_eq!(status, Status::Ok);
+    let (status, body) = post(&client, &reject, &approvers[1], json!({})).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["status"], json!(MessageStatus::Rejected));
+    let (status, _) = post(&client, &approve, &approvers[2], json!({})).await;
+    assert_eq!(status, Status::Conflict);
+
+    assert!(
+        timeout(Duration::from_millis(200), published(&queue, &request_id))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_approval_signatures_reach_the_signers() {
+    let queue = InProcessQueue::shared();
+    let (client, wallet, approvers) = client(settings()).await;
+
+    let (_, body) = post(&client, "/sign", &wallet, json!({ "message": "c" })).await;
+    let request_id = body["request_id"].as_str().unwrap().to_string();
+    let digest = approval_digest(&client, &approvers[0], &request_id).await;
+
+    let approve = format!("/signing_requests/{}/approve", request_id);
+    // Signatures must be over the digest of the request
+    let (status, _) = post(
+        &client,
+        &approve,
+        &approvers[0],
+        json!({ "signature": sign_approval(&[0; 32], 5) }),
+    )
+    .await;
+    assert_eq!(status, Status::BadRequest);
+    for (approver, secret) in [(&approvers[0], 5), (&approvers[1], 6)] {
+        let (status, _) = post(
+            &client,
+            &approve,
+            approver,
+            json!({ "signature": sign_approval(&digest, secret) }),
+        )
+        .await;
+        assert_eq!(status, Status::Ok);
+    }
+
+    let request = published(&queue, &request_id).await;
+    assert_eq!(request.approvals.len(), 2);
+    let public_key = |secret: u8| {
+        let secret = SecretKey::from_slice(&[secret; 32]).unwrap();
+        hex::encode(secret.public_key(&Secp256k1::new()).serialize())
+    };
+    let policy = SignerPolicy::from_config(&SignerPolicyConfig {
+        approver_keys: vec![public_key(5), public_key(6)],
+        approvals_required: 2,
+        ..SignerPolicyConfig::default()
+    })
+    .unwrap();
+    policy.check(&request, "0/1", &GE::generator()).unwrap();
+}
@@ -0,0 +1,541 @@
+use config::{Config, File, FileFormat};
+use jsonwebtoken::{decode_header, encode, EncodingKey, Header};
+use rcgen::{KeyPair, PKCS_ECDSA_P256_SHA256};
+use rocket::http::{ContentType, Header as HttpHeader, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::routes;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tss_network::auth::{
+    create_signer_token, create_token, validate_signer_token, JwtKeys, Role, SignerClaims,
+};
+use tss_network::common::{signing_room_id, RoomState, SignatureScheme};
+use tss_network::config::{
+    QueueBackend, QueueConfig, RoomStoreConfig, Settings, StorageBackend, StorageConfig,
+};
+use tss_network::manager::api::{
+    create_api_client, get_signing_result, issue_api_token, list_api_clients, revoke_api_client,
+    revoke_token, ApiClientCreatedDTO, ApiTokenDTO,
+};
+use tss_network::manager::handlers::{
+    get, set, signup_sign, update_signing_progress, update_signing_result,
+};
+use tss_network::manager::policy::SigningPolicy;
+use tss_network::manager::service::{ManagerService, SchemePublicKeys};
+
+const SETTINGS: &str = r#"
+manager_url = "http://127.0.0.1"
+manager_port = 8080
+signing_timeout = 30
+threshold = 1
+total_parties = 3
+path = ""
+signer_key_file = ""
+
+[security]
+jwt_secret = "jwt-secret"
+jwt_expiration = 600
+allowed_signer_ips = ["127.0.0.1"]
+signers = [
+    { party_id = 1, secret = "secret-1" },
+    { party_id = 2, secret = "secret-2" },
+    { party_id = 3, secret = "secret-3" },
+]
+"#;
+
+fn settings() -> Settings {
+    settings_with("")
+}
+
+// Settings with `security` lines appended
+fn settings_with(security: &str) -> Settings {
+    Config::builder()
+        .add_source(File::from_str(
+            &format!("{}{}", SETTINGS, security),
+            FileFormat::Toml,
+        ))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap()
+}
+
+fn token(party_id: u16) -> String {
+    create_signer_token(party_id, &format!("secret-{}", party_id)).unwrap()
+}
+
+#[test]
+fn test_signer_token_is_bound_to_party() {
+    let settings = settings();
+    assert_eq!(validate_signer_token(&token(2), &settings).unwrap(), 2);
+
+    // Signed with the secret of another party
+    let forged = create_signer_token(2, "secret-1").unwrap();
+    assert!(validate_signer_token(&forged, &settings).is_err());
+
+    // No credential configured for the party
+    let unknown = create_signer_token(4, "secret-4").unwrap();
+    assert!(validate_signer_token(&unknown, &settings).is_err());
+
+    // Key id of party 2 on the claims of party 1
+    let claims = SignerClaims {
+        party_id: 1,
+        exp: usize::MAX,
+        iat: 0,
+    };
+    let header = Header {
+        kid: Some("2".to_string()),
+        ..Header::default()
+    };
+    let mismatched = encode(
+        &header,
+        &claims,
+        &EncodingKey::from_secret("secret-2".as_ref()),
+    )
+    .unwrap();
+    assert!(validate_signer_token(&mismatched, &settings).is_err());
+
+    // API tokens do not authenticate signers
+    let admin = create_token(
+        &JwtKeys::load(&settings.security).unwrap(),
+        "admin",
+        Role::Admin,
+        600,
+    )
+    .unwrap();
+    assert!(validate_signer_token(&admin, &settings).is_err());
+}
+
+async fn manager() -> ManagerService {
+    let settings = settings();
+    let storage_path = std::env::temp_dir().join(format!("tss-auth-{}", uuid::Uuid::new_v4()));
+    let storage = StorageConfig {
+        backend: StorageBackend::Sled,
+        path: storage_path.to_str().unwrap().to_string(),
+    };
+    let queue = QueueConfig {
+        backend: QueueBackend::InProcess,
+        ..QueueConfig::default()
+    };
+    ManagerService::new(
+        "",
+        "",
+        settings.threshold,
+        settings.total_parties,
+        &storage,
+        &RoomStoreConfig::default(),
+        &queue,
+    )
+    .await
+    .unwrap()
+}
+
+async fn client() -> Client {
+    let rocket = rocket::build()
+        .manage(Arc::new(manager().await))
+        .manage(Arc::new(SchemePublicKeys::load(&settings()).unwrap()))
+        .manage(Arc::new(settings()))
+        .mount(
+            "/",
+            routes![
+                signup_sign,
+                set,
+                get,
+                update_signing_progress,
+                update_signing_result
+            ],
+        );
+    Client::untracked(rocket).await.unwrap()
+}
+
+async fn post<'c>(
+    client: &'c Client,
+    path: &'static str,
+    token: Option<String>,
+    body: Value,
+) -> LocalResponse<'c> {
+    let remote: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+    let mut request = client
+        .post(path)
+        .remote(remote)
+        .header(ContentType::JSON)
+        .body(body.to_string());
+    if let Some(token) = token {
+        request = request.header(HttpHeader::new(
+            "Authorization",
+            format!("Bearer {}", token),
+        ));
+    }
+    request.dispatch().await
+}
+
+async fn post_json(
+    client: &Client,
+    path: &'static str,
+    party_id: u16,
+    body: Value,
+) -> Result<Value, String> {
+    let response = post(client, path, Some(token(party_id)), body).await;
+    assert_eq!(response.status(), Status::Ok);
+    let answer: Value = response.into_json().await.unwrap();
+    match answer.get("Ok") {
+        Some(value) => Ok(value.clone()),
+        None => Err(answer["Err"]["error"].as_str().unwrap().to_string()),
+    }
+}
+
+fn signup(party_id: u16, party_uuid: &str) -> Value {
+    signup_in("room", party_id, party_uuid)
+}
+
+fn signup_in(room_id: &str, party_id: u16, party_uuid: &str) -> Value {
+    json!({
+        "threshold": 1,
+        "room_id": room_id,
+        "party_number": party_id,
+        "party_uuid": party_uuid,
+    })
+}
+
+#[tokio::test]
+async fn test_signer_endpoints_require_token() {
+    let client = client().await;
+    let response = post(&client, "/signupsign", None, signup(1, "")).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let forged = create_signer_token(1, "secret-2").unwrap();
+    let response = post(&client, "/signupsign", Some(forged), signup(1, "")).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[tokio::test]
+async fn test_signers_only_act_as_their_party() {
+    let client = client().await;
+
+    // Party 1 cannot sign up as party 2
+    let error = post_json(&client, "/signupsign", 1, signup(2, ""))
+        .await
+        .unwrap_err();
+    assert!(error.starts_with("Unauthorized"));
+
+    let party1 = post_json(&client, "/signupsign", 1, signup(1, ""))
+        .await
+        .unwrap();
+    post_json(&client, "/signupsign", 2, signup(2, ""))
+        .await
+        .unwrap();
+    // The ping of party 1 locks the full room
+    let party1_uuid = party1["party_uuid"].as_str().unwrap();
+    let locked = post_json(&client, "/signupsign", 1, signup(1, party1_uuid))
+        .await
+        .unwrap();
+    let room_uuid = locked["room_uuid"].as_str().unwrap().to_string();
+    assert!(!room_uuid.is_empty());
+    let order1 = locked["party_order"].as_u64().unwrap();
+    let order2 = 3 - order1;
+
+    let own_key = format!("{}-round1-{}", order1, room_uuid);
+    let other_key = format!("{}-round1-{}", order2, room_uuid);
+    let entry = |key: &str| json!({ "key": key, "value": "message" });
+    post_json(&client, "/set", 1, entry(&own_key))
+        .await
+        .unwrap();
+    // The room read to authorize the message records its round
+    let manager = client.rocket().state::<Arc<ManagerService>>().unwrap();
+    let room = manager.get_signing_room("room").await.unwrap().unwrap();
+    assert_eq!(room.state, RoomState::InRound(1));
+    assert!(room.rounds["round1"].contains(&(order1 as u16)));
+    assert!(post_json(&client, "/set", 1, entry(&other_key))
+        .await
+        .is_err());
+    // Party 3 is not in the room
+    let own_key3 = format!("3-round1-{}", room_uuid);
+    assert!(post_json(&client, "/set", 3, entry(&own_key3))
+        .await
+        .is_err());
+    // Keys outside signing rooms are rejected
+    assert!(post_json(&client, "/set", 1, entry("signup-sign-room"))
+        .await
+        .is_err());
+
+    let index = json!({ "key": own_key });
+    let read = post_json(&client, "/get", 2, index.clone()).await.unwrap();
+    assert_eq!(read["value"], "message");
+    assert!(post_json(&client, "/get", 3, index).await.is_err());
+
+    let progress = |party_id: u16| json!({ "request_id": "request", "room_uuid": room_uuid, "party_id": party_id });
+    assert!(
+        post_json(&client, "/update_signing_progress", 2, progress(1))
+            .await
+            .is_err()
+    );
+
+    let result = json!({
+        "request_id": "request",
+        "party_id": 1,
+        "signature": {
+            "r": "", "s": "", "status": "", "recid": 0, "x": "", "y": "", "msg_int": [],
+        },
+    });
+    let error = post_json(&client, "/update_signing_result", 2, result)
+        .await
+        .unwrap_err();
+    assert!(error.starts_with("Unauthorized"));
+}
+
+#[tokio::test]
+async fn test_requests_for_the_same_message_sign_in_separate_rooms() {
+    let client = client().await;
+    let rooms = ["request-a", "request-b"].map(|id| signing_room_id(SignatureScheme::Ecdsa, id));
+    assert_ne!(rooms[0], rooms[1]);
+
+    // Parties 1 and 2 sign up for both requests at once
+    let mut room_uuids = Vec::new();
+    let mut orders = Vec::new();
+    for room_id in &rooms {
+        let party1 = post_json(&client, "/signupsign", 1, signup_in(room_id, 1, ""))
+            .await
+            .unwrap();
+        post_json(&client, "/signupsign", 2, signup_in(room_id, 2, ""))
+            .await
+            .unwrap();
+        let party1_uuid = party1["party_uuid"].as_str().unwrap();
+        let locked = post_json(
+            &client,
+            "/signupsign",
+            1,
+            signup_in(room_id, 1, party1_uuid),
+        )
+        .await
+        .unwrap();
+        room_uuids.push(locked["room_uuid"].as_str().unwrap().to_string());
+        orders.push(locked["party_order"].as_u64().unwrap());
+    }
+    assert_ne!(room_uuids[0], room_uuids[1]);
+
+    // Rounds of one request do not advance the other
+    let manager = client.rocket().state::<Arc<ManagerService>>().unwrap();
+    let key = format!("{}-round1-{}", orders[0], room_uuids[0]);
+    let entry = json!({ "key": key, "value": "message" });
+    post_json(&client, "/set", 1, entry).await.unwrap();
+    let first = manager.get_signing_room(&rooms[0]).await.unwrap().unwrap();
+    let second = manager.get_signing_room(&rooms[1]).await.unwrap().unwrap();
+    assert_eq!(first.state, RoomState::InRound(1));
+    assert_eq!(second.state, RoomState::Locked);
+    assert!(second.rounds.is_empty());
+}
+
+#[test]
+fn test_api_tokens_use_configured_expiration() {
+    let settings = settings();
+    let keys = JwtKeys::load(&settings.security).unwrap();
+    let token = create_token(
+        &keys,
+        "user",
+        Role::Public,
+        settings.security.jwt_expiration,
+    )
+    .unwrap();
+    let claims = keys.validate(&token).unwrap();
+    assert_eq!(claims.exp - claims.iat, 600);
+    assert!(!claims.jti.is_empty());
+}
+
+// Writes an ES256 key pair, returns the paths of its public and private key
+fn write_key_pair(dir: &Path, kid: &str) -> (String, String) {
+    let key_pair = KeyPair::generate(&PKCS_ECDSA_P256_SHA256).unwrap();
+    let public_key = dir.join(format!("{}.pub.pem", kid));
+    let private_key = dir.join(format!("{}.pem", kid));
+    std::fs::write(&public_key, key_pair.public_key_pem()).unwrap();
+    std::fs::write(&private_key, key_pair.serialize_pem()).unwrap();
+    (
+        public_key.to_str().unwrap().to_string(),
+        private_key.to_str().unwrap().to_string(),
+    )
+}
+
+fn issue(keys: &JwtKeys) -> String {
+    let jti = uuid::Uuid::new_v4().to_string();
+    keys.issue("user", Role::Public, 600, &jti).unwrap().token
+}
+
+#[test]
+fn test_jwt_keys_rotate() {
+    let dir = std::env::temp_dir().join(format!("tss-jwt-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (public_a, private_a) = write_key_pair(&dir, "a");
+    let (public_b, private_b) = write_key_pair(&dir, "b");
+
+    let keys_a = JwtKeys::load(
+        &settings_with(&format!(
+            r#"jwt_keys = [{{ kid = "a", algorithm = "ES256", public_key = "{}", private_key = "{}" }}]"#,
+            public_a, private_a
+        ))
+        .security,
+    )
+    .unwrap();
+    let token_a = issue(&keys_a);
+    assert_eq!(decode_header(&token_a).unwrap().kid.as_deref(), Some("a"));
+    keys_a.validate(&token_a).unwrap();
+
+    // Key b signs new tokens, key a only verifies the tokens it signed
+    let rotated = JwtKeys::load(
+        &settings_with(&format!(
+            r#"jwt_signing_key = "b"
+jwt_keys = [
+    {{ kid = "a", algorithm = "ES256", public_key = "{}" }},
+    {{ kid = "b", algorithm = "ES256", public_key = "{}", private_key = "{}" }},
+]"#,
+            public_a, public_b, private_b
+        ))
+        .security,
+    )
+    .unwrap();
+    let token_b = issue(&rotated);
+    assert_eq!(decode_header(&token_b).unwrap().kid.as_deref(), Some("b"));
+    rotated.validate(&token_a).unwrap();
+    rotated.validate(&token_b).unwrap();
+    assert!(keys_a.validate(&token_b).is_err());
+
+    // Signed with key b under the id of key a
+    let mut forged = token_b.split('.').collect::<Vec<_>>();
+    let header_a = token_a.split('.').next().unwrap();
+    forged[0] = header_a;
+    assert!(rotated.validate(&forged.join(".")).is_err());
+
+    // HS256 tokens of the secret are refused once keys are configured, unless
+    // explicitly kept for legacy clients
+    let legacy = create_token(
+        &JwtKeys::load(&settings().security).unwrap(),
+        "user",
+        Role::Public,
+        600,
+    )
+    .unwrap();
+    assert!(rotated.validate(&legacy).is_err());
+    let keys = format!(
+        r#"jwt_keys = [{{ kid = "b", algorithm = "ES256", public_key = "{}", private_key = "{}" }}]"#,
+        public_b, private_b
+    );
+    let accepting = JwtKeys::load(
+        &settings_with(&format!("jwt_accept_legacy_hs256 = true\n{}", keys)).security,
+    )
+    .unwrap();
+    accepting.validate(&legacy).unwrap();
+    accepting.validate(&token_b).unwrap();
+}
+
+async fn request<'c>(
+    client: &'c Client,
+    method: &str,
+    path: String,
+    token: &str,
+    body: Option<Value>,
+) -> LocalResponse<'c> {
+    let mut request = match method {
+        "GET" => client.get(path),
+        _ => client.post(path),
+    }
+    .header(HttpHeader::new(
+        "Authorization",
+        format!("Bearer {}", token),
+    ));
+    if let Some(body) = body {
+        request = request.header(ContentType::JSON).body(body.to_string());
+    }
+    request.dispatch().await
+}
+
+#[tokio::test]
+async fn test_api_clients_are_managed_by_admins() {
+    let settings = settings();
+    let keys = JwtKeys::load(&settings.security).unwrap();
+    let manager = manager().await;
+    let (_, admin) = manager
+        .create_api_client(
+            "operator",
+            Role::Admin,
+            600,
+            SigningPolicy::default(),
+            &keys,
+        )
+        .await
+        .unwrap();
+    let admin = admin.token;
+    let rocket = rocket::build()
+        .manage(Arc::new(manager))
+        .manage(Arc::new(settings))
+        .manage(Arc::new(keys))
+        .mount(
+            "/",
+            routes![
+                create_api_client,
+                list_api_clients,
+                issue_api_token,
+                revoke_api_client,
+                revoke_token,
+                get_signing_result
+            ],
+        );
+    let client = Client::untracked(rocket).await.unwrap();
+    let result_path = || format!("/signing_result/{}", uuid::Uuid::new_v4());
+
+    let signer = json!({ "name": "signer", "role": "Signer" });
+    let response = request(&client, "POST", "/api_clients".into(), &admin, Some(signer)).await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let wallet = json!({ "name": "wallet", "role": "Public", "token_expiration": 120 });
+    let response = request(&client, "POST", "/api_clients".into(), &admin, Some(wallet)).await;
+    assert_eq!(response.status(), Status::Created);
+    let created: ApiClientCreatedDTO = response.into_json().await.unwrap();
+    let client_id = created.client.client_id.clone();
+    let first = created.token;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as usize;
+    assert!((now + 110..=now + 120).contains(&first.expires_at));
+
+    let response = request(&client, "GET", result_path(), &first.token, None).await;
+    assert_eq!(response.status(), Status::Ok);
+    // Public tokens cannot manage clients
+    let response = request(&client, "GET", "/api_clients".into(), &first.token, None).await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let path = format!("/api_clients/{}/tokens", client_id);
+    let response = request(&client, "POST", path.clone(), &admin, None).await;
+    assert_eq!(response.status(), Status::Ok);
+    let second: ApiTokenDTO = response.into_json().await.unwrap();
+
+    // Revoking a token leaves the other tokens of the client valid
+    let revoke = format!("/tokens/{}/revoke", first.jti);
+    let response = request(&client, "POST", revoke, &admin, None).await;
+    assert_eq!(response.status(), Status::NoContent);
+    let response = request(&client, "GET", result_path(), &first.token, None).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = request(&client, "GET", result_path(), &second.token, None).await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Revoking the client revokes all its tokens and stops issuing new ones
+    let revoke = format!("/api_clients/{}/revoke", client_id);
+    let response = request(&client, "POST", revoke, &admin, None).await;
+    assert_eq!(response.status(), Status::NoContent);
+    let response = request(&client, "GET", result_path(), &second.token, None).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = request(&client, "POST", path, &admin, None).await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = request(&client, "GET", "/api_clients".into(), &admin, None).await;
+    let clients: Value = response.into_json().await.unwrap();
+    let names: Vec<&str> = clients
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|client| client["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["operator", "wallet"]);
+    assert!(clients[1]["revoked_at"].is_u64());
+    assert_eq!(clients[1]["tokens"].as_array().unwrap().len(), 2);
+}
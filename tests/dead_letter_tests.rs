@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tss_network::common::{DeadLetter, SignatureScheme, SigningFailure, SigningRequest};
+use tss_network::config::{
+    QueueBackend, QueueConfig, RoomStoreConfig, StorageBackend, StorageConfig,
+};
+use tss_network::manager::service::ManagerService;
+use tss_network::queue::{InProcessQueue, ReceivedDeadLetter, ReceivedRequest, RequestQueue};
+
+// Queue whose publishes fail while `failing` is set, like a broker that is down
+struct FlakyQueue {
+    inner: InProcessQueue,
+    failing: AtomicBool,
+}
+
+#[async_trait]
+impl RequestQueue for FlakyQueue {
+    async fn publish_signing_request(&self, request: &SigningRequest) -> Result<()> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(anyhow!("broker unavailable"));
+        }
+        self.inner.publish_signing_request(request).await
+    }
+
+    async fn subscribe(&self, consumer: &str) -> Result<()> {
+        RequestQueue::subscribe(&self.inner, consumer).await
+    }
+
+    async fn receive_signing_request(&self) -> Result<ReceivedRequest> {
+        self.inner.receive_signing_request().await
+    }
+
+    async fn publish_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        self.inner.publish_dead_letter(letter).await
+    }
+
+    async fn receive_dead_letter(&self) -> Result<ReceivedDeadLetter> {
+        self.inner.receive_dead_letter().await
+    }
+}
+
+async fn manager(queue: Arc<FlakyQueue>) -> ManagerService {
+    let storage_path =
+        std::env::temp_dir().join(format!("tss-dead-letter-{}", uuid::Uuid::new_v4()));
+    let storage = StorageConfig {
+        backend: StorageBackend::Sled,
+        path: storage_path.to_str().unwrap().to_string(),
+    };
+    let queue_config = QueueConfig {
+        backend: QueueBackend::InProcess,
+        ..QueueConfig::default()
+    };
+    let mut manager = ManagerService::new(
+        "",
+        "",
+        1,
+        3,
+        &storage,
+        &RoomStoreConfig::default(),
+        &queue_config,
+    )
+    .await
+    .unwrap();
+    manager.queue = queue;
+    manager
+}
+
+#[tokio::test]
+async fn test_failed_replay_keeps_the_dead_letter() {
+    let queue = Arc::new(FlakyQueue {
+        inner: InProcessQueue::new(),
+        failing: AtomicBool::new(true),
+    });
+    let signers = queue.inner.subscribe();
+    let manager = manager(queue.clone()).await;
+    let request = SigningRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        message: b"message".to_vec(),
+        scheme: SignatureScheme::Ecdsa,
+        derivation_path: None,
+        client_id: None,
+        transaction: None,
+        approvals: Vec::new(),
+    };
+    let letter = DeadLetter {
+        request: request.clone(),
+        failure: SigningFailure {
+            party_id: 1,
+            error: "timed out".to_string(),
+            failed_at: 100,
+        },
+    };
+    manager.storage.insert_dead_letter(&letter).await.unwrap();
+
+    let request_ids = vec![request.id.clone()];
+    assert!(manager.replay_dead_letters(&request_ids).await.is_err());
+    let kept = manager
+        .storage
+        .get_dead_letter(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(kept.failures, vec![letter.failure]);
+
+    queue.failing.store(false, Ordering::SeqCst);
+    assert_eq!(
+        manager.replay_dead_letters(&request_ids).await.unwrap(),
+        request_ids
+    );
+    assert_eq!(
+        signers.receive_signing_request().await.unwrap().request.id,
+        request.id
+    );
+    assert!(manager
+        .storage
+        .get_dead_letter(&request.id)
+        .await
+        .unwrap()
+        .is_none());
+    // Replayed once
+    assert!(manager
+        .replay_dead_letters(&request_ids)
+        .await
+        .unwrap()
+        .is_empty());
+}
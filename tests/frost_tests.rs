@@ -0,0 +1,274 @@
+use bitcoin::secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey};
+use curv::elliptic::curves::Scalar;
+use ed25519_dalek::{Signature as Ed25519Signature, Verifier, VerifyingKey};
+use tss_network::common::frost::{
+    self, Ciphersuite, Ed25519Sha512, FrostKeyShare, KeyGenCommitment, KeyGenSecrets,
+    NonceCommitment, Secp256k1Bip340,
+};
+use tss_network::common::signature::verify_signature_data;
+use tss_network::common::{CurveKind, SignatureData, SignatureScheme};
+use tss_network::error::TssError;
+
+const CONTEXT: &str = "frost-test";
+
+// Runs the DKG for all parties in process, as the manager's keygen tasks do over the relay
+fn keygen<C: Ciphersuite>(threshold: u16, parties: u16) -> Vec<FrostKeyShare<C::Curve>> {
+    let (secrets, commitments): (Vec<KeyGenSecrets<_>>, Vec<KeyGenCommitment<_>>) = (1..=parties)
+        .map(|i| frost::keygen_commit::<C>(i, threshold, CONTEXT))
+        .unzip();
+    for commitment in &commitments {
+        assert!(frost::verify_keygen_commitment::<C>(
+            commitment, threshold, CONTEXT
+        ));
+    }
+
+    (1..=parties)
+        .map(|i| {
+            let shares: Vec<Scalar<C::Curve>> = secrets
+                .iter()
+                .zip(commitments.iter())
+                .map(|(sender, commitment)| {
+                    let share = sender.share_for(i);
+                    assert!(frost::verify_keygen_share(
+                        &share,
+                        i,
+                        &commitment.commitments
+                    ));
+                    share
+                })
+                .collect();
+            frost::finalize_keygen::<C>(i, threshold, &shares, &commitments)
+        })
+        .collect()
+}
+
+fn sign<C: Ciphersuite>(key_shares: &[&FrostKeyShare<C::Curve>], message: &[u8]) -> SignatureData {
+    let (nonces, commitments): (Vec<_>, Vec<NonceCommitment<C::Curve>>) = key_shares
+        .iter()
+        .map(|key_share| frost::commit_nonces(key_share.party_id))
+        .unzip();
+    let shares: Vec<Scalar<C::Curve>> = key_shares
+        .iter()
+        .zip(nonces.iter())
+        .map(|(key_share, nonces)| {
+            frost::sign_share::<C>(key_share, nonces, message, &commitments).unwrap()
+        })
+        .collect();
+    for (commitment, share) in commitments.iter().zip(shares.iter()) {
+        assert!(frost::verify_signature_share::<C>(
+            key_shares[0],
+            commitment.party_id,
+            share,
+            message,
+            &commitments
+        )
+        .unwrap());
+    }
+
+    let (r, s) = frost::aggregate::<C>(message, &commitments, &shares);
+    assert!(frost::verify::<C>(
+        &key_shares[0].public_key,
+        message,
+        &r,
+        &s
+    ));
+    SignatureData::new_frost::<C>(&r, &s, &key_shares[0].public_key, message)
+}
+
+#[test]
+fn test_keygen_shares_agree_on_even_y_group_key() {
+    let key_shares = keygen::<Secp256k1Bip340>(1, 3);
+
+    for key_share in &key_shares {
+        assert_eq!(key_share.public_key, key_shares[0].public_key);
+        assert_eq!(
+            key_share.verification_shares,
+            key_shares[0].verification_shares
+        );
+        assert!(frost::has_even_y(&key_share.public_key));
+    }
+}
+
+#[test]
+fn test_any_threshold_subset_produces_valid_bip340_signature() {
+    let key_shares = keygen::<Secp256k1Bip340>(1, 3);
+    let message = [7u8; 32];
+    let secp = Secp256k1::verification_only();
+
+    for signers in [[0, 1], [0, 2], [2, 1]] {
+        let signers: Vec<_> = signers.iter().map(|i| &key_shares[*i]).collect();
+        let signature = sign::<Secp256k1Bip340>(&signers, &message);
+
+        assert_eq!(signature.scheme, SignatureScheme::Schnorr);
+        assert_eq!(signature.compact, format!("{}{}", signature.r, signature.s));
+
+        // Cross check with libsecp256k1
+        let sig =
+            schnorr::Signature::from_slice(&hex::decode(&signature.compact).unwrap()).unwrap();
+        let public_key =
+            XOnlyPublicKey::from_slice(&hex::decode(&signature.public_key).unwrap()).unwrap();
+        assert!(secp
+            .verify_schnorr(&sig, &Message::from_digest(message), &public_key)
+            .is_ok());
+        assert!(secp
+            .verify_schnorr(&sig, &Message::from_digest([8u8; 32]), &public_key)
+            .is_err());
+    }
+}
+
+#[test]
+fn test_any_threshold_subset_produces_valid_ed25519_signature() {
+    let key_shares = keygen::<Ed25519Sha512>(1, 3);
+    let message = b"arbitrary length message signed with Ed25519";
+
+    for signers in [[0, 1], [0, 2], [2, 1]] {
+        let signers: Vec<_> = signers.iter().map(|i| &key_shares[*i]).collect();
+        let signature = sign::<Ed25519Sha512>(&signers, message);
+
+        assert_eq!(signature.scheme, SignatureScheme::Eddsa);
+        assert_eq!(signature.scheme.curve(), CurveKind::Ed25519);
+
+        // Cross check with an RFC 8032 implementation
+        let sig_bytes: [u8; 64] = hex::decode(&signature.compact).unwrap().try_into().unwrap();
+        let key_bytes: [u8; 32] = hex::decode(&signature.public_key)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let public_key = VerifyingKey::from_bytes(&key_bytes).unwrap();
+        let sig = Ed25519Signature::from_bytes(&sig_bytes);
+        assert!(public_key.verify(message, &sig).is_ok());
+        assert!(public_key.verify(b"another message", &sig).is_err());
+    }
+}
+
+// What the manager checks before it accepts a signature reported by a signer
+fn assert_verifies<C: Ciphersuite>(signature: &SignatureData, message: &[u8]) {
+    let public_key = &signature.public_key;
+    verify_signature_data(signature, C::SCHEME, message, public_key).unwrap();
+    assert!(verify_signature_data(signature, C::SCHEME, b"another message", public_key).is_err());
+
+    let mut forged = signature.clone();
+    forged.s = forged.r.clone();
+    forged.compact = format!("{}{}", forged.r, forged.s);
+    assert!(verify_signature_data(&forged, C::SCHEME, message, public_key).is_err());
+}
+
+#[test]
+fn test_reported_frost_signatures_are_verified() {
+    let key_shares = keygen::<Secp256k1Bip340>(1, 3);
+    let message = [7u8; 32];
+    let signature = sign::<Secp256k1Bip340>(&[&key_shares[0], &key_shares[2]], &message);
+    assert_verifies::<Secp256k1Bip340>(&signature, &message);
+    let other_key = keygen::<Secp256k1Bip340>(1, 3)[0].public_key.clone();
+    let other_key = hex::encode(Secp256k1Bip340::encode_point(&other_key));
+    assert!(
+        verify_signature_data(&signature, SignatureScheme::Schnorr, &message, &other_key).is_err()
+    );
+
+    let key_shares = keygen::<Ed25519Sha512>(1, 3);
+    let message = b"arbitrary length message signed with Ed25519";
+    let signature = sign::<Ed25519Sha512>(&[&key_shares[1], &key_shares[2]], message);
+    assert_verifies::<Ed25519Sha512>(&signature, message);
+}
+
+#[test]
+fn test_invalid_signature_share_is_rejected() {
+    let key_shares = keygen::<Secp256k1Bip340>(1, 3);
+    let message = b"message";
+    let (nonces, commitments): (Vec<_>, Vec<NonceCommitment<_>>) = key_shares[..2]
+        .iter()
+        .map(|key_share| frost::commit_nonces(key_share.party_id))
+        .unzip();
+    let share =
+        frost::sign_share::<Secp256k1Bip340>(&key_shares[0], &nonces[0], message, &commitments)
+            .unwrap();
+
+    assert!(frost::verify_signature_share::<Secp256k1Bip340>(
+        &key_shares[1],
+        1,
+        &share,
+        message,
+        &commitments
+    )
+    .unwrap());
+    // A share is bound to the party that produced it and to the message
+    assert!(!frost::verify_signature_share::<Secp256k1Bip340>(
+        &key_shares[1],
+        2,
+        &share,
+        message,
+        &commitments
+    )
+    .unwrap());
+    assert!(!frost::verify_signature_share::<Secp256k1Bip340>(
+        &key_shares[1],
+        1,
+        &share,
+        b"other message",
+        &commitments
+    )
+    .unwrap());
+}
+
+// Party ids come from peers, ids outside the key or the signing set must not panic
+fn reject_out_of_range_party_ids<C: Ciphersuite>() {
+    let key_shares = keygen::<C>(1, 3);
+    let message = b"message";
+    let (nonces, mut commitments): (Vec<_>, Vec<NonceCommitment<_>>) = key_shares[..2]
+        .iter()
+        .map(|key_share| frost::commit_nonces(key_share.party_id))
+        .unzip();
+    let share = frost::sign_share::<C>(&key_shares[0], &nonces[0], message, &commitments).unwrap();
+
+    for party_id in [0, 4] {
+        commitments[1].party_id = party_id;
+        assert!(matches!(
+            frost::verify_signature_share::<C>(
+                &key_shares[0],
+                party_id,
+                &share,
+                message,
+                &commitments
+            ),
+            Err(TssError::InvalidPartyId(id)) if id == party_id
+        ));
+    }
+    // A party of the key that is not in the signing set
+    assert!(matches!(
+        frost::verify_signature_share::<C>(&key_shares[0], 3, &share, message, &commitments),
+        Err(TssError::InvalidPartyId(3))
+    ));
+    // Signing without our own commitment in the set
+    assert!(matches!(
+        frost::sign_share::<C>(&key_shares[2], &nonces[0], message, &commitments),
+        Err(TssError::InvalidPartyId(3))
+    ));
+}
+
+#[test]
+fn test_out_of_range_party_ids_are_rejected() {
+    reject_out_of_range_party_ids::<Secp256k1Bip340>();
+    reject_out_of_range_party_ids::<Ed25519Sha512>();
+}
+
+#[test]
+fn test_keygen_rejects_forged_proof_of_knowledge() {
+    let (_, mut commitment) = frost::keygen_commit::<Ed25519Sha512>(1, 1, CONTEXT);
+    assert!(!frost::verify_keygen_commitment::<Ed25519Sha512>(
+        &commitment,
+        1,
+        "other-session"
+    ));
+    assert!(!frost::verify_keygen_commitment::<Ed25519Sha512>(
+        &commitment,
+        2,
+        CONTEXT
+    ));
+
+    commitment.proof_mu = Scalar::random();
+    assert!(!frost::verify_keygen_commitment::<Ed25519Sha512>(
+        &commitment,
+        1,
+        CONTEXT
+    ));
+}
@@ -0,0 +1,206 @@
+use config::{Config, File, FileFormat};
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tss_network::auth::{JwtKeys, Role};
+use tss_network::common::{SignatureScheme, SigningProgress, SigningRequest};
+use tss_network::config::{
+    QueueBackend, QueueConfig, RoomStoreConfig, Settings, StorageBackend, StorageConfig,
+};
+use tss_network::manager::api::list_signing_requests;
+use tss_network::manager::service::ManagerService;
+
+const SETTINGS: &str = r#"
+manager_url = "http://127.0.0.1"
+manager_port = 8080
+signing_timeout = 30
+threshold = 1
+total_parties = 3
+path = ""
+signer_key_file = ""
+
+[security]
+jwt_secret = "jwt-secret"
+jwt_expiration = 600
+allowed_signer_ips = ["127.0.0.1"]
+"#;
+
+fn settings() -> Settings {
+    Config::builder()
+        .add_source(File::from_str(SETTINGS, FileFormat::Toml))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap()
+}
+
+async fn manager() -> ManagerService {
+    let storage_path = std::env::temp_dir().join(format!("tss-listing-{}", uuid::Uuid::new_v4()));
+    let storage = StorageConfig {
+        backend: StorageBackend::Sled,
+        path: storage_path.to_str().unwrap().to_string(),
+    };
+    let queue = QueueConfig {
+        backend: QueueBackend::InProcess,
+        ..QueueConfig::default()
+    };
+    ManagerService::new("", "", 1, 3, &storage, &RoomStoreConfig::default(), &queue)
+        .await
+        .unwrap()
+}
+
+fn signing_request(scheme: SignatureScheme) -> SigningRequest {
+    SigningRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        message: b"message".to_vec(),
+        scheme,
+        derivation_path: None,
+        client_id: None,
+        transaction: None,
+        approvals: Vec::new(),
+    }
+}
+
+// Client with the requests of `schemes` stored oldest first, and their ids
+async fn client(schemes: &[SignatureScheme]) -> (Client, Vec<String>) {
+    let manager = manager().await;
+    let mut ids = Vec::new();
+    for scheme in schemes {
+        let request = signing_request(*scheme);
+        manager.storage.insert_request(&request).await.unwrap();
+        ids.push(request.id);
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+    let settings = settings();
+    let rocket = rocket::build()
+        .manage(Arc::new(manager))
+        .manage(Arc::new(JwtKeys::load(&settings.security).unwrap()))
+        .manage(Arc::new(settings))
+        .mount("/", routes![list_signing_requests]);
+    (Client::untracked(rocket).await.unwrap(), ids)
+}
+
+async fn token(client: &Client, role: Role) -> String {
+    let manager = client.rocket().state::<Arc<ManagerService>>().unwrap();
+    let keys = client.rocket().state::<Arc<JwtKeys>>().unwrap();
+    let (_, issued) = manager
+        .create_api_client("client", role, 600, Default::default(), keys)
+        .await
+        .unwrap();
+    issued.token
+}
+
+async fn list(client: &Client, token: &str, query: &str) -> (Status, Value) {
+    let response = client
+        .get(format!("/signing_requests{}", query))
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .dispatch()
+        .await;
+    let status = response.status();
+    (status, response.into_json().await.unwrap_or(Value::Null))
+}
+
+fn request_ids(page: &Value) -> Vec<String> {
+    page["requests"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|request| request["request_id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_signing_requests_are_listed_to_admins_and_approvers() {
+    let (client, ids) = client(&[SignatureScheme::Ecdsa]).await;
+
+    let public = token(&client, Role::Public).await;
+    assert_eq!(list(&client, &public, "").await.0, Status::Forbidden);
+    for role in [Role::Admin, Role::Approver] {
+        let token = token(&client, role).await;
+        let (status, page) = list(&client, &token, "").await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(request_ids(&page), ids);
+    }
+}
+
+#[tokio::test]
+async fn test_signing_requests_page_through_the_cursor() {
+    let (client, ids) = client(&[
+        SignatureScheme::Ecdsa,
+        SignatureScheme::Schnorr,
+        SignatureScheme::Ecdsa,
+        SignatureScheme::Ecdsa,
+        SignatureScheme::Ecdsa,
+    ])
+    .await;
+    let admin = token(&client, Role::Admin).await;
+
+    // Newest first, the ECDSA requests only
+    let mut listed = Vec::new();
+    let mut query = "?key_id=ecdsa&limit=2".to_string();
+    let mut pages = 0;
+    loop {
+        let (status, page) = list(&client, &admin, &query).await;
+        assert_eq!(status, Status::Ok);
+        listed.extend(request_ids(&page));
+        pages += 1;
+        match page["next_cursor"].as_str() {
+            Some(cursor) => query = format!("?key_id=ecdsa&limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+    assert_eq!(pages, 2);
+    assert_eq!(
+        listed.iter().collect::<Vec<_>>(),
+        [&ids[4], &ids[3], &ids[2], &ids[0]]
+    );
+
+    // Status and creation time filters
+    let manager = client.rocket().state::<Arc<ManagerService>>().unwrap();
+    manager
+        .update_signing_progress(SigningProgress {
+            request_id: ids[3].clone(),
+            room_uuid: "room".to_string(),
+            party_id: 1,
+        })
+        .await
+        .unwrap();
+    let (_, page) = list(&client, &admin, "?status=InProgress").await;
+    assert_eq!(request_ids(&page), vec![ids[3].clone()]);
+    let (_, page) = list(&client, &admin, "").await;
+    let created_at = |index: usize| page["requests"][4 - index]["created_at"].as_u64().unwrap();
+    // `since` is inclusive and `until` exclusive
+    let (since, until) = (created_at(1), created_at(3));
+    let (_, page) = list(
+        &client,
+        &admin,
+        &format!("?since={}&until={}", since, until),
+    )
+    .await;
+    assert_eq!(request_ids(&page), vec![ids[2].clone(), ids[1].clone()]);
+}
+
+#[tokio::test]
+async fn test_signing_request_listing_rejects_bad_parameters() {
+    let (client, _) = client(&[SignatureScheme::Ecdsa]).await;
+    let admin = token(&client, Role::Admin).await;
+
+    for query in [
+        "?status=Unknown",
+        "?limit=0",
+        "?limit=501",
+        "?cursor=not-a-cursor",
+        "?cursor=x_y",
+    ] {
+        assert_eq!(
+            list(&client, &admin, query).await.0,
+            Status::BadRequest,
+            "{}",
+            query
+        );
+    }
+    assert_eq!(list(&client, &admin, "?limit=500").await.0, Status::Ok);
+}
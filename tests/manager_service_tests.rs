@@ -1,6 +1,4 @@
 use reqwest::Client;
-use rocket::http::hyper::body;
-use rocket::response;
 use serde_json::{json, Value};
 use std::process::{Command, Stdio};
 use std::time::Duration;
@@ -76,7 +74,7 @@ async fn test_signing_flow() {
     );
 
     let timeout_duration = Duration::from_secs(60); // Adjust as needed
-    let result = timeout(
+    let _result = timeout(
         timeout_duration,
         poll_signing_result(&client, &signing_res_dto.request_id),
     )
@@ -154,7 +152,7 @@ async fn test_keygen_flow() {
     );
 
     let stored_response = client
-        .get(&format!(
+        .get(format!(
             "http://127.0.0.1:8080/key_gen_result/{}",
             keygen_res_dto.request_id
         ))
@@ -180,6 +178,7 @@ async fn test_keygen_flow() {
 
     // Clean up: kill the processes
     manager.kill().expect("Failed to kill manager");
+    manager.wait().expect("Failed to wait for manager");
     println!("key gen Test completed successfully");
 }
 // Function to poll for signing result
@@ -205,6 +204,9 @@ async fn poll_signing_result(client: &Client, request_id: &str) -> MessageToSign
                     println!("Status in progress, waiting...");
                     sleep(Duration::from_secs(1)).await;
                 }
+                MessageStatus::AwaitingApproval | MessageStatus::Rejected => {
+                    panic!("Unexpected status {:?}", stored_message.status);
+                }
             }
         } else {
             panic!("Unexpected empty response");
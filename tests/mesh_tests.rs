@@ -0,0 +1,238 @@
+use futures::future::join_all;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tss_network::common::frost::{FrostKeyShare, Secp256k1Bip340};
+use tss_network::common::{InMemoryTransport, MeshNode, MeshTransport, SignatureData, Transport};
+use tss_network::config::{MeshConfig, MeshPeer};
+use tss_network::manager::keygen::{frost_keygen, keygen};
+use tss_network::signer::service::{ecdsa_sign, frost_sign, SignerData};
+
+const THRESHOLD: u16 = 1;
+const PARTIES: u16 = 3;
+const MESSAGE: &[u8] = b"protocol over the mesh";
+const SESSION_ID: &str = "7d0c9e4a-2b1f-4c3d-9e8a-6f5b4a3c2d1e";
+
+fn server_name(party_id: u16) -> String {
+    format!("signer{}.tss.test", party_id)
+}
+
+fn new_ca() -> Certificate {
+    let mut params = CertificateParams::new(Vec::<String>::new());
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    Certificate::from_params(params).unwrap()
+}
+
+// Writes the CA, certificate and key of a signer, returns their paths
+fn write_identity(dir: &Path, name: &str, ca: &Certificate, server_name: &str) -> [String; 3] {
+    let cert =
+        Certificate::from_params(CertificateParams::new(vec![server_name.to_string()])).unwrap();
+    let files = [
+        (format!("{}-ca.pem", name), ca.serialize_pem().unwrap()),
+        (
+            format!("{}-cert.pem", name),
+            cert.serialize_pem_with_signer(ca).unwrap(),
+        ),
+        (
+            format!("{}-key.pem", name),
+            cert.serialize_private_key_pem(),
+        ),
+    ];
+    files.map(|(file, contents)| {
+        let path = dir.join(file);
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    })
+}
+
+// Reserves a local port for every party, the roster is fixed before the nodes bind
+fn roster() -> Vec<MeshPeer> {
+    (1..=PARTIES)
+        .map(|party_id| {
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            MeshPeer {
+                party_id,
+                addr: format!("127.0.0.1:{}", port),
+                server_name: server_name(party_id),
+            }
+        })
+        .collect()
+}
+
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tss-mesh-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn bind(peers: &[MeshPeer], party_id: u16, identity: [String; 3]) -> Arc<MeshNode> {
+    let [ca_cert, cert, key] = identity;
+    let config = MeshConfig {
+        enabled: true,
+        listen_addr: peers[usize::from(party_id - 1)].addr.clone(),
+        ca_cert,
+        cert,
+        key,
+        peers: peers.to_vec(),
+    };
+    MeshNode::bind(&config).await.unwrap()
+}
+
+// Nodes of every party of the roster, with certificates of one CA
+async fn mesh(dir: &Path, peers: &[MeshPeer]) -> Vec<Arc<MeshNode>> {
+    let ca = new_ca();
+    let mut nodes = Vec::new();
+    for peer in peers {
+        let name = format!("signer{}", peer.party_id);
+        let identity = write_identity(dir, &name, &ca, &peer.server_name);
+        nodes.push(bind(peers, peer.party_id, identity).await);
+    }
+    nodes
+}
+
+// Transports of the signers `members`, in party order
+fn session(nodes: &[Arc<MeshNode>], members: &[u16]) -> Vec<MeshTransport> {
+    members
+        .iter()
+        .enumerate()
+        .map(|(index, party_id)| {
+            MeshTransport::new(
+                nodes[usize::from(party_id - 1)].clone(),
+                index as u16 + 1,
+                members.to_vec(),
+                SESSION_ID,
+            )
+            .unwrap()
+        })
+        .collect()
+}
+
+fn assert_same_signature(signatures: &[SignatureData]) {
+    for signature in &signatures[1..] {
+        assert_eq!(signature.r, signatures[0].r);
+        assert_eq!(signature.s, signatures[0].s);
+    }
+}
+
+#[tokio::test]
+async fn test_frost_sign_over_mesh() {
+    let transports = InMemoryTransport::session(PARTIES);
+    let key_shares: Vec<FrostKeyShare<_>> = join_all(
+        transports
+            .iter()
+            .map(|transport| frost_keygen::<Secp256k1Bip340, _>(transport, THRESHOLD)),
+    )
+    .await
+    .into_iter()
+    .map(|key_share| serde_json::from_str(&key_share.unwrap()).unwrap())
+    .collect();
+
+    let dir = temp_dir();
+    let nodes = mesh(&dir, &roster()).await;
+    let members = [3, 1];
+    let transports = session(&nodes, &members);
+    let signatures: Vec<SignatureData> = timeout(
+        Duration::from_secs(30),
+        join_all(transports.iter().zip(members).map(|(transport, party_id)| {
+            let key_share = &key_shares[usize::from(party_id - 1)];
+            frost_sign::<Secp256k1Bip340, _>(transport, key_share, MESSAGE)
+        })),
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|signature| signature.unwrap())
+    .collect();
+    assert_same_signature(&signatures);
+}
+
+#[tokio::test]
+async fn test_ecdsa_sign_over_mesh() {
+    let transports = InMemoryTransport::session(PARTIES);
+    let signers: Vec<SignerData> = join_all(
+        transports
+            .iter()
+            .map(|transport| keygen(transport, THRESHOLD)),
+    )
+    .await
+    .into_iter()
+    .map(|key_file| SignerData::from_json(&key_file.unwrap()).unwrap())
+    .collect();
+
+    let dir = temp_dir();
+    let nodes = mesh(&dir, &roster()).await;
+    let members = [2, 3];
+    let transports = session(&nodes, &members);
+    let signatures: Vec<SignatureData> = timeout(
+        Duration::from_secs(30),
+        join_all(transports.iter().zip(members).map(|(transport, party_id)| {
+            let signer = &signers[usize::from(party_id - 1)];
+            ecdsa_sign(transport, signer, "", MESSAGE)
+        })),
+    )
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|signature| signature.unwrap())
+    .collect();
+    assert_same_signature(&signatures);
+}
+
+#[tokio::test]
+async fn test_sender_is_taken_from_certificate() {
+    let dir = temp_dir();
+    let nodes = mesh(&dir, &roster()).await;
+
+    // Party 3 claims the place of party 2 in a session of parties 1 and 2
+    let receiver = MeshTransport::new(nodes[0].clone(), 1, vec![1, 2], SESSION_ID).unwrap();
+    let impostor = MeshTransport::new(nodes[2].clone(), 2, vec![1, 3], SESSION_ID).unwrap();
+    impostor
+        .broadcast("round0", "forged".to_string())
+        .await
+        .unwrap();
+    let received = timeout(
+        Duration::from_millis(500),
+        receiver.receive_broadcasts("round0"),
+    )
+    .await;
+    assert!(received.is_err());
+
+    // The message was delivered as coming from party 3
+    let view = MeshTransport::new(nodes[0].clone(), 1, vec![1, 3], SESSION_ID).unwrap();
+    let received = timeout(Duration::from_secs(5), view.receive_broadcasts("round0"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(received, vec!["forged".to_string()]);
+}
+
+#[tokio::test]
+async fn test_peer_of_another_ca_is_rejected() {
+    let dir = temp_dir();
+    let peers = roster();
+    let ca = new_ca();
+    let identity = write_identity(&dir, "signer1", &ca, &server_name(1));
+    let mesh_ca_cert = identity[0].clone();
+    let node = bind(&peers, 1, identity).await;
+
+    // Trusts the CA of the mesh but holds a certificate of its own CA
+    let [_, cert, key] = write_identity(&dir, "rogue", &new_ca(), &server_name(2));
+    let rogue = bind(&peers, 2, [mesh_ca_cert, cert, key]).await;
+
+    let receiver = MeshTransport::new(node, 1, vec![1, 2], SESSION_ID).unwrap();
+    let sender = MeshTransport::new(rogue, 2, vec![1, 2], SESSION_ID).unwrap();
+    // The handshake is rejected after the client finished it, sending may still succeed
+    let _ = sender.broadcast("round0", "forged".to_string()).await;
+    let received = timeout(
+        Duration::from_millis(500),
+        receiver.receive_broadcasts("round0"),
+    )
+    .await;
+    assert!(received.is_err());
+}
@@ -0,0 +1,123 @@
+use futures::TryStreamExt;
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::Client;
+use tss_network::storage::migrations::{duplicate_to_keep, migrate, SCHEMA_VERSION};
+
+fn record(status: &str) -> Document {
+    doc! { "_id": ObjectId::new(), "request_id": "a", "status": status }
+}
+
+#[test]
+fn test_duplicate_furthest_along_is_kept() {
+    assert_eq!(duplicate_to_keep(&[]), None);
+    assert_eq!(duplicate_to_keep(&[record("Pending")]), Some(0));
+    // Oldest first, the newest wins a tie
+    assert_eq!(
+        duplicate_to_keep(&[record("Pending"), record("Pending")]),
+        Some(1)
+    );
+    assert_eq!(
+        duplicate_to_keep(&[record("Completed"), record("InProgress"), record("Pending")]),
+        Some(0)
+    );
+    assert_eq!(
+        duplicate_to_keep(&[record("InProgress"), record("Rejected"), record("Pending")]),
+        Some(1)
+    );
+    let without_status = doc! { "_id": ObjectId::new(), "request_id": "a" };
+    assert_eq!(
+        duplicate_to_keep(&[record("InProgress"), without_status]),
+        Some(0)
+    );
+}
+
+// Needs a MongoDB server at MONGODB_URI, mongodb://localhost:27017 by default
+#[tokio::test]
+#[ignore = "needs a MongoDB server"]
+async fn test_migration_sets_duplicate_requests_aside() {
+    let uri = std::env::var("MONGODB_URI").unwrap_or("mongodb://localhost:27017".to_string());
+    let client = Client::with_uri_str(&uri).await.unwrap();
+    let db = client.database(&format!("tss_migration_{}", uuid::Uuid::new_v4().simple()));
+
+    // A database migrated to version 1 before request ids were unique
+    db.collection::<Document>("schema_version")
+        .insert_one(doc! { "_id": "tss_network", "version": 1_i32 }, None)
+        .await
+        .unwrap();
+    let requests = db.collection::<Document>("messages_to_sign");
+    requests
+        .insert_many(
+            vec![
+                doc! { "request_id": "a", "status": "Completed", "created_at": 1_i64 },
+                doc! { "request_id": "a", "status": "Pending", "created_at": 2_i64 },
+                doc! { "request_id": "b", "status": "Pending", "created_at": 3_i64 },
+                doc! { "request_id": "b", "status": "Pending", "created_at": 4_i64 },
+                doc! { "request_id": "c", "status": "Pending", "created_at": 5_i64 },
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+    db.collection::<Document>("keys_gen_requests")
+        .insert_many(
+            vec![
+                doc! { "request_id": "k", "status": "Pending" },
+                doc! { "request_id": "k", "status": "Completed" },
+            ],
+            None,
+        )
+        .await
+        .unwrap();
+
+    migrate(&db).await.unwrap();
+
+    let kept: Vec<Document> = requests
+        .find(None, None)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    let mut kept: Vec<(String, i64)> = kept
+        .iter()
+        .map(|record| {
+            (
+                record.get_str("request_id").unwrap().to_string(),
+                record.get_i64("created_at").unwrap(),
+            )
+        })
+        .collect();
+    kept.sort();
+    assert_eq!(
+        kept,
+        vec![("a".into(), 1), ("b".into(), 4), ("c".into(), 5)]
+    );
+    let archived = db
+        .collection::<Document>("messages_to_sign_duplicates")
+        .count_documents(None, None)
+        .await
+        .unwrap();
+    assert_eq!(archived, 2);
+    let key_gen = db
+        .collection::<Document>("keys_gen_requests")
+        .find_one(None, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(key_gen.get_str("status").unwrap(), "Completed");
+
+    // The unique index now holds
+    assert!(requests
+        .insert_one(doc! { "request_id": "c" }, None)
+        .await
+        .is_err());
+    let schema = db
+        .collection::<Document>("schema_version")
+        .find_one(None, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(schema.get_i32("version").unwrap() as u32, SCHEMA_VERSION);
+
+    db.drop(None).await.unwrap();
+}
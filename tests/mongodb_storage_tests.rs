@@ -0,0 +1,273 @@
+use std::time::Duration;
+use tss_network::common::{
+    MessageStatus, SignatureData, SignatureScheme, SignerResult, SigningProgress, SigningRefusal,
+    SigningRequest, SigningRequestFilter,
+};
+use tss_network::storage::mongodb::{MongoDBStorage, MongoRoomStore};
+use tss_network::storage::room_store::{RoomStore, Swap};
+use tss_network::storage::Storage;
+
+// Needs a MongoDB server at MONGODB_URI, mongodb://localhost:27017 by default
+async fn open_storage() -> MongoDBStorage {
+    let uri = std::env::var("MONGODB_URI").unwrap_or("mongodb://localhost:27017".to_string());
+    let db_name = format!("tss_storage_{}", uuid::Uuid::new_v4().simple());
+    MongoDBStorage::new(&uri, &db_name).await.unwrap()
+}
+
+fn signing_request(scheme: SignatureScheme) -> SigningRequest {
+    SigningRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        message: b"message".to_vec(),
+        scheme,
+        derivation_path: None,
+        client_id: None,
+        transaction: None,
+        approvals: Vec::new(),
+    }
+}
+
+fn progress(request_id: &str, room_uuid: &str, party_id: u16) -> SigningProgress {
+    SigningProgress {
+        request_id: request_id.to_string(),
+        room_uuid: room_uuid.to_string(),
+        party_id,
+    }
+}
+
+fn signature() -> SignatureData {
+    SignatureData {
+        r: "01".repeat(32),
+        s: "02".repeat(32),
+        status: "signature_ready".to_string(),
+        recid: 0,
+        x: String::new(),
+        y: String::new(),
+        msg_int: Vec::new(),
+        der: String::new(),
+        compact: String::new(),
+        recoverable: String::new(),
+        public_key: String::new(),
+        scheme: SignatureScheme::Ecdsa,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a MongoDB server"]
+async fn test_signing_request_lifecycle_is_recorded() {
+    let storage = open_storage().await;
+    let request = signing_request(SignatureScheme::Ecdsa);
+    storage.insert_request(&request).await.unwrap();
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Pending);
+    assert!(stored.created_at > 0);
+    assert_eq!(stored.started_at, None);
+
+    storage
+        .update_signing_progress(&progress(&request.id, "room-1", 1))
+        .await
+        .unwrap();
+    storage
+        .update_signing_progress(&progress(&request.id, "room-1", 3))
+        .await
+        .unwrap();
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::InProgress);
+    assert_eq!(stored.attempts, 1);
+    assert_eq!(stored.room_uuid.as_deref(), Some("room-1"));
+    assert_eq!(stored.participants, vec![1, 3]);
+    let started_at = stored.started_at.unwrap();
+    assert!(started_at >= stored.created_at);
+
+    // A second room is a new attempt
+    storage
+        .update_signing_progress(&progress(&request.id, "room-2", 2))
+        .await
+        .unwrap();
+    let result = SignerResult {
+        request_id: request.id.clone(),
+        signature: signature(),
+        party_id: 2,
+    };
+    storage.update_signing_result(&result).await.unwrap();
+
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Completed);
+    assert_eq!(stored.attempts, 2);
+    assert_eq!(stored.room_uuid.as_deref(), Some("room-2"));
+    assert_eq!(stored.participants, vec![2]);
+    // Timed from the start of the attempt that signed
+    let restarted_at = stored.started_at.unwrap();
+    assert!(restarted_at >= started_at);
+    assert!(stored.completed_at.unwrap() >= restarted_at);
+    let history: Vec<MessageStatus> = stored
+        .status_history
+        .iter()
+        .map(|change| change.status.clone())
+        .collect();
+    assert_eq!(
+        history,
+        vec![
+            MessageStatus::Pending,
+            MessageStatus::InProgress,
+            MessageStatus::InProgress,
+            MessageStatus::InProgress,
+            MessageStatus::Completed,
+        ]
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a MongoDB server"]
+async fn test_list_signing_requests_pages_newest_first() {
+    let storage = open_storage().await;
+    let mut ids = Vec::new();
+    for scheme in [
+        SignatureScheme::Ecdsa,
+        SignatureScheme::Schnorr,
+        SignatureScheme::Ecdsa,
+        SignatureScheme::Ecdsa,
+    ] {
+        let request = signing_request(scheme);
+        storage.insert_request(&request).await.unwrap();
+        ids.push(request.id);
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+
+    let filter = SigningRequestFilter {
+        key_id: Some("ecdsa".to_string()),
+        ..Default::default()
+    };
+    let (page, cursor) = storage
+        .list_signing_requests(&filter, 2, None)
+        .await
+        .unwrap();
+    let page_ids: Vec<&str> = page.iter().map(|r| r.request_id.as_str()).collect();
+    assert_eq!(page_ids, vec![ids[3].as_str(), ids[2].as_str()]);
+
+    let (page, cursor) = storage
+        .list_signing_requests(&filter, 2, cursor.as_deref())
+        .await
+        .unwrap();
+    let page_ids: Vec<&str> = page.iter().map(|r| r.request_id.as_str()).collect();
+    assert_eq!(page_ids, vec![ids[0].as_str()]);
+    assert_eq!(cursor, None);
+
+    let all = SigningRequestFilter::default();
+    let (page, _) = storage.list_signing_requests(&all, 10, None).await.unwrap();
+    let filter = SigningRequestFilter {
+        since: Some(page[2].created_at),
+        until: Some(page[0].created_at),
+        ..Default::default()
+    };
+    let (page, _) = storage
+        .list_signing_requests(&filter, 10, None)
+        .await
+        .unwrap();
+    let page_ids: Vec<&str> = page.iter().map(|r| r.request_id.as_str()).collect();
+    assert_eq!(page_ids, vec![ids[2].as_str(), ids[1].as_str()]);
+
+    storage
+        .update_signing_progress(&progress(&ids[1], "room", 1))
+        .await
+        .unwrap();
+    let filter = SigningRequestFilter {
+        status: Some(MessageStatus::InProgress),
+        ..Default::default()
+    };
+    let (page, _) = storage
+        .list_signing_requests(&filter, 10, None)
+        .await
+        .unwrap();
+    let page_ids: Vec<&str> = page.iter().map(|r| r.request_id.as_str()).collect();
+    assert_eq!(page_ids, vec![ids[1].as_str()]);
+    assert!(storage
+        .list_signing_requests(&all, 10, Some("not-a-cursor"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs a MongoDB server"]
+async fn test_room_store_shares_the_storage_client() {
+    let uri = std::env::var("MONGODB_URI").unwrap_or("mongodb://localhost:27017".to_string());
+    let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    let db = client.database(&format!("tss_storage_{}", uuid::Uuid::new_v4().simple()));
+    let storage = MongoDBStorage::with_database(&db).await.unwrap();
+    let rooms = MongoRoomStore::new(&db, Duration::from_secs(60))
+        .await
+        .unwrap();
+
+    let request = signing_request(SignatureScheme::Ecdsa);
+    storage.insert_request(&request).await.unwrap();
+    assert!(rooms
+        .compare_and_swap("signup-sign-a", None, "room")
+        .await
+        .unwrap()
+        .is_written());
+    assert_eq!(
+        rooms
+            .compare_and_swap("signup-sign-a", Some("stale"), "other")
+            .await
+            .unwrap(),
+        Swap::Conflict(Some("room".to_string()))
+    );
+    assert_eq!(
+        rooms.get("signup-sign-a").await.unwrap().as_deref(),
+        Some("room")
+    );
+    let collections = db.list_collection_names(None).await.unwrap();
+    assert!(collections.contains(&"messages_to_sign".to_string()));
+    assert!(collections.contains(&"signing_rooms".to_string()));
+}
+
+#[tokio::test]
+#[ignore = "needs a MongoDB server"]
+async fn test_request_refused_beyond_quorum_is_rejected() {
+    let storage = open_storage().await;
+    let request = signing_request(SignatureScheme::Ecdsa);
+    storage.insert_request(&request).await.unwrap();
+    let refusal = |party_id, reason: &str| SigningRefusal {
+        request_id: request.id.clone(),
+        party_id,
+        reason: reason.to_string(),
+    };
+
+    for (party_id, status) in [(1, MessageStatus::Pending), (3, MessageStatus::Rejected)] {
+        storage
+            .record_signing_refusal(&refusal(party_id, "not allowed"), 1)
+            .await
+            .unwrap();
+        let stored = storage
+            .get_signing_result(&request.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.status, status);
+    }
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    let reason = stored
+        .status_history
+        .last()
+        .unwrap()
+        .reason
+        .clone()
+        .unwrap();
+    assert!(reason.contains("party 1: not allowed"));
+    assert!(reason.contains("party 3: not allowed"));
+}
@@ -0,0 +1,352 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use bitcoin::transaction::Version;
+use bitcoin::{Address, Amount, CompressedPublicKey, Network, Transaction, TxIn, TxOut};
+use config::{Config, File, FileFormat};
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tss_network::auth::{JwtKeys, Role};
+use tss_network::common::{PolicyDenial, SignatureScheme};
+use tss_network::config::{
+    QueueBackend, QueueConfig, RoomStoreConfig, Settings, StorageBackend, StorageConfig,
+};
+use tss_network::manager::api::{list_policy_denials, set_api_client_policy, sign};
+use tss_network::manager::policy::{transaction_value, SigningPolicy};
+use tss_network::manager::service::ManagerService;
+
+const SETTINGS: &str = r#"
+manager_url = "http://127.0.0.1"
+manager_port = 8080
+signing_timeout = 30
+threshold = 1
+total_parties = 3
+path = "0/1"
+signer_key_file = ""
+
+[security]
+jwt_secret = "jwt-secret"
+jwt_expiration = 600
+allowed_signer_ips = ["127.0.0.1"]
+"#;
+
+fn settings() -> Settings {
+    Config::builder()
+        .add_source(File::from_str(SETTINGS, FileFormat::Toml))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap()
+}
+
+fn address(secret: u8) -> Address {
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(&[secret; 32]).unwrap();
+    let public_key = CompressedPublicKey(secret_key.public_key(&secp));
+    Address::p2wpkh(&public_key, Network::Bitcoin)
+}
+
+fn psbt(outputs: &[(&Address, u64)]) -> Psbt {
+    let transaction = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn::default()],
+        output: outputs
+            .iter()
+            .map(|(address, value)| TxOut {
+                value: Amount::from_sat(*value),
+                script_pubkey: address.script_pubkey(),
+            })
+            .collect(),
+    };
+    Psbt::from_unsigned_tx(transaction).unwrap()
+}
+
+#[test]
+fn test_policy_restricts_keys_and_paths() {
+    assert!(SigningPolicy::default()
+        .check_request(SignatureScheme::Schnorr, "7")
+        .is_ok());
+
+    let policy = SigningPolicy {
+        allowed_key_ids: vec!["ecdsa".into(), "eddsa".into()],
+        allowed_derivation_paths: vec!["0/1".into(), "44/0/*".into()],
+        ..SigningPolicy::default()
+    };
+    policy.validate().unwrap();
+    for path in ["0/1", "0 / 1", "44/0/3", "44/0/3/9"] {
+        policy.check_request(SignatureScheme::Ecdsa, path).unwrap();
+    }
+    for path in ["", "0", "0/1/2", "44/0", "44/01/3"] {
+        assert!(policy.check_request(SignatureScheme::Ecdsa, path).is_err());
+    }
+    // FROST keys are not derived
+    policy.check_request(SignatureScheme::Eddsa, "").unwrap();
+    let reason = policy
+        .check_request(SignatureScheme::Schnorr, "")
+        .unwrap_err();
+    assert!(reason.contains("schnorr"));
+
+    for invalid in [
+        SigningPolicy {
+            allowed_key_ids: vec!["rsa".into()],
+            ..SigningPolicy::default()
+        },
+        SigningPolicy {
+            allowed_derivation_paths: vec!["0/x".into()],
+            ..SigningPolicy::default()
+        },
+        SigningPolicy {
+            allowed_destinations: vec!["not an address".into()],
+            ..SigningPolicy::default()
+        },
+    ] {
+        assert!(invalid.validate().is_err());
+    }
+}
+
+#[test]
+fn test_policy_restricts_transaction_outputs() {
+    let change = address(1);
+    let allowed = address(2);
+    let other = address(3);
+    let own_scripts = vec![change.script_pubkey()];
+    let policy = SigningPolicy {
+        allowed_destinations: vec![allowed.to_string()],
+        max_transaction_value: Some(50_000),
+        ..SigningPolicy::default()
+    };
+    policy.validate().unwrap();
+
+    // Change is neither a destination nor counted against the cap
+    policy
+        .check_transaction(
+            &psbt(&[(&allowed, 50_000), (&change, 900_000)]),
+            &own_scripts,
+        )
+        .unwrap();
+    let reason = policy
+        .check_transaction(
+            &psbt(&[(&allowed, 30_000), (&allowed, 30_000)]),
+            &own_scripts,
+        )
+        .unwrap_err();
+    assert!(reason.contains("60000"));
+    let reason = policy
+        .check_transaction(&psbt(&[(&allowed, 1_000), (&other, 1_000)]), &own_scripts)
+        .unwrap_err();
+    assert!(reason.contains("output 1"));
+
+    let unrestricted = SigningPolicy::default();
+    unrestricted
+        .check_transaction(&psbt(&[(&other, 10_000_000)]), &own_scripts)
+        .unwrap();
+
+    // A plain message could be the sighash of any transaction
+    unrestricted
+        .check_message(SignatureScheme::Ecdsa, "0/1")
+        .unwrap();
+    for policy in [
+        policy,
+        SigningPolicy {
+            max_transaction_value: Some(50_000),
+            ..SigningPolicy::default()
+        },
+    ] {
+        for scheme in [SignatureScheme::Ecdsa, SignatureScheme::Schnorr] {
+            assert!(policy.check_message(scheme, "0/1").is_err());
+        }
+    }
+}
+
+#[test]
+fn test_policy_approvals_above_threshold() {
+    assert_eq!(SigningPolicy::default().approvals_for_message(), 0);
+    assert_eq!(
+        SigningPolicy::default().approvals_for_transaction(u64::MAX),
+        0
+    );
+
+    let policy = SigningPolicy {
+        approvals_required: 2,
+        approval_threshold: Some(100_000),
+        ..SigningPolicy::default()
+    };
+    policy.validate().unwrap();
+    assert_eq!(policy.approvals_for_message(), 2);
+    assert_eq!(policy.approvals_for_transaction(100_000), 0);
+    assert_eq!(policy.approvals_for_transaction(100_001), 2);
+
+    let always = SigningPolicy {
+        approvals_required: 1,
+        ..SigningPolicy::default()
+    };
+    assert_eq!(always.approvals_for_transaction(0), 1);
+
+    let change = address(1);
+    let psbt = psbt(&[(&address(2), 30_000), (&change, 900_000)]);
+    assert_eq!(transaction_value(&psbt, &[change.script_pubkey()]), 30_000);
+
+    let invalid = SigningPolicy {
+        approval_threshold: Some(100_000),
+        ..SigningPolicy::default()
+    };
+    assert!(invalid.validate().is_err());
+}
+
+async fn manager() -> ManagerService {
+    let storage_path = std::env::temp_dir().join(format!("tss-policy-{}", uuid::Uuid::new_v4()));
+    let storage = StorageConfig {
+        backend: StorageBackend::Sled,
+        path: storage_path.to_str().unwrap().to_string(),
+    };
+    let queue = QueueConfig {
+        backend: QueueBackend::InProcess,
+        ..QueueConfig::default()
+    };
+    ManagerService::new("", "", 1, 3, &storage, &RoomStoreConfig::default(), &queue)
+        .await
+        .unwrap()
+}
+
+async fn send(
+    client: &Client,
+    method: &str,
+    path: &str,
+    token: &str,
+    body: Value,
+) -> (Status, Value) {
+    let request = match method {
+        "PUT" => client.put(path.to_string()),
+        _ => client.post(path.to_string()),
+    };
+    let response = request
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .body(body.to_string())
+        .dispatch()
+        .await;
+    let status = response.status();
+    (status, response.into_json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn test_sign_enforces_client_policy() {
+    let settings = settings();
+    let keys = JwtKeys::load(&settings.security).unwrap();
+    let manager = manager().await;
+    let (_, admin) = manager
+        .create_api_client("operator", Role::Admin, 600, Default::default(), &keys)
+        .await
+        .unwrap();
+    let policy = SigningPolicy {
+        allowed_key_ids: vec!["ecdsa".into()],
+        allowed_derivation_paths: vec!["0/1".into(), "0/2/*".into()],
+        max_requests_per_minute: Some(2),
+        ..SigningPolicy::default()
+    };
+    let (wallet, token) = manager
+        .create_api_client("wallet", Role::Public, 600, policy, &keys)
+        .await
+        .unwrap();
+    let (admin, token) = (admin.token, token.token);
+    let rocket = rocket::build()
+        .manage(Arc::new(manager))
+        .manage(Arc::new(settings))
+        .manage(Arc::new(keys))
+        .mount(
+            "/",
+            routes![sign, set_api_client_policy, list_policy_denials],
+        );
+    let client = Client::untracked(rocket).await.unwrap();
+    let sign = |body: Value| send(&client, "POST", "/sign", &token, body);
+
+    // The configured path is used when the request has none
+    let (status, _) = sign(json!({ "message": "a" })).await;
+    assert_eq!(status, Status::Created);
+
+    let (status, body) = sign(json!({ "message": "b", "scheme": "Schnorr" })).await;
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(body["error"], "policy_denied");
+    assert!(body["reason"].as_str().unwrap().contains("schnorr"));
+
+    let (status, body) = sign(json!({ "message": "c", "derivation_path": "0/3" })).await;
+    assert_eq!(status, Status::Forbidden);
+    assert!(body["reason"].as_str().unwrap().contains("0/3"));
+
+    // Derivation paths are validated before the policy
+    let (status, _) = sign(json!({ "message": "d", "derivation_path": "0/x" })).await;
+    assert_eq!(status, Status::BadRequest);
+
+    // Denied requests do not count against the rate limit
+    let (status, _) = sign(json!({ "message": "e", "derivation_path": "0/2/7" })).await;
+    assert_eq!(status, Status::Created);
+    let (status, body) = sign(json!({ "message": "f" })).await;
+    assert_eq!(status, Status::Forbidden);
+    assert!(body["reason"].as_str().unwrap().contains("rate limit"));
+
+    // Lifting the limit lets the client sign again
+    let path = format!("/api_clients/{}/policy", wallet.client_id);
+    let (status, updated) = send(&client, "PUT", &path, &admin, json!({})).await;
+    assert_eq!(status, Status::Ok);
+    assert_eq!(updated["policy"], json!(SigningPolicy::default()));
+    let (status, _) = sign(json!({ "message": "f" })).await;
+    assert_eq!(status, Status::Created);
+
+    let path = format!("/api_clients/{}/policy", uuid::Uuid::new_v4());
+    let (status, _) = send(&client, "PUT", &path, &admin, json!({})).await;
+    assert_eq!(status, Status::NotFound);
+
+    let response = client
+        .get("/policy_denials")
+        .header(Header::new("Authorization", format!("Bearer {}", admin)))
+        .dispatch()
+        .await;
+    let denials: Vec<PolicyDenial> = response.into_json().await.unwrap();
+    assert_eq!(denials.len(), 3);
+    assert!(denials[0].reason.contains("rate limit"));
+    assert!(denials
+        .iter()
+        .all(|denial| denial.client_id == wallet.client_id && denial.endpoint == "/sign"));
+}
+
+#[tokio::test]
+async fn test_sign_denies_messages_under_transaction_constraints() {
+    let settings = settings();
+    let keys = JwtKeys::load(&settings.security).unwrap();
+    let manager = manager().await;
+    let policy = SigningPolicy {
+        allowed_destinations: vec![address(2).to_string()],
+        ..SigningPolicy::default()
+    };
+    let (_, token) = manager
+        .create_api_client("wallet", Role::Public, 600, policy, &keys)
+        .await
+        .unwrap();
+    let rocket = rocket::build()
+        .manage(Arc::new(manager))
+        .manage(Arc::new(settings))
+        .manage(Arc::new(keys))
+        .mount("/", routes![sign]);
+    let client = Client::untracked(rocket).await.unwrap();
+
+    // The raw sighash of a transaction paying elsewhere
+    let sighash = hex::encode([7u8; 32]);
+    let (status, body) = send(
+        &client,
+        "POST",
+        "/sign",
+        &token.token,
+        json!({ "message": sighash }),
+    )
+    .await;
+    assert_eq!(status, Status::Forbidden);
+    assert_eq!(body["error"], "policy_denied");
+    assert!(body["reason"]
+        .as_str()
+        .unwrap()
+        .contains("transaction constraints"));
+}
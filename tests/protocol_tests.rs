@@ -0,0 +1,99 @@
+use futures::future::join_all;
+use serde_json::Value;
+use tss_network::common::frost::{Ciphersuite, Ed25519Sha512, FrostKeyShare, Secp256k1Bip340};
+use tss_network::common::secp256k1def::GE;
+use tss_network::common::signature::verify_signature_data;
+use tss_network::common::{InMemoryTransport, SignatureData, SignatureScheme};
+use tss_network::manager::keygen::{frost_keygen, keygen};
+use tss_network::signer::service::{ecdsa_sign, frost_sign, SignerData};
+
+const THRESHOLD: u16 = 1;
+const PARTIES: u16 = 3;
+const MESSAGE: &[u8] = b"protocol over the in-memory transport";
+
+// Every party returns the same signature, the protocols verify it before returning
+fn assert_same_signature(signatures: &[SignatureData]) {
+    for signature in &signatures[1..] {
+        assert_eq!(signature.r, signatures[0].r);
+        assert_eq!(signature.s, signatures[0].s);
+    }
+}
+
+async fn frost_keygen_and_sign<C: Ciphersuite>() {
+    let transports = InMemoryTransport::session(PARTIES);
+    let key_shares: Vec<FrostKeyShare<C::Curve>> = join_all(
+        transports
+            .iter()
+            .map(|transport| frost_keygen::<C, _>(transport, THRESHOLD)),
+    )
+    .await
+    .into_iter()
+    .map(|key_share| serde_json::from_str(&key_share.unwrap()).unwrap())
+    .collect();
+
+    // Parties 1 and 3 of the key sign as the parties 1 and 2 of the session
+    let signers = [&key_shares[0], &key_shares[2]];
+    let transports = InMemoryTransport::session(signers.len() as u16);
+    let signatures: Vec<SignatureData> = join_all(
+        transports
+            .iter()
+            .zip(signers)
+            .map(|(transport, key_share)| frost_sign::<C, _>(transport, key_share, MESSAGE)),
+    )
+    .await
+    .into_iter()
+    .map(|signature| signature.unwrap())
+    .collect();
+    assert_same_signature(&signatures);
+    let public_key = hex::encode(C::encode_point(&key_shares[0].public_key));
+    verify_signature_data(&signatures[0], C::SCHEME, MESSAGE, &public_key).unwrap();
+}
+
+#[tokio::test]
+async fn test_frost_schnorr_keygen_and_sign_in_memory() {
+    frost_keygen_and_sign::<Secp256k1Bip340>().await;
+}
+
+#[tokio::test]
+async fn test_frost_ed25519_keygen_and_sign_in_memory() {
+    frost_keygen_and_sign::<Ed25519Sha512>().await;
+}
+
+#[tokio::test]
+async fn test_ecdsa_keygen_and_sign_in_memory() {
+    let transports = InMemoryTransport::session(PARTIES);
+    let key_files: Vec<String> = join_all(
+        transports
+            .iter()
+            .map(|transport| keygen(transport, THRESHOLD)),
+    )
+    .await
+    .into_iter()
+    .map(|key_file| key_file.unwrap())
+    .collect();
+
+    let signers = [
+        SignerData::from_json(&key_files[1]).unwrap(),
+        SignerData::from_json(&key_files[2]).unwrap(),
+    ];
+    let transports = InMemoryTransport::session(signers.len() as u16);
+    let signatures: Vec<SignatureData> = join_all(
+        transports
+            .iter()
+            .zip(&signers)
+            .map(|(transport, signer)| ecdsa_sign(transport, signer, "", MESSAGE)),
+    )
+    .await
+    .into_iter()
+    .map(|signature| signature.unwrap())
+    .collect();
+    assert_same_signature(&signatures);
+
+    // The manager checks the result against the group key of the key files
+    type KeyFile = (Value, Value, Value, Value, Value, GE);
+    let (.., public_key): KeyFile = serde_json::from_str(&key_files[0]).unwrap();
+    let public_key = hex::encode(&*public_key.to_bytes(true));
+    for signature in &signatures {
+        verify_signature_data(signature, SignatureScheme::Ecdsa, MESSAGE, &public_key).unwrap();
+    }
+}
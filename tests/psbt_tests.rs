@@ -0,0 +1,222 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::bip32::{DerivationPath, Fingerprint};
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::{Psbt, PsbtSighashType};
+use bitcoin::secp256k1::{Message, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Amount, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use tss_network::common::scripts::owned_scripts;
+use tss_network::manager::psbt::{approval_request_id, input_sighashes, parse_psbt};
+
+const VALUE: u64 = 100_000;
+
+fn public_key(byte: u8) -> PublicKey {
+    let secret = SecretKey::from_slice(&[byte; 32]).unwrap();
+    PublicKey::new(secret.public_key(&Secp256k1::new()))
+}
+
+fn p2wpkh(key: &PublicKey) -> ScriptBuf {
+    ScriptBuf::new_p2wpkh(&key.wpubkey_hash().unwrap())
+}
+
+// Transaction spending `inputs` outpoints to a single output
+fn transaction(inputs: usize) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: (0..inputs)
+            .map(|index| TxIn {
+                previous_output: OutPoint::new(
+                    Txid::from_raw_hash(Hash::from_byte_array([index as u8 + 1; 32])),
+                    0,
+                ),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: vec![TxOut {
+            value: Amount::from_sat(VALUE / 2),
+            script_pubkey: p2wpkh(&public_key(9)),
+        }],
+    }
+}
+
+fn utxo(script_pubkey: ScriptBuf) -> TxOut {
+    TxOut {
+        value: Amount::from_sat(VALUE),
+        script_pubkey,
+    }
+}
+
+fn digest(sighash: impl Hash<Bytes = [u8; 32]>) -> Message {
+    Message::from_digest(sighash.to_byte_array())
+}
+
+#[test]
+fn test_owned_scripts() {
+    let key = public_key(1);
+    let scripts = owned_scripts(&key);
+    assert_eq!(
+        scripts,
+        vec![ScriptBuf::new_p2pkh(&key.pubkey_hash()), p2wpkh(&key)]
+    );
+    // Segwit only locks to compressed keys
+    let uncompressed = PublicKey::new_uncompressed(key.inner);
+    assert_eq!(
+        owned_scripts(&uncompressed),
+        vec![ScriptBuf::new_p2pkh(&uncompressed.pubkey_hash())]
+    );
+}
+
+#[test]
+fn test_parse_psbt() {
+    let psbt = Psbt::from_unsigned_tx(transaction(1)).unwrap();
+    assert_eq!(parse_psbt(&psbt.to_string()).unwrap(), psbt);
+
+    for malformed in ["", "not base64!", "cHNidP8=", "AAAA"] {
+        assert!(parse_psbt(malformed).is_err(), "{:?} parsed", malformed);
+    }
+    // A PSBT whose transaction already carries signatures
+    let mut signed = transaction(1);
+    signed.input[0].script_sig = ScriptBuf::from_bytes(vec![0x51]);
+    let mut raw = Psbt::from_unsigned_tx(transaction(1)).unwrap();
+    raw.unsigned_tx = signed;
+    assert!(parse_psbt(&raw.to_string()).is_err());
+}
+
+#[test]
+fn test_input_sighashes_per_input_type() {
+    let key = public_key(1);
+    let other = public_key(2);
+    let mut psbt = Psbt::from_unsigned_tx(transaction(6)).unwrap();
+
+    // 0: P2WPKH
+    psbt.inputs[0].witness_utxo = Some(utxo(p2wpkh(&key)));
+    // 1: P2PKH, with the full previous transaction
+    let previous = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: Vec::new(),
+        output: vec![utxo(ScriptBuf::new_p2pkh(&key.pubkey_hash()))],
+    };
+    psbt.unsigned_tx.input[1].previous_output = OutPoint::new(previous.compute_txid(), 0);
+    psbt.inputs[1].non_witness_utxo = Some(previous);
+    psbt.inputs[1].sighash_type =
+        Some(PsbtSighashType::from(EcdsaSighashType::AllPlusAnyoneCanPay));
+    // 2: P2SH wrapped P2WPKH
+    psbt.inputs[2].witness_utxo = Some(utxo(ScriptBuf::new_p2sh(&p2wpkh(&key).script_hash())));
+    psbt.inputs[2].redeem_script = Some(p2wpkh(&key));
+    // 3: P2WSH multisig the key is part of
+    let multisig = bitcoin::blockdata::script::Builder::new()
+        .push_int(1)
+        .push_key(&other)
+        .push_key(&key)
+        .push_int(2)
+        .push_opcode(bitcoin::opcodes::all::OP_CHECKMULTISIG)
+        .into_script();
+    psbt.inputs[3].witness_utxo = Some(utxo(ScriptBuf::new_p2wsh(&multisig.wscript_hash())));
+    psbt.inputs[3].witness_script = Some(multisig.clone());
+    // 4: locked to another key
+    psbt.inputs[4].witness_utxo = Some(utxo(p2wpkh(&other)));
+    // 5: taproot, not signed with ECDSA
+    let (x_only, _) = key.inner.x_only_public_key();
+    psbt.inputs[5].witness_utxo = Some(utxo(ScriptBuf::new_p2tr(&Secp256k1::new(), x_only, None)));
+
+    let sighashes = input_sighashes(&psbt, &key).unwrap();
+    let indices: Vec<usize> = sighashes.iter().map(|(index, ..)| *index).collect();
+    assert_eq!(indices, vec![0, 1, 2, 3]);
+
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let amount = Amount::from_sat(VALUE);
+    let all = EcdsaSighashType::All;
+    let expected = [
+        (
+            digest(
+                cache
+                    .p2wpkh_signature_hash(0, &p2wpkh(&key), amount, all)
+                    .unwrap(),
+            ),
+            all,
+        ),
+        (
+            digest(
+                cache
+                    .legacy_signature_hash(
+                        1,
+                        &ScriptBuf::new_p2pkh(&key.pubkey_hash()),
+                        EcdsaSighashType::AllPlusAnyoneCanPay.to_u32(),
+                    )
+                    .unwrap(),
+            ),
+            EcdsaSighashType::AllPlusAnyoneCanPay,
+        ),
+        (
+            digest(
+                cache
+                    .p2wpkh_signature_hash(2, &p2wpkh(&key), amount, all)
+                    .unwrap(),
+            ),
+            all,
+        ),
+        (
+            digest(
+                cache
+                    .p2wsh_signature_hash(3, &multisig, amount, all)
+                    .unwrap(),
+            ),
+            all,
+        ),
+    ];
+    for ((index, message, sighash_type), (expected_message, expected_type)) in
+        sighashes.into_iter().zip(expected)
+    {
+        assert_eq!(message, expected_message, "sighash of input {}", index);
+        assert_eq!(
+            sighash_type, expected_type,
+            "sighash type of input {}",
+            index
+        );
+    }
+}
+
+#[test]
+fn test_input_sighashes_rejects_malformed_inputs() {
+    let key = public_key(1);
+
+    // Claimed by its derivation but without the output it spends
+    let mut psbt = Psbt::from_unsigned_tx(transaction(1)).unwrap();
+    psbt.inputs[0].bip32_derivation.insert(
+        key.inner,
+        (Fingerprint::from([0; 4]), DerivationPath::master()),
+    );
+    let error = input_sighashes(&psbt, &key).unwrap_err();
+    assert!(error.to_string().contains("input 0"));
+
+    // Sighash type no standard signature uses
+    let mut psbt = Psbt::from_unsigned_tx(transaction(2)).unwrap();
+    psbt.inputs[0].witness_utxo = Some(utxo(p2wpkh(&public_key(2))));
+    psbt.inputs[1].witness_utxo = Some(utxo(p2wpkh(&key)));
+    psbt.inputs[1].sighash_type = Some(PsbtSighashType::from_u32(0x77));
+    let error = input_sighashes(&psbt, &key).unwrap_err();
+    assert!(error.to_string().contains("input 1"));
+
+    // Nothing to sign is not an error
+    let psbt = Psbt::from_unsigned_tx(transaction(1)).unwrap();
+    assert!(input_sighashes(&psbt, &key).unwrap().is_empty());
+}
+
+#[test]
+fn test_approval_request_ids() {
+    let txid = transaction(1).compute_txid().to_string();
+    let id = approval_request_id("client", &txid, 0);
+    assert_eq!(id, approval_request_id("client", &txid, 0));
+    assert_ne!(id, approval_request_id("client", &txid, 1));
+    assert_ne!(id, approval_request_id("other", &txid, 0));
+    // Not a name based SHA-1 UUID, the digest is SHA-256
+    let uuid = uuid::Uuid::parse_str(&id).unwrap();
+    assert_eq!(uuid.get_version(), Some(uuid::Version::Custom));
+}
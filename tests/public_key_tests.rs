@@ -0,0 +1,116 @@
+use config::{Config, File, FileFormat};
+use serde_json::json;
+use tss_network::common::hd_keys::call_hd_key;
+use tss_network::common::secp256k1def::GE;
+use tss_network::common::{MessageToSignStored, SignatureScheme};
+use tss_network::config::Settings;
+use tss_network::manager::service::SchemePublicKeys;
+
+// The secp256k1 generator, as SEC1 and x-only keys
+const ECDSA_KEY: &str = "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798";
+const SCHNORR_KEY: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+// The Ed25519 base point
+const EDDSA_KEY: &str = "5866666666666666666666666666666666666666666666666666666666666666";
+
+fn settings(path: &str, ecdsa: &str, schnorr: &str, eddsa: &str) -> Settings {
+    let toml = format!(
+        r#"
+manager_url = "http://127.0.0.1"
+manager_port = 8080
+signing_timeout = 30
+threshold = 1
+total_parties = 3
+path = "{}"
+signer_key_file = ""
+public_key = "{}"
+schnorr_public_key = "{}"
+eddsa_public_key = "{}"
+
+[security]
+jwt_secret = "jwt-secret"
+jwt_expiration = 600
+allowed_signer_ips = []
+"#,
+        path, ecdsa, schnorr, eddsa
+    );
+    Config::builder()
+        .add_source(File::from_str(&toml, FileFormat::Toml))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap()
+}
+
+fn request(scheme: SignatureScheme, derivation_path: Option<&str>) -> MessageToSignStored {
+    serde_json::from_value(json!({
+        "request_id": uuid::Uuid::new_v4().to_string(),
+        "message": [1, 2, 3],
+        "status": "Pending",
+        "signature": null,
+        "scheme": scheme,
+        "derivation_path": derivation_path,
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_scheme_public_keys_resolve_per_request() {
+    let keys = SchemePublicKeys::load(&settings("0/1", ECDSA_KEY, SCHNORR_KEY, EDDSA_KEY)).unwrap();
+    let network_key = GE::from_bytes(&hex::decode(ECDSA_KEY).unwrap()).unwrap();
+    let child_key =
+        |path: &str| hex::encode(&*call_hd_key(path, network_key.clone()).1.to_bytes(true));
+
+    // ECDSA signs with the child key at the configured path unless the request has its own
+    assert_eq!(
+        keys.for_request(&request(SignatureScheme::Ecdsa, None))
+            .unwrap(),
+        child_key("0/1")
+    );
+    assert_eq!(
+        keys.for_request(&request(SignatureScheme::Ecdsa, Some("2/3")))
+            .unwrap(),
+        child_key("2/3")
+    );
+    assert_eq!(
+        keys.for_request(&request(SignatureScheme::Ecdsa, Some("")))
+            .unwrap(),
+        ECDSA_KEY.to_lowercase()
+    );
+    assert_eq!(
+        keys.for_request(&request(SignatureScheme::Schnorr, None))
+            .unwrap(),
+        SCHNORR_KEY
+    );
+    assert_eq!(
+        keys.for_request(&request(SignatureScheme::Eddsa, None))
+            .unwrap(),
+        EDDSA_KEY
+    );
+}
+
+#[test]
+fn test_unconfigured_scheme_keys_reject_their_results() {
+    let keys = SchemePublicKeys::load(&settings("", ECDSA_KEY, "", "")).unwrap();
+    keys.for_request(&request(SignatureScheme::Ecdsa, None))
+        .unwrap();
+    for scheme in [SignatureScheme::Schnorr, SignatureScheme::Eddsa] {
+        let error = keys.for_request(&request(scheme, None)).unwrap_err();
+        assert!(error.to_string().contains(scheme.key_id()));
+    }
+}
+
+#[test]
+fn test_malformed_scheme_keys_fail_at_startup() {
+    for invalid in [
+        settings("", "02ff", "", ""),
+        settings("", "not hex", "", ""),
+        settings("0/x", ECDSA_KEY, "", ""),
+        // x-only keys are 32 bytes
+        settings("", ECDSA_KEY, ECDSA_KEY, ""),
+        // Not the x coordinate of a point
+        settings("", ECDSA_KEY, &"ff".repeat(32), ""),
+        settings("", ECDSA_KEY, SCHNORR_KEY, "abcd"),
+    ] {
+        assert!(SchemePublicKeys::load(&invalid).is_err());
+    }
+}
@@ -0,0 +1,155 @@
+use curv::cryptographic_primitives::secret_sharing::feldman_vss::VerifiableSS;
+use curv::elliptic::curves::{Point, Scalar, Secp256k1};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2018::party_i::{Keys, SharedKeys};
+use paillier::EncryptionKey;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::timeout;
+use tss_network::common::{DeadLetter, SignatureScheme, SigningFailure, SigningRequest};
+use tss_network::queue::{InProcessQueue, RequestQueue};
+use tss_network::signer::policy::SignerPolicy;
+use tss_network::signer::service::SignerService;
+
+fn request(id: &str) -> SigningRequest {
+    SigningRequest {
+        id: id.to_string(),
+        message: b"message".to_vec(),
+        scheme: SignatureScheme::Ecdsa,
+
+        derivation_path: None,
+        client_id: None,
+        transaction: None,
+        approvals: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_in_process_queue_delivers_to_every_signer() {
+    let manager = InProcessQueue::new();
+    let signers = [manager.subscribe(), manager.subscribe()];
+
+    manager
+        .publish_signing_request(&request("first"))
+        .await
+        .unwrap();
+    manager
+        .publish_signing_request(&request("second"))
+        .await
+        .unwrap();
+
+    for signer in &signers {
+        assert_eq!(
+            signer.receive_signing_request().await.unwrap().request.id,
+            "first"
+        );
+        assert_eq!(
+            signer.receive_signing_request().await.unwrap().request.id,
+            "second"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_in_process_queue_skips_requests_published_before_subscribing() {
+    let manager = InProcessQueue::new();
+    manager
+        .publish_signing_request(&request("missed"))
+        .await
+        .unwrap();
+
+    let signer = manager.subscribe();
+    assert!(
+        timeout(Duration::from_millis(50), signer.receive_signing_request())
+            .await
+            .is_err()
+    );
+
+    manager
+        .publish_signing_request(&request("received"))
+        .await
+        .unwrap();
+    assert_eq!(
+        signer.receive_signing_request().await.unwrap().request.id,
+        "received"
+    );
+}
+
+#[tokio::test]
+async fn test_in_process_queue_delivers_dead_letters() {
+    let manager = InProcessQueue::new();
+    let signer = manager.subscribe();
+
+    let letter = DeadLetter {
+        request: request("failed"),
+        failure: SigningFailure {
+            party_id: 2,
+            error: "Signing panicked".to_string(),
+            failed_at: 1,
+        },
+    };
+    signer.publish_dead_letter(&letter).await.unwrap();
+
+    let received = manager.receive_dead_letter().await.unwrap();
+    assert_eq!(received.letter, letter);
+    manager.ack(received.delivery_tag).await.unwrap();
+}
+
+// ECDSA key file of party 1, without any FROST key share
+fn key_file() -> String {
+    let secret = Scalar::<Secp256k1>::random();
+    let y_sum = Point::generator() * &secret;
+    let shared_keys = SharedKeys {
+        y: y_sum.clone(),
+        x_i: secret,
+    };
+    let contents = serde_json::to_string(&(
+        Keys::create(1),
+        shared_keys,
+        1u16,
+        Vec::<VerifiableSS<Secp256k1>>::new(),
+        Vec::<EncryptionKey>::new(),
+        y_sum,
+    ))
+    .unwrap();
+    let path = std::env::temp_dir().join(format!("tss-queue-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_in_process_queue_dead_letters_failed_requests() {
+    let manager = InProcessQueue::new();
+    let signer = SignerService::new(
+        "http://127.0.0.1",
+        &1,
+        Arc::new(manager.subscribe()),
+        &key_file(),
+        "signer-secret",
+        &1,
+        &3,
+        "",
+        None,
+        None,
+        1,
+        Duration::from_secs(5),
+        None,
+        SignerPolicy::default(),
+    )
+    .await
+    .unwrap();
+    let running = tokio::spawn(Arc::new(signer).run());
+
+    // Without a Schnorr key share the request fails, and is never redelivered
+    let mut failing = request("failing");
+    failing.scheme = SignatureScheme::Schnorr;
+    manager.publish_signing_request(&failing).await.unwrap();
+
+    let received = timeout(Duration::from_secs(5), manager.receive_dead_letter())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(received.letter.request, failing);
+    assert_eq!(received.letter.failure.party_id, 1);
+    assert!(received.letter.failure.error.contains("No key share"));
+    running.abort();
+}
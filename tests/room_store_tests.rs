@@ -0,0 +1,135 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tss_network::storage::room_store::{InMemoryRoomStore, RoomStore, Swap};
+
+const TTL: Duration = Duration::from_secs(3600);
+
+#[tokio::test]
+async fn test_get_and_set() {
+    let store = InMemoryRoomStore::new(TTL);
+
+    assert_eq!(store.get("1-round1-uuid").await.unwrap(), None);
+    store.set("1-round1-uuid", "message").await.unwrap();
+    assert_eq!(
+        store.get("1-round1-uuid").await.unwrap().as_deref(),
+        Some("message")
+    );
+}
+
+#[tokio::test]
+async fn test_compare_and_swap_only_writes_expected_value() {
+    let store = InMemoryRoomStore::new(TTL);
+
+    assert!(store
+        .compare_and_swap("signup-keygen", None, "a")
+        .await
+        .unwrap()
+        .is_written());
+    // The key now exists, a second creator loses the race and learns the value
+    assert_eq!(
+        store
+            .compare_and_swap("signup-keygen", None, "b")
+            .await
+            .unwrap(),
+        Swap::Conflict(Some("a".to_string()))
+    );
+    assert_eq!(
+        store
+            .compare_and_swap("signup-keygen", Some("stale"), "b")
+            .await
+            .unwrap(),
+        Swap::Conflict(Some("a".to_string()))
+    );
+    assert_eq!(
+        store
+            .compare_and_swap("missing", Some("a"), "b")
+            .await
+            .unwrap(),
+        Swap::Conflict(None)
+    );
+    assert!(store
+        .compare_and_swap("signup-keygen", Some("a"), "b")
+        .await
+        .unwrap()
+        .is_written());
+    assert_eq!(
+        store.get("signup-keygen").await.unwrap().as_deref(),
+        Some("b")
+    );
+}
+
+#[tokio::test]
+async fn test_concurrent_increments_are_not_lost() {
+    let store: Arc<dyn RoomStore> = Arc::new(InMemoryRoomStore::new(TTL));
+
+    let tasks: Vec<_> = (0..16)
+        .map(|_| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                loop {
+                    let current = store.get("counter").await.unwrap();
+                    let next = current.as_deref().map_or(0, |v| v.parse().unwrap()) + 1u32;
+                    if store
+                        .compare_and_swap("counter", current.as_deref(), &next.to_string())
+                        .await
+                        .unwrap()
+                        .is_written()
+                    {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    assert_eq!(store.get("counter").await.unwrap().as_deref(), Some("16"));
+}
+
+#[tokio::test]
+async fn test_remove_room_messages() {
+    let store = InMemoryRoomStore::new(TTL);
+    for key in [
+        "1-round1-room-a",
+        "2-round1-room-a",
+        "1-2-round2-room-a",
+        "1-round1-room-b",
+    ] {
+        store.set(key, "message").await.unwrap();
+    }
+    store.set("signup-sign-a", "room").await.unwrap();
+
+    assert_eq!(store.remove_suffix("-room-a").await.unwrap(), 3);
+    assert_eq!(store.scan_prefix("1-").await.unwrap().len(), 1);
+    assert_eq!(
+        store.scan_prefix("signup-sign-").await.unwrap(),
+        vec![("signup-sign-a".to_string(), "room".to_string())]
+    );
+
+    // A room changed since it was read is kept
+    assert!(!store.remove_if("signup-sign-a", "stale").await.unwrap());
+    assert!(store.remove_if("signup-sign-a", "room").await.unwrap());
+    assert_eq!(store.get("signup-sign-a").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_expired_entries_are_purged() {
+    let store = InMemoryRoomStore::new(Duration::from_millis(50));
+    store.set("1-round1-uuid", "message").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    store.set("2-round1-uuid", "message").await.unwrap();
+
+    // Expired entries are already invisible before they are purged
+    assert_eq!(store.get("1-round1-uuid").await.unwrap(), None);
+    assert!(store
+        .compare_and_swap("1-round1-uuid", None, "new")
+        .await
+        .unwrap()
+        .is_written());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(store.purge_expired().await.unwrap(), 2);
+    assert_eq!(store.purge_expired().await.unwrap(), 0);
+}
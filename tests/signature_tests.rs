@@ -0,0 +1,161 @@
+use bitcoin::secp256k1::ecdsa::Signature;
+use curv::arithmetic::{BitManipulation, Converter, Integer};
+use curv::BigInt;
+use tss_network::common::secp256k1def::{FE, GE};
+use tss_network::common::signature::{
+    decode_signature, message_to_bigint, recover_public_key, verify_signature,
+    verify_signature_data,
+};
+use tss_network::common::{SignatureData, SignatureScheme};
+
+// Plain single key ECDSA, only used to produce signatures for the tests
+fn sign(secret: &FE, message: &[u8]) -> SignatureData {
+    let k = FE::random();
+    let big_r = GE::generator() * &k;
+    let r = FE::from_bigint(&big_r.x_coord().unwrap().mod_floor(FE::group_order()));
+    let recid = u8::from(big_r.y_coord().unwrap().test_bit(0));
+    let e = FE::from_bigint(&message_to_bigint(message));
+    let s = k.invert().unwrap() * (e + &r * secret);
+    SignatureData::new(&r, &s, recid, &(GE::generator() * secret), message)
+}
+
+#[test]
+fn test_signature_encodings_are_low_s_and_fixed_width() {
+    let public_key = GE::generator() * FE::random();
+    // r with the top bit set needs a DER padding byte, s in the upper half must be flipped
+    let r = FE::from_bigint(
+        &BigInt::from_hex("8000000000000000000000000000000000000000000000000000000000000001")
+            .unwrap(),
+    );
+    let s = FE::from_bigint(&(FE::group_order() - BigInt::from(5)));
+
+    let signature = SignatureData::new(&r, &s, 0, &public_key, b"message");
+
+    assert_eq!(signature.recid, 1);
+    assert_eq!(signature.r.len(), 64);
+    assert_eq!(
+        signature.s,
+        "0000000000000000000000000000000000000000000000000000000000000005"
+    );
+    assert_eq!(signature.compact, format!("{}{}", signature.r, signature.s));
+    assert_eq!(signature.recoverable, format!("{}01", signature.compact));
+    assert_eq!(
+        signature.public_key,
+        hex::encode(&*public_key.to_bytes(true))
+    );
+
+    let parsed = Signature::from_der(&hex::decode(&signature.der).unwrap()).unwrap();
+    assert_eq!(hex::encode(parsed.serialize_compact()), signature.compact);
+}
+
+#[test]
+fn test_low_s_signature_is_unchanged() {
+    let public_key = GE::generator() * FE::random();
+    let r = FE::random();
+    let s = FE::from_bigint(&BigInt::from(7));
+
+    let signature = SignatureData::new(&r, &s, 1, &public_key, b"message");
+
+    assert_eq!(signature.recid, 1);
+    assert!(signature.s.ends_with("07"));
+}
+
+#[test]
+fn test_verify_and_recover_every_encoding() {
+    let secret = FE::random();
+    let public_key = GE::generator() * &secret;
+    let message = b"0123456789abcdef0123456789abcdef";
+    let signature = sign(&secret, message);
+    let message_bn = message_to_bigint(message);
+
+    for encoded in [&signature.der, &signature.compact, &signature.recoverable] {
+        let (r, s, recid) = decode_signature(&hex::decode(encoded).unwrap()).unwrap();
+        assert!(verify_signature(&r, &s, &public_key, &message_bn));
+
+        let recovered: Vec<GE> = recid
+            .map_or(vec![0, 1], |recid| vec![recid])
+            .into_iter()
+            .filter_map(|recid| recover_public_key(&r, &s, recid, &message_bn))
+            .collect();
+        assert!(recovered.contains(&public_key));
+    }
+
+    let (r, s, recid) = decode_signature(&hex::decode(&signature.recoverable).unwrap()).unwrap();
+    assert_eq!(
+        recover_public_key(&r, &s, recid.unwrap(), &message_bn),
+        Some(public_key.clone())
+    );
+
+    let other_key = GE::generator() * FE::random();
+    assert!(!verify_signature(&r, &s, &other_key, &message_bn));
+    assert!(!verify_signature(
+        &r,
+        &s,
+        &public_key,
+        &message_to_bigint(b"another message")
+    ));
+}
+
+#[test]
+fn test_decode_rejects_malformed_signatures() {
+    assert!(decode_signature(&[0u8; 63]).is_err());
+    assert!(decode_signature(&[0u8; 64]).is_err());
+    assert!(decode_signature(&[0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01]).is_err());
+}
+
+#[test]
+fn test_short_der_signatures_are_not_read_as_compact() {
+    // r and s short enough for the DER form to be 64 and 65 bytes long
+    for (r_len, s_len) in [(29, 29), (29, 30)] {
+        let r = [0x11u8; 32][..r_len].to_vec();
+        let s = [0x22u8; 32][..s_len].to_vec();
+        let mut der = vec![0x30, (4 + r_len + s_len) as u8, 0x02, r_len as u8];
+        der.extend_from_slice(&r);
+        der.extend_from_slice(&[0x02, s_len as u8]);
+        der.extend_from_slice(&s);
+        assert_eq!(der.len(), 6 + r_len + s_len);
+
+        let (decoded_r, decoded_s, recid) = decode_signature(&der).unwrap();
+        assert_eq!(decoded_r.to_bigint(), BigInt::from_bytes(&r));
+        assert_eq!(decoded_s.to_bigint(), BigInt::from_bytes(&s));
+        assert_eq!(recid, None);
+    }
+
+    // Compact signatures whose r happens to start like a DER header
+    let mut compact = [0x11u8; 64];
+    compact[..2].copy_from_slice(&[0x30, 62]);
+    let (r, _, recid) = decode_signature(&compact).unwrap();
+    assert_eq!(r.to_bigint(), BigInt::from_bytes(&compact[..32]));
+    assert_eq!(recid, None);
+}
+
+#[test]
+fn test_reported_signature_is_checked_against_message_and_key() {
+    let secret = FE::random();
+    let public_key = hex::encode(&*(GE::generator() * &secret).to_bytes(true));
+    let message = b"0123456789abcdef0123456789abcdef";
+    let signature = sign(&secret, message);
+    let verify = |signature: &SignatureData, message: &[u8], public_key: &str| {
+        verify_signature_data(signature, SignatureScheme::Ecdsa, message, public_key)
+    };
+    verify(&signature, message, &public_key).unwrap();
+
+    let other_key = hex::encode(&*(GE::generator() * FE::random()).to_bytes(true));
+    assert!(verify(&signature, message, &other_key).is_err());
+    assert!(verify(&signature, b"another message", &public_key).is_err());
+    assert!(
+        verify_signature_data(&signature, SignatureScheme::Schnorr, message, &public_key).is_err()
+    );
+
+    // Valid signature, tampered encodings
+    let tampered = SignatureData {
+        recid: 1 - signature.recid,
+        ..signature.clone()
+    };
+    assert!(verify(&tampered, message, &public_key).is_err());
+    let tampered = SignatureData {
+        der: String::new(),
+        ..signature.clone()
+    };
+    assert!(verify(&tampered, message, &public_key).is_err());
+}
@@ -0,0 +1,239 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::hashes::Hash;
+use bitcoin::psbt::{Psbt, PsbtSighashType};
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    Address, Amount, CompressedPublicKey, Network, OutPoint, Transaction, TxIn, TxOut, Txid,
+};
+use curv::elliptic::curves::{Point, Secp256k1 as Curve};
+use tss_network::common::approval::approval_digest;
+use tss_network::common::secp256k1def::GE;
+use tss_network::common::{ApprovalSignature, SignatureScheme, SigningRequest, TransactionInput};
+use tss_network::config::SignerPolicyConfig;
+use tss_network::signer::SignerPolicy;
+
+fn secret(byte: u8) -> SecretKey {
+    SecretKey::from_slice(&[byte; 32]).unwrap()
+}
+
+fn public_key(byte: u8) -> PublicKey {
+    secret(byte).public_key(&Secp256k1::new())
+}
+
+fn address(byte: u8) -> Address {
+    Address::p2wpkh(&CompressedPublicKey(public_key(byte)), Network::Bitcoin)
+}
+
+// The network key, requests are signed with it directly under the empty path
+fn network_key() -> GE {
+    Point::<Curve>::from_bytes(&public_key(1).serialize()).unwrap()
+}
+
+// Request for the input of a transaction spending from the network key
+fn transaction_request(
+    outputs: &[(&Address, u64)],
+    sighash_type: EcdsaSighashType,
+) -> SigningRequest {
+    let transaction = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_raw_hash(Hash::from_byte_array([7; 32])), 0),
+            ..TxIn::default()
+        }],
+        output: outputs
+            .iter()
+            .map(|(address, value)| TxOut {
+                value: Amount::from_sat(*value),
+                script_pubkey: address.script_pubkey(),
+            })
+            .collect(),
+    };
+    let mut psbt = Psbt::from_unsigned_tx(transaction).unwrap();
+    psbt.inputs[0].witness_utxo = Some(TxOut {
+        value: Amount::from_sat(1_000_000),
+        script_pubkey: address(1).script_pubkey(),
+    });
+    psbt.inputs[0].sighash_type = Some(PsbtSighashType::from(sighash_type));
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let (sighash, _) = psbt.sighash_ecdsa(0, &mut cache).unwrap();
+    SigningRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        message: sighash.as_ref().to_vec(),
+        scheme: SignatureScheme::Ecdsa,
+        derivation_path: None,
+        client_id: None,
+        transaction: Some(TransactionInput {
+            psbt: psbt.to_string(),
+            input: 0,
+        }),
+        approvals: Vec::new(),
+    }
+}
+
+fn message_request(scheme: SignatureScheme) -> SigningRequest {
+    SigningRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        message: b"message".to_vec(),
+        scheme,
+        derivation_path: None,
+        client_id: None,
+        transaction: None,
+        approvals: Vec::new(),
+    }
+}
+
+fn approve(request: &SigningRequest, approver: u8) -> ApprovalSignature {
+    let digest = approval_digest(request).unwrap();
+    let signature = Secp256k1::new().sign_ecdsa(&Message::from_digest(digest), &secret(approver));
+    ApprovalSignature {
+        public_key: hex::encode(public_key(approver).serialize()),
+        signature: hex::encode(signature.serialize_der()),
+    }
+}
+
+fn policy(config: SignerPolicyConfig) -> SignerPolicy {
+    SignerPolicy::from_config(&config).unwrap()
+}
+
+#[test]
+fn test_signer_policy_rejects_invalid_config() {
+    for invalid in [
+        SignerPolicyConfig {
+            allowed_hash_modes: vec!["SIGHASH_SOME".into()],
+            ..SignerPolicyConfig::default()
+        },
+        SignerPolicyConfig {
+            allowed_derivation_paths: vec!["0/x".into()],
+            ..SignerPolicyConfig::default()
+        },
+        SignerPolicyConfig {
+            allowed_destinations: vec!["not an address".into()],
+            ..SignerPolicyConfig::default()
+        },
+        SignerPolicyConfig {
+            approver_keys: vec!["02ff".into()],
+            approvals_required: 1,
+            ..SignerPolicyConfig::default()
+        },
+        // More approvals than approvers
+        SignerPolicyConfig {
+            approver_keys: vec![hex::encode(public_key(5).serialize())],
+            approvals_required: 2,
+            ..SignerPolicyConfig::default()
+        },
+    ] {
+        assert!(SignerPolicy::from_config(&invalid).is_err());
+    }
+}
+
+#[test]
+fn test_signer_policy_checks_hash_modes_and_paths() {
+    let key = network_key();
+    let unrestricted = SignerPolicy::default();
+    unrestricted
+        .check(&message_request(SignatureScheme::Ecdsa), "0/1", &key)
+        .unwrap();
+    let transaction = transaction_request(&[(&address(2), 1_000)], EcdsaSighashType::All);
+    unrestricted.check(&transaction, "", &key).unwrap();
+
+    let policy = policy(SignerPolicyConfig {
+        allowed_hash_modes: vec!["SIGHASH_ALL".into()],
+        allowed_derivation_paths: vec!["".into(), "0/1/*".into()],
+        ..SignerPolicyConfig::default()
+    });
+    policy.check(&transaction, "", &key).unwrap();
+    let reason = policy
+        .check(&message_request(SignatureScheme::Ecdsa), "0/1/4", &key)
+        .unwrap_err();
+    assert!(reason.contains("hash mode message"));
+    let reason = policy
+        .check(
+            &transaction_request(&[(&address(2), 1_000)], EcdsaSighashType::None),
+            "",
+            &key,
+        )
+        .unwrap_err();
+    assert!(reason.contains("SIGHASH_NONE"));
+    let reason = policy.check(&transaction, "0/2", &key).unwrap_err();
+    assert!(reason.contains("0/2"));
+
+    // The message must be the sighash of the input it claims to be
+    let mut forged = transaction.clone();
+    forged.message = vec![9; 32];
+    let reason = unrestricted.check(&forged, "", &key).unwrap_err();
+    assert!(reason.contains("sighash of input 0"));
+    let mut forged = transaction.clone();
+    forged.scheme = SignatureScheme::Schnorr;
+    assert!(unrestricted.check(&forged, "", &key).is_err());
+}
+
+#[test]
+fn test_signer_policy_checks_destinations() {
+    let key = network_key();
+    let change = address(1);
+    let allowed = address(2);
+    let policy = policy(SignerPolicyConfig {
+        allowed_destinations: vec![allowed.to_string()],
+        ..SignerPolicyConfig::default()
+    });
+
+    let request = transaction_request(
+        &[(&allowed, 1_000), (&change, 900_000)],
+        EcdsaSighashType::All,
+    );
+    policy.check(&request, "", &key).unwrap();
+    let request = transaction_request(
+        &[(&allowed, 1_000), (&address(3), 1_000)],
+        EcdsaSighashType::All,
+    );
+    let reason = policy.check(&request, "", &key).unwrap_err();
+    assert!(reason.contains("output 1"));
+    // Change is only change to the key signing
+    let request = transaction_request(&[(&change, 1_000)], EcdsaSighashType::All);
+    assert!(policy.check(&request, "0/1", &key).is_err());
+
+    // A plain message could be the sighash of any transaction
+    for scheme in [SignatureScheme::Ecdsa, SignatureScheme::Schnorr] {
+        assert!(policy.check(&message_request(scheme), "", &key).is_err());
+    }
+}
+
+#[test]
+fn test_signer_policy_requires_known_approvers() {
+    let key = network_key();
+    let policy = policy(SignerPolicyConfig {
+        approver_keys: [5, 6, 7]
+            .iter()
+            .map(|approver| hex::encode(public_key(*approver).serialize()))
+            .collect(),
+        approvals_required: 2,
+        ..SignerPolicyConfig::default()
+    });
+
+    let mut request = message_request(SignatureScheme::Eddsa);
+    let reason = policy.check(&request, "", &key).unwrap_err();
+    assert!(reason.contains("0 of the 2"));
+    // Repeated, unknown and misdirected approvals do not count
+    let other = message_request(SignatureScheme::Ecdsa);
+    request.approvals = vec![
+        approve(&request, 5),
+        approve(&request, 5),
+        approve(&request, 8),
+        approve(&other, 6),
+    ];
+    let reason = policy.check(&request, "", &key).unwrap_err();
+    assert!(reason.contains("1 of the 2"));
+    request.approvals.push(approve(&request, 7));
+    policy.check(&request, "", &key).unwrap();
+
+    // One approval covers every input of a transaction
+    let mut transaction = transaction_request(&[(&address(2), 1_000)], EcdsaSighashType::All);
+    transaction.approvals = vec![approve(&transaction, 6), approve(&transaction, 7)];
+    let mut other_input = transaction.clone();
+    other_input.id = uuid::Uuid::new_v4().to_string();
+    policy.check(&transaction, "", &key).unwrap();
+    policy.check(&other_input, "", &key).unwrap();
+}
@@ -0,0 +1,83 @@
+use rocket::fairing::AdHoc;
+use rocket::post;
+use rocket::routes;
+use rocket::serde::json::Json;
+use tokio::sync::oneshot;
+use tss_network::common::{ManagerClient, ManagerError, SignatureData, SignerResult};
+use tss_network::signer::service::submit_signature;
+
+// Stores every signature except those of the request `rejected`
+#[post("/update_signing_result", format = "json", data = "<result>")]
+async fn update_signing_result(result: Json<SignerResult>) -> Json<Result<(), ManagerError>> {
+    if result.request_id == "rejected" {
+        return Json(Err(ManagerError {
+            error: "Signature does not verify".to_string(),
+        }));
+    }
+    Json(Ok(()))
+}
+
+// Serves `/update_signing_result` like the manager, returns its address
+async fn start_manager() -> String {
+    let (port_sender, port_receiver) = oneshot::channel();
+    let config = rocket::Config {
+        port: 0,
+        log_level: rocket::config::LogLevel::Off,
+        ..rocket::Config::debug_default()
+    };
+    let rocket = rocket::custom(config)
+        .mount("/", routes![update_signing_result])
+        .attach(AdHoc::on_liftoff("Port", |rocket| {
+            let port = rocket.config().port;
+            Box::pin(async move {
+                let _ = port_sender.send(port);
+            })
+        }));
+    tokio::spawn(rocket.launch());
+    format!("http://127.0.0.1:{}", port_receiver.await.unwrap())
+}
+
+fn result(request_id: &str) -> SignerResult {
+    SignerResult {
+        request_id: request_id.to_string(),
+        signature: SignatureData {
+            r: "01".to_string(),
+            s: "02".to_string(),
+            status: "signature_ready".to_string(),
+            recid: 0,
+            x: String::new(),
+            y: String::new(),
+            msg_int: Vec::new(),
+            der: String::new(),
+            compact: String::new(),
+            recoverable: String::new(),
+            public_key: String::new(),
+            scheme: Default::default(),
+        },
+        party_id: 1,
+    }
+}
+
+// A signature the manager did not store fails the request, so that the
+// signer retries or dead-letters it instead of acknowledging it
+#[tokio::test]
+async fn test_unsubmitted_signature_is_an_error() {
+    let addr = start_manager().await;
+    let client = ManagerClient::default();
+
+    submit_signature(&addr, &client, result("stored"))
+        .await
+        .unwrap();
+    let error = submit_signature(&addr, &client, result("rejected"))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("does not verify"));
+
+    // No manager listening
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let closed = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+    assert!(submit_signature(&closed, &client, result("stored"))
+        .await
+        .is_err());
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use curv::cryptographic_primitives::secret_sharing::feldman_vss::VerifiableSS;
+use curv::elliptic::curves::{Point, Scalar, Secp256k1};
+use multi_party_ecdsa::protocols::multi_party_ecdsa::gg_2018::party_i::{Keys, SharedKeys};
+use paillier::EncryptionKey;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tss_network::common::{DeadLetter, SignatureScheme, SigningRequest};
+use tss_network::queue::{InProcessQueue, ReceivedDeadLetter, ReceivedRequest, RequestQueue};
+use tss_network::signer::policy::SignerPolicy;
+use tss_network::signer::service::SignerService;
+
+const SESSION_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Redelivering queue counting the requests taken from it and how they were settled
+struct CountingQueue {
+    inner: InProcessQueue,
+    received: AtomicUsize,
+    requeued: AtomicUsize,
+}
+
+#[async_trait]
+impl RequestQueue for CountingQueue {
+    async fn publish_signing_request(&self, request: &SigningRequest) -> Result<()> {
+        self.inner.publish_signing_request(request).await
+    }
+
+    async fn subscribe(&self, consumer: &str) -> Result<()> {
+        RequestQueue::subscribe(&self.inner, consumer).await
+    }
+
+    async fn receive_signing_request(&self) -> Result<ReceivedRequest> {
+        let received = self.inner.receive_signing_request().await?;
+        self.received.fetch_add(1, Ordering::SeqCst);
+        Ok(received)
+    }
+
+    async fn publish_dead_letter(&self, letter: &DeadLetter) -> Result<()> {
+        self.inner.publish_dead_letter(letter).await
+    }
+
+    async fn receive_dead_letter(&self) -> Result<ReceivedDeadLetter> {
+        self.inner.receive_dead_letter().await
+    }
+
+    fn supports_redelivery(&self) -> bool {
+        true
+    }
+
+    async fn nack(&self, _delivery_tag: u64, requeue: bool) -> Result<()> {
+        if requeue {
+            self.requeued.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+// Manager that accepts connections and never answers, so that every
+// signing session waits on its signup until it times out
+async fn silent_manager() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((connection, _)) = listener.accept().await {
+            connections.push(connection);
+        }
+    });
+    port
+}
+
+// Key file of party 1, never used past the signup
+fn key_file() -> String {
+    let secret = Scalar::<Secp256k1>::random();
+    let y_sum = Point::generator() * &secret;
+    let shared_keys = SharedKeys {
+        y: y_sum.clone(),
+        x_i: secret,
+    };
+    let contents = serde_json::to_string(&(
+        Keys::create(1),
+        shared_keys,
+        1u16,
+        Vec::<VerifiableSS<Secp256k1>>::new(),
+        Vec::<EncryptionKey>::new(),
+        y_sum,
+    ))
+    .unwrap();
+    let path = std::env::temp_dir().join(format!("tss-signer-{}.json", uuid::Uuid::new_v4()));
+    std::fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+fn signing_request() -> SigningRequest {
+    SigningRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        message: uuid::Uuid::new_v4().as_bytes().to_vec(),
+        scheme: SignatureScheme::Ecdsa,
+        derivation_path: None,
+        client_id: None,
+        transaction: None,
+        approvals: Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn test_signer_runs_at_most_max_concurrent_sessions() {
+    let queue = Arc::new(CountingQueue {
+        inner: InProcessQueue::new(),
+        received: AtomicUsize::new(0),
+        requeued: AtomicUsize::new(0),
+    });
+    let port = silent_manager().await;
+    let signer = SignerService::new(
+        "http://127.0.0.1",
+        &port,
+        queue.clone(),
+        &key_file(),
+        "signer-secret",
+        &1,
+        &3,
+        "",
+        None,
+        None,
+        2,
+        SESSION_TIMEOUT,
+        None,
+        SignerPolicy::default(),
+    )
+    .await
+    .unwrap();
+    for _ in 0..5 {
+        queue
+            .publish_signing_request(&signing_request())
+            .await
+            .unwrap();
+    }
+    let running = tokio::spawn(Arc::new(signer).run());
+
+    // Two sessions wait on the manager, the other requests stay in the queue
+    tokio::time::sleep(SESSION_TIMEOUT / 2).await;
+    assert_eq!(queue.received.load(Ordering::SeqCst), 2);
+    assert_eq!(queue.requeued.load(Ordering::SeqCst), 0);
+
+    // Timed out sessions are requeued and free their slot for the next requests
+    tokio::time::sleep(SESSION_TIMEOUT).await;
+    assert_eq!(queue.requeued.load(Ordering::SeqCst), 2);
+    assert_eq!(queue.received.load(Ordering::SeqCst), 4);
+    running.abort();
+}
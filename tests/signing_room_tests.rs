@@ -0,0 +1,78 @@
+use tss_network::common::{parse_round_key, RoomState, SigningRoom};
+
+const ROOM_UUID: &str = "9b2c8c1e-5f4a-4d52-8f0e-3c1d2a4b5e6f";
+
+#[test]
+fn test_room_state_transitions() {
+    let mut room = SigningRoom::new("room".to_string(), 2);
+    assert_eq!(room.state, RoomState::Signup);
+
+    // Rounds only start once the room is locked
+    assert!(room.transition(RoomState::InRound(0)).is_err());
+    assert!(room.transition(RoomState::Completed).is_err());
+    room.transition(RoomState::Locked).unwrap();
+    room.transition(RoomState::InRound(1)).unwrap();
+    assert!(room.transition(RoomState::InRound(0)).is_err());
+    room.transition(RoomState::InRound(2)).unwrap();
+    assert_eq!(room.closed_at, None);
+
+    room.transition(RoomState::Completed).unwrap();
+    assert!(room.is_closed());
+    assert!(room.closed_at.is_some());
+    assert!(room.transition(RoomState::Aborted).is_err());
+}
+
+#[test]
+fn test_round_tracking() {
+    let mut room = SigningRoom::new("room".to_string(), 2);
+    room.transition(RoomState::Locked).unwrap();
+
+    assert!(room.record_round("round0", 1));
+    assert!(!room.record_round("round0", 1));
+    assert_eq!(room.state, RoomState::InRound(0));
+    assert!(room.completed_rounds().is_empty());
+
+    assert!(room.record_round("round0", 2));
+    assert!(room.record_round("round1", 2));
+    assert_eq!(room.state, RoomState::InRound(1));
+    assert_eq!(room.completed_rounds(), vec!["round0".to_string()]);
+
+    // A late message of an earlier round does not move the room back
+    room.record_round("round0", 3);
+    assert_eq!(room.state, RoomState::InRound(1));
+}
+
+#[test]
+fn test_members_are_shared_once_locked() {
+    let mut room = SigningRoom::new("room".to_string(), 2);
+    room.add_party(3);
+    assert!(room.get_signup_info(3).members.is_empty());
+
+    room.add_party(1);
+    let signup = room.update_ping(1);
+    assert_eq!(room.state, RoomState::Locked);
+    assert!(!signup.room_uuid.is_empty());
+    // The party at index `i` of the members signs as party order `i + 1`
+    let mut members = signup.members.clone();
+    members.sort_unstable();
+    assert_eq!(members, vec![1, 3]);
+    for (index, party_number) in signup.members.iter().enumerate() {
+        let party_order = room.get_signup_info(*party_number).party_order;
+        assert_eq!(usize::from(party_order), index + 1);
+    }
+}
+
+#[test]
+fn test_parse_round_key() {
+    assert_eq!(
+        parse_round_key(&format!("2-round1-{}", ROOM_UUID)),
+        Some((2, "round1", ROOM_UUID))
+    );
+    assert_eq!(
+        parse_round_key(&format!("3-1-round2-{}", ROOM_UUID)),
+        Some((3, "round2", ROOM_UUID))
+    );
+    assert_eq!(parse_round_key("signup-keygen"), None);
+    assert_eq!(parse_round_key(&format!("signup-sign-{}", ROOM_UUID)), None);
+    assert_eq!(parse_round_key("1-round1-not-a-uuid"), None);
+}
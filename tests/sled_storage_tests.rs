@@ -0,0 +1,512 @@
+use std::time::Duration;
+use tss_network::common::{
+    Approval, ApprovalDecision, DeadLetter, KeyGenParams, KeyGenRequest, MessageStatus,
+    SignatureData, SignatureScheme, SignerResult, SigningFailure, SigningProgress, SigningRefusal,
+    SigningRequest, SigningRequestFilter,
+};
+use tss_network::storage::sled::SledStorage;
+use tss_network::storage::Storage;
+
+fn open_storage() -> SledStorage {
+    SledStorage::temporary().unwrap()
+}
+
+fn signing_request(scheme: SignatureScheme) -> SigningRequest {
+    SigningRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        message: b"message".to_vec(),
+        scheme,
+
+        derivation_path: None,
+        client_id: None,
+        transaction: None,
+        approvals: Vec::new(),
+    }
+}
+
+fn progress(request_id: &str, room_uuid: &str, party_id: u16) -> SigningProgress {
+    SigningProgress {
+        request_id: request_id.to_string(),
+        room_uuid: room_uuid.to_string(),
+        party_id,
+    }
+}
+
+fn signature() -> SignatureData {
+    SignatureData {
+        r: "01".repeat(32),
+        s: "02".repeat(32),
+        status: "signature_ready".to_string(),
+        recid: 0,
+        x: String::new(),
+        y: String::new(),
+        msg_int: Vec::new(),
+        der: String::new(),
+        compact: String::new(),
+        recoverable: String::new(),
+        public_key: String::new(),
+        scheme: SignatureScheme::Ecdsa,
+    }
+}
+
+#[tokio::test]
+async fn test_signing_request_lifecycle() {
+    let storage = open_storage();
+    let request = signing_request(SignatureScheme::Ecdsa);
+    storage.insert_request(&request).await.unwrap();
+    assert!(storage.insert_request(&request).await.is_err());
+
+    storage
+        .update_signing_progress(&progress(&request.id, "room-1", 1))
+        .await
+        .unwrap();
+    storage
+        .update_signing_progress(&progress(&request.id, "room-1", 3))
+        .await
+        .unwrap();
+    // A second room is a new attempt
+    storage
+        .update_signing_progress(&progress(&request.id, "room-2", 2))
+        .await
+        .unwrap();
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::InProgress);
+    assert_eq!(stored.attempts, 2);
+    assert_eq!(stored.room_uuid.as_deref(), Some("room-2"));
+    assert_eq!(stored.participants, vec![2]);
+    assert_eq!(stored.key_id, "ecdsa");
+    let started_at = stored.started_at.unwrap();
+    assert!(started_at >= stored.created_at);
+
+    let result = SignerResult {
+        request_id: request.id.clone(),
+        signature: signature(),
+        party_id: 2,
+    };
+    storage.update_signing_result(&result).await.unwrap();
+    storage.update_signing_result(&result).await.unwrap();
+    // Only signers reporting the stored signature are recorded
+    let disagreeing = SignerResult {
+        signature: SignatureData {
+            s: "03".repeat(32),
+            ..signature()
+        },
+        party_id: 1,
+        ..result.clone()
+    };
+    storage.update_signing_result(&disagreeing).await.unwrap();
+    let agreeing = SignerResult {
+        party_id: 3,
+        ..result.clone()
+    };
+    storage.update_signing_result(&agreeing).await.unwrap();
+    storage
+        .update_signing_progress(&progress(&request.id, "room-3", 1))
+        .await
+        .unwrap();
+
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Completed);
+    assert_eq!(stored.signature.unwrap().s, "02".repeat(32));
+    assert_eq!(stored.reported_by, vec![2, 3]);
+    assert!(stored.completed_at.unwrap() >= started_at);
+    let history: Vec<MessageStatus> = stored
+        .status_history
+        .iter()
+        .map(|change| change.status.clone())
+        .collect();
+    assert_eq!(
+        history,
+        vec![
+            MessageStatus::Pending,
+            MessageStatus::InProgress,
+            MessageStatus::InProgress,
+            MessageStatus::InProgress,
+            MessageStatus::Completed,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_list_signing_requests_pages_newest_first() {
+    let storage = open_storage();
+    let mut ids = Vec::new();
+    for scheme in [
+        SignatureScheme::Ecdsa,
+        SignatureScheme::Schnorr,
+        SignatureScheme::Ecdsa,
+        SignatureScheme::Ecdsa,
+    ] {
+        let request = signing_request(scheme);
+        storage.insert_request(&request).await.unwrap();
+        ids.push(request.id);
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+
+    let filter = SigningRequestFilter {
+        key_id: Some("ecdsa".to_string()),
+        ..Default::default()
+    };
+    let (page, cursor) = storage
+        .list_signing_requests(&filter, 2, None)
+        .await
+        .unwrap();
+    let page_ids: Vec<&str> = page.iter().map(|r| r.request_id.as_str()).collect();
+    assert_eq!(page_ids, vec![ids[3].as_str(), ids[2].as_str()]);
+
+    let (page, cursor) = storage
+        .list_signing_requests(&filter, 2, cursor.as_deref())
+        .await
+        .unwrap();
+    let page_ids: Vec<&str> = page.iter().map(|r| r.request_id.as_str()).collect();
+    assert_eq!(page_ids, vec![ids[0].as_str()]);
+    assert_eq!(cursor, None);
+
+    // Time bounds apply on top of the other criteria
+    let all = SigningRequestFilter::default();
+    let (page, _) = storage.list_signing_requests(&all, 10, None).await.unwrap();
+    let filter = SigningRequestFilter {
+        since: Some(page[2].created_at),
+        until: Some(page[0].created_at),
+        ..Default::default()
+    };
+    let (page, _) = storage
+        .list_signing_requests(&filter, 10, None)
+        .await
+        .unwrap();
+    let page_ids: Vec<&str> = page.iter().map(|r| r.request_id.as_str()).collect();
+    assert_eq!(page_ids, vec![ids[2].as_str(), ids[1].as_str()]);
+
+    let filter = SigningRequestFilter {
+        status: Some(MessageStatus::Completed),
+        ..Default::default()
+    };
+    let (page, _) = storage
+        .list_signing_requests(&filter, 10, None)
+        .await
+        .unwrap();
+    assert!(page.is_empty());
+    assert!(storage
+        .list_signing_requests(&all, 10, Some("not-a-cursor"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_key_gen_request_lifecycle() {
+    let storage = open_storage();
+    let request = KeyGenRequest {
+        id: uuid::Uuid::new_v4().to_string(),
+        keygen_params: KeyGenParams {
+            parties: 3,
+            threshold: 1,
+            scheme: SignatureScheme::Eddsa,
+        },
+    };
+    storage.insert_key_gen_request(&request).await.unwrap();
+    assert!(storage.insert_key_gen_request(&request).await.is_err());
+
+    storage
+        .update_key_gen_result(&request.id, vec!["key".to_string()])
+        .await
+        .unwrap();
+    // Completed results are not overwritten
+    storage
+        .update_key_gen_result(&request.id, vec!["other".to_string()])
+        .await
+        .unwrap();
+
+    let stored = storage
+        .get_key_gen_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Completed);
+    assert_eq!(stored.keys, Some(vec!["key".to_string()]));
+    assert!(storage.get_key_gen_result("not-a-uuid").await.is_err());
+}
+
+#[tokio::test]
+async fn test_dead_letters_merge_failures_and_are_taken_once() {
+    let storage = open_storage();
+    let first = signing_request(SignatureScheme::Ecdsa);
+    let second = signing_request(SignatureScheme::Schnorr);
+    let letter = |request: &SigningRequest, party_id, failed_at| DeadLetter {
+        request: request.clone(),
+        failure: SigningFailure {
+            party_id,
+            error: "timed out".to_string(),
+            failed_at,
+        },
+    };
+
+    storage
+        .insert_dead_letter(&letter(&first, 1, 100))
+        .await
+        .unwrap();
+    storage
+        .insert_dead_letter(&letter(&second, 1, 200))
+        .await
+        .unwrap();
+    storage
+        .insert_dead_letter(&letter(&first, 2, 300))
+        .await
+        .unwrap();
+
+    let dead_letters = storage.list_dead_letters(10).await.unwrap();
+    let ids: Vec<&str> = dead_letters.iter().map(|d| d.request_id.as_str()).collect();
+    assert_eq!(ids, vec![first.id.as_str(), second.id.as_str()]);
+    assert_eq!(dead_letters[0].failures.len(), 2);
+    assert_eq!(dead_letters[0].first_failed_at, 100);
+    assert_eq!(dead_letters[0].last_failed_at, 300);
+    assert_eq!(storage.list_dead_letters(1).await.unwrap().len(), 1);
+
+    let taken = storage.take_dead_letter(&first.id).await.unwrap().unwrap();
+    assert_eq!(taken.request, first);
+    assert!(storage.take_dead_letter(&first.id).await.unwrap().is_none());
+    assert_eq!(storage.list_dead_letters(10).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_approval_decisions_release_or_reject_requests() {
+    let storage = open_storage();
+    let decision = |approver: &str, approved| ApprovalDecision {
+        approver: approver.to_string(),
+        approved,
+        comment: None,
+        signature: None,
+        at: 100,
+    };
+    let approval = Approval {
+        required: 2,
+        summary: "message".to_string(),
+        digest: String::new(),
+        decisions: Vec::new(),
+        batch: vec![signing_request(SignatureScheme::Ecdsa)],
+    };
+
+    let approved = signing_request(SignatureScheme::Ecdsa);
+    storage
+        .insert_request_for_approval(&approved, &approval)
+        .await
+        .unwrap();
+    // Held back requests take no signing progress
+    storage
+        .update_signing_progress(&progress(&approved.id, "room-1", 1))
+        .await
+        .unwrap();
+    let stored = storage
+        .record_approval_decision(&approved.id, &decision("alice", true))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::AwaitingApproval);
+    assert!(stored.room_uuid.is_none());
+    assert!(storage
+        .record_approval_decision(&approved.id, &decision("alice", true))
+        .await
+        .unwrap()
+        .is_none());
+    let stored = storage
+        .record_approval_decision(&approved.id, &decision("bob", true))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Pending);
+    assert_eq!(stored.approval.as_ref().unwrap().decisions.len(), 2);
+    assert_eq!(stored.approval.unwrap().batch, approval.batch);
+    assert!(storage
+        .record_approval_decision(&approved.id, &decision("carol", true))
+        .await
+        .unwrap()
+        .is_none());
+
+    let rejected = signing_request(SignatureScheme::Ecdsa);
+    storage
+        .insert_request_for_approval(&rejected, &approval)
+        .await
+        .unwrap();
+    let stored = storage
+        .record_approval_decision(&rejected.id, &decision("alice", false))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Rejected);
+    let statuses: Vec<MessageStatus> = stored
+        .status_history
+        .into_iter()
+        .map(|change| change.status)
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![MessageStatus::AwaitingApproval, MessageStatus::Rejected]
+    );
+
+    // Requests signed without approval take no decisions
+    let direct = signing_request(SignatureScheme::Ecdsa);
+    storage.insert_request(&direct).await.unwrap();
+    assert!(storage
+        .record_approval_decision(&direct.id, &decision("alice", true))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(storage
+        .record_approval_decision(&uuid::Uuid::new_v4().to_string(), &decision("alice", true),)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_signing_refusals_are_recorded_once_per_signer() {
+    let storage = open_storage();
+    let request = signing_request(SignatureScheme::Ecdsa);
+    storage.insert_request(&request).await.unwrap();
+    let refusal = |party_id, reason: &str| SigningRefusal {
+        request_id: request.id.clone(),
+        party_id,
+        reason: reason.to_string(),
+    };
+
+    for (party_id, reason) in [
+        (1, "hash mode message is not allowed"),
+        (1, "again"),
+        (2, "other"),
+    ] {
+        storage
+            .record_signing_refusal(&refusal(party_id, reason), 2)
+            .await
+            .unwrap();
+    }
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    let refusals: Vec<(u16, &str)> = stored
+        .refusals
+        .iter()
+        .map(|refusal| (refusal.party_id, refusal.error.as_str()))
+        .collect();
+    assert_eq!(
+        refusals,
+        vec![(1, "hash mode message is not allowed"), (2, "other")]
+    );
+    // Refusals the quorum can spare leave the status to the other signers
+    assert_eq!(stored.status, MessageStatus::Pending);
+}
+
+#[tokio::test]
+async fn test_request_refused_beyond_quorum_is_rejected() {
+    let storage = open_storage();
+    let request = signing_request(SignatureScheme::Ecdsa);
+    storage.insert_request(&request).await.unwrap();
+    let refusal = |party_id, reason: &str| SigningRefusal {
+        request_id: request.id.clone(),
+        party_id,
+        reason: reason.to_string(),
+    };
+
+    // Three parties signing with two tolerate one refusal
+    storage
+        .record_signing_refusal(&refusal(1, "destination not allowed"), 1)
+        .await
+        .unwrap();
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Pending);
+
+    storage
+        .record_signing_refusal(&refusal(3, "hash mode message is not allowed"), 1)
+        .await
+        .unwrap();
+    let stored = storage
+        .get_signing_result(&request.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Rejected);
+    let rejection = stored.status_history.last().unwrap();
+    assert_eq!(rejection.status, MessageStatus::Rejected);
+    assert_eq!(rejection.party_id, None);
+    let reason = rejection.reason.as_deref().unwrap();
+    assert!(reason.contains("party 1: destination not allowed"));
+    assert!(reason.contains("party 3: hash mode message is not allowed"));
+
+    // Completed requests keep their signature
+    let signed = signing_request(SignatureScheme::Ecdsa);
+    storage.insert_request(&signed).await.unwrap();
+    let result = SignerResult {
+        request_id: signed.id.clone(),
+        signature: signature(),
+        party_id: 2,
+    };
+    storage.update_signing_result(&result).await.unwrap();
+    storage
+        .record_signing_refusal(
+            &SigningRefusal {
+                request_id: signed.id.clone(),
+                party_id: 1,
+                reason: "late".to_string(),
+            },
+            0,
+        )
+        .await
+        .unwrap();
+    let stored = storage
+        .get_signing_result(&signed.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.status, MessageStatus::Completed);
+}
+
+#[tokio::test]
+async fn test_client_requests_are_counted_per_client() {
+    let storage = open_storage();
+    // "client" is a prefix of "client-2", their requests must not mix
+    let mut created = Vec::new();
+    for client_id in [Some("client"), Some("client-2"), Some("client"), None] {
+        let request = SigningRequest {
+            client_id: client_id.map(str::to_string),
+            ..signing_request(SignatureScheme::Ecdsa)
+        };
+        storage.insert_request(&request).await.unwrap();
+        let stored = storage.get_signing_result(&request.id).await.unwrap();
+        created.push(stored.unwrap().created_at);
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+
+    assert_eq!(storage.count_client_requests("client", 0).await.unwrap(), 2);
+    assert_eq!(
+        storage.count_client_requests("client-2", 0).await.unwrap(),
+        1
+    );
+    assert_eq!(storage.count_client_requests("other", 0).await.unwrap(), 0);
+    // Only requests created since the given time count
+    assert_eq!(
+        storage
+            .count_client_requests("client", created[1])
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        storage
+            .count_client_requests("client", created[3] + 1)
+            .await
+            .unwrap(),
+        0
+    );
+}
@@ -0,0 +1,316 @@
+use futures::future::join_all;
+use rocket::fairing::AdHoc;
+use rocket::serde::json::Json;
+use rocket::{post, routes, State};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::{timeout, Instant};
+use tss_network::common::{
+    broadcast, poll_for_broadcasts, poll_for_p2p, sendp2p, Entry, HttpTransport, Index,
+    ManagerClient, ManagerError, Transport, KEY_NOT_FOUND,
+};
+use tss_network::storage::room_store::{InMemoryRoomStore, RoomStore};
+
+const PARTIES: u16 = 8;
+const POLL_DELAY: Duration = Duration::from_millis(10);
+
+fn deadline() -> Instant {
+    Instant::now() + Duration::from_secs(30)
+}
+
+type Relay = Arc<InMemoryRoomStore>;
+
+#[post("/get", format = "json", data = "<request>")]
+async fn get(relay: &State<Relay>, request: Json<Index>) -> Json<Result<Entry, ManagerError>> {
+    if request.key.contains("forbidden") {
+        return Json(Err(ManagerError {
+            error: "Unauthorized: not a member".to_string(),
+        }));
+    }
+    match relay.get(&request.key).await.unwrap() {
+        Some(value) => Json(Ok(Entry {
+            key: request.key.clone(),
+            value,
+        })),
+        None => Json(Err(ManagerError {
+            error: KEY_NOT_FOUND.to_string(),
+        })),
+    }
+}
+
+#[post("/set", format = "json", data = "<request>")]
+async fn set(relay: &State<Relay>, request: Json<Entry>) -> Json<Result<(), ManagerError>> {
+    relay.set(&request.key, &request.value).await.unwrap();
+    Json(Ok(()))
+}
+
+// Serves `/get` and `/set` like the manager on the test runtime, returns its address
+async fn start_relay() -> String {
+    let (port_sender, port_receiver) = oneshot::channel();
+    let config = rocket::Config {
+        port: 0,
+        log_level: rocket::config::LogLevel::Off,
+        ..rocket::Config::debug_default()
+    };
+    let relay: Relay = Arc::new(InMemoryRoomStore::new(Duration::from_secs(60)));
+    let rocket = rocket::custom(config)
+        .manage(relay)
+        .mount("/", routes![get, set])
+        .attach(AdHoc::on_liftoff("Port", |rocket| {
+            let port = rocket.config().port;
+            Box::pin(async move {
+                let _ = port_sender.send(port);
+            })
+        }));
+    tokio::spawn(rocket.launch());
+    format!("http://127.0.0.1:{}", port_receiver.await.unwrap())
+}
+
+// Parties start one after the other, so most of them poll while peers have not posted yet
+fn start_delay(party: u16) -> Duration {
+    Duration::from_millis(20 * (PARTIES - party) as u64)
+}
+
+// `#[tokio::test]` runs on a current-thread runtime, a blocking poll would stall the relay
+#[tokio::test]
+async fn test_parties_exchange_broadcasts_on_one_thread() {
+    let addr = start_relay().await;
+    let sessions = (1..=PARTIES).map(|party| {
+        let addr = addr.clone();
+        async move {
+            let client = ManagerClient::default();
+            tokio::time::sleep(start_delay(party)).await;
+            broadcast(
+                &addr,
+                &client,
+                party,
+                "round1",
+                format!("from-{}", party),
+                "uuid".to_string(),
+            )
+            .await
+            .unwrap();
+            poll_for_broadcasts(
+                &addr,
+                &client,
+                party,
+                PARTIES,
+                POLL_DELAY,
+                "round1",
+                "uuid".to_string(),
+                deadline(),
+            )
+            .await
+            .unwrap()
+        }
+    });
+
+    let received = timeout(Duration::from_secs(30), join_all(sessions))
+        .await
+        .unwrap();
+    for (party, values) in (1..=PARTIES).zip(received) {
+        let expected: Vec<String> = (1..=PARTIES)
+            .filter(|other| *other != party)
+            .map(|other| format!("from-{}", other))
+            .collect();
+        assert_eq!(values, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_parties_exchange_p2p_messages_on_one_thread() {
+    let addr = start_relay().await;
+    let sessions = (1..=PARTIES).map(|party| {
+        let addr = addr.clone();
+        async move {
+            let client = ManagerClient::default();
+            tokio::time::sleep(start_delay(party)).await;
+            for other in (1..=PARTIES).filter(|other| *other != party) {
+                sendp2p(
+                    &addr,
+                    &client,
+                    party,
+                    other,
+                    "round2",
+                    format!("{}-to-{}", party, other),
+                    "uuid".to_string(),
+                )
+                .await
+                .unwrap();
+            }
+            poll_for_p2p(
+                &addr,
+                &client,
+                party,
+                PARTIES,
+                POLL_DELAY,
+                "round2",
+                "uuid".to_string(),
+                deadline(),
+            )
+            .await
+            .unwrap()
+        }
+    });
+
+    let received = timeout(Duration::from_secs(30), join_all(sessions))
+        .await
+        .unwrap();
+    for (party, values) in (1..=PARTIES).zip(received) {
+        let expected: Vec<String> = (1..=PARTIES)
+            .filter(|other| *other != party)
+            .map(|other| format!("{}-to-{}", other, party))
+            .collect();
+        assert_eq!(values, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_poll_is_cancelled_when_dropped() {
+    let addr = start_relay().await;
+    let client = ManagerClient::default();
+
+    // Party 2 never broadcasts, the poll only ends through cancellation
+    let poll = poll_for_broadcasts(
+        &addr,
+        &client,
+        1,
+        2,
+        POLL_DELAY,
+        "round1",
+        "uuid".to_string(),
+        deadline(),
+    );
+    assert!(timeout(Duration::from_millis(200), poll).await.is_err());
+
+    // The relay kept serving while the poll waited
+    broadcast(
+        &addr,
+        &client,
+        2,
+        "round1",
+        "late".to_string(),
+        "uuid".to_string(),
+    )
+    .await
+    .unwrap();
+    let values = poll_for_broadcasts(
+        &addr,
+        &client,
+        1,
+        2,
+        POLL_DELAY,
+        "round1",
+        "uuid".to_string(),
+        deadline(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(values, vec!["late".to_string()]);
+}
+
+#[tokio::test]
+async fn test_poll_fails_on_errors_and_deadline() {
+    let addr = start_relay().await;
+    let client = ManagerClient::default();
+
+    // Only missing keys are polled again
+    let error = poll_for_broadcasts(
+        &addr,
+        &client,
+        1,
+        2,
+        POLL_DELAY,
+        "round1",
+        "forbidden".to_string(),
+        deadline(),
+    )
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("Unauthorized"));
+
+    let started = Instant::now();
+    let error = poll_for_p2p(
+        &addr,
+        &client,
+        1,
+        2,
+        POLL_DELAY,
+        "round2",
+        "uuid".to_string(),
+        started + Duration::from_millis(100),
+    )
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("Timed out"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    // Nothing listens on port 1
+    let unreachable = "http://127.0.0.1:1";
+    assert!(broadcast(
+        unreachable,
+        &client,
+        1,
+        "round1",
+        "message".to_string(),
+        "uuid".to_string(),
+    )
+    .await
+    .is_err());
+    assert!(sendp2p(
+        unreachable,
+        &client,
+        1,
+        2,
+        "round2",
+        "message".to_string(),
+        "uuid".to_string(),
+    )
+    .await
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_http_transport_returns_relay_errors() {
+    let addr = start_relay().await;
+    let transport = |session_id: &str| {
+        HttpTransport::new(
+            &addr,
+            ManagerClient::default(),
+            1,
+            2,
+            session_id,
+            POLL_DELAY,
+            deadline(),
+        )
+    };
+
+    let error = transport("forbidden")
+        .receive_broadcasts("round1")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Unauthorized"));
+    let error = transport("forbidden")
+        .receive_p2p("round2")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Unauthorized"));
+
+    let unreachable = HttpTransport::new(
+        "http://127.0.0.1:1",
+        ManagerClient::default(),
+        1,
+        2,
+        "uuid",
+        POLL_DELAY,
+        deadline(),
+    );
+    let error = unreachable
+        .send_p2p(2, "round2", "message".to_string())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("party 2"));
+    // The cause is kept
+    assert!(format!("{:#}", error).contains("unreachable"));
+}